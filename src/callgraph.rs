@@ -0,0 +1,100 @@
+//! Call graphs (`--emit=callgraph` and `--emit=callgraph-json`): which functions of the
+//! program call which, and which runtime intrinsics and external functions each one uses.
+//!
+//! The graph is read from the resolved program IR, so calls name the function they bind
+//! to after module resolution and overloading, and the bundled runtime and prelude only
+//! contribute the functions the program reaches. Names are shown as written
+//! (`geom::area`, not the mangled symbol).
+//!
+//! `--gc-functions` uses the same graph to drop the functions nothing reaches from `main`,
+//! the `--entry` function or a `pub fn`.
+
+use crate::{fn_is_public, ir_section, json_string, mangle, IRNode, Symbol};
+use std::collections::HashSet;
+
+/// One function of the program and everything it calls, each listed once in call order.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: Symbol,
+    /// Functions of the program.
+    pub calls: Vec<Symbol>,
+    /// `__` runtime intrinsics.
+    pub intrinsics: Vec<Symbol>,
+    /// `extern` functions, and calls left for the system linker to resolve.
+    pub externs: Vec<Symbol>,
+}
+
+fn collect_calls(n: &IRNode, out: &mut Vec<Symbol>) {
+    if let IRNode::List(l) = n {
+        if l.len() > 1 && l[0].as_atom().is_some_and(|h| h == "call") && let Some(name) = l[1].as_atom() && !out.contains(name) {
+            out.push(*name);
+        }
+        for child in l { collect_calls(child, out); }
+    }
+}
+
+/// The call graph of `ir`, with its functions in program order.
+pub fn build(ir: &IRNode) -> Vec<Function> {
+    let fns: Vec<&[IRNode]> = ir_section(ir, "functions").iter().filter_map(|f| f.as_list().map(|l| l.as_slice())).collect();
+    let defined: Vec<Symbol> = fns.iter().filter_map(|f| f.get(1)?.as_atom().copied()).collect();
+    fns.iter().filter_map(|f| {
+        let mut called = Vec::new();
+        for n in &f[2..] { collect_calls(n, &mut called); }
+        let (intrinsics, rest): (Vec<Symbol>, Vec<Symbol>) = called.into_iter().partition(|c| c.starts_with("__"));
+        let (calls, externs) = rest.into_iter().partition(|c| defined.contains(c));
+        Some(Function { name: *f.get(1)?.as_atom()?, calls, intrinsics, externs })
+    }).collect()
+}
+
+/// Removes the functions of `ir` that no chain of calls reaches from `main`, the `entry`
+/// function or a `pub fn`, and returns their names in program order.
+pub fn strip_unreachable(ir: &mut IRNode, entry: &str) -> Vec<Symbol> {
+    let graph = build(ir);
+    let mut live: HashSet<Symbol> = ir_section(ir, "functions").iter()
+        .filter_map(|f| f.as_list().filter(|l| fn_is_public(l, entry))?.get(1)?.as_atom().copied())
+        .collect();
+    let mut pending: Vec<Symbol> = live.iter().copied().collect();
+    while let Some(name) = pending.pop() {
+        for f in graph.iter().filter(|f| f.name == name) {
+            pending.extend(f.calls.iter().filter(|c| live.insert(**c)));
+        }
+    }
+    let dead: Vec<Symbol> = graph.iter().map(|f| f.name).filter(|n| !live.contains(n)).collect();
+    if let IRNode::List(root) = ir {
+        for section in root.iter_mut() {
+            if let IRNode::List(s) = section && s.first().and_then(|h| h.as_atom()).is_some_and(|h| h == "functions") {
+                s.retain(|f| f.as_list().and_then(|l| l.get(1)).and_then(|n| n.as_atom()).is_none_or(|n| !dead.contains(n)));
+            }
+        }
+    }
+    dead
+}
+
+/// A Graphviz `digraph`: a box per function, an edge per call, and intrinsics and
+/// external functions as ellipses reached by dashed edges.
+pub fn dot(graph: &[Function]) -> String {
+    let quoted = |s: &Symbol| format!("\"{}\"", mangle::display(s).replace('\\', "\\\\").replace('"', "\\\""));
+    let mut out = String::from("digraph callgraph {\n  node [shape=box, fontname=\"monospace\"];\n");
+    for f in graph {
+        out.push_str(&format!("  {};\n", quoted(&f.name)));
+        for c in &f.calls { out.push_str(&format!("  {} -> {};\n", quoted(&f.name), quoted(c))); }
+        for c in f.intrinsics.iter().chain(&f.externs) {
+            out.push_str(&format!("  {} -> {} [style=dashed];\n", quoted(&f.name), quoted(c)));
+        }
+    }
+    let mut leaves: Vec<&Symbol> = graph.iter().flat_map(|f| f.intrinsics.iter().chain(&f.externs)).collect();
+    leaves.sort();
+    leaves.dedup();
+    for c in leaves { out.push_str(&format!("  {} [shape=ellipse];\n", quoted(c))); }
+    out.push_str("}\n");
+    out
+}
+
+/// A JSON array of `{ "name", "calls", "intrinsics", "externs" }` objects, one per function.
+pub fn json(graph: &[Function]) -> String {
+    let list = |names: &[Symbol]| names.iter().map(|n| json_string(&mangle::display(n))).collect::<Vec<_>>().join(", ");
+    let entries: Vec<String> = graph.iter().map(|f| {
+        format!("  {{ \"name\": {}, \"calls\": [{}], \"intrinsics\": [{}], \"externs\": [{}] }}", json_string(&mangle::display(&f.name)), list(&f.calls), list(&f.intrinsics), list(&f.externs))
+    }).collect();
+    if entries.is_empty() { "[]\n".to_string() } else { format!("[\n{}\n]\n", entries.join(",\n")) }
+}
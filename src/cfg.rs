@@ -0,0 +1,148 @@
+//! Control-flow graphs (`--emit=cfg-dot`): one Graphviz `digraph` per function, with its
+//! basic blocks as boxes listing their statements in IR form and the branches between them
+//! as labelled edges.
+//!
+//! Blocks are cut where the backends branch: at the condition of an `if`, `while` or
+//! `match`, where control joins again after one, and at `return`. A `return` jumps to the
+//! function's `exit` block and a call to `__panic` leaves its block with no successor.
+//! Statements after either start a block nothing jumps to. The graph is of the IR as it is
+//! lowered, so under `--profile=release` it shows the folded constants.
+
+use crate::{mangle, IRNode, MatchArms};
+
+struct Block {
+    lines: Vec<String>,
+    /// Successor blocks, with the label of the edge (empty for a plain fallthrough).
+    edges: Vec<(usize, String)>,
+}
+
+struct Graph {
+    blocks: Vec<Block>,
+    exit: usize,
+}
+
+impl Graph {
+    fn block(&mut self) -> usize {
+        self.blocks.push(Block { lines: Vec::new(), edges: Vec::new() });
+        self.blocks.len() - 1
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: impl Into<String>) {
+        self.blocks[from].edges.push((to, label.into()));
+    }
+
+    /// The block `at`, or a new unreachable one when the previous statement never falls
+    /// through.
+    fn current(&mut self, at: Option<usize>) -> usize {
+        match at { Some(b) => b, None => self.block() }
+    }
+
+    /// Adds statement `n` to the graph after block `at` and returns the block control falls
+    /// through to afterwards, or `None` when it does not.
+    fn stmt(&mut self, n: &IRNode, at: Option<usize>) -> Option<usize> {
+        let Some(l) = n.as_list() else { return at };
+        match l.first().and_then(|h| h.as_atom()).map(|h| h.as_str()) {
+            Some("block") => l[1..].iter().fold(at, |at, s| self.stmt(s, at)),
+            Some("if") => {
+                let head = self.current(at);
+                self.blocks[head].lines.push(format!("if {}", l[1].to_ir()));
+                let (then, join) = (self.block(), self.block());
+                self.edge(head, then, "true");
+                if let Some(end) = self.stmt(&l[2], Some(then)) { self.edge(end, join, ""); }
+                match l.get(3).and_then(|e| e.as_list()).and_then(|e| e.get(1)) {
+                    Some(other) => {
+                        let els = self.block();
+                        self.edge(head, els, "false");
+                        if let Some(end) = self.stmt(other, Some(els)) { self.edge(end, join, ""); }
+                    }
+                    None => self.edge(head, join, "false"),
+                }
+                Some(join)
+            }
+            Some("while") => {
+                let before = self.current(at);
+                let (head, body, after) = (self.block(), self.block(), self.block());
+                self.edge(before, head, "");
+                self.blocks[head].lines.push(format!("while {}", l[1].to_ir()));
+                self.edge(head, body, "true");
+                self.edge(head, after, "false");
+                if let Some(end) = self.stmt(&l[2], Some(body)) { self.edge(end, head, ""); }
+                Some(after)
+            }
+            Some("match") => {
+                let head = self.current(at);
+                self.blocks[head].lines.push(format!("match {}", l[1].to_ir()));
+                let m = MatchArms::new(l);
+                let join = self.block();
+                let arms = m.arms.iter().map(|(v, body)| (v.to_string(), *body));
+                for (label, body) in arms.chain(m.default.map(|d| ("_".to_string(), d))) {
+                    let arm = self.block();
+                    self.edge(head, arm, label);
+                    if let Some(end) = self.stmt(body, Some(arm)) { self.edge(end, join, ""); }
+                }
+                if m.default.is_none() { self.edge(head, join, "_"); }
+                Some(join)
+            }
+            Some("return") => {
+                let b = self.current(at);
+                self.blocks[b].lines.push(n.to_ir());
+                self.edge(b, self.exit, "");
+                None
+            }
+            Some("loc") => at,
+            _ => {
+                let b = self.current(at);
+                self.blocks[b].lines.push(n.to_ir());
+                let panics = matches!(l.get(1).and_then(|e| e.as_list()).map(|e| e.as_slice()), Some([IRNode::Atom(c), IRNode::Atom(f), ..]) if c == "call" && f == "__panic");
+                (!panics).then_some(b)
+            }
+        }
+    }
+}
+
+/// Escapes `s` for a double-quoted DOT string.
+fn dot_string(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The control-flow graph of the `(fn ...)` node `f` as a DOT `digraph`.
+fn render_fn(f: &[IRNode]) -> String {
+    let name = mangle::display(f[1].as_atom().map(|n| n.as_str()).unwrap_or_default());
+    let mut g = Graph { blocks: Vec::new(), exit: 0 };
+    let entry = g.block();
+    g.exit = g.block();
+    if let Some(end) = f.get(4).and_then(|b| g.stmt(b, Some(entry))) { g.edge(end, g.exit, ""); }
+
+    // Joins that every branch returned from before reaching are left out when empty.
+    let mut shown = vec![true; g.blocks.len()];
+    loop {
+        let reached: Vec<bool> = (0..g.blocks.len()).map(|i| i < 2 || g.blocks.iter().enumerate().any(|(j, b)| shown[j] && b.edges.iter().any(|e| e.0 == i))).collect();
+        let Some(i) = (2..g.blocks.len()).find(|&i| shown[i] && !reached[i] && g.blocks[i].lines.is_empty()) else { break };
+        shown[i] = false;
+    }
+
+    let mut out = format!("digraph \"{}\" {{\n  node [shape=box, fontname=\"monospace\"];\n", dot_string(&name));
+    for (i, b) in g.blocks.iter().enumerate().filter(|(i, _)| shown[*i]) {
+        let title = match i { 0 => "entry".to_string(), 1 => "exit".to_string(), _ => format!("b{}", i) };
+        let label: String = std::iter::once(title).chain(b.lines.iter().cloned()).map(|l| dot_string(&l) + "\\l").collect();
+        out.push_str(&format!("  b{} [label=\"{}\"];\n", i, label));
+        for (to, edge) in &b.edges {
+            if edge.is_empty() { out.push_str(&format!("  b{} -> b{};\n", i, to)); }
+            else { out.push_str(&format!("  b{} -> b{} [label=\"{}\"];\n", i, to, dot_string(edge))); }
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// The control-flow graphs of every function of `ir`, one `digraph` each.
+pub fn render(ir: &IRNode) -> String {
+    render_each(ir).into_iter().map(|(_, graph)| graph).collect()
+}
+
+/// The control-flow graph of each function of `ir` with the function's symbol name, which
+/// `--out-dir` names its file after.
+pub fn render_each(ir: &IRNode) -> Vec<(String, String)> {
+    crate::ir_section(ir, "functions").iter().filter_map(|f| f.as_list())
+        .map(|f| (f[1].as_atom().map(|n| n.as_str().to_string()).unwrap_or_default(), render_fn(f))).collect()
+}
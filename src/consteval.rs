@@ -0,0 +1,205 @@
+//! Compile-time evaluation of `const fn` calls.
+//!
+//! A `const fn` (a trailing `(const)` node in the IR) may only use `i32` and `bool` values,
+//! locals, `if`, `while`, `return`, arithmetic and comparisons, and calls to other const
+//! fns. A call to one whose arguments are all constant is replaced by its result. When the
+//! evaluation overflows `i32`, divides by zero or runs out of steps, the call is left to run
+//! time, where a const fn is an ordinary function.
+//!
+//! The values of a `static` table are folded the same way, but must be constant: each is
+//! replaced by its literal, and one that cannot be evaluated is an error.
+
+use crate::{ir_section, mangle, IRNode, Symbol};
+use std::collections::HashMap;
+
+/// Statements and expressions evaluated per top-level call before giving up.
+const STEP_BUDGET: usize = 1_000_000;
+/// Deepest nesting of const fn calls inside one evaluation.
+const MAX_CALL_DEPTH: usize = 256;
+
+struct ConstFn {
+    params: Vec<Symbol>,
+    ret: Symbol,
+    body: IRNode,
+}
+
+/// True when the function node carries the `(const)` marker.
+pub fn is_const(f: &[IRNode]) -> bool {
+    f.get(5..).unwrap_or(&[]).iter().any(|m| matches!(m.as_list().map(|m| m.as_slice()), Some([IRNode::Atom(k)]) if k == "const"))
+}
+
+/// Checks every const fn body and replaces constant calls to them throughout `ir`.
+pub fn evaluate(ir: &mut IRNode) -> Result<(), Vec<String>> {
+    let mut fns = HashMap::new();
+    let mut errors = Vec::new();
+    for f in ir_section(ir, "functions").iter().filter_map(|f| f.as_list()) {
+        if !is_const(f) { continue; }
+        let name = f[1].as_atom().cloned().unwrap_or_default();
+        let params: Vec<(Symbol, Symbol)> = f[2].as_list().map(|p| p[1..].iter().filter_map(|p| Some((*p.as_list()?.get(1)?.as_atom()?, *p.as_list()?.get(2)?.as_atom()?))).collect()).unwrap_or_default();
+        let ret = f[3].as_list().and_then(|r| r.get(1)?.as_atom().cloned()).unwrap_or_default();
+        for ty in params.iter().map(|(_, t)| t).chain([&ret]) {
+            if ty != "i32" && ty != "bool" {
+                errors.push(format!("const fn `{}` uses type {}; const fns work on i32 and bool", mangle::display(&name), ty));
+            }
+        }
+        fns.insert(name, ConstFn { params: params.into_iter().map(|(n, _)| n).collect(), ret, body: f[4].clone() });
+    }
+    for (name, f) in &fns { check(&f.body, name, &fns, &mut errors); }
+    if !errors.is_empty() {
+        errors.sort();
+        errors.dedup();
+        return Err(errors);
+    }
+    if !fns.is_empty() { replace_calls(ir, &fns); }
+    fold_statics(ir, &fns)
+}
+
+/// Replaces each value of every `(static NAME T v...)` with the literal it evaluates to.
+fn fold_statics(ir: &mut IRNode, fns: &HashMap<Symbol, ConstFn>) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    let IRNode::List(root) = ir else { return Ok(()) };
+    let statics = root.iter_mut().filter_map(|s| match s { IRNode::List(s) if s.first().is_some_and(|h| h.as_atom().is_some_and(|h| h == "statics")) => Some(s), _ => None });
+    for st in statics.flat_map(|s| s.iter_mut().skip(1)) {
+        let IRNode::List(l) = st else { continue };
+        let name = l.get(1).and_then(|n| n.as_atom()).cloned().unwrap_or_default();
+        for v in l.iter_mut().skip(3).filter(|v| v.as_list().is_some()) {
+            let mut eval = Eval { fns, steps: 0, depth: 0 };
+            match eval.expr(v, &mut vec![HashMap::new()]) {
+                Some(value) => *v = IRNode::atom(value.to_string()),
+                None => errors.push(format!("static `{}` has a value that is not a constant i32 expression", mangle::display(&name))),
+            }
+        }
+    }
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+/// Reports the nodes a const fn body may not contain.
+fn check(node: &IRNode, name: &str, fns: &HashMap<Symbol, ConstFn>, errors: &mut Vec<String>) {
+    let Some(l) = node.as_list() else { return };
+    let head = l.first().and_then(|h| h.as_atom()).map(|h| h.as_str()).unwrap_or("");
+    match head {
+        "block" | "let" | "assign" | "if" | "else" | "while" | "return" | "expr" | "binary" | "int" | "bool" | "ident" | "loc" => {}
+        "call" => {
+            let callee = l.get(1).and_then(|c| c.as_atom()).cloned().unwrap_or_default();
+            if !fns.contains_key(&callee) {
+                errors.push(format!("const fn `{}` calls `{}`, which is not a const fn", mangle::display(name), mangle::display(&callee)));
+            }
+        }
+        other => errors.push(format!("const fn `{}` cannot use `{}`", mangle::display(name), other)),
+    }
+    let skip = if head == "call" { 2 } else { 1 };
+    for child in &l[skip..] { check(child, name, fns, errors); }
+}
+
+fn replace_calls(node: &mut IRNode, fns: &HashMap<Symbol, ConstFn>) {
+    let IRNode::List(l) = node else { return };
+    for child in l.iter_mut() { replace_calls(child, fns); }
+    if l.first().and_then(|h| h.as_atom()).map(|h| h != "call").unwrap_or(true) { return; }
+    let Some(f) = l.get(1).and_then(|c| c.as_atom()).and_then(|c| fns.get(c)) else { return };
+    let mut eval = Eval { fns, steps: 0, depth: 0 };
+    let Some(value) = eval.expr(node, &mut Vec::new()) else { return };
+    let kind = if f.ret == "bool" { "bool" } else { "int" };
+    *node = IRNode::List(vec![IRNode::atom(kind.to_string()), IRNode::atom(value.to_string())]);
+}
+
+/// Locals in scope, innermost block last: a `let` binds in its own block, as in codegen.
+type Scopes = Vec<HashMap<Symbol, i64>>;
+
+enum Flow {
+    Next,
+    Return(i64),
+}
+
+struct Eval<'a> {
+    fns: &'a HashMap<Symbol, ConstFn>,
+    steps: usize,
+    depth: usize,
+}
+
+impl Eval<'_> {
+    fn tick(&mut self) -> Option<()> {
+        self.steps += 1;
+        (self.steps <= STEP_BUDGET).then_some(())
+    }
+
+    fn block(&mut self, node: &IRNode, env: &mut Scopes) -> Option<Flow> {
+        env.push(HashMap::new());
+        let flow = self.statements(node, env);
+        env.pop();
+        flow
+    }
+
+    fn statements(&mut self, node: &IRNode, env: &mut Scopes) -> Option<Flow> {
+        for stmt in node.as_list()?.iter().skip(1) {
+            if let Flow::Return(v) = self.stmt(stmt, env)? { return Some(Flow::Return(v)); }
+        }
+        Some(Flow::Next)
+    }
+
+    fn stmt(&mut self, node: &IRNode, env: &mut Scopes) -> Option<Flow> {
+        self.tick()?;
+        let l = node.as_list()?;
+        match l.first()?.as_atom()?.as_str() {
+            "let" => { let v = self.expr(l.get(3)?, env)?; env.last_mut()?.insert(*l[1].as_atom()?, v); }
+            "assign" => {
+                let v = self.expr(l.get(2)?, env)?;
+                *env.iter_mut().rev().find_map(|scope| scope.get_mut(l[1].as_atom()?))? = v;
+            }
+            "expr" => { self.expr(l.get(1)?, env)?; }
+            "return" => return Some(Flow::Return(self.expr(l.get(1)?, env)?)),
+            "if" => {
+                if self.expr(l.get(1)?, env)? != 0 { return self.block(l.get(2)?, env); }
+                if let Some(e) = l.get(3).and_then(|e| e.as_list()) { return self.block(e.get(1)?, env); }
+            }
+            "while" => {
+                while self.expr(l.get(1)?, env)? != 0 {
+                    if let Flow::Return(v) = self.block(l.get(2)?, env)? { return Some(Flow::Return(v)); }
+                    self.tick()?;
+                }
+            }
+            "block" => return self.block(node, env),
+            "loc" => {}
+            _ => return None,
+        }
+        Some(Flow::Next)
+    }
+
+    fn expr(&mut self, node: &IRNode, env: &mut Scopes) -> Option<i64> {
+        self.tick()?;
+        let l = node.as_list()?;
+        let value = match l.first()?.as_atom()?.as_str() {
+            "int" | "bool" => l.get(1)?.as_atom()?.parse().ok()?,
+            "ident" => *env.iter().rev().find_map(|scope| scope.get(l.get(1)?.as_atom()?))?,
+            "binary" => {
+                let (a, b) = (self.expr(l.get(2)?, env)?, self.expr(l.get(3)?, env)?);
+                match l[1].as_atom()?.as_str() {
+                    "add" => a.checked_add(b)?,
+                    "sub" => a.checked_sub(b)?,
+                    "mul" => a.checked_mul(b)?,
+                    "div" if b != 0 => a.checked_div(b)?,
+                    "and" => a & b,
+                    "or" => a | b,
+                    "eq" => (a == b) as i64,
+                    "ne" => (a != b) as i64,
+                    "lt" => (a < b) as i64,
+                    "gt" => (a > b) as i64,
+                    "le" => (a <= b) as i64,
+                    "ge" => (a >= b) as i64,
+                    _ => return None,
+                }
+            }
+            "call" => {
+                let f = self.fns.get(l.get(1)?.as_atom()?)?;
+                if l.len() - 2 != f.params.len() || self.depth >= MAX_CALL_DEPTH { return None; }
+                let mut args = HashMap::new();
+                for (p, a) in f.params.iter().zip(&l[2..]) { args.insert(*p, self.expr(a, env)?); }
+                self.depth += 1;
+                let flow = self.block(&f.body, &mut vec![args]);
+                self.depth -= 1;
+                match flow? { Flow::Return(v) => v, Flow::Next => return None }
+            }
+            _ => return None,
+        };
+        i32::try_from(value).ok().map(i64::from)
+    }
+}
@@ -0,0 +1,191 @@
+//! Definite-assignment analysis.
+//!
+//! A `let` may leave out its initializer (`let x: i32`), which is `(let x i32 (uninit))`
+//! in the IR. Every read of such a variable must then come after an assignment on every
+//! path that reaches it: both branches of an `if` with an `else`, every arm of a `match`
+//! with a `_` arm, and not only inside a loop body, which may run zero times. A path
+//! that ends in `return` or `panic` reaches nothing after it.
+//!
+//! A struct variable declared without an initializer can be built one field at a time
+//! with `p.x = ...`. Reading a field needs that field (or the whole variable) assigned;
+//! reading or passing the whole variable needs every field assigned.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::layout::Field;
+use crate::typecheck::Diagnostic;
+use crate::{IRNode, Symbol};
+
+/// The variables and field paths (`key.a.b`) definitely assigned at a point of the
+/// function, or `None` where the point cannot be reached.
+type State = Option<HashSet<String>>;
+
+/// Both paths joined: what is assigned on each of them.
+fn meet(a: State, b: State) -> State {
+    match (a, b) {
+        (None, s) | (s, None) => s,
+        (Some(a), Some(b)) => Some(a.intersection(&b).cloned().collect()),
+    }
+}
+
+struct Analysis<'a> {
+    structs: &'a HashMap<Symbol, (Vec<Field>, u32)>,
+    /// `let`s in scope by name: the key the state uses for that declaration, and its type.
+    vars: HashMap<Symbol, (String, Symbol)>,
+    declared: usize,
+    /// Keys already reported, so each variable or field is reported once.
+    reported: HashSet<String>,
+    current_fn: Symbol,
+    errors: Vec<Diagnostic>,
+}
+
+fn atom(n: Option<&IRNode>) -> Symbol {
+    n.and_then(|n| n.as_atom()).cloned().unwrap_or_default()
+}
+
+impl Analysis<'_> {
+    /// Whether `path` below `ty` is assigned as a whole, or field by field.
+    fn covered(&self, path: &str, ty: &str, assigned: &HashSet<String>) -> bool {
+        self.covered_within(path, ty, assigned, &mut Vec::new())
+    }
+
+    /// [`Self::covered`], with `outer` the struct types `path` is nested in: a struct
+    /// that contains itself (an error the checker reports) is only covered as a whole.
+    fn covered_within<'t>(&'t self, path: &str, ty: &'t str, assigned: &HashSet<String>, outer: &mut Vec<&'t str>) -> bool {
+        if assigned.contains(path) { return true; }
+        if outer.contains(&ty) { return false; }
+        match self.structs.get(ty) {
+            Some((fields, _)) if !fields.is_empty() => {
+                outer.push(ty);
+                let covered = fields.iter().all(|f| self.covered_within(&format!("{}.{}", path, f.name), &f.ty, assigned, outer));
+                outer.pop();
+                covered
+            }
+            _ => false,
+        }
+    }
+
+    /// Reports a read of `name`, or of its field reached through `path`, that is not
+    /// definitely assigned in `state`. Parameters and statics are always assigned.
+    fn read(&mut self, name: &str, path: &[IRNode], state: &State) {
+        let (Some(assigned), Some((key, ty))) = (state, self.vars.get(name)) else { return };
+        let mut at = key.clone();
+        let mut ty = *ty;
+        if assigned.contains(&at) { return; }
+        for field in path.iter().filter_map(|f| f.as_atom()) {
+            at = format!("{}.{}", at, field);
+            ty = self.structs.get(&ty).and_then(|(fields, _)| fields.iter().find(|f| &f.name == field)).map(|f| f.ty).unwrap_or_default();
+            if assigned.contains(&at) { return; }
+        }
+        if self.covered(&at, &ty, assigned) || !self.reported.insert(at) { return; }
+        let shown = std::iter::once(name.to_string()).chain(path.iter().filter_map(|f| f.as_atom().map(|f| f.to_string()))).collect::<Vec<_>>().join(".");
+        let what = if path.is_empty() { format!("`{}`", shown) } else { format!("field `{}`", shown) };
+        self.errors.push(Diagnostic::new(format!("in fn {}: {} is read before it is definitely assigned; give it an initializer or assign it on every path before this use", crate::mangle::display(&self.current_fn), what)));
+    }
+
+    /// Marks `name`, or its field reached through `path`, as assigned.
+    fn assign(&self, name: &str, path: &[IRNode], state: &mut State) {
+        let (Some(assigned), Some((key, _))) = (state.as_mut(), self.vars.get(name)) else { return };
+        let fields: Vec<&str> = path.iter().filter_map(|f| f.as_atom()).map(|f| f.as_str()).collect();
+        assigned.insert(std::iter::once(key.as_str()).chain(fields).collect::<Vec<_>>().join("."));
+    }
+
+    fn expr(&mut self, n: &IRNode, state: &State) {
+        let Some(l) = n.as_list() else { return };
+        match l.first().and_then(|h| h.as_atom()).map(|h| h.as_str()) {
+            Some("ident") => self.read(&atom(l.get(1)), &[], state),
+            Some("field") => self.read(&atom(l.get(1)), &l[2..], state),
+            Some("slice_index") | Some("slice_range") | Some("array_index") => {
+                self.read(&atom(l.get(1)), &[], state);
+                for child in &l[2..] { self.expr(child, state); }
+            }
+            _ => for child in &l[1..] { self.expr(child, state); },
+        }
+    }
+
+    /// The statements of `(block ...)`, with its `let`s going out of scope at the end.
+    fn block(&mut self, n: &IRNode, state: &mut State) {
+        let outer = self.vars.clone();
+        for s in n.as_list().map(|l| &l[1..]).unwrap_or_default() { self.stmt(s, state); }
+        self.vars = outer;
+    }
+
+    fn stmt(&mut self, n: &IRNode, state: &mut State) {
+        let Some(l) = n.as_list() else { return };
+        match l.first().and_then(|h| h.as_atom()).map(|h| h.as_str()) {
+            Some("let") if l.len() > 3 => {
+                let initialized = l[3].as_list().and_then(|e| e.first()).and_then(|h| h.as_atom()).is_none_or(|h| h != "uninit");
+                if initialized { self.expr(&l[3], state); }
+                self.declared += 1;
+                let key = format!("{}#{}", atom(l.get(1)), self.declared);
+                self.vars.insert(atom(l.get(1)), (key.clone(), atom(l.get(2))));
+                if initialized && let Some(assigned) = state.as_mut() { assigned.insert(key); }
+            }
+            Some("assign") if l.len() > 2 => {
+                self.expr(&l[2], state);
+                self.assign(&atom(l.get(1)), &[], state);
+            }
+            Some("field_assign") if l.len() > 3 => {
+                self.expr(&l[l.len() - 1], state);
+                self.assign(&atom(l.get(1)), &l[2..l.len() - 1], state);
+            }
+            Some("slice_assign") | Some("array_assign") => {
+                self.read(&atom(l.get(1)), &[], state);
+                for child in &l[2..] { self.expr(child, state); }
+            }
+            Some("block") => self.block(n, state),
+            Some("if") if l.len() > 2 => {
+                self.expr(&l[1], state);
+                let mut then = state.clone();
+                self.block(&l[2], &mut then);
+                let mut otherwise = state.clone();
+                if let Some(e) = l.get(3).and_then(|e| e.as_list()) && let Some(b) = e.get(1) { self.block(b, &mut otherwise); }
+                *state = meet(then, otherwise);
+            }
+            Some("while") if l.len() > 2 => {
+                self.expr(&l[1], state);
+                let mut body = state.clone();
+                self.block(&l[2], &mut body);
+            }
+            Some("match") if l.len() > 1 => {
+                self.expr(&l[1], state);
+                let arms: Vec<&[IRNode]> = l[2..].iter().filter_map(|a| a.as_list()).map(|a| a.as_slice()).collect();
+                let exhaustive = arms.iter().any(|a| atom(a.first()) == "default");
+                let mut joined = if exhaustive { None } else { state.clone() };
+                for arm in arms {
+                    let mut s = state.clone();
+                    if let Some(body) = arm.last() { self.block(body, &mut s); }
+                    joined = meet(joined, s);
+                }
+                *state = joined;
+            }
+            Some("return") => {
+                for child in &l[1..] { self.expr(child, state); }
+                *state = None;
+            }
+            Some("expr") => {
+                for child in &l[1..] { self.expr(child, state); }
+                let panics = l.get(1).and_then(|c| c.as_list()).is_some_and(|c| atom(c.first()) == "call" && atom(c.get(1)) == "__panic");
+                if panics { *state = None; }
+            }
+            // Inline assembly may write any of its operands.
+            Some("asm") => {
+                for op in l.get(2).and_then(|o| o.as_list()).map(|o| &o[1..]).unwrap_or_default() { self.assign(&atom(Some(op)), &[], state); }
+            }
+            Some("loc") => {}
+            _ => for child in &l[1..] { self.expr(child, state); },
+        }
+    }
+}
+
+/// Checks every function of the program, reporting reads of variables and struct fields
+/// that are not definitely assigned.
+pub fn check(fns: &[&[IRNode]], structs: &HashMap<Symbol, (Vec<Field>, u32)>) -> Vec<Diagnostic> {
+    let mut analysis = Analysis { structs, vars: HashMap::new(), declared: 0, reported: HashSet::new(), current_fn: Symbol::default(), errors: Vec::new() };
+    for f in fns {
+        analysis.current_fn = atom(f.get(1));
+        analysis.vars.clear();
+        if let Some(body) = f.get(4) { analysis.block(body, &mut Some(HashSet::new())); }
+    }
+    analysis.errors
+}
@@ -0,0 +1,97 @@
+//! API pages (`coatl doc`): the functions and structs of one source file with their
+//! signatures and the `///` comments written above them, as Markdown or HTML.
+//!
+//! The parser attaches a doc comment to its item as a trailing `(doc "text")` node, so
+//! pages are built from the IR of the file alone; imports are not followed.
+
+use crate::{ir_section, IRNode, Symbol};
+
+/// A documented item: its signature as source text and its doc comment, if any.
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub name: String,
+    pub signature: String,
+    pub doc: Option<String>,
+}
+
+/// The API page of one module.
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub module: String,
+    pub structs: Vec<Item>,
+    pub functions: Vec<Item>,
+}
+
+/// The `(name ...)` marker of an item node, such as `(doc "text")` or `(vis pub)`.
+fn marker<'a>(item: &'a [IRNode], name: &str) -> Option<&'a [IRNode]> {
+    item.iter().filter_map(|n| n.as_list()).find(|l| l.first().and_then(|h| h.as_atom()).is_some_and(|h| h == name)).map(|l| &l[1..])
+}
+
+fn atom(n: Option<&IRNode>) -> Symbol {
+    n.and_then(|n| n.as_atom()).cloned().unwrap_or_default()
+}
+
+fn doc_of(item: &[IRNode]) -> Option<String> {
+    marker(item, "doc").map(|d| d.first().and_then(|d| d.as_str()).unwrap_or_default().to_string())
+}
+
+/// `[pub ][const ]fn name(a: T, ...) returns R`.
+fn fn_signature(f: &[IRNode]) -> String {
+    let params: Vec<String> = marker(f, "params").unwrap_or_default().iter().filter_map(|p| p.as_list()).map(|p| format!("{}: {}", atom(p.get(1)), atom(p.get(2)))).collect();
+    let ret = marker(f, "ret").map(|r| atom(r.first())).unwrap_or_default();
+    let public = if marker(f, "vis").is_some() { "pub " } else { "" };
+    let constant = if marker(f, "const").is_some() { "const " } else { "" };
+    format!("{}{}fn {}({}) returns {}", public, constant, atom(f.get(1)), params.join(", "), ret)
+}
+
+/// `struct Name { a: T, ... }`, one field per line.
+fn struct_signature(s: &[IRNode]) -> String {
+    let fields: Vec<String> = s[2..].iter().filter_map(|f| f.as_list()).filter(|f| atom(f.first()) == "field").map(|f| format!("    {}: {}", atom(f.get(1)), atom(f.get(2)))).collect();
+    if fields.is_empty() { format!("struct {}", atom(s.get(1))) } else { format!("struct {} {{\n{}\n}}", atom(s.get(1)), fields.join(",\n")) }
+}
+
+/// The page of `module` from the IR returned by [`crate::parse_items`], in source order.
+pub fn page(module: &str, ir: &IRNode) -> Page {
+    let items = |section: &str, signature: fn(&[IRNode]) -> String| -> Vec<Item> {
+        ir_section(ir, section).iter().filter_map(|n| n.as_list()).map(|n| Item { name: atom(n.get(1)).to_string(), signature: signature(n), doc: doc_of(n) }).collect()
+    };
+    Page { module: module.to_string(), structs: items("structs", struct_signature), functions: items("functions", fn_signature) }
+}
+
+/// The page as Markdown: a heading per item, its signature in a code block, then its doc.
+pub fn markdown(page: &Page) -> String {
+    let mut out = format!("# Module `{}`\n", page.module);
+    for (title, items) in [("Structs", &page.structs), ("Functions", &page.functions)] {
+        if items.is_empty() { continue; }
+        out.push_str(&format!("\n## {}\n", title));
+        for item in items {
+            out.push_str(&format!("\n### `{}`\n\n```coatl\n{}\n```\n", item.name, item.signature));
+            if let Some(doc) = &item.doc { out.push_str(&format!("\n{}\n", doc)); }
+        }
+    }
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// The page as a standalone HTML document with the same structure as [`markdown`]. Doc
+/// text is escaped and its paragraphs (separated by blank lines) kept.
+pub fn html(page: &Page) -> String {
+    let module = escape(&page.module);
+    let mut out = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>Module <code>{0}</code></h1>\n", module);
+    for (title, items) in [("Structs", &page.structs), ("Functions", &page.functions)] {
+        if items.is_empty() { continue; }
+        out.push_str(&format!("<h2>{}</h2>\n", title));
+        for item in items {
+            let name = escape(&item.name);
+            out.push_str(&format!("<h3 id=\"{0}\"><code>{0}</code></h3>\n<pre><code>{1}</code></pre>\n", name, escape(&item.signature)));
+            for para in item.doc.iter().flat_map(|d| d.split("\n\n")).filter(|p| !p.trim().is_empty()) {
+                out.push_str(&format!("<p>{}</p>\n", escape(para.trim())));
+            }
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
@@ -17,6 +17,8 @@ __cmdline_buf:
   .zero 4096
 __pollfd:
   .zero 8
+__coatl_trace_depth:
+  .quad 0
 
 .text
 
@@ -27,6 +29,8 @@ __pollfd:
 .globl __mem_load8
 .globl __fd_write
 .globl __fd_read
+.globl __fd_pread
+.globl __fd_pwrite
 .globl __fd_close
 .globl __path_open
 .globl __tty_get_mode
@@ -37,6 +41,13 @@ __pollfd:
 .globl __get_argv
 .globl __path_create
 .globl __tty_has_input
+.globl __sleep_ms
+.globl __panic
+.globl __dbg
+.globl __str_eq
+.globl __itoa
+.globl __print_int
+.globl __atoi
 
 __mem_store:
   lea r8, [rip+__coatl_mem]
@@ -62,108 +73,6 @@ __mem_load8:
   movsx rax, byte ptr [rdi]
   ret
 
-__fd_write:
-  lea r8, [rip+__coatl_mem]
-  push rcx
-  add rsi, r8
-  mov eax, [rsi+4]
-  push rax
-  mov eax, [rsi]
-  add rax, r8
-  push rax
-  mov rsi, rsp
-  mov eax, 20
-  syscall
-  add rsp, 16
-  pop rcx
-  lea r8, [rip+__coatl_mem]
-  add rcx, r8
-  mov [rcx], eax
-  mov eax, 0
-  ret
-
-__fd_read:
-  lea r8, [rip+__coatl_mem]
-  push rcx
-  add rsi, r8
-  mov eax, [rsi+4]
-  push rax
-  mov eax, [rsi]
-  add rax, r8
-  push rax
-  mov rsi, rsp
-  mov eax, 19
-  syscall
-  add rsp, 16
-  pop rcx
-  lea r8, [rip+__coatl_mem]
-  add rcx, r8
-  mov [rcx], eax
-  mov eax, 0
-  ret
-
-__fd_close:
-  mov eax, 3
-  syscall
-  ret
-
-__path_open:
-  push rbx
-  push r12
-  mov r12, [rsp+40]
-  lea rbx, [rip+__coatl_mem]
-  mov rsi, rdx
-  add rsi, rbx
-  mov eax, 257
-  mov edi, -100
-  xor edx, edx
-  xor r10d, r10d
-  xor r8d, r8d
-  xor r9d, r9d
-  syscall
-  cmp rax, 0
-  jl .L_open_fail
-  lea rbx, [rip+__coatl_mem]
-  mov dword ptr [rbx + r12], eax
-  xor eax, eax
-  pop r12
-  pop rbx
-  ret
-.L_open_fail:
-  lea rbx, [rip+__coatl_mem]
-  mov dword ptr [rbx + r12], -1
-  mov eax, 1
-  pop r12
-  pop rbx
-  ret
-
-__print:
-  push rbp
-  mov rbp, rsp
-  push r12
-  push r13
-  lea r8, [rip+__coatl_mem]
-  mov r12, rdi
-  add r12, r8
-  mov r13, 0
-.L_print_len_loop:
-  mov al, byte ptr [r12 + r13]
-  cmp al, 0
-  je .L_print_len_done
-  inc r13
-  jmp .L_print_len_loop
-.L_print_len_done:
-  mov eax, 1
-  mov edi, 1
-  mov rsi, r12
-  mov rdx, r13
-  syscall
-  mov eax, 0
-  pop r13
-  pop r12
-  pop rbp
-  ret
-
 __tty_get_mode:
   push rbp
   mov rbp, rsp
@@ -382,6 +291,524 @@ __get_argv:
   xor eax, eax
   ret
 
+__tty_has_input:
+  mov dword ptr [rip+__pollfd], edi
+  mov word ptr [rip+__pollfd + 4], 1
+  mov word ptr [rip+__pollfd + 6], 0
+  mov edx, esi
+  mov esi, 1
+  lea rdi, [rip+__pollfd]
+  mov eax, 7
+  syscall
+  cmp rax, 0
+  jle .L_no_input
+  mov ax, word ptr [rip+__pollfd + 6]
+  test ax, 1
+  jz .L_no_input
+  mov eax, 1
+  ret
+.L_no_input:
+  xor eax, eax
+  ret
+
+__sleep_ms:
+  sub rsp, 24
+  xor eax, eax
+  test rdi, rdi
+  jle .L_sleep_done
+  mov rax, rdi
+  xor edx, edx
+  mov ecx, 1000
+  div rcx
+  mov [rsp], rax
+  imul rdx, rdx, 1000000
+  mov [rsp+8], rdx
+.L_sleep_again:
+  mov rdi, rsp
+  mov rsi, rsp
+  mov eax, 35
+  syscall
+  cmp rax, -4
+  je .L_sleep_again
+  xor eax, eax
+.L_sleep_done:
+  add rsp, 24
+  ret
+
+__str_eq:
+  lea r8, [rip+__coatl_mem]
+  add rdi, r8
+  add rsi, r8
+.L_str_eq_loop:
+  movzx eax, byte ptr [rdi]
+  cmp al, byte ptr [rsi]
+  jne .L_str_eq_ne
+  test al, al
+  jz .L_str_eq_eq
+  inc rdi
+  inc rsi
+  jmp .L_str_eq_loop
+.L_str_eq_eq:
+  mov eax, 1
+  ret
+.L_str_eq_ne:
+  xor eax, eax
+  ret
+
+__panic:
+  push rbp
+  mov rbp, rsp
+  push r12
+  push r13
+  lea r8, [rip+__coatl_mem]
+  lea r12, [r8 + rdi]
+  lea r13, [r8 + rsi]
+  mov rdi, r12
+  mov rsi, r13
+  call __coatl_panic_hook
+  lea rsi, [rip+.L_panic_prefix]
+  mov edx, 9
+  call .L_panic_write
+  mov rsi, r13
+  call .L_panic_write_cstr
+  lea rsi, [rip+.L_panic_sep]
+  mov edx, 2
+  call .L_panic_write
+  mov rsi, r12
+  call .L_panic_write_cstr
+  lea rsi, [rip+.L_panic_sep + 2]
+  mov edx, 1
+  call .L_panic_write
+  call __coatl_backtrace
+  mov edi, 101
+  call __coatl_exit
+.L_panic_write_cstr:
+  xor edx, edx
+.L_panic_len_loop:
+  cmp byte ptr [rsi + rdx], 0
+  je .L_panic_write
+  inc rdx
+  jmp .L_panic_len_loop
+.L_panic_write:
+  mov edi, 2
+  call __coatl_write
+  ret
+.L_panic_prefix:
+  .ascii "panic at "
+.L_panic_sep:
+  .ascii ": \n"
+
+__coatl_write_dec:
+  mov esi, 2
+__coatl_write_dec_fd:
+  push rbp
+  mov rbp, rsp
+  sub rsp, 32
+  mov rax, rdi
+  mov edi, esi
+  mov r8, rbp
+  mov ecx, 10
+.L_wdec_loop:
+  xor edx, edx
+  div rcx
+  add dl, 48
+  dec r8
+  mov byte ptr [r8], dl
+  test rax, rax
+  jnz .L_wdec_loop
+  mov rsi, r8
+  mov rdx, rbp
+  sub rdx, r8
+  call __coatl_write
+  leave
+  ret
+
+__dbg:
+  push rbp
+  mov rbp, rsp
+  push rbx
+  push r12
+  mov rbx, rdi
+  lea r8, [rip+__coatl_mem]
+  add rsi, r8
+  xor edx, edx
+.L_dbg_len_loop:
+  cmp byte ptr [rsi + rdx], 0
+  je .L_dbg_len_done
+  inc rdx
+  jmp .L_dbg_len_loop
+.L_dbg_len_done:
+  mov edi, 2
+  call __coatl_write
+  lea rsi, [rip+.L_dbg_text]
+  mov edx, 3
+  mov edi, 2
+  call __coatl_write
+  mov rdi, rbx
+  test rbx, rbx
+  jns .L_dbg_value
+  lea rsi, [rip+.L_dbg_text + 3]
+  mov edx, 1
+  mov edi, 2
+  call __coatl_write
+  mov rdi, rbx
+  neg rdi
+.L_dbg_value:
+  call __coatl_write_dec
+  lea rsi, [rip+.L_dbg_text + 4]
+  mov edx, 1
+  mov edi, 2
+  call __coatl_write
+  mov rax, rbx
+  pop r12
+  pop rbx
+  pop rbp
+  ret
+.L_dbg_text:
+  .ascii " = -\n"
+
+__itoa:
+  push rbp
+  mov rbp, rsp
+  sub rsp, 16
+  lea r8, [rip+__coatl_mem]
+  add rsi, r8
+  mov r9, rsi
+  movsxd rax, edi
+  test rax, rax
+  jns .L_itoa_digits
+  mov byte ptr [rsi], 45
+  inc rsi
+  neg rax
+.L_itoa_digits:
+  mov r10, rbp
+  mov ecx, 10
+.L_itoa_loop:
+  xor edx, edx
+  div rcx
+  add dl, 48
+  dec r10
+  mov byte ptr [r10], dl
+  test rax, rax
+  jnz .L_itoa_loop
+.L_itoa_copy:
+  mov al, byte ptr [r10]
+  mov byte ptr [rsi], al
+  inc rsi
+  inc r10
+  cmp r10, rbp
+  jne .L_itoa_copy
+  mov byte ptr [rsi], 0
+  mov rax, rsi
+  sub rax, r9
+  leave
+  ret
+
+__atoi:
+  lea r8, [rip+__coatl_mem]
+  add rdi, r8
+  lea r9, [rdx + r8]
+  movsxd rsi, esi
+.L_atoi_trim:
+  test rsi, rsi
+  jle .L_atoi_fail
+  movzx eax, byte ptr [rdi + rsi - 1]
+  cmp al, 10
+  je .L_atoi_trim_one
+  cmp al, 13
+  jne .L_atoi_sign
+.L_atoi_trim_one:
+  dec rsi
+  jmp .L_atoi_trim
+.L_atoi_sign:
+  add rsi, rdi
+  xor ecx, ecx
+  movzx eax, byte ptr [rdi]
+  cmp al, 45
+  jne .L_atoi_plus
+  mov ecx, 1
+  inc rdi
+  jmp .L_atoi_first
+.L_atoi_plus:
+  cmp al, 43
+  jne .L_atoi_first
+  inc rdi
+.L_atoi_first:
+  cmp rdi, rsi
+  je .L_atoi_fail
+  xor eax, eax
+  mov r10d, 0x80000000
+.L_atoi_loop:
+  movzx edx, byte ptr [rdi]
+  sub edx, 48
+  cmp edx, 9
+  ja .L_atoi_fail
+  imul rax, rax, 10
+  add rax, rdx
+  cmp rax, r10
+  ja .L_atoi_fail
+  inc rdi
+  cmp rdi, rsi
+  jne .L_atoi_loop
+  test ecx, ecx
+  jnz .L_atoi_neg
+  cmp rax, r10
+  jae .L_atoi_fail
+  jmp .L_atoi_done
+.L_atoi_neg:
+  neg rax
+.L_atoi_done:
+  mov dword ptr [r9], eax
+  xor eax, eax
+  ret
+.L_atoi_fail:
+  mov rax, -1
+  ret
+
+__print_int:
+  push rbp
+  mov rbp, rsp
+  push rbx
+  push r12
+  movsxd rbx, edi
+  test rbx, rbx
+  jns .L_print_int_value
+  lea rsi, [rip+.L_dbg_text + 3]
+  mov edx, 1
+  mov edi, 1
+  call __coatl_write
+  neg rbx
+.L_print_int_value:
+  mov rdi, rbx
+  mov esi, 1
+  call __coatl_write_dec_fd
+  xor eax, eax
+  pop r12
+  pop rbx
+  pop rbp
+  ret
+
+__coatl_trace:
+  push rbp
+  mov rbp, rsp
+  push r12
+  push r13
+  mov r12, rdi
+  lea r8, [rip+__coatl_mem]
+  lea r13, [r8 + rsi]
+  test r12, r12
+  jz .L_trace_indent
+  dec qword ptr [rip+__coatl_trace_depth]
+.L_trace_indent:
+  mov r9, qword ptr [rip+__coatl_trace_depth]
+.L_trace_indent_loop:
+  test r9, r9
+  jle .L_trace_arrow
+  lea rsi, [rip+.L_trace_text]
+  mov edx, 2
+  mov edi, 2
+  call __coatl_write
+  dec r9
+  jmp .L_trace_indent_loop
+.L_trace_arrow:
+  lea rsi, [rip+.L_trace_text + 2]
+  test r12, r12
+  jz .L_trace_arrow_write
+  add rsi, 3
+.L_trace_arrow_write:
+  mov edx, 3
+  mov edi, 2
+  call __coatl_write
+  mov rsi, r13
+  xor edx, edx
+.L_trace_len_loop:
+  cmp byte ptr [rsi + rdx], 0
+  je .L_trace_len_done
+  inc rdx
+  jmp .L_trace_len_loop
+.L_trace_len_done:
+  mov edi, 2
+  call __coatl_write
+  lea rsi, [rip+.L_trace_text + 8]
+  mov edx, 1
+  mov edi, 2
+  call __coatl_write
+  test r12, r12
+  jnz .L_trace_done
+  inc qword ptr [rip+__coatl_trace_depth]
+.L_trace_done:
+  pop r13
+  pop r12
+  pop rbp
+  ret
+.L_trace_text:
+  .ascii "  -> <- \n"
+"#
+    };
+}
+
+// File and console I/O through raw Linux syscalls (the default, freestanding mode).
+// `__coatl_write` (fd in edi, address in rsi, length in rdx) and `__coatl_exit` (status in
+// edi) are what the runtime routines above use for diagnostics; like the syscalls they
+// replace, they preserve every register but rax, rcx and r11.
+macro_rules! x86_64_asm_io_syscall {
+    () => {
+        r#"
+__coatl_write:
+  mov eax, 1
+  syscall
+  ret
+
+__coatl_exit:
+  mov eax, 231
+  syscall
+
+__fd_write:
+  lea r8, [rip+__coatl_mem]
+  push rcx
+  add rsi, r8
+  mov eax, [rsi+4]
+  push rax
+  mov eax, [rsi]
+  add rax, r8
+  push rax
+  mov rsi, rsp
+  mov eax, 20
+  syscall
+  add rsp, 16
+  pop rcx
+  lea r8, [rip+__coatl_mem]
+  add rcx, r8
+  mov [rcx], eax
+  mov eax, 0
+  ret
+
+__fd_read:
+  lea r8, [rip+__coatl_mem]
+  push rcx
+  add rsi, r8
+  mov eax, [rsi+4]
+  push rax
+  mov eax, [rsi]
+  add rax, r8
+  push rax
+  mov rsi, rsp
+  mov eax, 19
+  syscall
+  add rsp, 16
+  pop rcx
+  lea r8, [rip+__coatl_mem]
+  add rcx, r8
+  mov [rcx], eax
+  mov eax, 0
+  ret
+
+__fd_pread:
+  lea r9, [rip+__coatl_mem]
+  push r8
+  add rsi, r9
+  mov eax, [rsi+4]
+  push rax
+  mov eax, [rsi]
+  add rax, r9
+  push rax
+  mov rsi, rsp
+  mov r10, rcx
+  xor r8d, r8d
+  mov eax, 295
+  syscall
+  add rsp, 16
+  pop rcx
+  lea r8, [rip+__coatl_mem]
+  add rcx, r8
+  mov [rcx], eax
+  mov eax, 0
+  ret
+
+__fd_pwrite:
+  lea r9, [rip+__coatl_mem]
+  push r8
+  add rsi, r9
+  mov eax, [rsi+4]
+  push rax
+  mov eax, [rsi]
+  add rax, r9
+  push rax
+  mov rsi, rsp
+  mov r10, rcx
+  xor r8d, r8d
+  mov eax, 296
+  syscall
+  add rsp, 16
+  pop rcx
+  lea r8, [rip+__coatl_mem]
+  add rcx, r8
+  mov [rcx], eax
+  mov eax, 0
+  ret
+
+__fd_close:
+  mov eax, 3
+  syscall
+  ret
+
+__path_open:
+  push rbx
+  push r12
+  mov r12, [rsp+40]
+  lea rbx, [rip+__coatl_mem]
+  mov rsi, rdx
+  add rsi, rbx
+  mov eax, 257
+  mov edi, -100
+  xor edx, edx
+  xor r10d, r10d
+  xor r8d, r8d
+  xor r9d, r9d
+  syscall
+  cmp rax, 0
+  jl .L_open_fail
+  lea rbx, [rip+__coatl_mem]
+  mov dword ptr [rbx + r12], eax
+  xor eax, eax
+  pop r12
+  pop rbx
+  ret
+.L_open_fail:
+  lea rbx, [rip+__coatl_mem]
+  mov dword ptr [rbx + r12], -1
+  mov eax, 1
+  pop r12
+  pop rbx
+  ret
+
+__print:
+  push rbp
+  mov rbp, rsp
+  push r12
+  push r13
+  lea r8, [rip+__coatl_mem]
+  mov r12, rdi
+  add r12, r8
+  mov r13, 0
+.L_print_len_loop:
+  mov al, byte ptr [r12 + r13]
+  cmp al, 0
+  je .L_print_len_done
+  inc r13
+  jmp .L_print_len_loop
+.L_print_len_done:
+  mov eax, 1
+  mov edi, 1
+  mov rsi, r12
+  mov rdx, r13
+  syscall
+  mov eax, 0
+  pop r13
+  pop r12
+  pop rbp
+  ret
+
 __path_create:
   push rbx
   push r12
@@ -412,24 +839,182 @@ __path_create:
   pop rbx
   ret
 
-__tty_has_input:
-  mov dword ptr [rip+__pollfd], edi
-  mov word ptr [rip+__pollfd + 4], 1
-  mov word ptr [rip+__pollfd + 6], 0
-  mov edx, esi
-  mov esi, 1
-  lea rdi, [rip+__pollfd]
-  mov eax, 7
-  syscall
-  cmp rax, 0
-  jle .L_no_input
-  mov ax, word ptr [rip+__pollfd + 6]
-  test ax, 1
-  jz .L_no_input
+"#
+    };
+}
+
+// The same I/O entry points implemented with libc calls through the PLT (`--libc`).
+// Each routine realigns the stack because Coatl code may call it mid-expression.
+macro_rules! x86_64_asm_io_libc {
+    () => {
+        r#"
+__coatl_write:
+  push rbp
+  mov rbp, rsp
+  push rdi
+  push rsi
+  push rdx
+  push r8
+  push r9
+  push r10
+  and rsp, -16
+  call write@PLT
+  mov r10, [rbp-48]
+  mov r9, [rbp-40]
+  mov r8, [rbp-32]
+  mov rdx, [rbp-24]
+  mov rsi, [rbp-16]
+  mov rdi, [rbp-8]
+  leave
+  ret
+
+__coatl_exit:
+  and rsp, -16
+  call _exit@PLT
+
+__fd_write:
+  push rbp
+  mov rbp, rsp
+  push rcx
+  and rsp, -16
+  lea r8, [rip+__coatl_mem]
+  add rsi, r8
+  mov edx, [rsi+4]
+  mov esi, [rsi]
+  add rsi, r8
+  call write@PLT
+  mov rcx, [rbp-8]
+  lea r8, [rip+__coatl_mem]
+  mov [r8+rcx], eax
+  xor eax, eax
+  leave
+  ret
+
+__fd_read:
+  push rbp
+  mov rbp, rsp
+  push rcx
+  and rsp, -16
+  lea r8, [rip+__coatl_mem]
+  add rsi, r8
+  mov edx, [rsi+4]
+  mov esi, [rsi]
+  add rsi, r8
+  call read@PLT
+  mov rcx, [rbp-8]
+  lea r8, [rip+__coatl_mem]
+  mov [r8+rcx], eax
+  xor eax, eax
+  leave
+  ret
+
+__fd_pread:
+  push rbp
+  mov rbp, rsp
+  push r8
+  and rsp, -16
+  lea r9, [rip+__coatl_mem]
+  add rsi, r9
+  mov edx, [rsi+4]
+  mov esi, [rsi]
+  add rsi, r9
+  call pread@PLT
+  mov rcx, [rbp-8]
+  lea r8, [rip+__coatl_mem]
+  mov [r8+rcx], eax
+  xor eax, eax
+  leave
+  ret
+
+__fd_pwrite:
+  push rbp
+  mov rbp, rsp
+  push r8
+  and rsp, -16
+  lea r9, [rip+__coatl_mem]
+  add rsi, r9
+  mov edx, [rsi+4]
+  mov esi, [rsi]
+  add rsi, r9
+  call pwrite@PLT
+  mov rcx, [rbp-8]
+  lea r8, [rip+__coatl_mem]
+  mov [r8+rcx], eax
+  xor eax, eax
+  leave
+  ret
+
+__fd_close:
+  push rbp
+  mov rbp, rsp
+  and rsp, -16
+  call close@PLT
+  leave
+  ret
+
+__path_open:
+  push rbp
+  mov rbp, rsp
+  and rsp, -16
+  lea r8, [rip+__coatl_mem]
+  lea rdi, [r8+rdx]
+  xor esi, esi
+  xor eax, eax
+  call open@PLT
+  mov rcx, [rbp+32]
+  lea r8, [rip+__coatl_mem]
+  mov [r8+rcx], eax
+  cmp eax, 0
+  jl .L_open_fail
+  xor eax, eax
+  leave
+  ret
+.L_open_fail:
   mov eax, 1
+  leave
   ret
-.L_no_input:
+
+__print:
+  push rbp
+  mov rbp, rsp
+  and rsp, -16
+  lea rsi, [rip+__coatl_mem]
+  add rsi, rdi
+  xor edx, edx
+.L_print_len_loop:
+  cmp byte ptr [rsi + rdx], 0
+  je .L_print_len_done
+  inc rdx
+  jmp .L_print_len_loop
+.L_print_len_done:
+  mov edi, 1
+  call write@PLT
+  xor eax, eax
+  leave
+  ret
+
+__path_create:
+  push rbp
+  mov rbp, rsp
+  push rsi
+  and rsp, -16
+  lea r8, [rip+__coatl_mem]
+  add rdi, r8
+  mov esi, 577
+  mov edx, 420
+  xor eax, eax
+  call open@PLT
+  mov rcx, [rbp-8]
+  lea r8, [rip+__coatl_mem]
+  mov [r8+rcx], eax
+  cmp eax, 0
+  jl .L_create_fail
   xor eax, eax
+  leave
+  ret
+.L_create_fail:
+  mov eax, 1
+  leave
   ret
 "#
     };
@@ -437,7 +1022,7 @@ __tty_has_input:
 
 macro_rules! x86_64_asm_text {
     () => {
-        concat!(".intel_syntax noprefix\n", x86_64_asm_body!())
+        concat!(".intel_syntax noprefix\n", x86_64_asm_body!(), x86_64_asm_io_syscall!())
     };
 }
 
@@ -449,6 +1034,8 @@ macro_rules! aarch64_asm_text {
 .globl __mem_load8
 .globl __fd_write
 .globl __fd_read
+.globl __fd_pread
+.globl __fd_pwrite
 .globl __fd_close
 .globl __path_open
 .globl __tty_get_mode
@@ -459,7 +1046,14 @@ macro_rules! aarch64_asm_text {
 .globl __get_argv
 .globl __path_create
 .globl __tty_has_input
+.globl __sleep_ms
 .globl __tty_get_size
+.globl __panic
+.globl __dbg
+.globl __str_eq
+.globl __itoa
+.globl __print_int
+.globl __atoi
 
 .section .rodata
 __proc_self_cmdline:
@@ -477,6 +1071,8 @@ __cmdline_buf:
   .zero 4096
 __pollfd:
   .zero 8
+__coatl_trace_depth:
+  .quad 0
 
 .text
 
@@ -557,6 +1153,56 @@ __fd_read:
   add sp, sp, #48
   ret
 
+__fd_pread:
+  GET_COATL_MEM x8
+  sub sp, sp, #48
+  str x8, [sp, #32]
+  str x4, [sp, #24]
+  add x9, x1, x8
+  ldr w10, [x9]
+  add x10, x10, x8
+  str x10, [sp, #0]
+  ldr w10, [x9, #4]
+  uxtw x10, w10
+  str x10, [sp, #8]
+  mov x1, sp
+  mov x2, #1
+  mov x4, #0
+  mov x8, #69
+  svc #0
+  ldr x8, [sp, #32]
+  ldr x4, [sp, #24]
+  add x4, x4, x8
+  str w0, [x4]
+  mov x0, #0
+  add sp, sp, #48
+  ret
+
+__fd_pwrite:
+  GET_COATL_MEM x8
+  sub sp, sp, #48
+  str x8, [sp, #32]
+  str x4, [sp, #24]
+  add x9, x1, x8
+  ldr w10, [x9]
+  add x10, x10, x8
+  str x10, [sp, #0]
+  ldr w10, [x9, #4]
+  uxtw x10, w10
+  str x10, [sp, #8]
+  mov x1, sp
+  mov x2, #1
+  mov x4, #0
+  mov x8, #70
+  svc #0
+  ldr x8, [sp, #32]
+  ldr x4, [sp, #24]
+  add x4, x4, x8
+  str w0, [x4]
+  mov x0, #0
+  add sp, sp, #48
+  ret
+
 __fd_close:
   mov x8, #57
   svc #0
@@ -730,6 +1376,31 @@ __tty_has_input:
   mov x0, #0
   ret
 
+__sleep_ms:
+  sub sp, sp, #16
+  mov x9, x0
+  mov x0, #0
+  cmp x9, #0
+  b.le .L_sleep_done
+  mov x10, #1000
+  udiv x11, x9, x10
+  msub x12, x11, x10, x9
+  mov x10, #16960
+  movk x10, #15, lsl #16
+  mul x12, x12, x10
+  stp x11, x12, [sp]
+.L_sleep_again:
+  mov x0, sp
+  mov x1, sp
+  mov x8, #101
+  svc #0
+  cmn x0, #4
+  b.eq .L_sleep_again
+  mov x0, #0
+.L_sleep_done:
+  add sp, sp, #16
+  ret
+
 __tty_get_mode:
   stp x29, x30, [sp, #-16]!
   mov x29, sp
@@ -842,13 +1513,796 @@ __tty_get_size:
   neg x0, x0
   ldp x29, x30, [sp], #16
   ret
+
+__str_eq:
+  GET_COATL_MEM x8
+  add x0, x0, x8
+  add x1, x1, x8
+.L_str_eq_loop:
+  ldrb w9, [x0], #1
+  ldrb w10, [x1], #1
+  cmp w9, w10
+  b.ne .L_str_eq_ne
+  cbnz w9, .L_str_eq_loop
+  mov x0, #1
+  ret
+.L_str_eq_ne:
+  mov x0, #0
+  ret
+
+__panic:
+  GET_COATL_MEM x8
+  add x19, x0, x8
+  add x20, x1, x8
+  mov x0, x19
+  mov x1, x20
+  bl __coatl_panic_hook
+  adr x1, .L_panic_prefix
+  mov x2, #9
+  bl .L_panic_write
+  mov x1, x20
+  bl .L_panic_write_cstr
+  adr x1, .L_panic_sep
+  mov x2, #2
+  bl .L_panic_write
+  mov x1, x19
+  bl .L_panic_write_cstr
+  adr x1, .L_panic_sep + 2
+  mov x2, #1
+  bl .L_panic_write
+  bl __coatl_backtrace
+  mov x0, #101
+  mov x8, #94
+  svc #0
+.L_panic_write_cstr:
+  mov x2, #0
+.L_panic_len_loop:
+  ldrb w9, [x1, x2]
+  cbz w9, .L_panic_write
+  add x2, x2, #1
+  b .L_panic_len_loop
+.L_panic_write:
+  mov x0, #2
+  mov x8, #64
+  svc #0
+  ret
+.L_panic_prefix:
+  .ascii "panic at "
+.L_panic_sep:
+  .ascii ": \n"
+  .balign 4
+
+__coatl_write_dec:
+  mov x1, #2
+__coatl_write_dec_fd:
+  stp x29, x30, [sp, #-48]!
+  mov x29, sp
+  mov x7, x1
+  add x1, sp, #48
+  mov x3, x1
+  mov x4, #10
+.L_wdec_loop:
+  udiv x5, x0, x4
+  msub x6, x5, x4, x0
+  add w6, w6, #48
+  strb w6, [x1, #-1]!
+  mov x0, x5
+  cbnz x0, .L_wdec_loop
+  sub x2, x3, x1
+  mov x0, x7
+  mov x8, #64
+  svc #0
+  ldp x29, x30, [sp], #48
+  ret
+
+__dbg:
+  stp x29, x30, [sp, #-32]!
+  mov x29, sp
+  str x19, [sp, #16]
+  mov x19, x0
+  GET_COATL_MEM x8
+  add x1, x1, x8
+  mov x2, #0
+.L_dbg_len_loop:
+  ldrb w9, [x1, x2]
+  cbz w9, .L_dbg_len_done
+  add x2, x2, #1
+  b .L_dbg_len_loop
+.L_dbg_len_done:
+  mov x0, #2
+  mov x8, #64
+  svc #0
+  mov x0, #2
+  adr x1, .L_dbg_text
+  mov x2, #3
+  mov x8, #64
+  svc #0
+  mov x0, x19
+  cmp x19, #0
+  b.ge .L_dbg_value
+  mov x0, #2
+  adr x1, .L_dbg_text + 3
+  mov x2, #1
+  mov x8, #64
+  svc #0
+  neg x0, x19
+.L_dbg_value:
+  bl __coatl_write_dec
+  mov x0, #2
+  adr x1, .L_dbg_text + 4
+  mov x2, #1
+  mov x8, #64
+  svc #0
+  mov x0, x19
+  ldr x19, [sp, #16]
+  ldp x29, x30, [sp], #32
+  ret
+.L_dbg_text:
+  .ascii " = -\n"
+  .balign 4
+
+__itoa:
+  stp x29, x30, [sp, #-32]!
+  mov x29, sp
+  GET_COATL_MEM x8
+  add x1, x1, x8
+  mov x9, x1
+  sxtw x0, w0
+  cmp x0, #0
+  b.ge .L_itoa_digits
+  mov w10, #45
+  strb w10, [x1], #1
+  neg x0, x0
+.L_itoa_digits:
+  add x3, sp, #32
+  mov x2, x3
+  mov x4, #10
+.L_itoa_loop:
+  udiv x5, x0, x4
+  msub x6, x5, x4, x0
+  add w6, w6, #48
+  strb w6, [x2, #-1]!
+  mov x0, x5
+  cbnz x0, .L_itoa_loop
+.L_itoa_copy:
+  ldrb w6, [x2], #1
+  strb w6, [x1], #1
+  cmp x2, x3
+  b.ne .L_itoa_copy
+  strb wzr, [x1]
+  sub x0, x1, x9
+  ldp x29, x30, [sp], #32
+  ret
+
+__atoi:
+  GET_COATL_MEM x8
+  add x0, x0, x8
+  add x7, x2, x8
+  sxtw x1, w1
+.L_atoi_trim:
+  cmp x1, #0
+  b.le .L_atoi_fail
+  sub x9, x1, #1
+  ldrb w2, [x0, x9]
+  cmp w2, #10
+  b.eq .L_atoi_trim_one
+  cmp w2, #13
+  b.ne .L_atoi_sign
+.L_atoi_trim_one:
+  mov x1, x9
+  b .L_atoi_trim
+.L_atoi_sign:
+  add x1, x1, x0
+  mov x3, #0
+  ldrb w2, [x0]
+  cmp w2, #45
+  b.ne .L_atoi_plus
+  mov x3, #1
+  add x0, x0, #1
+  b .L_atoi_first
+.L_atoi_plus:
+  cmp w2, #43
+  b.ne .L_atoi_first
+  add x0, x0, #1
+.L_atoi_first:
+  cmp x0, x1
+  b.eq .L_atoi_fail
+  mov x4, #0
+  mov x5, #10
+  mov x6, #0x80000000
+.L_atoi_loop:
+  ldrb w2, [x0], #1
+  sub w2, w2, #48
+  cmp w2, #9
+  b.hi .L_atoi_fail
+  madd x4, x4, x5, x2
+  cmp x4, x6
+  b.hi .L_atoi_fail
+  cmp x0, x1
+  b.ne .L_atoi_loop
+  cbnz x3, .L_atoi_neg
+  cmp x4, x6
+  b.hs .L_atoi_fail
+  b .L_atoi_done
+.L_atoi_neg:
+  neg x4, x4
+.L_atoi_done:
+  str w4, [x7]
+  mov x0, #0
+  ret
+.L_atoi_fail:
+  mov x0, #-1
+  ret
+
+__print_int:
+  stp x29, x30, [sp, #-32]!
+  mov x29, sp
+  str x19, [sp, #16]
+  sxtw x19, w0
+  cmp x19, #0
+  b.ge .L_print_int_value
+  mov x0, #1
+  adr x1, .L_dbg_text + 3
+  mov x2, #1
+  mov x8, #64
+  svc #0
+  neg x19, x19
+.L_print_int_value:
+  mov x0, x19
+  mov x1, #1
+  bl __coatl_write_dec_fd
+  mov x0, #0
+  ldr x19, [sp, #16]
+  ldp x29, x30, [sp], #32
+  ret
+
+__coatl_trace:
+  stp x29, x30, [sp, #-32]!
+  mov x29, sp
+  stp x19, x20, [sp, #16]
+  mov x19, x0
+  GET_COATL_MEM x8
+  add x20, x1, x8
+  adrp x10, __coatl_trace_depth
+  add x10, x10, :lo12:__coatl_trace_depth
+  ldr x11, [x10]
+  cbz x19, .L_trace_indent_loop
+  sub x11, x11, #1
+  str x11, [x10]
+.L_trace_indent_loop:
+  cmp x11, #0
+  b.le .L_trace_arrow
+  mov x0, #2
+  adr x1, .L_trace_text
+  mov x2, #2
+  mov x8, #64
+  svc #0
+  sub x11, x11, #1
+  b .L_trace_indent_loop
+.L_trace_arrow:
+  adr x1, .L_trace_text + 2
+  cbz x19, .L_trace_arrow_write
+  add x1, x1, #3
+.L_trace_arrow_write:
+  mov x0, #2
+  mov x2, #3
+  mov x8, #64
+  svc #0
+  mov x1, x20
+  mov x2, #0
+.L_trace_len_loop:
+  ldrb w9, [x1, x2]
+  cbz w9, .L_trace_len_done
+  add x2, x2, #1
+  b .L_trace_len_loop
+.L_trace_len_done:
+  mov x0, #2
+  mov x8, #64
+  svc #0
+  mov x0, #2
+  adr x1, .L_trace_text + 8
+  mov x2, #1
+  mov x8, #64
+  svc #0
+  cbnz x19, .L_trace_done
+  adrp x10, __coatl_trace_depth
+  add x10, x10, :lo12:__coatl_trace_depth
+  ldr x11, [x10]
+  add x11, x11, #1
+  str x11, [x10]
+.L_trace_done:
+  ldp x19, x20, [sp, #16]
+  ldp x29, x30, [sp], #32
+  ret
+.L_trace_text:
+  .ascii "  -> <- \n"
+  .balign 4
 "#
     };
 }
 
+// `--instrument=profile` report, printed at exit. Walks the `__coatl_prof_table` the
+// backend emits (32-byte entries: calls, cycles, name offset, printed flag) hottest first.
+pub const PROFILE_X86_64: &str = r#"
+__coatl_prof_report:
+  push rbp
+  mov rbp, rsp
+  push rbx
+  push r12
+  push r13
+  push r14
+  lea rsi, [rip+.L_prof_text]
+  mov edx, 15
+  mov edi, 2
+  call __coatl_write
+  mov r12, qword ptr [rip+__coatl_prof_len]
+.L_prof_pass:
+  test r12, r12
+  jz .L_prof_done
+  dec r12
+  lea rbx, [rip+__coatl_prof_table]
+  mov rcx, qword ptr [rip+__coatl_prof_len]
+  xor r13, r13
+.L_prof_scan:
+  test rcx, rcx
+  jz .L_prof_print
+  cmp qword ptr [rbx + 24], 0
+  jne .L_prof_next
+  test r13, r13
+  jz .L_prof_take
+  mov rax, qword ptr [rbx + 8]
+  cmp rax, qword ptr [r13 + 8]
+  jbe .L_prof_next
+.L_prof_take:
+  mov r13, rbx
+.L_prof_next:
+  add rbx, 32
+  dec rcx
+  jmp .L_prof_scan
+.L_prof_print:
+  mov qword ptr [r13 + 24], 1
+  cmp qword ptr [r13], 0
+  je .L_prof_pass
+  lea rsi, [rip+.L_prof_text + 15]
+  mov edx, 2
+  mov edi, 2
+  call __coatl_write
+  lea r8, [rip+__coatl_mem]
+  mov rsi, qword ptr [r13 + 16]
+  add rsi, r8
+  xor edx, edx
+.L_prof_len_loop:
+  cmp byte ptr [rsi + rdx], 0
+  je .L_prof_len_done
+  inc rdx
+  jmp .L_prof_len_loop
+.L_prof_len_done:
+  mov edi, 2
+  call __coatl_write
+  lea rsi, [rip+.L_prof_text + 17]
+  mov edx, 7
+  mov edi, 2
+  call __coatl_write
+  mov rdi, qword ptr [r13]
+  call __coatl_write_dec
+  lea rsi, [rip+.L_prof_text + 24]
+  mov edx, 8
+  mov edi, 2
+  call __coatl_write
+  mov rdi, qword ptr [r13 + 8]
+  call __coatl_write_dec
+  lea rsi, [rip+.L_prof_text + 32]
+  mov edx, 1
+  mov edi, 2
+  call __coatl_write
+  jmp .L_prof_pass
+.L_prof_done:
+  pop r14
+  pop r13
+  pop r12
+  pop rbx
+  pop rbp
+  ret
+.L_prof_text:
+  .ascii "coatl profile:\n   calls= cycles=\n"
+"#;
+
+pub const PROFILE_AARCH64: &str = r#"
+__coatl_prof_report:
+  stp x29, x30, [sp, #-48]!
+  mov x29, sp
+  stp x19, x20, [sp, #16]
+  str x21, [sp, #32]
+  mov x0, #2
+  adr x1, .L_prof_text
+  mov x2, #15
+  mov x8, #64
+  svc #0
+  adrp x21, __coatl_prof_len
+  ldr x19, [x21, :lo12:__coatl_prof_len]
+.L_prof_pass:
+  cbz x19, .L_prof_done
+  sub x19, x19, #1
+  adrp x9, __coatl_prof_table
+  add x9, x9, :lo12:__coatl_prof_table
+  ldr x10, [x21, :lo12:__coatl_prof_len]
+  mov x20, #0
+.L_prof_scan:
+  cbz x10, .L_prof_print
+  ldr x11, [x9, #24]
+  cbnz x11, .L_prof_next
+  cbz x20, .L_prof_take
+  ldr x11, [x9, #8]
+  ldr x12, [x20, #8]
+  cmp x11, x12
+  b.ls .L_prof_next
+.L_prof_take:
+  mov x20, x9
+.L_prof_next:
+  add x9, x9, #32
+  sub x10, x10, #1
+  b .L_prof_scan
+.L_prof_print:
+  mov x11, #1
+  str x11, [x20, #24]
+  ldr x11, [x20]
+  cbz x11, .L_prof_pass
+  mov x0, #2
+  adr x1, .L_prof_text + 15
+  mov x2, #2
+  mov x8, #64
+  svc #0
+  GET_COATL_MEM x8
+  ldr x1, [x20, #16]
+  add x1, x1, x8
+  mov x2, #0
+.L_prof_len_loop:
+  ldrb w9, [x1, x2]
+  cbz w9, .L_prof_len_done
+  add x2, x2, #1
+  b .L_prof_len_loop
+.L_prof_len_done:
+  mov x0, #2
+  mov x8, #64
+  svc #0
+  mov x0, #2
+  adr x1, .L_prof_text + 17
+  mov x2, #7
+  mov x8, #64
+  svc #0
+  ldr x0, [x20]
+  bl __coatl_write_dec
+  mov x0, #2
+  adr x1, .L_prof_text + 24
+  mov x2, #8
+  mov x8, #64
+  svc #0
+  ldr x0, [x20, #8]
+  bl __coatl_write_dec
+  mov x0, #2
+  adr x1, .L_prof_text + 32
+  mov x2, #1
+  mov x8, #64
+  svc #0
+  b .L_prof_pass
+.L_prof_done:
+  ldp x19, x20, [sp, #16]
+  ldr x21, [sp, #32]
+  ldp x29, x30, [sp], #48
+  ret
+.L_prof_text:
+  .ascii "coatl profile:\n   calls= cycles=\n"
+  .balign 4
+"#;
+
+// `--backtrace` report, printed by `__panic` and the stack overflow trap. Every function
+// records its name's string pool offset at `__coatl_bt_depth` (mod 64) in
+// `__coatl_bt_stack` on entry and pops it on return; the innermost 64 frames are printed,
+// innermost first. Without `--backtrace` the backend defines `__coatl_backtrace` as a `ret`.
+pub const BACKTRACE_X86_64: &str = r#"
+.section .bss
+.align 8
+__coatl_bt_depth:
+  .quad 0
+__coatl_bt_stack:
+  .zero 256
+.text
+__coatl_backtrace:
+  push rbp
+  mov rbp, rsp
+  push r12
+  push r13
+  mov r12, qword ptr [rip+__coatl_bt_depth]
+  test r12, r12
+  jz .L_bt_done
+  lea rsi, [rip+.L_bt_text]
+  mov edx, 17
+  mov edi, 2
+  call __coatl_write
+  xor r13, r13
+.L_bt_frame:
+  test r12, r12
+  jz .L_bt_done
+  cmp r13, 64
+  je .L_bt_more
+  dec r12
+  lea rsi, [rip+.L_bt_text + 17]
+  mov edx, 2
+  mov edi, 2
+  call __coatl_write
+  mov rdi, r13
+  call __coatl_write_dec
+  lea rsi, [rip+.L_bt_text + 23]
+  mov edx, 2
+  mov edi, 2
+  call __coatl_write
+  mov rax, r12
+  and eax, 63
+  lea rcx, [rip+__coatl_bt_stack]
+  mov esi, dword ptr [rcx + rax*4]
+  lea r8, [rip+__coatl_mem]
+  add rsi, r8
+  xor edx, edx
+.L_bt_len_loop:
+  cmp byte ptr [rsi + rdx], 0
+  je .L_bt_len_done
+  inc rdx
+  jmp .L_bt_len_loop
+.L_bt_len_done:
+  mov edi, 2
+  call __coatl_write
+  lea rsi, [rip+.L_bt_text + 16]
+  mov edx, 1
+  mov edi, 2
+  call __coatl_write
+  inc r13
+  jmp .L_bt_frame
+.L_bt_more:
+  lea rsi, [rip+.L_bt_text + 17]
+  mov edx, 6
+  mov edi, 2
+  call __coatl_write
+.L_bt_done:
+  pop r13
+  pop r12
+  pop rbp
+  ret
+.L_bt_text:
+  .ascii "stack backtrace:\n  ...\n: "
+"#;
+
+pub const BACKTRACE_AARCH64: &str = r#"
+.section .bss
+.align 8
+__coatl_bt_depth:
+  .quad 0
+__coatl_bt_stack:
+  .zero 256
+.text
+__coatl_backtrace:
+  stp x29, x30, [sp, #-32]!
+  mov x29, sp
+  stp x19, x20, [sp, #16]
+  adrp x9, __coatl_bt_depth
+  ldr x19, [x9, :lo12:__coatl_bt_depth]
+  cbz x19, .L_bt_done
+  mov x0, #2
+  adr x1, .L_bt_text
+  mov x2, #17
+  mov x8, #64
+  svc #0
+  mov x20, #0
+.L_bt_frame:
+  cbz x19, .L_bt_done
+  cmp x20, #64
+  b.eq .L_bt_more
+  sub x19, x19, #1
+  mov x0, #2
+  adr x1, .L_bt_text + 17
+  mov x2, #2
+  mov x8, #64
+  svc #0
+  mov x0, x20
+  bl __coatl_write_dec
+  mov x0, #2
+  adr x1, .L_bt_text + 23
+  mov x2, #2
+  mov x8, #64
+  svc #0
+  adrp x9, __coatl_bt_stack
+  add x9, x9, :lo12:__coatl_bt_stack
+  and x10, x19, #63
+  ldr w1, [x9, x10, lsl #2]
+  GET_COATL_MEM x8
+  add x1, x1, x8
+  mov x2, #0
+.L_bt_len_loop:
+  ldrb w9, [x1, x2]
+  cbz w9, .L_bt_len_done
+  add x2, x2, #1
+  b .L_bt_len_loop
+.L_bt_len_done:
+  mov x0, #2
+  mov x8, #64
+  svc #0
+  mov x0, #2
+  adr x1, .L_bt_text + 16
+  mov x2, #1
+  mov x8, #64
+  svc #0
+  add x20, x20, #1
+  b .L_bt_frame
+.L_bt_more:
+  mov x0, #2
+  adr x1, .L_bt_text + 17
+  mov x2, #6
+  mov x8, #64
+  svc #0
+.L_bt_done:
+  ldp x19, x20, [sp, #16]
+  ldp x29, x30, [sp], #32
+  ret
+.L_bt_text:
+  .ascii "stack backtrace:\n  ...\n: "
+  .balign 4
+"#;
+
+// `--panic=hook`: `__panic` passes the addresses of its message and location (NUL-terminated
+// strings) to the host's `coatl_panic_hook` before printing them and exiting, so an embedder
+// can `longjmp` back to a recovery point instead. The stack is realigned for the C call.
+// Without `--panic=hook` the backend defines `__coatl_panic_hook` as a `ret`.
+pub const PANIC_HOOK_X86_64: &str = r#"
+__coatl_panic_hook:
+  push rbp
+  mov rbp, rsp
+  and rsp, -16
+  call coatl_panic_hook@PLT
+  mov rsp, rbp
+  pop rbp
+  ret
+"#;
+
+pub const PANIC_HOOK_AARCH64: &str = r#"
+__coatl_panic_hook:
+  stp x29, x30, [sp, #-16]!
+  mov x29, sp
+  bl coatl_panic_hook
+  ldp x29, x30, [sp], #16
+  ret
+"#;
+
+// Shared library exports for passing strings in from the host: `coatl_memory` returns the
+// pool address, `coatl_str(ptr, len)` copies a string into the argv area (unused in shared
+// libraries, [900000, 1048576)) and returns its offset or -1 when the area is full, and
+// `coatl_str_reset` frees every copied string.
+pub const SHARED_X86_64: &str = r#"
+.globl coatl_memory
+.type coatl_memory, @function
+coatl_memory:
+  lea rax, [rip+__coatl_mem]
+  ret
+
+.globl coatl_str
+.type coatl_str, @function
+coatl_str:
+  cmp rsi, 1048576
+  jae .L_coatl_str_full
+  mov eax, dword ptr [rip+__coatl_host_next]
+  lea rcx, [rax+rsi+1]
+  cmp rcx, 1048576
+  ja .L_coatl_str_full
+  mov dword ptr [rip+__coatl_host_next], ecx
+  lea rdx, [rip+__coatl_mem]
+  add rdx, rax
+  xor ecx, ecx
+.L_coatl_str_copy:
+  cmp rcx, rsi
+  jae .L_coatl_str_done
+  mov r8b, byte ptr [rdi+rcx]
+  mov byte ptr [rdx+rcx], r8b
+  inc rcx
+  jmp .L_coatl_str_copy
+.L_coatl_str_done:
+  mov byte ptr [rdx+rsi], 0
+  ret
+.L_coatl_str_full:
+  mov eax, -1
+  ret
+
+.globl coatl_str_reset
+.type coatl_str_reset, @function
+coatl_str_reset:
+  mov dword ptr [rip+__coatl_host_next], 900000
+  ret
+
+.data
+.balign 4
+.hidden __coatl_host_next
+__coatl_host_next:
+  .long 900000
+.text
+"#;
+
+pub const SHARED_AARCH64: &str = r#"
+.globl coatl_memory
+.type coatl_memory, %function
+coatl_memory:
+  GET_COATL_MEM x0
+  ret
+
+.globl coatl_str
+.type coatl_str, %function
+coatl_str:
+  mov x10, #1048576
+  cmp x1, x10
+  b.hs .L_coatl_str_full
+  adrp x11, __coatl_host_next
+  add x11, x11, :lo12:__coatl_host_next
+  ldr w9, [x11]
+  add x12, x9, x1
+  add x12, x12, #1
+  cmp x12, x10
+  b.hi .L_coatl_str_full
+  str w12, [x11]
+  GET_COATL_MEM x13
+  add x13, x13, x9
+  mov x14, #0
+.L_coatl_str_copy:
+  cmp x14, x1
+  b.hs .L_coatl_str_done
+  ldrb w15, [x0, x14]
+  strb w15, [x13, x14]
+  add x14, x14, #1
+  b .L_coatl_str_copy
+.L_coatl_str_done:
+  strb wzr, [x13, x1]
+  mov w0, w9
+  ret
+.L_coatl_str_full:
+  mov w0, #-1
+  ret
+
+.globl coatl_str_reset
+.type coatl_str_reset, %function
+coatl_str_reset:
+  adrp x11, __coatl_host_next
+  add x11, x11, :lo12:__coatl_host_next
+  movz w9, #0xbba0
+  movk w9, #0xd, lsl #16
+  str w9, [x11]
+  ret
+
+.data
+.balign 4
+.hidden __coatl_host_next
+__coatl_host_next:
+  .long 900000
+.text
+"#;
+
 pub const INTRINSICS_X86_64: &str = x86_64_asm_text!();
+pub const INTRINSICS_X86_64_LIBC: &str = concat!(".intel_syntax noprefix\n", x86_64_asm_body!(), x86_64_asm_io_libc!());
 pub const INTRINSICS_AARCH64: &str = aarch64_asm_text!();
 
+/// C functions the `--libc` I/O routines call, and the `coatl_sys_*` shims an embedder
+/// supplies for `--syscalls=extern`, with the same C signatures.
+pub const EXTERN_SYSCALLS: [(&str, &str); 7] = [
+    ("write", "coatl_sys_write"),
+    ("read", "coatl_sys_read"),
+    ("pread", "coatl_sys_pread"),
+    ("pwrite", "coatl_sys_pwrite"),
+    ("open", "coatl_sys_open"),
+    ("close", "coatl_sys_close"),
+    ("_exit", "coatl_sys_exit"),
+];
+
+/// The `--libc` intrinsics with every libc call renamed to its `coatl_sys_*` shim.
+pub fn intrinsics_x86_64_extern() -> String {
+    EXTERN_SYSCALLS.iter().fold(INTRINSICS_X86_64_LIBC.to_string(), |asm, (libc, shim)| {
+        asm.replace(&format!("call {}@PLT", libc), &format!("call {}@PLT", shim))
+    })
+}
+
 // These blocks embed the runtime intrinsics into the `coatl` binary itself. The
 // assembly is Linux/GNU-as specific (raw `syscall`, `.section .bss`, a weak
 // `__coatl_mem`, etc.), so it is only emitted when building for Linux. The compiler
@@ -860,6 +2314,7 @@ pub const INTRINSICS_AARCH64: &str = aarch64_asm_text!();
 std::arch::global_asm!(
     ".weak __coatl_mem\n",
     x86_64_asm_body!(),
+    x86_64_asm_io_syscall!(),
 );
 
 #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
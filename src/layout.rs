@@ -0,0 +1,173 @@
+//! Byte layout of struct values.
+//!
+//! A struct value lives in a single 8-byte stack slot and is passed and returned in one
+//! register. Fields are placed in declaration order, each at the next offset aligned to
+//! its size: `bool`, `char` and `u8` take one byte, `i64` and `f64` eight, and every other
+//! type (`i32`, `f32`, `str`, pointers into `__coatl_mem`) four. A field whose type is
+//! another struct is that struct's bytes, aligned like its most aligned field.
+//!
+//! `#[repr(packed)]` places every field right after the previous one, with no padding, and
+//! aligns the struct to one byte. `#[repr(align(N))]` raises the struct's alignment to `N`
+//! and pads its size to a multiple of it. `#[repr(C)]` is the default layout.
+//!
+//! The slice types `[]u8` and `[]i32` are built-in structs of two `i32` fields: `ptr`, the
+//! offset of the first element in `__coatl_mem`, and `len`, the number of elements. So is
+//! `range`, the value of `start..end`, with the fields `start` and `end`.
+
+use std::collections::HashMap;
+
+use crate::{ir_section, IRNode, Symbol};
+
+/// Largest struct value in bytes: one register.
+pub const MAX_STRUCT_SIZE: u32 = 8;
+
+/// The built-in slice types, laid out like `struct { ptr: i32, len: i32 }`.
+pub const SLICE_TYPES: &[&str] = &["[]u8", "[]i32"];
+
+/// The type of `start..end`, laid out like `struct { start: i32, end: i32 }`.
+pub const RANGE_TYPE: &str = "range";
+
+/// The built-in struct types with their `(name, type)` fields.
+pub fn builtin_structs() -> Vec<(Symbol, Vec<(Symbol, Symbol)>)> {
+    let fields = |a: &str, b: &str| vec![(a.into(), "i32".into()), (b.into(), "i32".into())];
+    let mut out: Vec<(Symbol, Vec<(Symbol, Symbol)>)> = SLICE_TYPES.iter().map(|&ty| (ty.into(), fields("ptr", "len"))).collect();
+    out.push((RANGE_TYPE.into(), fields("start", "end")));
+    out
+}
+
+/// Element type of the slice type `ty` (`u8` for `[]u8`).
+pub fn slice_elem(ty: &str) -> Option<&str> {
+    ty.strip_prefix("[]")
+}
+
+/// Size in bytes of a struct field of type `ty`, which is also its alignment.
+pub fn size_of(ty: &str) -> u32 {
+    match ty {
+        "bool" | "char" | "u8" => 1,
+        "i64" | "f64" => 8,
+        _ => 4,
+    }
+}
+
+/// Layout control from a struct's `#[repr(...)]` attribute.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Repr {
+    pub packed: bool,
+    pub align: Option<u32>,
+}
+
+/// The `#[repr(...)]` of a `(struct Name ...)` node: any of `C`, `packed` and `align(N)`,
+/// where `N` is a power of two.
+pub fn struct_repr(s: &[IRNode]) -> Result<Repr, String> {
+    let mut repr = Repr::default();
+    let name = s.get(1).and_then(|n| n.as_atom()).map(|n| n.as_str()).unwrap_or_default();
+    for arg in crate::item_attr(s, "repr").unwrap_or_default() {
+        match arg {
+            IRNode::Atom(a) if a == "C" => {}
+            IRNode::Atom(a) if a == "packed" => repr.packed = true,
+            IRNode::List(l) if l.len() == 2 && l[0].as_atom().is_some_and(|k| k == "align") => {
+                let n = l[1].as_atom().map(|n| n.as_str()).unwrap_or_default();
+                match n.parse::<u32>() {
+                    Ok(n) if n.is_power_of_two() => repr.align = Some(n),
+                    _ => return Err(format!("struct `{}`: `#[repr(align({}))]` needs a power of two", name, n)),
+                }
+            }
+            _ => return Err(format!("struct `{}`: unknown repr `{}`; expected `C`, `packed` or `align(N)`", name, arg.to_ir())),
+        }
+    }
+    if repr.packed && repr.align.is_some() {
+        return Err(format!("struct `{}` cannot be both `#[repr(packed)]` and `#[repr(align(N))]`", name));
+    }
+    Ok(repr)
+}
+
+/// Placement of one field within its struct.
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: Symbol,
+    pub ty: Symbol,
+    pub offset: u32,
+    pub size: u32,
+    pub align: u32,
+}
+
+/// Alignment of a struct laid out as `fields`.
+pub fn align_of(fields: &[Field]) -> u32 {
+    fields.iter().map(|f| f.align).max().unwrap_or(1)
+}
+
+/// Lays out `(name, type)` fields in order as `repr` asks, returning them with the
+/// struct's total size and alignment. Fields of a struct type take their size and
+/// alignment from `structs`.
+pub fn struct_layout(fields: &[(Symbol, Symbol)], repr: Repr, structs: &HashMap<Symbol, (Vec<Field>, u32, u32)>) -> (Vec<Field>, u32, u32) {
+    let mut offset: u32 = 0;
+    let mut out = Vec::new();
+    for (name, ty) in fields {
+        let (size, align) = match structs.get(ty) {
+            Some((_, size, align)) => (*size, *align),
+            None => (size_of(ty), size_of(ty)),
+        };
+        let align = if repr.packed { 1 } else { align };
+        offset = offset.next_multiple_of(align);
+        out.push(Field { name: *name, ty: *ty, offset, size, align });
+        offset += size;
+    }
+    let align = align_of(&out).max(repr.align.unwrap_or(1));
+    (out, offset.next_multiple_of(align), align)
+}
+
+/// The structs of a program that contain themselves, directly or through other structs.
+pub fn recursive_structs(ir: &IRNode) -> Vec<Symbol> {
+    let decls = struct_decls(ir);
+    let mut found: Vec<Symbol> = decls.keys().filter(|name| {
+        let mut stack: Vec<&str> = decls[*name].iter().map(|(_, ty)| ty.as_str()).collect();
+        let mut seen = Vec::new();
+        while let Some(ty) = stack.pop() {
+            if ty == name.as_str() { return true; }
+            if seen.contains(&ty) { continue; }
+            seen.push(ty);
+            if let Some(fields) = decls.get(ty) { stack.extend(fields.iter().map(|(_, t)| t.as_str())); }
+        }
+        false
+    }).cloned().collect();
+    found.sort();
+    found
+}
+
+/// Every `(struct Name (field x ty)...)` of a program as its `(name, type)` fields, with
+/// the built-in structs.
+fn struct_decls(ir: &IRNode) -> HashMap<Symbol, Vec<(Symbol, Symbol)>> {
+    let mut decls: HashMap<Symbol, Vec<(Symbol, Symbol)>> = ir_section(ir, "structs").iter().filter_map(|s| s.as_list()).filter_map(|s| {
+        let fields = s[2..].iter().filter_map(|f| {
+            let f = f.as_list().filter(|f| f[0].as_atom().is_some_and(|h| h == "field"))?;
+            Some((*f.get(1)?.as_atom()?, *f.get(2)?.as_atom()?))
+        }).collect();
+        Some((*s.get(1)?.as_atom()?, fields))
+    }).collect();
+    decls.extend(builtin_structs());
+    decls
+}
+
+/// The `#[repr(...)]` of every struct of a program that has a valid one.
+fn struct_reprs(ir: &IRNode) -> HashMap<Symbol, Repr> {
+    ir_section(ir, "structs").iter().filter_map(|s| s.as_list()).filter_map(|s| Some((*s.get(1)?.as_atom()?, struct_repr(s).ok()?))).collect()
+}
+
+/// Lays out every `(struct Name (field x ty)...)` of a program, keyed by struct name, with
+/// each struct's total size. Structs are laid out after the structs they contain; a struct
+/// that contains itself (see [`recursive_structs`]) treats the cyclic fields as `i32`.
+pub fn program_layouts(ir: &IRNode) -> HashMap<Symbol, (Vec<Field>, u32)> {
+    let decls = struct_decls(ir);
+    let reprs = struct_reprs(ir);
+    let mut layouts = HashMap::new();
+    let mut pending: Vec<&Symbol> = decls.keys().collect();
+    pending.sort();
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|name| decls[*name].iter().all(|(_, ty)| !decls.contains_key(ty) || layouts.contains_key(ty)));
+        // Only a cycle leaves no struct ready; its unresolved fields fall back to `i32`.
+        let name = pending.remove(ready.unwrap_or(0));
+        let layout = struct_layout(&decls[name], reprs.get(name).copied().unwrap_or_default(), &layouts);
+        layouts.insert(*name, layout);
+    }
+    layouts.into_iter().map(|(name, (fields, size, _))| (name, (fields, size))).collect()
+}
@@ -0,0 +1,3610 @@
+//! The Coatl compiler: the `.coatl` front end, the IR, the type checker and the native
+//! backends. The `coatl` binary is a thin command line driver over this library.
+
+pub mod callgraph;
+pub mod cfg;
+mod consteval;
+mod definite;
+pub mod doc;
+pub mod link;
+mod macros;
+pub mod manifest;
+pub mod mangle;
+pub mod map;
+#[cfg(feature = "object")]
+pub mod obj;
+pub mod stack;
+pub mod symbol;
+pub mod tags;
+mod intrinsics;
+mod layout;
+pub mod typecheck;
+
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+pub use symbol::Symbol;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IRNode {
+    Atom(Symbol),
+    /// The text of a string literal, doc comment, message or `asm` template. Most are seen
+    /// once, so unlike atoms they are not interned.
+    Str(String),
+    List(Vec<IRNode>),
+}
+
+impl IRNode {
+    pub fn is_list(&self) -> bool { matches!(self, IRNode::List(_)) }
+    pub fn as_list(&self) -> Option<&Vec<IRNode>> {
+        match self { IRNode::List(l) => Some(l), _ => None }
+    }
+    pub fn atom(s: impl Into<Symbol>) -> IRNode { IRNode::Atom(s.into()) }
+    pub fn as_atom(&self) -> Option<&Symbol> {
+        match self { IRNode::Atom(s) => Some(s), _ => None }
+    }
+    /// The text of a string payload, or of an atom read back from IR text.
+    pub fn as_str(&self) -> Option<&str> {
+        match self { IRNode::Atom(s) => Some(s), IRNode::Str(s) => Some(s), _ => None }
+    }
+    pub fn to_ir(&self) -> String {
+        match self {
+            IRNode::Atom(_) | IRNode::Str(_) => {
+                let s = self.as_str().unwrap_or_default();
+                if s.contains(' ') || s.is_empty() || s.contains('\n') || s.contains('\"') || s.contains('(') || s.contains(')') {
+                    format!("\"{}\"", s.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n").replace("\r", "\\r").replace("\t", "\\t"))
+                } else {
+                    s.to_string()
+                }
+            }
+            IRNode::List(l) => {
+                let mut res = String::from("(");
+                for (i, item) in l.iter().enumerate() {
+                    if i > 0 { res.push(' '); }
+                    res.push_str(&item.to_ir());
+                }
+                res.push(')');
+                res
+            }
+        }
+    }
+}
+
+pub struct IRParser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl IRParser {
+    pub fn new(input: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut chars = input.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() { chars.next(); }
+            else if c == '(' || c == ')' { tokens.push(c.to_string()); chars.next(); }
+            else if c == '"' {
+                chars.next();
+                let mut s = String::new();
+                while let Some(nc) = chars.next() {
+                    if nc == '"' { break; }
+                    if nc == '\\' {
+                        if let Some(esc) = chars.next() {
+                            s.push(match esc {
+                                'n' => '\n', 'r' => '\r', 't' => '\t', _ => esc,
+                            });
+                        }
+                    } else { s.push(nc); }
+                }
+                tokens.push(format!("\"{}\"", s));
+            } else {
+                let mut s = String::new();
+                while let Some(&nc) = chars.peek() {
+                    if nc.is_whitespace() || nc == '(' || nc == ')' { break; }
+                    s.push(chars.next().unwrap());
+                }
+                tokens.push(s);
+            }
+        }
+        Self { tokens, pos: 0 }
+    }
+
+    pub fn parse(&mut self) -> Option<IRNode> {
+        if self.pos >= self.tokens.len() { return None; }
+        let token = &self.tokens[self.pos];
+        self.pos += 1;
+        if token == "(" {
+            let mut list = Vec::new();
+            while self.pos < self.tokens.len() && self.tokens[self.pos] != ")" {
+                if let Some(node) = self.parse() { list.push(node); }
+            }
+            if self.pos < self.tokens.len() { self.pos += 1; }
+            Some(IRNode::List(list))
+        } else if token.starts_with('"') && token.ends_with('"') {
+            Some(IRNode::atom(token[1..token.len()-1].to_string()))
+        } else {
+            Some(IRNode::atom(token.clone()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind { Ident, Num, Str, Sym, Eof }
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    value: Symbol,
+    /// The contents of a string literal, which are not interned; empty for other tokens.
+    text: String,
+    line: usize,
+    col: usize,
+}
+
+impl Token {
+    /// The token as an atom: its text, or a string literal's contents.
+    fn atom(self) -> IRNode {
+        IRNode::atom(if self.kind == TokenKind::Str { self.text.into() } else { self.value })
+    }
+}
+
+struct Lexer {
+    source: Vec<char>,
+    pos: usize,
+    line: usize,
+    col: usize,
+    /// The text of each `///` doc comment by line, without the slashes and one space.
+    docs: HashMap<usize, String>,
+}
+
+impl Lexer {
+    fn new(source: String) -> Self {
+        Self { source: source.chars().collect(), pos: 0, line: 1, col: 1, docs: HashMap::new() }
+    }
+    fn peek(&self, n: usize) -> Option<char> {
+        if self.pos + n < self.source.len() { Some(self.source[self.pos + n]) } else { None }
+    }
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek(0)?;
+        self.pos += 1;
+        if c == '\n' { self.line += 1; self.col = 1; } else { self.col += 1; }
+        Some(c)
+    }
+    fn tokenize(&mut self) -> Result<Vec<Token>, String> {
+        let mut tokens = Vec::new();
+        while self.pos < self.source.len() {
+            let c = self.peek(0).unwrap();
+            if c.is_whitespace() { self.advance(); }
+            else if c == '/' && self.peek(1) == Some('/') {
+                let (line, doc) = (self.line, self.peek(2) == Some('/') && self.peek(3) != Some('/'));
+                let mut text = String::new();
+                while self.peek(0).is_some() && self.peek(0) != Some('\n') { text.push(self.advance().unwrap()); }
+                if doc {
+                    let text = &text[3..];
+                    self.docs.insert(line, text.strip_prefix(' ').unwrap_or(text).trim_end().to_string());
+                }
+            } else if c.is_alphabetic() || c == '_' {
+                let (sl, sc) = (self.line, self.col);
+                let mut val = String::new();
+                while let Some(nc) = self.peek(0) {
+                    if nc.is_alphanumeric() || nc == '_' { val.push(self.advance().unwrap()); } else { break; }
+                }
+                tokens.push(Token { kind: TokenKind::Ident, text: String::new(), value: val.into(), line: sl, col: sc });
+            } else if c.is_ascii_digit() {
+                let (sl, sc) = (self.line, self.col);
+                let mut val = String::new();
+                if c == '0' && self.peek(1) == Some('x') {
+                    val.push(self.advance().unwrap()); val.push(self.advance().unwrap());
+                    while let Some(nc) = self.peek(0) {
+                        if nc.is_ascii_hexdigit() { val.push(self.advance().unwrap()); } else { break; }
+                    }
+                } else {
+                    while let Some(nc) = self.peek(0) {
+                        // `0..n` is a range, not the float `0.`.
+                        if nc.is_ascii_digit() || (nc == '.' && self.peek(1) != Some('.')) { val.push(self.advance().unwrap()); } else { break; }
+                    }
+                }
+                for suf in ["i64", "i32", "f64", "f32"] {
+                    let mut match_suf = true;
+                    for (j, sc) in suf.chars().enumerate() {
+                        if self.peek(j) != Some(sc) { match_suf = false; break; }
+                    }
+                    if match_suf {
+                        val.push_str(suf);
+                        for _ in 0..suf.len() { self.advance(); }
+                        break;
+                    }
+                }
+                tokens.push(Token { kind: TokenKind::Num, text: String::new(), value: val.into(), line: sl, col: sc });
+            } else if c == '"' {
+                let (sl, sc) = (self.line, self.col);
+                self.advance();
+                let mut val = String::new();
+                while let Some(nc) = self.peek(0) {
+                    if nc == '"' { break; }
+                    if nc == '\\' {
+                        let (el, ec) = (self.line, self.col);
+                        self.advance();
+                        let Some(esc) = self.advance() else { break };
+                        let char_to_push = match esc {
+                            'n' => '\n', 't' => '\t', 'r' => '\r', '"' => '"', '\\' => '\\',
+                            'x' => {
+                                let hex: String = [self.advance(), self.advance()].into_iter().flatten().collect();
+                                if hex.len() != 2 || !hex.chars().all(|h| h.is_ascii_hexdigit()) { return Err(format!("Invalid escape \\x{} at {}:{}", hex, el, ec)); }
+                                u8::from_str_radix(&hex, 16).unwrap_or_default() as char
+                            }
+                            _ => { val.push('\\'); esc }
+                        };
+                        val.push(char_to_push);
+                    } else { val.push(nc); self.advance(); }
+                }
+                if self.advance() != Some('"') { return Err(format!("Unterminated string starting at {}:{}", sl, sc)); }
+                tokens.push(Token { kind: TokenKind::Str, value: Symbol::default(), text: val, line: sl, col: sc });
+            } else {
+                let (sl, sc) = (self.line, self.col);
+                let mut sym = String::new();
+                for s in ["==", "!=", "<=", ">=", "->", "=>", "&&", "||", ".."] {
+                    let mut match_s = true;
+                    for (j, sc) in s.chars().enumerate() {
+                        if self.peek(j) != Some(sc) { match_s = false; break; }
+                    }
+                    if match_s { sym = s.to_string(); break; }
+                }
+                if !sym.is_empty() {
+                    for _ in 0..sym.len() { self.advance(); }
+                } else {
+                    sym.push(self.advance().unwrap());
+                }
+                tokens.push(Token { kind: TokenKind::Sym, text: String::new(), value: sym.into(), line: sl, col: sc });
+            }
+        }
+        tokens.push(Token { kind: TokenKind::Eof, value: Symbol::default(), text: String::new(), line: self.line, col: self.col });
+        Ok(tokens)
+    }
+}
+
+/// Returns the text of a `(string_typed "...")` literal node.
+fn string_literal(n: &IRNode) -> Option<&str> {
+    match n.as_list()?.as_slice() {
+        [IRNode::Atom(head), s] if head == "string_typed" => s.as_str(),
+        _ => None,
+    }
+}
+
+/// Deepest nesting of blocks and expressions the parser accepts. The passes after it walk
+/// the IR recursively, so deeper input would overflow the stack instead of being reported.
+const MAX_NESTING: usize = 256;
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Blocks and expressions open around the current token (see [`MAX_NESTING`]).
+    depth: usize,
+    /// Source path used in the locations reported by `panic`.
+    file: String,
+    /// Declared types of the current function's params and lets, for `__dbg` labels.
+    locals: HashMap<Symbol, Symbol>,
+    /// Field names and types of the structs parsed so far, for destructuring `let`s.
+    struct_fields: HashMap<Symbol, Vec<(Symbol, Symbol)>>,
+    /// Type aliases declared so far in this file.
+    aliases: HashMap<Symbol, Symbol>,
+    /// Architecture being compiled for, matched by `#[cfg(target = "...")]`.
+    target: String,
+    /// Statics declared so far in this file, which `NAME[i]` reads from.
+    statics: HashSet<Symbol>,
+    /// Lines of the file, when each statement is preceded by a `(loc ...)` (`--listing`).
+    listing: Option<Vec<String>>,
+    /// Warnings found while parsing, such as unknown attributes.
+    warnings: Vec<String>,
+    /// Return type of the function being parsed, or `None` in a macro body.
+    ret: Option<Symbol>,
+    /// The early return a `?` ending the statement just parsed adds after it.
+    try_return: Option<IRNode>,
+    /// Check every `__mem_*` access and slice element against the memory layout
+    /// (`--sanitize=memory`).
+    sanitize: bool,
+}
+
+impl Parser {
+    fn new(tokens: Vec<Token>, file: String, target: String) -> Self {
+        let struct_fields = layout::builtin_structs().into_iter().collect();
+        Self { tokens, pos: 0, depth: 0, file, locals: HashMap::new(), struct_fields, aliases: HashMap::new(), target, statics: HashSet::new(), listing: None, warnings: Vec::new(), sanitize: false, ret: None, try_return: None }
+    }
+    fn peek(&self, n: usize) -> &Token {
+        if self.pos + n < self.tokens.len() { &self.tokens[self.pos + n] } else { &self.tokens[self.tokens.len() - 1] }
+    }
+    fn consume(&mut self, kind: Option<TokenKind>, val: Option<&str>) -> Result<Token, String> {
+        let t = self.peek(0).clone();
+        if let Some(k) = kind && t.kind != k { return Err(format!("Expected {:?}, got {:?} at {}:{}", k, t.kind, t.line, t.col)); }
+        if let Some(v) = val && t.value != v {
+            let got = if t.kind == TokenKind::Str { format!("{:?}", t.text) } else { t.value.to_string() };
+            return Err(format!("Expected {}, got {} at {}:{}", v, got, t.line, t.col));
+        }
+        self.pos += 1;
+        Ok(t)
+    }
+    /// Enters one more level of nesting, refusing input nested deeper than [`MAX_NESTING`].
+    fn nest(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            let t = self.peek(0);
+            return Err(format!("Nesting deeper than {} levels at {}:{}", MAX_NESTING, t.line, t.col));
+        }
+        Ok(())
+    }
+    fn parse_type(&mut self) -> Result<Symbol, String> {
+        self.nest()?;
+        let ty = self.parse_type_inner()?;
+        self.depth -= 1;
+        Ok(ty)
+    }
+    fn parse_type_inner(&mut self) -> Result<Symbol, String> {
+        let t = self.peek(0);
+        if t.value == "[" && self.peek(1).value == "]" {
+            self.consume(None, Some("["))?;
+            self.consume(None, Some("]"))?;
+            Ok(format!("[]{}", self.parse_type()?).into())
+        } else if t.value == "[" {
+            self.consume(None, Some("["))?;
+            let ty = self.parse_type()?;
+            if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+            let sz = self.consume(Some(TokenKind::Num), None)?.value;
+            self.consume(None, Some("]"))?;
+            Ok(format!("[{} {}]", ty, sz).into())
+        } else if t.value == "*" {
+            self.consume(None, Some("*"))?;
+            Ok(format!("*{}", self.parse_type()?).into())
+        } else { Ok(self.consume(Some(TokenKind::Ident), None)?.value) }
+    }
+    fn parse_struct(&mut self) -> Result<IRNode, String> {
+        self.consume(Some(TokenKind::Ident), Some("struct"))?;
+        let name = self.consume(Some(TokenKind::Ident), None)?.value;
+        let mut fields = vec![IRNode::atom("struct"), IRNode::atom(name)];
+        if self.peek(0).value == "{" {
+            self.consume(None, Some("{"))?;
+            while self.peek(0).value != "}" {
+                let fn_name = self.consume(Some(TokenKind::Ident), None)?.value;
+                self.consume(None, Some(":"))?;
+                let ft = self.parse_type()?;
+                self.struct_fields.entry(name).or_default().push((fn_name, ft));
+                let mut field = vec![IRNode::atom("field"), IRNode::atom(fn_name), IRNode::atom(ft)];
+                if self.peek(0).value == "=" {
+                    self.consume(None, Some("="))?;
+                    field.push(self.parse_expr()?);
+                }
+                fields.push(IRNode::List(field));
+                if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+            }
+            self.consume(None, Some("}"))?;
+        }
+        Ok(IRNode::List(fields))
+    }
+    fn parse_signature(&mut self) -> Result<(IRNode, Symbol), String> {
+        self.consume(None, Some("("))?;
+        let mut params = vec![IRNode::atom("params")];
+        while self.peek(0).value != ")" {
+            let pn = self.consume(Some(TokenKind::Ident), None)?.value;
+            self.consume(None, Some(":"))?;
+            let pt = self.parse_type()?;
+            params.push(IRNode::List(vec![IRNode::atom("param"), IRNode::atom(pn), IRNode::atom(pt)]));
+            if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+        }
+        self.consume(None, Some(")"))?;
+        let mut rt = Symbol::from("i32");
+        if self.peek(0).value == "returns" || self.peek(0).value == "->" {
+            self.consume(None, None)?;
+            rt = self.parse_type()?;
+        }
+        Ok((IRNode::List(params), rt))
+    }
+    /// `type Name = T` (optionally `;`-terminated), as `(Name, T)`.
+    fn parse_type_alias(&mut self) -> Result<(Symbol, Symbol), String> {
+        self.consume(Some(TokenKind::Ident), Some("type"))?;
+        let name = self.consume(Some(TokenKind::Ident), None)?.value;
+        self.consume(None, Some("="))?;
+        let ty = self.parse_type()?;
+        if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+        self.aliases.insert(name, ty);
+        Ok((name, ty))
+    }
+    /// `enum Name { A, B = 44, C }` (optionally `;`-terminated) as its variants with their
+    /// `i32` values: each is one more than the previous unless given, starting from 0. The
+    /// enum's name becomes a type alias of `i32`.
+    fn parse_enum(&mut self) -> Result<(Symbol, Vec<(Symbol, i32)>), String> {
+        self.consume(Some(TokenKind::Ident), Some("enum"))?;
+        let name = self.consume(Some(TokenKind::Ident), None)?.value;
+        self.consume(None, Some("{"))?;
+        let (mut variants, mut next): (Vec<(Symbol, i32)>, i64) = (Vec::new(), 0);
+        while self.peek(0).value != "}" {
+            let t = self.consume(Some(TokenKind::Ident), None)?;
+            if self.peek(0).value == "=" {
+                self.consume(None, Some("="))?;
+                let negative = self.peek(0).value == "-";
+                if negative { self.consume(None, Some("-"))?; }
+                let v = self.consume(Some(TokenKind::Num), None)?.value;
+                let parsed = match v.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16),
+                    None => v.parse::<i64>(),
+                };
+                next = match parsed {
+                    Ok(n) if negative => -n,
+                    Ok(n) => n,
+                    Err(_) => return Err(format!("Enum value {} of {}::{} is not an integer at {}:{}", v, name, t.value, t.line, t.col)),
+                };
+            }
+            let Ok(value) = i32::try_from(next) else { return Err(format!("Enum value {} of {}::{} does not fit in i32 at {}:{}", next, name, t.value, t.line, t.col)) };
+            if variants.iter().any(|(v, _)| *v == t.value) { return Err(format!("Variant {} of enum {} is declared twice at {}:{}", t.value, name, t.line, t.col)); }
+            variants.push((t.value, value));
+            next += 1;
+            if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+        }
+        self.consume(None, Some("}"))?;
+        if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+        self.aliases.insert(name, "i32".into());
+        Ok((name, variants))
+    }
+    /// `static NAME: [T N] = [v, ...]` as `(static NAME "[T N]" v...)`, checked by
+    /// `typecheck::check_program` and placed in read-only data by the backends.
+    fn parse_static(&mut self) -> Result<IRNode, String> {
+        self.consume(Some(TokenKind::Ident), Some("static"))?;
+        let name = self.consume(Some(TokenKind::Ident), None)?.value;
+        self.consume(None, Some(":"))?;
+        let ty = self.parse_type()?;
+        self.consume(None, Some("="))?;
+        self.consume(None, Some("["))?;
+        let mut node = vec![IRNode::atom("static"), IRNode::atom(name), IRNode::atom(ty)];
+        // Each value is a constant expression, folded to a literal by `consteval::evaluate`.
+        while self.peek(0).value != "]" {
+            node.push(self.parse_expr()?);
+            if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+        }
+        self.consume(None, Some("]"))?;
+        if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+        self.statics.insert(name);
+        Ok(IRNode::List(node))
+    }
+    fn parse_extern(&mut self) -> Result<IRNode, String> {
+        self.consume(Some(TokenKind::Ident), Some("extern"))?;
+        let abi = if self.peek(0).kind == TokenKind::Str { Symbol::from(self.consume(Some(TokenKind::Str), None)?.text) } else { "C".into() };
+        self.consume(Some(TokenKind::Ident), Some("fn"))?;
+        let name = self.consume(Some(TokenKind::Ident), None)?.value;
+        let (params, rt) = self.parse_signature()?;
+        if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+        Ok(IRNode::List(vec![IRNode::atom("extern"), IRNode::atom(name), IRNode::atom(abi), params, IRNode::List(vec![IRNode::atom("ret"), IRNode::atom(rt)])]))
+    }
+    fn parse_fn(&mut self) -> Result<IRNode, String> {
+        let public = self.peek(0).value == "pub";
+        if public { self.consume(Some(TokenKind::Ident), Some("pub"))?; }
+        let constant = self.peek(0).value == "const";
+        if constant { self.consume(Some(TokenKind::Ident), Some("const"))?; }
+        self.consume(Some(TokenKind::Ident), Some("fn"))?;
+        let name = self.consume(Some(TokenKind::Ident), None)?.value;
+        let (params, rt) = self.parse_signature()?;
+        self.ret = Some(rt);
+        self.locals = params.as_list().map(|p| p[1..].iter().filter_map(|p| Some((*p.as_list()?.get(1)?.as_atom()?, *p.as_list()?.get(2)?.as_atom()?))).collect()).unwrap_or_default();
+        let block = if self.peek(0).value == "{" { self.parse_block()? } else { IRNode::List(vec![IRNode::atom("block")]) };
+        let mut node = vec![IRNode::atom("fn"), IRNode::atom(name), params, IRNode::List(vec![IRNode::atom("ret"), IRNode::atom(rt)]), block];
+        if public { node.push(IRNode::List(vec![IRNode::atom("vis"), IRNode::atom("pub")])); }
+        if constant { node.push(IRNode::List(vec![IRNode::atom("const")])); }
+        Ok(IRNode::List(node))
+    }
+    /// `macro name(a, b) { ... }`, returned with its `file:line:col`.
+    fn parse_macro(&mut self) -> Result<(Symbol, String, macros::Macro), String> {
+        let t = self.consume(Some(TokenKind::Ident), Some("macro"))?;
+        let name = self.consume(Some(TokenKind::Ident), None)?.value;
+        self.consume(None, Some("("))?;
+        let mut params = Vec::new();
+        while self.peek(0).value != ")" {
+            params.push(self.consume(Some(TokenKind::Ident), None)?.value);
+            if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+        }
+        self.consume(None, Some(")"))?;
+        self.locals.clear();
+        self.ret = None;
+        let body = match self.parse_block()? {
+            IRNode::List(mut l) => l.split_off(1),
+            _ => Vec::new(),
+        };
+        Ok((name, format!("{}:{}:{}", self.file, t.line, t.col), macros::Macro { params, body }))
+    }
+    /// `(loc "file:line" "text")` for the statement starting at the next token, quoting its
+    /// source line for `--listing`.
+    fn listing_loc(&self) -> Option<IRNode> {
+        let line = self.peek(0).line;
+        let text = self.listing.as_ref()?.get(line.wrapping_sub(1))?.trim().to_string();
+        Some(IRNode::List(vec![IRNode::atom("loc"), IRNode::Str(format!("{}:{}", self.file, line)), IRNode::Str(text)]))
+    }
+    /// Offset of the first token after the `.field` chain starting at `self.peek(from)`.
+    fn field_path_len(&self, from: usize) -> usize {
+        let mut at = from;
+        while self.peek(at).value == "." && self.peek(at + 1).kind == TokenKind::Ident { at += 2; }
+        at
+    }
+    /// The `"file:line:col"` string literal passed to `__panic` for a call at `t`.
+    fn location(&self, t: &Token) -> IRNode {
+        IRNode::List(vec![IRNode::atom("string_typed"), IRNode::Str(format!("{}:{}:{}", self.file, t.line, t.col))])
+    }
+    /// Approximate source text of `tokens[from..to]`, for assertion messages.
+    fn source_text(&self, from: usize, to: usize) -> String {
+        let mut out = String::new();
+        let mut prev: Option<&Token> = None;
+        for t in &self.tokens[from..to] {
+            let tight_before = t.kind == TokenKind::Sym && match t.value.as_str() {
+                "(" | "[" => prev.map(|p| p.kind == TokenKind::Ident).unwrap_or(false),
+                ")" | "]" | "," | "." => true,
+                _ => false,
+            };
+            let tight_after = prev.map(|p| p.kind == TokenKind::Sym && matches!(p.value.as_str(), "(" | "[" | ".")).unwrap_or(true);
+            if !tight_before && !tight_after { out.push(' '); }
+            if t.kind == TokenKind::Str { out.push_str(&format!("{:?}", t.text)); } else { out.push_str(&t.value); }
+            prev = Some(t);
+        }
+        out
+    }
+    /// `assert(cond)` / `assert_eq(a, b)`: an `if` on the negated condition that panics with
+    /// the asserted source text and location.
+    fn parse_assert(&mut self) -> Result<IRNode, String> {
+        let t = self.consume(Some(TokenKind::Ident), None)?;
+        self.consume(None, Some("("))?;
+        let start = self.pos;
+        let first = self.parse_expr()?;
+        let mut text = self.source_text(start, self.pos);
+        let check = if t.value == "assert_eq" {
+            self.consume(None, Some(","))?;
+            let start = self.pos;
+            let second = self.parse_expr()?;
+            text = format!("{} == {}", text, self.source_text(start, self.pos));
+            IRNode::List(vec![IRNode::atom("binary"), IRNode::atom("ne"), first, second, IRNode::atom("bool")])
+        } else {
+            IRNode::List(vec![IRNode::atom("binary"), IRNode::atom("eq"), first, IRNode::List(vec![IRNode::atom("int"), IRNode::atom("0")]), IRNode::atom("bool")])
+        };
+        self.consume(None, Some(")"))?;
+        if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+        let msg = IRNode::List(vec![IRNode::atom("string_typed"), IRNode::Str(format!("assertion failed: {}", text))]);
+        let panic = IRNode::List(vec![IRNode::atom("call"), IRNode::atom("__panic"), msg, self.location(&t)]);
+        let body = IRNode::List(vec![IRNode::atom("block"), IRNode::List(vec![IRNode::atom("expr"), panic])]);
+        Ok(IRNode::List(vec![IRNode::atom("if"), check, body]))
+    }
+    /// Parses `{ stmt... }` into `(block ...)`.
+    fn parse_block(&mut self) -> Result<IRNode, String> {
+        self.nest()?;
+        self.consume(None, Some("{"))?;
+        let outer = self.locals.clone();
+        let mut b = vec![IRNode::atom("block")];
+        while self.peek(0).value != "}" {
+            let (enabled, attrs) = self.parse_attrs()?;
+            let at = self.peek(0).clone();
+            self.ignore_attrs(&attrs, &at);
+            let (len, locals) = (b.len(), (!enabled).then(|| self.locals.clone()));
+            if let Some(loc) = self.listing_loc() { b.push(loc); }
+            if self.peek(0).value == "let" && self.peek(2).value == "{" { self.parse_destructure(&mut b)?; } else { b.push(self.parse_stmt()?); }
+            b.extend(self.try_return.take());
+            // A statement configured out is still parsed, then dropped with the locals it declared.
+            if let Some(locals) = locals {
+                b.truncate(len);
+                self.locals = locals;
+            }
+        }
+        self.consume(None, Some("}"))?;
+        self.locals = outer;
+        self.depth -= 1;
+        Ok(IRNode::List(b))
+    }
+    /// `for i in r { ... }` as a block that counts the `i32` local `i` from `r.start` up to
+    /// just below `r.end`. The bounds of a literal `a..b` are kept in two `i32` locals, the
+    /// loop variable and the end; any other range is held whole and its end read each time.
+    fn parse_for(&mut self) -> Result<IRNode, String> {
+        let atom = |s: &str| IRNode::atom(s);
+        let ident = |s: &str| IRNode::List(vec![IRNode::atom("ident"), IRNode::atom(s)]);
+        self.consume(None, Some("for"))?;
+        let var = self.consume(Some(TokenKind::Ident), None)?.value;
+        self.consume(None, Some("in"))?;
+        let hidden = format!("__for{}", self.pos);
+        let range = self.parse_expr()?;
+        let mut out = vec![atom("block")];
+        let end = match range.as_list().map(|l| l.as_slice()) {
+            Some([IRNode::Atom(head), IRNode::Atom(ty), start, end]) if head == "struct_lit" && ty == layout::RANGE_TYPE => {
+                out.push(IRNode::List(vec![atom("let"), atom(&hidden), atom("i32"), end.clone()]));
+                out.push(IRNode::List(vec![atom("let"), atom(&var), atom("i32"), start.clone()]));
+                ident(&hidden)
+            }
+            _ => {
+                out.push(IRNode::List(vec![atom("let"), atom(&hidden), atom(layout::RANGE_TYPE), range]));
+                out.push(IRNode::List(vec![atom("let"), atom(&var), atom("i32"), IRNode::List(vec![atom("field"), atom(&hidden), atom("start")])]));
+                IRNode::List(vec![atom("field"), atom(&hidden), atom("end")])
+            }
+        };
+        let outer = self.locals.clone();
+        self.locals.insert(var, "i32".into());
+        let mut body = self.parse_block()?;
+        self.locals = outer;
+        let step = IRNode::List(vec![atom("binary"), atom("add"), ident(&var), IRNode::List(vec![atom("int"), atom("1")])]);
+        if let IRNode::List(b) = &mut body { b.push(IRNode::List(vec![atom("assign"), atom(&var), step])); }
+        let cond = IRNode::List(vec![atom("binary"), atom("lt"), ident(&var), end, atom("bool")]);
+        out.push(IRNode::List(vec![atom("while"), cond, body]));
+        Ok(IRNode::List(out))
+    }
+    /// Parses the `#[name]`, `#[name = value]` and `#[name(args)]` attributes ahead of an
+    /// item or statement. Returns whether every `#[cfg(...)]` among them holds for the
+    /// target being compiled, and the others as `(attr name arg...)` nodes, where an
+    /// argument is an identifier, number or string, or a `(key value)` list for `key = value`. Attributes not in
+    /// [`ATTRIBUTES`] are dropped with a warning.
+    fn parse_attrs(&mut self) -> Result<(bool, Vec<IRNode>), String> {
+        let (mut enabled, mut attrs) = (true, Vec::new());
+        while self.peek(0).value == "#" && self.peek(1).value == "[" {
+            self.consume(None, Some("#"))?;
+            self.consume(None, Some("["))?;
+            let t = self.consume(Some(TokenKind::Ident), None)?;
+            if t.value == "cfg" {
+                self.consume(None, Some("("))?;
+                enabled &= self.parse_cfg_predicate()?;
+                self.consume(None, Some(")"))?;
+                self.consume(None, Some("]"))?;
+                continue;
+            }
+            let mut attr = vec![IRNode::atom("attr"), IRNode::atom(t.value)];
+            if self.peek(0).value == "=" {
+                self.consume(None, Some("="))?;
+                attr.push(self.consume(None, None)?.atom());
+            } else if self.peek(0).value == "(" {
+                self.consume(None, Some("("))?;
+                while self.peek(0).value != ")" {
+                    if self.peek(0).kind == TokenKind::Eof { return Err(format!("Unterminated attribute arguments of `{}` at {}:{}", t.value, t.line, t.col)); }
+                    let arg = self.consume(None, None)?.atom();
+                    if self.peek(0).value == "=" {
+                        self.consume(None, Some("="))?;
+                        attr.push(IRNode::List(vec![arg, self.consume(None, None)?.atom()]));
+                    } else if self.peek(0).value == "(" {
+                        // `key(value)`, as in `#[repr(align(8))]`, is kept like `key = value`.
+                        self.consume(None, Some("("))?;
+                        attr.push(IRNode::List(vec![arg, self.consume(None, None)?.atom()]));
+                        self.consume(None, Some(")"))?;
+                    } else {
+                        attr.push(arg);
+                    }
+                    if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+                }
+                self.consume(None, Some(")"))?;
+            }
+            self.consume(None, Some("]"))?;
+            if ATTRIBUTES.contains(&t.value.as_str()) {
+                attrs.push(IRNode::List(attr));
+            } else {
+                let hint = typecheck::closest_match(&t.value, ATTRIBUTES.iter().copied()).map(|m| format!("; did you mean `{}`?", m)).unwrap_or_default();
+                self.warnings.push(format!("{}:{}:{}: unknown attribute `{}` is ignored{}", self.file, t.line, t.col, t.value, hint));
+            }
+        }
+        Ok((enabled, attrs))
+    }
+    /// Warns that `attrs`, parsed ahead of something other than a function or struct,
+    /// have no effect there.
+    fn ignore_attrs(&mut self, attrs: &[IRNode], t: &Token) {
+        for a in attrs {
+            let name = a.as_list().and_then(|l| l.get(1)).and_then(|n| n.as_atom()).cloned().unwrap_or_default();
+            self.warnings.push(format!("{}:{}:{}: attribute `{}` only applies to functions and structs and is ignored", self.file, t.line, t.col, name));
+        }
+    }
+    /// `target = "x86_64"`, `not(p)`, `any(p, ...)` or `all(p, ...)`.
+    fn parse_cfg_predicate(&mut self) -> Result<bool, String> {
+        let t = self.consume(Some(TokenKind::Ident), None)?;
+        Ok(match t.value.as_str() {
+            "target" => {
+                self.consume(None, Some("="))?;
+                self.consume(Some(TokenKind::Str), None)?.text == self.target
+            }
+            "not" | "any" | "all" => {
+                self.consume(None, Some("("))?;
+                let mut values = Vec::new();
+                while self.peek(0).value != ")" {
+                    self.nest()?;
+                    values.push(self.parse_cfg_predicate()?);
+                    self.depth -= 1;
+                    if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+                }
+                self.consume(None, Some(")"))?;
+                match t.value.as_str() {
+                    "not" => !values.iter().all(|v| *v),
+                    "any" => values.iter().any(|v| *v),
+                    _ => values.iter().all(|v| *v),
+                }
+            }
+            other => return Err(format!("Unknown cfg predicate {} at {}:{}", other, t.line, t.col)),
+        })
+    }
+    /// `let Point { x, y: py } = p` declares one local per named field, initialized from
+    /// `(field p x)`. A non-variable right-hand side is first bound to a hidden local.
+    fn parse_destructure(&mut self, out: &mut Vec<IRNode>) -> Result<(), String> {
+        let start = self.pos;
+        self.consume(None, Some("let"))?;
+        let ty = self.consume(Some(TokenKind::Ident), None)?.value;
+        self.consume(None, Some("{"))?;
+        let mut bindings = Vec::new();
+        while self.peek(0).value != "}" {
+            let field = self.consume(Some(TokenKind::Ident), None)?.value;
+            let local = if self.peek(0).value == ":" {
+                self.consume(None, Some(":"))?;
+                self.consume(Some(TokenKind::Ident), None)?.value
+            } else {
+                field
+            };
+            bindings.push((field, local));
+            if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+        }
+        self.consume(None, Some("}"))?;
+        self.consume(None, Some("="))?;
+        let e = self.parse_expr()?;
+        if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+        let source = match e.as_list().map(|l| l.as_slice()) {
+            Some([IRNode::Atom(head), IRNode::Atom(name)]) if head == "ident" => *name,
+            _ => {
+                let tmp = Symbol::from(format!("__destructure{}", start));
+                out.push(IRNode::List(vec![IRNode::atom("let"), IRNode::atom(tmp), IRNode::atom(ty), e]));
+                tmp
+            }
+        };
+        for (field, local) in bindings {
+            // The struct may be declared later in the file or in another module, so the
+            // local's type is left as `(field_type Struct field)` for
+            // `Program::resolve_destructures`; the parser goes by the structs seen so far.
+            let fty = self.struct_fields.get(&ty).and_then(|f| f.iter().find(|f| f.0 == field)).map(|f| f.1).unwrap_or_else(|| "i32".into());
+            self.locals.insert(local, fty);
+            out.push(IRNode::List(vec![
+                IRNode::atom("let"), IRNode::atom(local),
+                IRNode::List(vec![IRNode::atom("field_type"), IRNode::atom(ty), IRNode::atom(field)]),
+                IRNode::List(vec![IRNode::atom("field"), IRNode::atom(source), IRNode::atom(field)]),
+            ]));
+        }
+        Ok(())
+    }
+    /// Consumes a `?` ending the value of a `let`, assignment or expression statement, which
+    /// makes the function return that value, read back from `var`, when it is negative (an
+    /// errno-style error): `(if (binary lt (ident var) (int 0) bool) (block (return ...)))`
+    /// is added after the statement. Returns whether there was one.
+    fn parse_try(&mut self, var: Symbol) -> Result<bool, String> {
+        if self.peek(0).value != "?" { return Ok(false); }
+        let t = self.consume(None, Some("?"))?;
+        if let Some(ret) = self.ret.map(|r| self.resolve_alias(&r)) && ret != "i32" && ret != "i64" {
+            return Err(format!("`?` returns the error from a function returning {}, not i32 or i64, at {}:{}", ret, t.line, t.col));
+        }
+        let value = IRNode::List(vec![IRNode::atom("ident"), IRNode::atom(var)]);
+        let negative = IRNode::List(vec![IRNode::atom("binary"), IRNode::atom("lt"), value.clone(), IRNode::List(vec![IRNode::atom("int"), IRNode::atom("0")]), IRNode::atom("bool")]);
+        let ret = IRNode::List(vec![IRNode::atom("block"), IRNode::List(vec![IRNode::atom("return"), value])]);
+        self.try_return = Some(IRNode::List(vec![IRNode::atom("if"), negative, ret]));
+        Ok(true)
+    }
+    /// `match x { 1 => { ... } -2 => { ... } _ => { ... } }` over integer literal arms, as
+    /// `(match x (arm 1 (block ...)) (arm -2 (block ...)) (default (block ...)))`.
+    fn parse_match(&mut self) -> Result<IRNode, String> {
+        self.consume(None, Some("match"))?;
+        let mut res = vec![IRNode::atom("match"), self.parse_expr()?];
+        self.consume(None, Some("{"))?;
+        while self.peek(0).value != "}" {
+            let pattern = if self.peek(0).value == "_" {
+                self.consume(None, Some("_"))?;
+                None
+            } else if self.peek(0).kind == TokenKind::Ident {
+                // `Enum::Variant`, replaced by its value in `Program::resolve_enums`.
+                let e = self.consume(Some(TokenKind::Ident), None)?.value;
+                self.consume(None, Some(":"))?;
+                self.consume(None, Some(":"))?;
+                Some(format!("{}::{}", e, self.consume(Some(TokenKind::Ident), None)?.value).into())
+            } else {
+                let neg = self.peek(0).value == "-";
+                if neg { self.consume(None, Some("-"))?; }
+                let v = self.consume(Some(TokenKind::Num), None)?.value;
+                Some(if neg { format!("-{}", v).into() } else { v })
+            };
+            self.consume(None, Some("=>"))?;
+            let b = self.parse_block()?;
+            if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+            res.push(IRNode::List(match pattern {
+                Some(v) => vec![IRNode::atom("arm"), IRNode::atom(v), b],
+                None => vec![IRNode::atom("default"), b],
+            }));
+        }
+        self.consume(None, Some("}"))?;
+        Ok(IRNode::List(res))
+    }
+    fn parse_stmt(&mut self) -> Result<IRNode, String> {
+        let t = self.peek(0);
+        Ok(if (t.value == "assert" || t.value == "assert_eq") && self.peek(1).value == "(" {
+            self.parse_assert()?
+        } else if t.kind == TokenKind::Ident && self.peek(1).value == "!" && self.peek(2).value == "(" {
+            // `name!(args)` is spliced in by `macros::expand` once the file is parsed.
+            let t = t.clone();
+            let name = self.consume(Some(TokenKind::Ident), None)?.value;
+            self.consume(None, Some("!"))?;
+            self.consume(None, Some("("))?;
+            let mut call = vec![IRNode::atom("macro_call"), IRNode::atom(name), IRNode::atom(format!("{}:{}:{}", self.file, t.line, t.col))];
+            while self.peek(0).value != ")" {
+                call.push(self.parse_expr()?);
+                if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+            }
+            self.consume(None, Some(")"))?;
+            if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+            IRNode::List(call)
+        } else if t.value == "let" {
+            self.consume(None, Some("let"))?;
+            let n = self.consume(Some(TokenKind::Ident), None)?.value;
+            self.consume(None, Some(":"))?;
+            let ty = self.parse_type()?;
+            // Without `= e` the variable must be assigned before it is read; see `definite`.
+            let e = if self.peek(0).value == "=" {
+                self.consume(None, Some("="))?;
+                self.parse_expr()?
+            } else {
+                IRNode::List(vec![IRNode::atom("uninit")])
+            };
+            self.parse_try(n)?;
+            if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+            self.locals.insert(n, ty);
+            IRNode::List(vec![IRNode::atom("let"), IRNode::atom(n), IRNode::atom(ty), e])
+        } else if t.value == "return" {
+            self.consume(None, Some("return"))?;
+            let e = self.parse_expr()?;
+            if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+            IRNode::List(vec![IRNode::atom("return"), e])
+        } else if t.value == "svc" {
+            self.consume(None, Some("svc"))?;
+            let mut args = vec![IRNode::atom("svc")];
+            if self.peek(0).value == "(" {
+                self.consume(None, Some("("))?;
+                while self.peek(0).value != ")" {
+                    args.push(self.parse_expr()?);
+                    if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+                }
+                self.consume(None, Some(")"))?;
+            } else {
+                while self.peek(0).kind != TokenKind::Sym && self.peek(0).kind != TokenKind::Eof {
+                    args.push(self.parse_expr()?);
+                    if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+                }
+            }
+            if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+            IRNode::List(args)
+        } else if t.value == "syscall" {
+            self.consume(None, Some("syscall"))?;
+            let mut args = vec![IRNode::atom("syscall")];
+            if self.peek(0).value == "(" {
+                self.consume(None, Some("("))?;
+                while self.peek(0).value != ")" {
+                    args.push(self.parse_expr()?);
+                    if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+                }
+                self.consume(None, Some(")"))?;
+            }
+            if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+            IRNode::List(args)
+        } else if t.value == "asm" && self.peek(1).value == "(" {
+            self.consume(None, Some("asm"))?;
+            self.consume(None, Some("("))?;
+            let template = self.consume(Some(TokenKind::Str), None)?.text;
+            let mut operands = vec![IRNode::atom("operands")];
+            while self.peek(0).value == "," {
+                self.consume(None, Some(","))?;
+                if self.peek(0).value == ")" { break; }
+                operands.push(IRNode::atom(self.consume(Some(TokenKind::Ident), None)?.value));
+            }
+            self.consume(None, Some(")"))?;
+            if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+            IRNode::List(vec![IRNode::atom("asm"), IRNode::Str(template), IRNode::List(operands)])
+        } else if t.value == "if" {
+            self.consume(None, Some("if"))?;
+            let c = self.parse_expr()?;
+            let mut res = vec![IRNode::atom("if"), c, self.parse_block()?];
+            if self.peek(0).value == "else" {
+                self.consume(None, Some("else"))?;
+                res.push(IRNode::List(vec![IRNode::atom("else"), self.parse_block()?]));
+            }
+            IRNode::List(res)
+        } else if t.value == "match" {
+            self.parse_match()?
+        } else if t.value == "for" && self.peek(2).value == "in" {
+            self.parse_for()?
+        } else if t.value == "while" {
+            self.consume(None, Some("while"))?;
+            let c = self.parse_expr()?;
+            IRNode::List(vec![IRNode::atom("while"), c, self.parse_block()?])
+        } else if t.kind == TokenKind::Ident && self.peek(1).value == "[" {
+            let at = t.clone();
+            let n = self.consume(Some(TokenKind::Ident), None)?.value;
+            self.consume(None, Some("["))?;
+            let idx = self.parse_expr()?;
+            self.consume(None, Some("]"))?;
+            self.consume(None, Some("="))?;
+            let e = self.parse_expr()?;
+            if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+            if self.is_slice(&n) {
+                let bounds = self.bounds_panic(format!("index out of bounds of slice `{}`", n), &at);
+                let idx = self.sanitized_element(&n, idx, true, &at);
+                return Ok(IRNode::List(vec![IRNode::atom("slice_assign"), IRNode::atom(n), idx, e, bounds]));
+            }
+            IRNode::List(vec![IRNode::atom("array_assign"), IRNode::atom(n), idx, e])
+        } else if t.kind == TokenKind::Ident && self.peek(1).value == "=" {
+            let n = self.consume(Some(TokenKind::Ident), None)?.value;
+            self.consume(None, Some("="))?;
+            let e = self.parse_expr()?;
+            self.parse_try(n)?;
+            if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+            IRNode::List(vec![IRNode::atom("assign"), IRNode::atom(n), e])
+        } else if t.kind == TokenKind::Ident && self.peek(1).value == "." && self.peek(self.field_path_len(1)).value == "=" {
+            // `a.b.c = e` is `(field_assign a b c e)`.
+            let v = self.consume(Some(TokenKind::Ident), None)?.value;
+            let mut node = vec![IRNode::atom("field_assign"), IRNode::atom(v)];
+            while self.peek(0).value == "." {
+                self.consume(None, Some("."))?;
+                node.push(IRNode::atom(self.consume(Some(TokenKind::Ident), None)?.value));
+            }
+            self.consume(None, Some("="))?;
+            node.push(self.parse_expr()?);
+            if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+            IRNode::List(node)
+        } else {
+            let start = self.pos;
+            let e = self.parse_expr()?;
+            // `f()?` keeps the result in a hidden local to test it.
+            let tmp = Symbol::from(format!("__try{}", start));
+            let stmt = if self.parse_try(tmp)? {
+                let ty = self.expr_type(&e);
+                self.locals.insert(tmp, ty);
+                IRNode::List(vec![IRNode::atom("let"), IRNode::atom(tmp), IRNode::atom(ty), e])
+            } else {
+                IRNode::List(vec![IRNode::atom("expr"), e])
+            };
+            if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+            stmt
+        })
+    }
+    fn parse_expr(&mut self) -> Result<IRNode, String> {
+        self.nest()?;
+        let start = self.parse_or()?;
+        let e = if self.peek(0).value == ".." {
+            // `start..end` is the built-in struct `range`.
+            self.consume(None, Some(".."))?;
+            IRNode::List(vec![IRNode::atom("struct_lit"), IRNode::atom(layout::RANGE_TYPE), start, self.parse_or()?])
+        } else {
+            start
+        };
+        self.depth -= 1;
+        Ok(e)
+    }
+    // A chain like `a + b + c` nests each operation inside the next, so every link
+    // counts towards `MAX_NESTING` like a parenthesized operand would.
+    fn parse_or(&mut self) -> Result<IRNode, String> {
+        let (mut l, depth) = (self.parse_and()?, self.depth);
+        while self.peek(0).value == "||" || self.peek(0).value == "|" {
+            self.consume(None, None)?;
+            self.nest()?;
+            l = IRNode::List(vec![IRNode::atom("binary"), IRNode::atom("or"), l, self.parse_and()?]);
+        }
+        self.depth = depth;
+        Ok(l)
+    }
+    fn parse_and(&mut self) -> Result<IRNode, String> {
+        let (mut l, depth) = (self.parse_cmp()?, self.depth);
+        while self.peek(0).value == "&&" || self.peek(0).value == "&" {
+            self.consume(None, None)?;
+            self.nest()?;
+            l = IRNode::List(vec![IRNode::atom("binary"), IRNode::atom("and"), l, self.parse_cmp()?]);
+        }
+        self.depth = depth;
+        Ok(l)
+    }
+    /// Whether the `{` after identifier `name` opens a struct literal rather than a block
+    /// (`if done { return }`, `match x { 1 => ...`). `{ field: ...` always does (but not
+    /// `{ Enum::Variant => ...`); the empty
+    /// `{}` and the shorthand `{ x, y }` only after a capitalized struct name.
+    fn at_struct_literal(&self, name: &str) -> bool {
+        if self.peek(0).value != "{" { return false; }
+        if self.peek(1).kind == TokenKind::Ident && self.peek(2).value == ":" && self.peek(3).value != ":" { return true; }
+        name.starts_with(|c: char| c.is_ascii_uppercase())
+            && (self.peek(1).value == "}" || (self.peek(1).kind == TokenKind::Ident && (self.peek(2).value == "," || self.peek(2).value == "}")))
+    }
+    /// The rest of a struct literal after its type, `{ field: e, ... }`, as
+    /// `(struct_init Name (init field e)...)`.
+    fn parse_struct_init(&mut self, name: Symbol) -> Result<IRNode, String> {
+        self.consume(None, Some("{"))?;
+        let mut fields = vec![IRNode::atom("struct_init"), IRNode::atom(name)];
+        while self.peek(0).value != "}" {
+            let field = self.consume(Some(TokenKind::Ident), None)?.value;
+            // `Point { x }` is shorthand for `Point { x: x }`.
+            let value = if self.peek(0).value == ":" {
+                self.consume(None, Some(":"))?;
+                self.parse_expr()?
+            } else {
+                IRNode::List(vec![IRNode::atom("ident"), IRNode::atom(field)])
+            };
+            fields.push(IRNode::List(vec![IRNode::atom("init"), IRNode::atom(field), value]));
+            if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+        }
+        self.consume(None, Some("}"))?;
+        Ok(IRNode::List(fields))
+    }
+    /// True when `name` is a local declared with a slice type.
+    fn is_slice(&self, name: &str) -> bool {
+        self.locals.get(name).is_some_and(|ty| layout::slice_elem(&self.resolve_alias(ty)).is_some())
+    }
+    /// `__panic(message, loc)` for an index read or written at `t`, run when the index is
+    /// past the end.
+    fn bounds_panic(&self, message: String, t: &Token) -> IRNode {
+        let message = IRNode::List(vec![IRNode::atom("string_typed"), IRNode::Str(message)]);
+        IRNode::List(vec![IRNode::atom("call"), IRNode::atom("__panic"), message, self.location(t)])
+    }
+    /// Under `--sanitize=memory`, `args` passed through the runtime's `check` function
+    /// with the access `width` and the end of the region it may touch: the user data
+    /// region for a write, all of `__coatl_mem` for a read. The check panics at `t` with
+    /// `what` when the access falls outside, and otherwise returns its first argument.
+    fn sanitized_access(&self, check: &str, mut args: Vec<IRNode>, width: i32, write: bool, what: String, t: &Token) -> IRNode {
+        let int = |v: i32| IRNode::List(vec![IRNode::atom("int"), IRNode::atom(v.to_string())]);
+        let (end, region) = if write { (STRING_POOL_BASE, "the user data region") } else { (MEM_SIZE, "__coatl_mem") };
+        let message = format!("memory sanitizer: {} outside {} [0, {})", what, region, end);
+        args.extend([int(width), int(end), IRNode::List(vec![IRNode::atom("string_typed"), IRNode::Str(message)]), self.location(t)]);
+        let mut call = vec![IRNode::atom("call"), IRNode::atom(mangle::mangle(RUNTIME_MODULE, check))];
+        call.extend(args);
+        IRNode::List(call)
+    }
+    /// The index `idx` of slice `n`, checked by `--sanitize=memory` when enabled.
+    fn sanitized_element(&self, n: &str, idx: IRNode, write: bool, t: &Token) -> IRNode {
+        if !self.sanitize { return idx; }
+        let width = if self.locals.get(n).and_then(|ty| layout::slice_elem(&self.resolve_alias(ty)).map(|e| e == "u8")).unwrap_or(false) { 1 } else { 4 };
+        let ptr = IRNode::List(vec![IRNode::atom("field"), IRNode::atom(n.to_string()), IRNode::atom("ptr")]);
+        self.sanitized_access("san_element", vec![ptr, idx], width, write, format!("{} of an element of slice `{}`", if write { "write" } else { "read" }, n), t)
+    }
+    /// `ty` with type aliases replaced by what they name.
+    fn resolve_alias(&self, ty: &str) -> Symbol {
+        let mut ty = Symbol::from(ty);
+        for _ in 0..self.aliases.len() {
+            match self.aliases.get(&ty) { Some(&t) => ty = t, None => break }
+        }
+        ty
+    }
+    /// The type of `n` as far as the parser can tell: literal kinds and declared locals,
+    /// `i32` otherwise.
+    fn expr_type(&self, n: &IRNode) -> Symbol {
+        match n {
+            IRNode::List(l) => match l[0].as_atom().map(|h| h.as_str()) {
+                Some("ident") => self.resolve_alias(l[1].as_atom().and_then(|v| self.locals.get(v)).map(|t| t.as_str()).unwrap_or("i32")),
+                Some("int_i64") => "i64".into(),
+                Some("bool") => "bool".into(),
+                Some("struct_lit") | Some("struct_init") => l[1].as_atom().copied().unwrap_or_default(),
+                Some("string_typed") => "str".into(),
+                _ => "i32".into(),
+            },
+            IRNode::Atom(_) | IRNode::Str(_) => "i32".into(),
+        }
+    }
+    fn parse_cmp(&mut self) -> Result<IRNode, String> {
+        let mut l = self.parse_add()?;
+        let ops: HashMap<&str, &str> = [("==", "eq"), ("!=", "ne"), ("<", "lt"), (">", "gt"), ("<=", "le"), (">=", "ge")].iter().cloned().collect();
+        let val = self.peek(0).value.as_str();
+        if let Some(&op) = ops.get(val) {
+            self.consume(None, None)?;
+            let r = self.parse_add()?;
+            // `==`/`!=` between two strings compares their bytes, not their pool offsets.
+            if (op == "eq" || op == "ne") && self.expr_type(&l) == "str" && self.expr_type(&r) == "str" {
+                let eq = IRNode::List(vec![IRNode::atom("call"), IRNode::atom("__str_eq"), l, r]);
+                if op == "eq" { return Ok(eq); }
+                return Ok(IRNode::List(vec![IRNode::atom("binary"), IRNode::atom("eq"), eq, IRNode::List(vec![IRNode::atom("int"), IRNode::atom("0")]), IRNode::atom("bool")]));
+            }
+            l = IRNode::List(vec![IRNode::atom("binary"), IRNode::atom(op.to_string()), l, r, IRNode::atom("bool")]);
+        }
+        Ok(l)
+    }
+    fn parse_add(&mut self) -> Result<IRNode, String> {
+        let (mut l, depth) = (self.parse_mul()?, self.depth);
+        while self.peek(0).value == "+" || self.peek(0).value == "-" {
+            let op = if self.consume(None, None)?.value == "+" { "add" } else { "sub" };
+            self.nest()?;
+            let r = self.parse_mul()?;
+            // `"a" + "b"` is folded into one pooled literal.
+            if op == "add" && let (Some(a), Some(b)) = (string_literal(&l), string_literal(&r)) {
+                l = IRNode::List(vec![IRNode::atom("string_typed"), IRNode::Str(format!("{}{}", a, b))]);
+                self.depth -= 1;
+                continue;
+            }
+            l = IRNode::List(vec![IRNode::atom("binary"), IRNode::atom(op.to_string()), l, r]);
+        }
+        self.depth = depth;
+        Ok(l)
+    }
+    fn parse_mul(&mut self) -> Result<IRNode, String> {
+        let (mut l, depth) = (self.parse_unary()?, self.depth);
+        while self.peek(0).value == "*" || self.peek(0).value == "/" {
+            let op = if self.consume(None, None)?.value == "*" { "mul" } else { "div" };
+            self.nest()?;
+            l = IRNode::List(vec![IRNode::atom("binary"), IRNode::atom(op.to_string()), l, self.parse_unary()?]);
+        }
+        self.depth = depth;
+        Ok(l)
+    }
+    /// Prefix operators: `!x` is `x == 0`, and `-x` is `0 - x`, folded into the literal
+    /// when `x` is a number.
+    fn parse_unary(&mut self) -> Result<IRNode, String> {
+        let zero = || IRNode::List(vec![IRNode::atom("int"), IRNode::atom("0")]);
+        if self.peek(0).kind != TokenKind::Sym { return self.parse_term(); }
+        Ok(match self.peek(0).value.as_str() {
+            "!" => {
+                self.consume(None, Some("!"))?;
+                self.nest()?;
+                let operand = self.parse_unary()?;
+                self.depth -= 1;
+                IRNode::List(vec![IRNode::atom("binary"), IRNode::atom("eq"), operand, zero(), IRNode::atom("bool")])
+            }
+            "-" => {
+                self.consume(None, Some("-"))?;
+                self.nest()?;
+                let operand = self.parse_unary()?;
+                self.depth -= 1;
+                if let IRNode::List(l) = &operand
+                    && let [IRNode::Atom(kind), IRNode::Atom(v)] = l.as_slice()
+                    && matches!(kind.as_str(), "int" | "int_i64" | "f32" | "f64") {
+                    let v = v.strip_prefix('-').map(|v| v.to_string()).unwrap_or_else(|| format!("-{}", v));
+                    return Ok(IRNode::List(vec![IRNode::atom(*kind), IRNode::atom(v)]));
+                }
+                IRNode::List(vec![IRNode::atom("binary"), IRNode::atom("sub"), zero(), operand])
+            }
+            _ => self.parse_term()?,
+        })
+    }
+    fn parse_term(&mut self) -> Result<IRNode, String> {
+        let t = self.peek(0).clone();
+        Ok(if t.value == "svc" {
+            self.consume(None, None)?;
+            let imm = self.consume(Some(TokenKind::Num), None)?.value;
+            if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+            IRNode::List(vec![IRNode::atom("svc"), IRNode::atom(imm)])
+        } else if t.value == "syscall" {
+            self.consume(None, None)?;
+            if self.peek(0).value == ";" { self.consume(None, Some(";"))?; }
+            IRNode::List(vec![IRNode::atom("syscall")])
+        } else if t.value == "(" {
+            self.consume(None, Some("("))?;
+            let e = self.parse_expr()?;
+            self.consume(None, Some(")"))?;
+            e
+        } else if t.value == "[" && self.peek(1).value == "]" {
+            // `[]u8 { ptr: p, len: n }` builds a slice like a struct literal.
+            let ty = self.parse_type()?;
+            self.parse_struct_init(ty)?
+        } else if t.value == "[" {
+            self.consume(None, Some("["))?;
+            let val = self.parse_expr()?;
+            let sz = self.consume(Some(TokenKind::Num), None)?.value;
+            self.consume(None, Some("]"))?;
+            IRNode::List(vec![IRNode::atom("array_lit"), val, IRNode::atom(sz)])
+        } else if t.kind == TokenKind::Num {
+            let v = self.consume(Some(TokenKind::Num), None)?.value;
+            if let Some(n) = v.strip_suffix("i64") { IRNode::List(vec![IRNode::atom("int_i64"), IRNode::atom(n)]) }
+            else if let Some(n) = v.strip_suffix("f32") { IRNode::List(vec![IRNode::atom("f32"), IRNode::atom(n)]) }
+            else if let Some(n) = v.strip_suffix("f64") { IRNode::List(vec![IRNode::atom("f64"), IRNode::atom(n)]) }
+            else if let Some(n) = v.strip_suffix("i32") { IRNode::List(vec![IRNode::atom("int"), IRNode::atom(n)]) }
+            else { IRNode::List(vec![IRNode::atom("int"), IRNode::atom(v)]) }
+        } else if t.kind == TokenKind::Str {
+            IRNode::List(vec![IRNode::atom("string_typed"), IRNode::Str(self.consume(Some(TokenKind::Str), None)?.text)])
+        } else if t.kind == TokenKind::Ident {
+            let mut n = self.consume(Some(TokenKind::Ident), None)?.value;
+            if self.peek(0).value == ":" && self.peek(1).value == ":" {
+                self.consume(None, Some(":"))?; self.consume(None, Some(":"))?;
+                n = format!("{}::{}", n, self.consume(Some(TokenKind::Ident), None)?.value).into();
+            }
+            if n == "true" || n == "false" { return Ok(IRNode::List(vec![IRNode::atom("bool"), IRNode::atom(if n == "true" { "1" } else { "0" }.to_string())])); }
+            // `Name { field: ... }` as `(struct_init Name (init field e)...)`, put in field
+            // order by `Program::resolve_struct_inits`.
+            if self.at_struct_literal(&n) { return self.parse_struct_init(n); }
+            if self.peek(0).value == "(" {
+                self.consume(None, Some("("))?;
+                let args_start = self.pos;
+                let mut args = Vec::new();
+                while self.peek(0).value != ")" {
+                    // `name: expr` is bound to the callee's parameter by `Program::resolve_named_args`.
+                    if self.peek(0).kind == TokenKind::Ident && self.peek(1).value == ":" && self.peek(2).value != ":" {
+                        let param = self.consume(Some(TokenKind::Ident), None)?.value;
+                        self.consume(None, Some(":"))?;
+                        args.push(IRNode::List(vec![IRNode::atom("named"), IRNode::atom(param), self.parse_expr()?]));
+                    } else {
+                        args.push(self.parse_expr()?);
+                    }
+                    if self.peek(0).value == "," { self.consume(None, Some(","))?; }
+                }
+                self.consume(None, Some(")"))?;
+                if (n == "str_len" || n == "str_ptr") && args.len() != 1 { return Err(format!("{} takes one argument at {}:{}", n, t.line, t.col)); }
+                if n == "str_len" { return Ok(IRNode::List(vec![IRNode::atom("str_len"), args[0].clone()])); }
+                if n == "str_ptr" { return Ok(IRNode::List(vec![IRNode::atom("str_ptr"), args[0].clone()])); }
+                if n == "__dbg" && args.len() == 1 {
+                    // The runtime prints this label, then ` = ` and the value in decimal.
+                    let ty = self.expr_type(&args[0]);
+                    let label = format!("[{}:{}:{}] {}: {}", self.file, t.line, t.col, self.source_text(args_start, self.pos - 1), ty);
+                    args.push(IRNode::List(vec![IRNode::atom("string_typed"), IRNode::Str(label)]));
+                }
+                if n == "panic" {
+                    // `panic(msg)` becomes `__panic(msg, "file:line:col")`, which never returns.
+                    n = "__panic".into();
+                    args.push(self.location(&t));
+                }
+                if n == "print_int" {
+                    // `print_int(n)` writes `n` in decimal to stdout through the runtime.
+                    n = "__print_int".into();
+                }
+                if let Some((width, write)) = mem_access(&n).filter(|_| self.sanitize && !args.is_empty()) {
+                    let addr = args.remove(0);
+                    args.insert(0, self.sanitized_access("san_check", vec![addr], width, write, format!("{} of {} byte(s) by `{}`", if write { "write" } else { "read" }, width, n), &t));
+                }
+                let mut call = vec![IRNode::atom("call"), IRNode::atom(n)];
+                call.extend(args);
+                return Ok(IRNode::List(call));
+            }
+            if self.peek(0).value == "." {
+                // `a.b.c` is `(field a b c)`.
+                let mut node = vec![IRNode::atom("field"), IRNode::atom(n)];
+                while self.peek(0).value == "." {
+                    self.consume(None, Some("."))?;
+                    node.push(IRNode::atom(self.consume(Some(TokenKind::Ident), None)?.value));
+                }
+                return Ok(IRNode::List(node));
+            }
+            if self.peek(0).value == "[" {
+                self.consume(None, Some("["))?;
+                let idx = self.parse_expr()?;
+                self.consume(None, Some("]"))?;
+                if self.statics.contains(&n) && !self.locals.contains_key(&n) {
+                    let bounds = self.bounds_panic(format!("index out of bounds of static `{}`", n), &t);
+                    return Ok(IRNode::List(vec![IRNode::atom("static_index"), IRNode::atom(n), idx, bounds]));
+                }
+                if self.is_slice(&n) && self.expr_type(&idx) == layout::RANGE_TYPE {
+                    let bounds = self.bounds_panic(format!("range out of bounds of slice `{}`", n), &t);
+                    return Ok(IRNode::List(vec![IRNode::atom("slice_range"), IRNode::atom(n), idx, bounds]));
+                }
+                if self.is_slice(&n) {
+                    let bounds = self.bounds_panic(format!("index out of bounds of slice `{}`", n), &t);
+                    let idx = self.sanitized_element(&n, idx, false, &t);
+                    return Ok(IRNode::List(vec![IRNode::atom("slice_index"), IRNode::atom(n), idx, bounds]));
+                }
+                return Ok(IRNode::List(vec![IRNode::atom("array_index"), IRNode::atom(n), idx]));
+            }
+            IRNode::List(vec![IRNode::atom("ident"), IRNode::atom(n)])
+        } else if t.kind == TokenKind::Eof { return Err(format!("Unexpected end of input at {}:{}", t.line, t.col)) }
+        else { return Err(format!("Unexpected token {} at {}:{}", t.value, t.line, t.col)) })
+    }
+}
+
+/// Wall-clock durations collected for `--timings`. Phases are accumulated by name so
+/// that per-file work (lexing and parsing every imported module) sums into one entry.
+#[derive(Default)]
+pub struct Timings {
+    pub enabled: bool,
+    pub verbose: bool,
+    phases: Vec<(&'static str, Duration)>,
+    pub functions: Vec<(String, Duration)>,
+    /// Warnings the front end printed, counted for `--summary=json`.
+    pub warnings: usize,
+}
+
+impl Timings {
+    pub fn record(&mut self, phase: &'static str, d: Duration) {
+        if let Some(p) = self.phases.iter_mut().find(|p| p.0 == phase) { p.1 += d; } else { self.phases.push((phase, d)); }
+    }
+    pub fn report(&self) {
+        if !self.enabled { return; }
+        let ms = |d: &Duration| d.as_secs_f64() * 1000.0;
+        eprintln!("timings:");
+        for (phase, d) in &self.phases { eprintln!("  {:<12} {:>10.3}ms", phase, ms(d)); }
+        eprintln!("  {:<12} {:>10.3}ms", "total", ms(&self.phases.iter().map(|p| p.1).sum()));
+        if self.verbose && !self.functions.is_empty() {
+            eprintln!("codegen per function:");
+            let mut fns = self.functions.clone();
+            fns.sort_by_key(|f| std::cmp::Reverse(f.1));
+            for (name, d) in &fns { eprintln!("  {:<24} {:>10.3}ms", name, ms(d)); }
+        }
+    }
+}
+
+/// Items gathered from the root file and every module it imports.
+#[derive(Default)]
+struct Program {
+    structs: Vec<IRNode>,
+    fns: Vec<IRNode>,
+    externs: Vec<IRNode>,
+    /// `type Name = T` declarations, visible in every module.
+    aliases: Vec<(Symbol, Symbol)>,
+    /// `enum` declarations with their variants' values, visible in every module.
+    enums: Vec<(Symbol, Vec<(Symbol, i32)>)>,
+    /// `static` tables, emitted as a `(statics ...)` section when there are any.
+    statics: Vec<IRNode>,
+    /// Errors found while loading modules, such as an unusable dependency package.
+    errors: Vec<String>,
+}
+
+/// Replaces the type in `(param n T)`, `(let n T e)`, `(ret T)` and the struct name of
+/// `(struct_lit Name ...)` and `(field_type Name field)` everywhere under `n` with `f(T)`.
+fn rename_types(n: &mut IRNode, f: &impl Fn(&str) -> Symbol) {
+    if let IRNode::List(l) = n {
+        let at = match l.first().and_then(|h| h.as_atom()).map(|h| h.as_str()) {
+            Some("param") | Some("let") => Some(2),
+            Some("ret") | Some("struct_lit") | Some("struct_init") | Some("field_type") => Some(1),
+            _ => None,
+        };
+        if let Some(i) = at && let Some(ty) = l.get(i).and_then(|t| t.as_atom()) {
+            l[i] = IRNode::atom(f(ty));
+        }
+        for child in l.iter_mut() { rename_types(child, f); }
+    }
+}
+
+/// Resolves `ty` through `aliases`, including the element types of `*T` and `[T N]`.
+fn resolve_type(ty: &str, aliases: &HashMap<Symbol, Symbol>) -> Symbol {
+    if let Some(inner) = ty.strip_prefix('*') { return format!("*{}", resolve_type(inner, aliases)).into(); }
+    if let Some((elem, len)) = ty.strip_prefix('[').and_then(|t| t.strip_suffix(']')).and_then(|t| t.rsplit_once(' ')) {
+        return format!("[{} {}]", resolve_type(elem, aliases), len).into();
+    }
+    aliases.get(ty).copied().unwrap_or_else(|| ty.into())
+}
+
+/// Rewrites the callee of every `(call name ...)` under `n` for which `f` returns a new name.
+pub(crate) fn rename_calls(n: &mut IRNode, f: &mut impl FnMut(&str) -> Option<Symbol>) {
+    if let IRNode::List(l) = n {
+        if l.len() > 1 && l[0].as_atom().map(|h| h == "call").unwrap_or(false)
+            && let Some(new) = l[1].as_atom().and_then(|c| f(c)) {
+            l[1] = IRNode::atom(new);
+        }
+        for child in l.iter_mut() { rename_calls(child, f); }
+    }
+}
+
+/// Prepends `prefix` to the symbol of every global program function (`--export-prefix`):
+/// `pub fn`s, `main` and the `entry` function, except those named by `#[export_name]` or
+/// `#[no_mangle]`. Calls are renamed to match; returns the symbol `entry` ends up with.
+pub fn prefix_exports(ir: &mut IRNode, prefix: &str, entry: &str) -> String {
+    let mut renames: HashMap<Symbol, Symbol> = HashMap::new();
+    let IRNode::List(root) = ir else { return entry.to_string() };
+    for section in root.iter_mut() {
+        let IRNode::List(fns) = section else { continue };
+        if fns.first().and_then(|h| h.as_atom()).is_none_or(|h| h != "functions") { continue; }
+        for f in fns.iter_mut().skip(1) {
+            let IRNode::List(l) = f else { continue };
+            if !fn_is_public(l, entry) || item_attr(l, "export_name").is_some() || item_attr(l, "no_mangle").is_some() { continue; }
+            let Some(name) = l.get(1).and_then(|n| n.as_atom()).cloned() else { continue };
+            let symbol: Symbol = format!("{}{}", prefix, name).into();
+            l[1] = IRNode::atom(symbol);
+            renames.insert(name, symbol);
+        }
+        for f in fns.iter_mut() { rename_calls(f, &mut |c| renames.get(c).cloned()); }
+    }
+    renames.get(entry).map(|s| s.to_string()).unwrap_or_else(|| entry.to_string())
+}
+
+/// Whether `name` is a function of the runtime or prelude compiled into `coatl`.
+pub(crate) fn is_bundled_symbol(name: &str) -> bool {
+    mangle::demangle(name).map(|(module, _)| module == RUNTIME_MODULE || module == PRELUDE_MODULE).unwrap_or(false)
+}
+
+impl Program {
+    /// Resolves calls left unqualified by their module: a root-file function or extern wins,
+    /// otherwise the call binds to the single imported module defining that name.
+    fn resolve_calls(&mut self) -> Result<(), Vec<String>> {
+        let names: Vec<Symbol> = self.fns.iter().chain(&self.externs).filter_map(|f| f.as_list()?.get(1)?.as_atom().cloned()).collect();
+        let mut errors = Vec::new();
+        for f in self.fns.iter_mut().chain(&mut self.statics) {
+            rename_calls(f, &mut |c| {
+                if c.starts_with("__") || names.iter().any(|n| n == c) { return None; }
+                let mut found: Vec<&Symbol> = names.iter().filter(|n| mangle::demangle(n).map(|(_, name)| name == c).unwrap_or(false)).collect();
+                // Overloads share a name, so one module may define it more than once.
+                found.sort();
+                found.dedup();
+                // A module of the program wins over the bundled runtime and prelude.
+                if found.len() > 1 { found.retain(|n| !is_bundled_symbol(n)); }
+                match found.as_slice() {
+                    [one] => Some(**one),
+                    [] => None,
+                    many => {
+                        let list: Vec<String> = many.iter().map(|m| mangle::display(m)).collect();
+                        errors.push(format!("call to `{}` is ambiguous between {}; qualify it as module::{}", c, list.join(", "), c));
+                        None
+                    }
+                }
+            });
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Replaces every `Enum::Variant`, as a value or a match arm, with the variant's value.
+    fn resolve_enums(&mut self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let mut enums: HashMap<Symbol, &[(Symbol, i32)]> = HashMap::new();
+        for (name, variants) in &self.enums {
+            if enums.insert(*name, variants).is_some_and(|v| v != variants.as_slice()) {
+                errors.push(format!("enum `{}` is declared twice with different variants", name));
+            }
+        }
+        fn rewrite(n: &mut IRNode, enums: &HashMap<Symbol, &[(Symbol, i32)]>, errors: &mut Vec<String>) {
+            let IRNode::List(l) = n else { return };
+            for child in l.iter_mut() { rewrite(child, enums, errors); }
+            let Some(head) = l.first().and_then(|h| h.as_atom()).copied().filter(|h| h == "ident" || h == "arm") else { return };
+            let Some((e, variant)) = l.get(1).and_then(|v| v.as_atom()).and_then(|v| v.split_once("::")) else { return };
+            let Some(variants) = enums.get(e) else { return };
+            let Some(&(_, value)) = variants.iter().find(|(v, _)| v == variant) else {
+                let message = format!("enum `{}` has no variant `{}`", e, variant);
+                errors.push(typecheck::Diagnostic::unknown(message, variant, variants.iter().map(|(v, _)| v.as_str())).text());
+                return;
+            };
+            if head == "ident" {
+                *n = IRNode::List(vec![IRNode::atom("int"), IRNode::atom(value.to_string())]);
+            } else {
+                l[1] = IRNode::atom(value.to_string());
+            }
+        }
+        for f in &mut self.fns { rewrite(f, &enums, &mut errors); }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Replaces every type alias with the type it names, so the IR only holds real types.
+    fn resolve_aliases(&mut self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let struct_names: Vec<Symbol> = self.structs.iter().filter_map(|s| s.as_list()?.get(1)?.as_atom().cloned()).collect();
+        let mut aliases: HashMap<Symbol, Symbol> = HashMap::new();
+        for (name, ty) in &self.aliases {
+            if struct_names.contains(name) {
+                errors.push(format!("type alias `{}` conflicts with the struct of the same name", name));
+            } else if aliases.get(name).is_some_and(|t| t != ty) {
+                errors.push(format!("type alias `{}` is declared twice with different types", name));
+            }
+            aliases.insert(*name, *ty);
+        }
+        // Follow alias chains (`type A = B`, `type B = i32`) to the underlying type.
+        let mut resolved: HashMap<Symbol, Symbol> = HashMap::new();
+        let mut names: Vec<&Symbol> = aliases.keys().collect();
+        names.sort();
+        for name in names {
+            let mut seen = vec![*name];
+            let mut ty = resolve_type(&aliases[name], &HashMap::new());
+            loop {
+                let next = resolve_type(&ty, &aliases);
+                if next == ty { break; }
+                if seen.contains(&next) || seen.len() > aliases.len() {
+                    errors.push(format!("type alias `{}` refers to itself", name));
+                    break;
+                }
+                seen.push(next);
+                ty = next;
+            }
+            resolved.insert(*name, ty);
+        }
+        if !errors.is_empty() { return Err(errors); }
+        let f = |ty: &str| resolve_type(ty, &resolved);
+        for item in self.fns.iter_mut().chain(&mut self.externs) { rename_types(item, &f); }
+        for s in &mut self.structs {
+            if let IRNode::List(l) = s {
+                for field in l.iter_mut().skip(2) {
+                    if let IRNode::List(fl) = field && let Some(ty) = fl.get(2).and_then(|t| t.as_atom()) {
+                        fl[2] = IRNode::atom(f(ty));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites every `(struct_init Name (init field e)...)` into the positional
+    /// `(struct_lit Name e...)`, in declaration order, with omitted fields taking their
+    /// declared defaults.
+    fn resolve_struct_inits(&mut self) -> Result<(), Vec<String>> {
+        let mut decls: HashMap<Symbol, Vec<(Symbol, Option<IRNode>)>> = HashMap::new();
+        for s in self.structs.iter().filter_map(|s| s.as_list()) {
+            let Some(name) = s.get(1).and_then(|n| n.as_atom()) else { continue };
+            let fields = s[2..].iter().filter_map(|f| f.as_list()).filter(|f| f[0].as_atom().is_some_and(|h| h == "field")).filter_map(|f| Some((*f.get(1)?.as_atom()?, f.get(3).cloned()))).collect();
+            decls.insert(*name, fields);
+        }
+        for (ty, fields) in layout::builtin_structs() { decls.insert(ty, fields.into_iter().map(|(f, _)| (f, None)).collect()); }
+        fn rewrite(n: &mut IRNode, decls: &HashMap<Symbol, Vec<(Symbol, Option<IRNode>)>>, errors: &mut Vec<String>) {
+            let IRNode::List(l) = n else { return };
+            for child in l.iter_mut() { rewrite(child, decls, errors); }
+            if l.first().and_then(|h| h.as_atom()).map(|h| h != "struct_init").unwrap_or(true) { return; }
+            let name = l[1].as_atom().cloned().unwrap_or_default();
+            let Some(fields) = decls.get(&name) else {
+                errors.push(typecheck::Diagnostic::unknown(format!("struct literal of unknown struct `{}`", name), &name, decls.keys().map(|k| k.as_str())).text());
+                return;
+            };
+            let mut given: Vec<(Symbol, IRNode)> = Vec::new();
+            for init in l[2..].iter().filter_map(|i| i.as_list()) {
+                let field = init[1].as_atom().cloned().unwrap_or_default();
+                if !fields.iter().any(|f| f.0 == field) {
+                    errors.push(format!("struct `{}` has no field `{}`", name, field));
+                } else if given.iter().any(|g| g.0 == field) {
+                    errors.push(format!("field `{}` of struct `{}` is given twice", field, name));
+                } else {
+                    given.push((field, init[2].clone()));
+                }
+            }
+            let mut lit = vec![IRNode::atom("struct_lit"), IRNode::atom(name)];
+            for (field, default) in fields {
+                match given.iter().find(|g| &g.0 == field).map(|g| g.1.clone()).or_else(|| default.clone()) {
+                    Some(value) => lit.push(value),
+                    None => errors.push(format!("struct literal `{}` is missing field `{}`", name, field)),
+                }
+            }
+            *n = IRNode::List(lit);
+        }
+        let mut errors = Vec::new();
+        for f in &mut self.fns { rewrite(f, &decls, &mut errors); }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Gives each local of a destructuring `let`, typed `(field_type Struct field)` by the
+    /// parser, the declared type of that field, once every struct of the program is known.
+    fn resolve_destructures(&mut self) -> Result<(), Vec<String>> {
+        let mut decls: HashMap<Symbol, Vec<(Symbol, Symbol)>> = layout::builtin_structs().into_iter().collect();
+        for s in self.structs.iter().filter_map(|s| s.as_list()) {
+            let Some(name) = s.get(1).and_then(|n| n.as_atom()) else { continue };
+            let fields = s[2..].iter().filter_map(|f| f.as_list()).filter(|f| f[0].as_atom().is_some_and(|h| h == "field")).filter_map(|f| Some((*f.get(1)?.as_atom()?, *f.get(2)?.as_atom()?))).collect();
+            decls.insert(*name, fields);
+        }
+        fn rewrite(n: &mut IRNode, decls: &HashMap<Symbol, Vec<(Symbol, Symbol)>>, errors: &mut Vec<String>) {
+            let IRNode::List(l) = n else { return };
+            for child in l.iter_mut() { rewrite(child, decls, errors); }
+            if l.first().and_then(|h| h.as_atom()).map(|h| h != "let").unwrap_or(true) { return; }
+            let Some(placeholder) = l.get(2).and_then(|t| t.as_list()).filter(|t| t[0].as_atom().is_some_and(|h| h == "field_type")) else { return };
+            let (name, field) = (placeholder[1].as_atom().cloned().unwrap_or_default(), placeholder[2].as_atom().cloned().unwrap_or_default());
+            let Some(fields) = decls.get(&name) else {
+                errors.push(typecheck::Diagnostic::unknown(format!("destructuring `let` of unknown struct `{}`", name), &name, decls.keys().map(|k| k.as_str())).text());
+                return;
+            };
+            match fields.iter().find(|f| f.0 == field) {
+                Some(&(_, ty)) => l[2] = IRNode::atom(ty),
+                None => errors.push(format!("struct `{}` has no field `{}`", name, field)),
+            }
+        }
+        let mut errors = Vec::new();
+        for f in &mut self.fns { rewrite(f, &decls, &mut errors); }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Puts the `(named param e)` arguments of every call in the callee's parameter order,
+    /// after the positional ones. Runs once calls are resolved to their callees.
+    fn resolve_named_args(&mut self) -> Result<(), Vec<String>> {
+        let mut params: HashMap<Symbol, Vec<Symbol>> = HashMap::new();
+        let mut overloaded: HashSet<Symbol> = HashSet::new();
+        for (f, pi) in self.fns.iter().map(|f| (f, 2)).chain(self.externs.iter().map(|e| (e, 3))) {
+            let Some(l) = f.as_list() else { continue };
+            let Some(name) = l.get(1).and_then(|n| n.as_atom()) else { continue };
+            let names = l.get(pi).and_then(|p| p.as_list()).map(|p| p[1..].iter().filter_map(|p| p.as_list()?.get(1)?.as_atom().cloned()).collect()).unwrap_or_default();
+            if params.insert(*name, names).is_some() { overloaded.insert(*name); }
+        }
+        fn rewrite(n: &mut IRNode, params: &HashMap<Symbol, Vec<Symbol>>, overloaded: &HashSet<Symbol>, errors: &mut Vec<String>) {
+            let IRNode::List(l) = n else { return };
+            for child in l.iter_mut() { rewrite(child, params, overloaded, errors); }
+            let is_named = |a: &IRNode| a.as_list().and_then(|a| a.first()).and_then(|h| h.as_atom()).map(|h| h == "named").unwrap_or(false);
+            if l.first().and_then(|h| h.as_atom()).map(|h| h != "call").unwrap_or(true) || !l[2..].iter().any(is_named) { return; }
+            let callee = l[1].as_atom().cloned().unwrap_or_default();
+            if overloaded.contains(&callee) {
+                errors.push(format!("named arguments cannot be used to call overloaded fn `{}`", mangle::display(&callee)));
+                return;
+            }
+            let names: Vec<Symbol> = match params.get(&callee) {
+                Some(names) => names.clone(),
+                None => match typecheck::lookup_intrinsic(&callee) {
+                    Some(intr) => intr.params.iter().map(|&p| p.into()).collect(),
+                    None => {
+                        errors.push(format!("named arguments in call to unknown function `{}`", mangle::display(&callee)));
+                        return;
+                    }
+                },
+            };
+            let shown = mangle::display(&callee);
+            let positional = l[2..].iter().take_while(|a| !is_named(a)).count();
+            let mut slots: Vec<Option<IRNode>> = vec![None; names.len().max(positional)];
+            for (i, a) in l[2..2 + positional].iter().enumerate() { slots[i] = Some(a.clone()); }
+            for a in &l[2 + positional..] {
+                let Some([_, IRNode::Atom(param), value]) = a.as_list().map(|a| a.as_slice()).filter(|_| is_named(a)) else {
+                    errors.push(format!("positional argument after named arguments in call to `{}`", shown));
+                    continue;
+                };
+                match names.iter().position(|p| p == param) {
+                    None => errors.push(format!("`{}` has no parameter named `{}`", shown, param)),
+                    Some(i) if slots[i].is_some() => errors.push(format!("argument `{}` of `{}` is given twice", param, shown)),
+                    Some(i) => slots[i] = Some(value.clone()),
+                }
+            }
+            let mut call = vec![l[0].clone(), l[1].clone()];
+            for (i, slot) in slots.into_iter().enumerate() {
+                match slot {
+                    Some(a) => call.push(a),
+                    None => errors.push(format!("call to `{}` is missing argument `{}`", shown, names[i])),
+                }
+            }
+            *n = IRNode::List(call);
+        }
+        let mut errors = Vec::new();
+        for f in &mut self.fns { rewrite(f, &params, &overloaded, &mut errors); }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Drops the runtime and prelude functions that the program's own functions never reach.
+    fn strip_unused_bundled(&mut self) {
+        fn calls(n: &IRNode, out: &mut Vec<Symbol>) {
+            if let IRNode::List(l) = n {
+                if l.len() > 1 && l[0].as_atom().map(|h| h == "call").unwrap_or(false) && let Some(name) = l[1].as_atom() {
+                    out.push(*name);
+                }
+                for child in l { calls(child, out); }
+            }
+        }
+        let name_of = |f: &IRNode| f.as_list().and_then(|l| l.get(1)).and_then(|n| n.as_atom()).cloned().unwrap_or_default();
+        let mut live: HashSet<Symbol> = HashSet::new();
+        let mut pending: Vec<&IRNode> = self.fns.iter().filter(|f| !is_bundled_symbol(&name_of(f))).collect();
+        while let Some(f) = pending.pop() {
+            let mut called = Vec::new();
+            calls(f, &mut called);
+            for c in called {
+                if is_bundled_symbol(&c) && live.insert(c) && let Some(g) = self.fns.iter().find(|g| name_of(g) == c) {
+                    pending.push(g);
+                }
+            }
+        }
+        self.fns.retain(|f| !is_bundled_symbol(&name_of(f)) || live.contains(&name_of(f)));
+    }
+
+    fn section(name: &str, items: Vec<IRNode>) -> IRNode {
+        IRNode::List(vec![IRNode::atom(name.to_string())].into_iter().chain(items).collect())
+    }
+    fn into_ir(self) -> IRNode {
+        let mut root = vec![
+            IRNode::atom("coatl_ir"),
+            IRNode::atom("v1"),
+            IRNode::List(vec![IRNode::atom("imports")]), // Simplification: imports already resolved
+            Self::section("externs", self.externs),
+            Self::section("structs", self.structs),
+            Self::section("functions", self.fns),
+        ];
+        if !self.statics.is_empty() { root.push(Self::section("statics", self.statics)); }
+        IRNode::List(root)
+    }
+}
+
+/// Returns the items of the root-level `(name ...)` section of a program.
+pub fn ir_section<'a>(ir: &'a IRNode, name: &str) -> &'a [IRNode] {
+    if let IRNode::List(root) = ir {
+        for child in root {
+            if let IRNode::List(c) = child && c.first().and_then(|h| h.as_atom()).map(|s| s == name).unwrap_or(false) {
+                return &c[1..];
+            }
+        }
+    }
+    &[]
+}
+
+/// Parses `filepath` and its imports into `program`. `module` is `None` for the root file;
+/// functions of imported modules are mangled with the module name (see `coatl::mangle`).
+/// A dependency package: its root source file and its own `[dependencies]`.
+struct Package {
+    main: PathBuf,
+    deps: HashMap<String, PathBuf>,
+}
+
+/// Files already parsed, and each dependency package's manifest read once by directory.
+#[derive(Default)]
+struct Loader {
+    visited: HashSet<PathBuf>,
+    packages: HashMap<PathBuf, Package>,
+    /// Names of the dependency packages being loaded, outermost first, to report cycles.
+    stack: Vec<(String, PathBuf)>,
+}
+
+impl Loader {
+    /// Reads the package at `dir`, defaulting to `main.coatl` and no dependencies when
+    /// it has no manifest.
+    fn package(&mut self, dir: &Path) -> Result<&Package, String> {
+        if !self.packages.contains_key(dir) {
+            let path = dir.join(manifest::FILE_NAME);
+            let package = if path.is_file() {
+                let m = manifest::Manifest::load(&path).map_err(|e| format!("{}: {}", dir.display(), e))?;
+                Package { main: m.main_path(), deps: m.dependency_dirs() }
+            } else {
+                Package { main: dir.join("main.coatl"), deps: HashMap::new() }
+            };
+            self.packages.insert(dir.to_path_buf(), package);
+        }
+        Ok(&self.packages[dir])
+    }
+}
+
+fn parse_file_recursive(filepath: PathBuf, module: Option<&str>, deps: &HashMap<String, PathBuf>, loader: &mut Loader, program: &mut Program, timings: &mut Timings, opts: &ParseOptions) {
+    let filepath = match fs::canonicalize(&filepath) {
+        Ok(p) => p,
+        Err(e) => return program.errors.push(format!("cannot read {}: {}", filepath.display(), e)),
+    };
+    if loader.visited.contains(&filepath) { return; }
+    loader.visited.insert(filepath.clone());
+    if opts.verbose { eprintln!("coatl: parsing {}", filepath.display()); }
+    let source = fs::read_to_string(&filepath).expect("Failed to read file");
+    let display_path = env::current_dir().ok().and_then(|d| filepath.strip_prefix(d).ok().map(|p| p.to_path_buf())).unwrap_or_else(|| filepath.clone());
+    let file = SourceFile { text: source, name: display_path.display().to_string(), dir: filepath.parent().unwrap().to_path_buf() };
+    parse_text_recursive(file, module, deps, loader, program, timings, opts);
+}
+
+/// Source text to parse, with the name locations show for it and the directory its
+/// relative imports are found in.
+struct SourceFile {
+    text: String,
+    name: String,
+    dir: PathBuf,
+}
+
+/// Parses `file` and then every module it imports.
+fn parse_text_recursive(file: SourceFile, module: Option<&str>, deps: &HashMap<String, PathBuf>, loader: &mut Loader, program: &mut Program, timings: &mut Timings, opts: &ParseOptions) {
+    let dir = file.dir;
+    let imports = parse_module(file.text, file.name, module, program, timings, opts);
+    for imp in imports {
+        let stem = PathBuf::from(&imp).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or(imp.clone());
+        // `import "pkg"` is the root file of dependency `pkg`, as module `pkg`, and
+        // `import "pkg/a/b"` is `a/b.coatl` inside it. Other imports are relative paths.
+        let (head, rest) = imp.split_once('/').unwrap_or((&imp, ""));
+        let Some(dep_dir) = deps.get(head) else {
+            let mut imp_path = dir.to_path_buf();
+            imp_path.push(format!("{}.coatl", imp));
+            parse_file_recursive(imp_path, Some(&stem), deps, loader, program, timings, opts);
+            continue;
+        };
+        let dep_dir = match fs::canonicalize(dep_dir) {
+            Ok(d) => d,
+            Err(e) => { program.errors.push(format!("dependency `{}` at {}: {}", head, dep_dir.display(), e)); continue; }
+        };
+        if let Some(i) = loader.stack.iter().position(|(_, d)| *d == dep_dir) {
+            let cycle: Vec<&str> = loader.stack[i..].iter().map(|(n, _)| n.as_str()).chain([head]).collect();
+            program.errors.push(format!("dependency cycle: {}", cycle.join(" -> ")));
+            continue;
+        }
+        let (main, pkg_deps) = match loader.package(&dep_dir) {
+            Ok(p) => (p.main.clone(), p.deps.clone()),
+            Err(e) => { program.errors.push(e); continue; }
+        };
+        let (path, module) = if rest.is_empty() { (main, head.to_string()) } else { (dep_dir.join(format!("{}.coatl", rest)), stem) };
+        loader.stack.push((head.to_string(), dep_dir));
+        parse_file_recursive(path, Some(&module), &pkg_deps, loader, program, timings, opts);
+        loader.stack.pop();
+    }
+}
+
+/// Renames the static in each `(static_index NAME ...)` under `node` to `module__NAME`.
+fn mangle_static_reads(node: &mut IRNode, module: &str) {
+    let IRNode::List(l) = node else { return };
+    if l.first().and_then(|h| h.as_atom()).map(|h| h == "static_index").unwrap_or(false) && let Some(name) = l[1].as_atom().cloned() {
+        l[1] = IRNode::atom(mangle::mangle(module, &name));
+    }
+    for child in l.iter_mut() { mangle_static_reads(child, module); }
+}
+
+/// Attributes kept on functions and structs as `(attr name ...)`; `cfg` is evaluated by
+/// the parser instead.
+const ATTRIBUTES: &[&str] = &["cfg", "test", "export", "inline", "repr", "export_name", "no_mangle"];
+
+/// Width in bytes of the `__mem_*` intrinsic `name`, and whether it writes.
+fn mem_access(name: &str) -> Option<(i32, bool)> {
+    match name {
+        "__mem_load" => Some((4, false)),
+        "__mem_load8" => Some((1, false)),
+        "__mem_store" => Some((4, true)),
+        "__mem_store8" => Some((1, true)),
+        _ => None,
+    }
+}
+
+/// The `///` lines directly above `line`, joined with newlines.
+fn doc_comment(docs: &HashMap<usize, String>, line: usize) -> Option<String> {
+    let first = (1..line).rev().take_while(|l| docs.contains_key(l)).last()?;
+    Some((first..line).map(|l| docs[&l].as_str()).collect::<Vec<_>>().join("\n"))
+}
+
+/// Appends the `(attr ...)` nodes and any `(doc "text")` to a `fn` or `struct` node.
+fn with_attrs(node: IRNode, attrs: Vec<IRNode>, doc: Option<String>) -> IRNode {
+    match node {
+        IRNode::List(mut l) => {
+            l.extend(attrs);
+            l.extend(doc.map(|doc| IRNode::List(vec![IRNode::atom("doc"), IRNode::Str(doc)])));
+            IRNode::List(l)
+        }
+        node => node,
+    }
+}
+
+/// Parses one source file into `program` and returns the paths it imports.
+fn parse_module(source: String, file: String, module: Option<&str>, program: &mut Program, timings: &mut Timings, opts: &ParseOptions) -> Vec<String> {
+    let start = Instant::now();
+    let lines = opts.listing.then(|| source.lines().map(|l| l.to_string()).collect());
+    let mut lexer = Lexer::new(source);
+    let tokens = match lexer.tokenize() {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            program.errors.push(format!("{}: {}", file, e));
+            return Vec::new();
+        }
+    };
+    timings.record("lex", start.elapsed());
+    let start = Instant::now();
+    let mut parser = Parser::new(tokens, file, opts.target.clone());
+    parser.listing = lines;
+    // The bundled code is trusted: its scratch and argv accesses are outside the user region.
+    parser.sanitize = opts.sanitize && module.is_none_or(|m| m != RUNTIME_MODULE && m != PRELUDE_MODULE);
+    
+    let mut imports = Vec::new();
+    let mut structs = Vec::new();
+    let mut fns = Vec::new();
+    let mut externs = Vec::new();
+    let mut macro_defs = Vec::new();
+    let mut statics = Vec::new();
+    
+    // A syntax error ends the file: it is reported, and nothing of the file is kept.
+    let mut items = || -> Result<(), String> {
+        while parser.peek(0).kind != TokenKind::Eof {
+            // Doc comments go above an item's attributes.
+            let doc = doc_comment(&lexer.docs, parser.peek(0).line);
+            let (enabled, attrs) = parser.parse_attrs()?;
+            let counts = (imports.len(), structs.len(), fns.len(), externs.len(), program.aliases.len(), macro_defs.len(), statics.len(), program.enums.len());
+            let declared = (!enabled).then(|| (parser.struct_fields.clone(), parser.aliases.clone(), parser.statics.clone()));
+            let t = parser.peek(0).clone();
+            let is_fn = t.value == "fn" || t.value == "pub" || (t.value == "const" && parser.peek(1).value == "fn");
+            if !is_fn && t.value != "struct" { parser.ignore_attrs(&attrs, &t); }
+            if t.value == "import" {
+                parser.consume(None, None)?;
+                imports.push(parser.consume(Some(TokenKind::Str), None)?.text);
+            } else if t.value == "struct" { structs.push(with_attrs(parser.parse_struct()?, attrs, doc)); }
+            else if is_fn { fns.push(with_attrs(parser.parse_fn()?, attrs, doc)); }
+            else if t.value == "extern" { externs.push(parser.parse_extern()?); }
+            else if t.value == "macro" && parser.peek(1).kind == TokenKind::Ident { macro_defs.push(parser.parse_macro()?); }
+            else if t.value == "static" && parser.peek(2).value == ":" { statics.push(parser.parse_static()?); }
+            else if t.value == "type" && parser.peek(2).value == "=" { program.aliases.push(parser.parse_type_alias()?); }
+            else if t.value == "enum" && parser.peek(2).value == "{" {
+                let (name, variants) = parser.parse_enum()?;
+                program.aliases.push((name, "i32".into()));
+                program.enums.push((name, variants));
+            }
+            else { parser.pos += 1; }
+            // Items configured out for this target are parsed, then dropped.
+            if let Some((struct_fields, aliases, declared_statics)) = declared {
+                imports.truncate(counts.0);
+                structs.truncate(counts.1);
+                fns.truncate(counts.2);
+                externs.truncate(counts.3);
+                program.aliases.truncate(counts.4);
+                macro_defs.truncate(counts.5);
+                statics.truncate(counts.6);
+                program.enums.truncate(counts.7);
+                parser.struct_fields = struct_fields;
+                parser.aliases = aliases;
+                parser.statics = declared_statics;
+            }
+        }
+        Ok(())
+    };
+    if let Err(e) = items() {
+        program.errors.push(format!("{}: {}", parser.file, e));
+        return Vec::new();
+    }
+    for w in &parser.warnings { eprintln!("warning: {}", w); }
+    timings.warnings += parser.warnings.len();
+    let mut macro_table = HashMap::new();
+    for (name, at, m) in macro_defs {
+        if macro_table.insert(name, m).is_some() { program.errors.push(format!("{}: macro `{}` is defined twice", at, name)); }
+    }
+    let mut expansions = 0;
+    for f in &mut fns { macros::expand(f, &macro_table, &mut expansions, &mut program.errors); }
+    // `module::f` names a function in another module; unqualified calls to this file's
+    // functions bind here, anything else is left for `Program::resolve_calls`.
+    let local: Vec<Symbol> = fns.iter().filter_map(|f| f.as_list()?.get(1)?.as_atom().cloned()).collect();
+    let mut bind = |c: &str| match c.split_once("::") {
+        Some((m, name)) => Some(mangle::mangle(m, name).into()),
+        None => match module {
+            Some(m) if local.iter().any(|n| n == c) => Some(mangle::mangle(m, c).into()),
+            _ => None,
+        },
+    };
+    // Static initializers may call const fns too.
+    for st in &mut statics { rename_calls(st, &mut bind); }
+    for f in &mut fns {
+        rename_calls(f, &mut bind);
+        if let Some(m) = module && let IRNode::List(l) = f && let Some(name) = l[1].as_atom().cloned() {
+            l[1] = IRNode::atom(mangle::mangle(m, &name));
+        }
+        if let Some(m) = module { mangle_static_reads(f, m); }
+    }
+    // Statics are private to their file; an imported module's are named like its functions.
+    if let Some(m) = module {
+        for st in &mut statics {
+            if let IRNode::List(l) = st && let Some(name) = l[1].as_atom().cloned() { l[1] = IRNode::atom(mangle::mangle(m, &name)); }
+        }
+    }
+    timings.record("parse", start.elapsed());
+    if opts.verbose {
+        eprintln!("coatl:   {} function(s), {} extern(s), {} struct(s), {} import(s)", fns.len(), externs.len(), structs.len(), imports.len());
+    }
+    
+    program.structs.extend(structs);
+    program.fns.extend(fns);
+    program.externs.extend(externs);
+    program.statics.extend(statics);
+    imports
+}
+
+use intrinsics::{INTRINSICS_X86_64, INTRINSICS_X86_64_LIBC, INTRINSICS_AARCH64, PROFILE_X86_64, PROFILE_AARCH64, BACKTRACE_X86_64, BACKTRACE_AARCH64, PANIC_HOOK_X86_64, PANIC_HOOK_AARCH64, SHARED_X86_64, SHARED_AARCH64};
+
+/// Size of the `__coatl_mem` pool that backs every `__mem_*` address.
+const MEM_SIZE: i32 = 1048576;
+/// Pool offset where string literals are laid out by `__coatl_init_memory`.
+const STRING_POOL_BASE: i32 = 65536;
+/// Pool offset where `__init_args` copies the NUL-terminated argv strings.
+const ARGV_BASE: i32 = 900000;
+/// Pool offset of the 16 bytes where the runtime's `write_bytes` and `read_bytes` build
+/// their I/O requests: an iovec, then the count transferred, then a returned fd.
+const RUNTIME_REQUEST: i32 = ARGV_BASE - 16;
+/// Bytes of standard input `read_line` reads at a time.
+const LINE_BUF_SIZE: i32 = 4096;
+/// Pool offset of the runtime's I/O area: `read_line`'s read position and end, the
+/// [`LINE_BUF_SIZE`] bytes it buffers standard input in, then the request at
+/// [`RUNTIME_REQUEST`].
+const RUNTIME_SCRATCH: i32 = RUNTIME_REQUEST - 8 - LINE_BUF_SIZE;
+/// Comment line separating the lowered program from the bundled runtime in emitted assembly.
+pub const RUNTIME_MARKER: &str = "/* coatl runtime */";
+
+/// Minimum number of `match` arms lowered through a jump table rather than compares.
+const JUMP_TABLE_MIN_ARMS: usize = 4;
+
+/// The arms of a `(match x (arm v block)... (default block))` statement.
+struct MatchArms<'a> {
+    arms: Vec<(i32, &'a IRNode)>,
+    default: Option<&'a IRNode>,
+}
+
+impl<'a> MatchArms<'a> {
+    fn new(l: &'a [IRNode]) -> Self {
+        let mut m = MatchArms { arms: Vec::new(), default: None };
+        for arm in l[2..].iter().filter_map(|a| a.as_list()) {
+            match arm[0].as_atom().map(|s| s.as_str()) {
+                Some("arm") => m.arms.push((arm[1].as_atom().unwrap().parse().unwrap(), &arm[2])),
+                Some("default") => m.default = Some(&arm[1]),
+                _ => {}
+            }
+        }
+        m
+    }
+
+    /// The `(low, high)` range to index a jump table by, when the arms are dense: at least
+    /// `JUMP_TABLE_MIN_ARMS` of them, covering at least half of the values in the range.
+    fn table_range(&self) -> Option<(i32, i32)> {
+        let low = self.arms.iter().map(|a| a.0).min()?;
+        let high = self.arms.iter().map(|a| a.0).max()?;
+        let span = high as i64 - low as i64 + 1;
+        (self.arms.len() >= JUMP_TABLE_MIN_ARMS && span <= 2 * self.arms.len() as i64).then_some((low, high))
+    }
+
+    /// Jump-table entries for `low..=high`: the index of the arm taken, or `None` for the default.
+    fn table(&self, low: i32, high: i32) -> Vec<Option<usize>> {
+        (low..=high).map(|v| self.arms.iter().position(|a| a.0 == v)).collect()
+    }
+}
+
+/// The integer value of an `(int N)` literal.
+fn int_literal(n: &IRNode) -> Option<i64> {
+    match n.as_list().map(|l| l.as_slice()) {
+        Some([IRNode::Atom(head), IRNode::Atom(v)]) if head == "int" => v.parse::<i64>().ok(),
+        _ => None,
+    }
+}
+
+/// The shift `k` when `n` is the literal `2^k`, `k >= 1`, so the backends can multiply and
+/// divide by it with shifts instead of `imul`/`idiv` (`mul`/`sdiv`).
+fn pow2_shift(n: &IRNode) -> Option<u32> {
+    int_literal(n).filter(|v| *v > 1 && v.count_ones() == 1).map(|v| v.trailing_zeros())
+}
+
+/// Folds `binary` operations on two integer literals into a literal, innermost first, for
+/// `--profile=release`. Results that do not fit an `i32` are left to run time. The
+/// identities `x + 0`, `0 + x`, `x - 0`, `x * 1`, `1 * x` and `x / 1` fold to `x`.
+pub fn fold_constants(node: &mut IRNode) {
+    let IRNode::List(l) = node else { return };
+    for child in l.iter_mut() { fold_constants(child); }
+    if l.len() < 4 || l[0].as_atom().map(|h| h != "binary").unwrap_or(true) { return; }
+    let op = l[1].as_atom().map(|op| op.as_str());
+    let (Some(a), Some(b)) = (int_literal(&l[2]), int_literal(&l[3])) else {
+        let keep = match (op, int_literal(&l[2]), int_literal(&l[3])) {
+            (Some("add"), Some(0), _) | (Some("mul"), Some(1), _) => Some(3),
+            (Some("add" | "sub"), _, Some(0)) | (Some("mul" | "div"), _, Some(1)) => Some(2),
+            _ => None,
+        };
+        if let Some(i) = keep { *node = l.swap_remove(i); }
+        return;
+    };
+    let value = match op {
+        Some("add") => a.checked_add(b),
+        Some("sub") => a.checked_sub(b),
+        Some("mul") => a.checked_mul(b),
+        Some("div") if b != 0 => a.checked_div(b),
+        Some("and") => Some(a & b),
+        Some("or") => Some(a | b),
+        Some("eq") => Some((a == b) as i64),
+        Some("ne") => Some((a != b) as i64),
+        Some("lt") => Some((a < b) as i64),
+        Some("gt") => Some((a > b) as i64),
+        Some("le") => Some((a <= b) as i64),
+        Some("ge") => Some((a >= b) as i64),
+        _ => None,
+    };
+    if let Some(v) = value.filter(|v| i32::try_from(*v).is_ok()) {
+        *node = IRNode::List(vec![IRNode::atom("int"), IRNode::atom(v.to_string())]);
+    }
+}
+
+/// Collects the names of all `__`-prefixed intrinsics called anywhere under `node`.
+pub fn collect_intrinsic_calls(node: &IRNode, out: &mut Vec<Symbol>) {
+    if let IRNode::List(l) = node {
+        if l.len() > 1 && l[0].as_atom().map(|s| s == "call").unwrap_or(false)
+            && let Some(name) = l[1].as_atom()
+            && name.starts_with("__") && !out.contains(name) {
+            out.push(*name);
+        }
+        for child in l { collect_intrinsic_calls(child, out); }
+    }
+}
+
+/// Reports each function called under `ir` that it neither defines nor declares `extern`,
+/// which is left to the system linker, with the closest known function as a suggestion:
+/// the program's own, or any of the bundled runtime and prelude, by the name a call uses.
+pub fn unknown_calls(ir: &IRNode) -> Vec<typecheck::Diagnostic> {
+    let name_of = |f: &IRNode| f.as_list().and_then(|l| l.get(1)).and_then(|n| n.as_atom()).copied();
+    let known: Vec<Symbol> = ir_section(ir, "functions").iter().chain(ir_section(ir, "externs")).filter_map(name_of).collect();
+    let mut candidates: Vec<String> = known.iter().map(|n| mangle::demangle(n).map(|(_, name)| name.to_string()).unwrap_or_else(|| n.to_string())).collect();
+    for source in [RUNTIME_SOURCE, PRELUDE_SOURCE] {
+        let mut words = source.split(|c: char| !(c.is_alphanumeric() || c == '_'));
+        while let Some(w) = words.next() {
+            if w == "fn" && let Some(name) = words.find(|w| !w.is_empty()) { candidates.push(name.to_string()); }
+        }
+    }
+    fn calls(n: &IRNode, out: &mut Vec<Symbol>) {
+        let IRNode::List(l) = n else { return };
+        if l.len() > 1 && l[0].as_atom().is_some_and(|h| h == "call") && let Some(name) = l[1].as_atom() && !out.contains(name) {
+            out.push(*name);
+        }
+        for child in l { calls(child, out); }
+    }
+    let mut called = Vec::new();
+    calls(ir, &mut called);
+    called.iter().filter(|c| !c.starts_with("__") && !known.contains(c)).map(|c| {
+        let shown = mangle::display(c);
+        typecheck::Diagnostic::unknown(format!("unknown function `{}`", shown), &shown, candidates.iter().map(|n| n.as_str()))
+    }).collect()
+}
+
+/// Expands an `asm(...)` template: `{name}` placeholders become the memory operand of the
+/// named local, produced by `slot` from its frame offset. Returns one string per line.
+fn expand_asm_template(template: &str, operands: &[IRNode], vars: &HashMap<Symbol, (i32, Symbol)>, slot: impl Fn(i32) -> String) -> Vec<String> {
+    let mut text = template.to_string();
+    for op in operands {
+        if let Some(name) = op.as_atom() && let Some((off, _)) = vars.get(name) {
+            text = text.replace(&format!("{{{}}}", name), &slot(*off));
+        }
+    }
+    text.split(['\n', ';']).map(|l| l.trim()).filter(|l| !l.is_empty()).map(|l| format!("  {}", l)).collect()
+}
+
+/// Assigns every pooled string its offset in `strings` and returns the strings that must be
+/// written to the pool. A string that ends another pooled string reuses that string's tail,
+/// since both are read up to the same NUL terminator.
+fn layout_string_pool(strings: &mut HashMap<String, i32>) -> Vec<(i32, String)> {
+    let mut order: Vec<String> = strings.keys().cloned().collect();
+    order.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+    let mut written: Vec<(i32, String)> = Vec::new();
+    let mut off = STRING_POOL_BASE;
+    for s in order {
+        let at = match written.iter().find(|(_, w)| w.ends_with(s.as_str())) {
+            Some((base, w)) => base + (w.len() - s.len()) as i32,
+            None => {
+                written.push((off, s.clone()));
+                off += s.len() as i32 + 1;
+                off - s.len() as i32 - 1
+            }
+        };
+        strings.insert(s, at);
+    }
+    written
+}
+
+/// End of the string pool: one past the NUL of the last string laid out.
+fn string_pool_end(strings: &HashMap<String, i32>) -> i32 {
+    strings.iter().map(|(s, off)| off + s.len() as i32 + 1).max().unwrap_or(STRING_POOL_BASE)
+}
+
+/// Prints the memory layout chosen for a lowered program (`--verbose`).
+pub fn log_layout(strings: &HashMap<String, i32>) {
+    let pool_end = string_pool_end(strings);
+    eprintln!("coatl: memory layout: __coatl_mem {} bytes", MEM_SIZE);
+    eprintln!("coatl:   {:<18} user data", format!("[0, {})", STRING_POOL_BASE));
+    eprintln!("coatl:   {:<18} string pool ({} string(s))", format!("[{}, {})", STRING_POOL_BASE, pool_end), strings.len());
+    eprintln!("coatl:   {:<18} runtime I/O buffers", format!("[{}, {})", RUNTIME_SCRATCH, ARGV_BASE));
+    eprintln!("coatl:   {:<18} argv copies", format!("[{}, {})", ARGV_BASE, MEM_SIZE));
+}
+
+/// Quotes `s` as a JSON string.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Describes `__coatl_mem` as JSON (`--layout-map`): its regions, and the offset and byte
+/// length (without the NUL) of every pooled string, ordered by offset.
+pub fn layout_json(strings: &HashMap<String, i32>) -> String {
+    let mut pooled: Vec<(&i32, &String)> = strings.iter().map(|(s, off)| (off, s)).collect();
+    pooled.sort();
+    let regions = [("user", 0, STRING_POOL_BASE), ("strings", STRING_POOL_BASE, string_pool_end(strings)), ("runtime", RUNTIME_SCRATCH, ARGV_BASE), ("argv", ARGV_BASE, MEM_SIZE)];
+    let mut out = format!("{{\n  \"memory\": {{ \"symbol\": \"__coatl_mem\", \"size\": {} }},\n  \"regions\": [\n", MEM_SIZE);
+    let regions: Vec<String> = regions.iter().map(|(name, start, end)| format!("    {{ \"name\": \"{}\", \"start\": {}, \"end\": {} }}", name, start, end)).collect();
+    out.push_str(&regions.join(",\n"));
+    out.push_str("\n  ],\n  \"strings\": [");
+    let entries: Vec<String> = pooled.iter().map(|(off, s)| format!("\n    {{ \"offset\": {}, \"length\": {}, \"value\": {} }}", off, s.len(), json_string(s))).collect();
+    out.push_str(&entries.join(","));
+    out.push_str(if entries.is_empty() { "]\n}\n" } else { "\n  ]\n}\n" });
+    out
+}
+
+/// How the x86_64 runtime performs I/O (`--syscalls`).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Syscalls {
+    /// Raw Linux `syscall` instructions.
+    #[default]
+    Linux,
+    /// libc calls through the PLT, starting up via the C runtime (`--libc`).
+    Libc,
+    /// Calls to `coatl_sys_write`, `coatl_sys_read`, `coatl_sys_pwrite`, `coatl_sys_pread`,
+    /// `coatl_sys_open`, `coatl_sys_close` and `coatl_sys_exit`, supplied by the embedder at
+    /// link time.
+    Extern,
+}
+
+impl Syscalls {
+    pub fn parse(name: &str) -> Option<Syscalls> {
+        match name {
+            "linux" => Some(Syscalls::Linux),
+            "libc" => Some(Syscalls::Libc),
+            "extern" => Some(Syscalls::Extern),
+            _ => None,
+        }
+    }
+}
+
+/// Backend settings chosen on the command line.
+#[derive(Debug, Clone, Default)]
+pub struct CodegenOptions {
+    /// I/O personality of the runtime intrinsics (x86_64 only).
+    pub syscalls: Syscalls,
+    /// Extra non-allocated ELF sections (`--custom-section`), as name and contents.
+    pub custom_sections: Vec<(String, Vec<u8>)>,
+    /// Function called by the startup code instead of `main` (`--entry`).
+    pub entry: Option<String>,
+    /// Build a shared library (`-o *.so`): no startup code, runtime symbols hidden.
+    pub shared: bool,
+    /// Emit `_start` and link with `-nostdlib -static`, without any C runtime objects.
+    pub freestanding: bool,
+    /// Stack budget in bytes checked in every function prologue (`--stack-limit`).
+    pub stack_limit: Option<u32>,
+    /// Log every function entry and exit to stderr (`--instrument=trace`).
+    pub trace: bool,
+    /// Count calls and cycles per function and report them at exit (`--instrument=profile`).
+    pub profile: bool,
+    /// Keep a shadow stack of function names for `__panic` to print (`--backtrace`).
+    pub backtrace: bool,
+    /// Let `__panic` call the host's `coatl_panic_hook` before it exits (`--panic=hook`).
+    pub panic_hook: bool,
+    /// Comment the assembly of each statement with its `(loc ...)` source line (`--listing`).
+    pub listing: bool,
+    /// Give every program function an ELF type and size, read back for `--map`.
+    pub symbol_sizes: bool,
+    /// Leave `__coatl_mem` undefined for the embedding host to supply (`--import-memory`).
+    pub import_memory: bool,
+    /// Prefix given to the program's global functions (`--export-prefix`). The start symbol
+    /// gets it too, and the memory pool and runtime intrinsics stay local to the object.
+    pub export_prefix: Option<String>,
+}
+
+/// Bytes a function's frame grows by under `--instrument=profile`, below its 4 KiB of
+/// locals, to hold the entry cycle count at [`PROFILE_SLOT`]; 16 keeps the stack aligned.
+const PROFILE_FRAME_BYTES: i32 = 16;
+/// Frame slot (below the frame pointer) holding a function's entry cycle count under
+/// `--instrument=profile`, just past the last local slot.
+const PROFILE_SLOT: i32 = 4096 + 8;
+
+/// Written to stderr by `__coatl_stack_overflow` before exiting with status 134.
+const STACK_OVERFLOW_MSG: &str = "coatl: stack overflow\n";
+
+/// Parses a byte count with an optional `K` or `M` suffix, e.g. `65536`, `512K`, `8M`.
+pub fn parse_size(s: &str) -> Option<u32> {
+    let (digits, scale) = match s.chars().last()? {
+        'k' | 'K' => (&s[..s.len() - 1], 1024),
+        'm' | 'M' => (&s[..s.len() - 1], 1024 * 1024),
+        _ => (s, 1),
+    };
+    digits.parse::<u32>().ok()?.checked_mul(scale).filter(|n| *n <= i32::MAX as u32)
+}
+
+impl CodegenOptions {
+    fn entry(&self) -> &str { self.entry.as_deref().unwrap_or("main") }
+    /// The symbol the startup code is entered at, with any `--export-prefix`.
+    pub fn start_symbol(&self) -> String {
+        format!("{}{}", self.export_prefix.as_deref().unwrap_or(""), if self.freestanding { "_start" } else { "coatl_start" })
+    }
+    /// The runtime intrinsics `asm`, without their `.globl` directives under `--export-prefix`
+    /// so that every object linked into one host keeps its own copy.
+    fn runtime_asm(&self, asm: String) -> String {
+        if self.export_prefix.is_none() { return asm; }
+        asm.lines().filter(|l| !l.starts_with(".globl ")).map(|l| format!("{}\n", l)).collect()
+    }
+    /// Whether `__coatl_mem` is a global symbol: unless each prefixed object keeps its own.
+    fn global_memory(&self) -> bool { self.import_memory || self.export_prefix.is_none() }
+    /// Whether to define `main` as an alias of another entry: only the C runtime calls it,
+    /// so prefixed objects, which would all define it, skip it without `--libc`.
+    fn main_alias(&self) -> bool {
+        !self.shared && !self.freestanding && (self.export_prefix.is_none() || self.syscalls == Syscalls::Libc)
+    }
+}
+
+/// Whether a `(fn ...)` node gets a global symbol: `pub fn`s, `main` and the `--entry`
+/// function. Everything else stays local to the generated object.
+pub(crate) fn fn_is_public(f: &[IRNode], entry: &str) -> bool {
+    let is_pub = |m: &IRNode| matches!(m.as_list().map(|m| m.as_slice()), Some([IRNode::Atom(k), IRNode::Atom(v)]) if k == "vis" && v == "pub");
+    f.get(1).and_then(|n| n.as_atom()).map(|n| n == "main" || n == entry).unwrap_or(false) || f.get(5..).unwrap_or(&[]).iter().any(is_pub)
+}
+
+/// Registers `__coatl_init_memory` as a constructor, for outputs whose startup is owned by
+/// the C runtime or a host process rather than `coatl_start`.
+fn init_array_asm() -> Vec<String> {
+    [".section .init_array,\"aw\"", ".balign 8", "  .quad __coatl_init_memory", ".text"].iter().map(|l| l.to_string()).collect()
+}
+
+/// Element type and length of each `static`, keyed by its (mangled) name.
+fn static_shapes(ir: &IRNode) -> HashMap<Symbol, (Symbol, usize)> {
+    ir_section(ir, "statics").iter().filter_map(|s| {
+        let s = s.as_list()?;
+        Some((*s.get(1)?.as_atom()?, typecheck::static_type(s.get(2)?.as_atom()?)?))
+    }).collect()
+}
+
+/// The `static` tables in `.rodata`, as `.Lstatic_NAME`; `word` is the assembler's
+/// directive for a 32-bit value.
+fn static_data_asm(ir: &IRNode, word: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for s in ir_section(ir, "statics").iter().filter_map(|s| s.as_list()) {
+        let (Some(name), Some((elem, _))) = (s[1].as_atom(), s.get(2).and_then(|t| t.as_atom()).and_then(|t| typecheck::static_type(t))) else { continue };
+        let values: Vec<&str> = s[3..].iter().filter_map(|v| v.as_atom()).map(|v| v.as_str()).collect();
+        out.extend([".section .rodata".to_string(), ".balign 4".to_string(), format!(".Lstatic_{}:", name)]);
+        if !values.is_empty() { out.push(format!("  {} {}", if elem == "u8" { ".byte" } else { word }, values.join(", "))); }
+    }
+    if !out.is_empty() { out.push(".text".to_string()); }
+    out
+}
+
+/// Gives the memory pool and runtime intrinsics hidden visibility in shared libraries, so
+/// they neither leak into the host's namespace nor need GOT/PLT indirection. `__breakpoint`
+/// is lowered inline and has no symbol to hide.
+fn hidden_runtime_asm() -> Vec<String> {
+    let runtime = typecheck::INTRINSICS.iter().map(|i| i.name).filter(|n| *n != "__breakpoint");
+    std::iter::once("__coatl_mem").chain(runtime).map(|n| format!(".hidden {}", n)).collect()
+}
+
+/// The `--instrument=profile` counters: one 32-byte entry per function (calls, cycles, name
+/// offset in the string pool, printed flag), read by `__coatl_prof_report`.
+fn profile_table_asm(fns: &[IRNode], strings: &HashMap<String, i32>) -> Vec<String> {
+    let mut out = vec![".data".to_string(), ".balign 8".to_string(), "__coatl_prof_len:".to_string(), format!("  .quad {}", fns.len()), "__coatl_prof_table:".to_string()];
+    for name in fns.iter().filter_map(|f| f.as_list()?.get(1)?.as_atom()) {
+        out.push(format!("  .quad 0, 0, {}, 0", strings[&mangle::display(name)]));
+    }
+    out.push(".text".to_string());
+    out
+}
+
+/// Runs the profile report at exit when the C runtime or host owns process teardown.
+fn fini_array_asm() -> Vec<String> {
+    [".section .fini_array,\"aw\"", ".balign 8", "  .quad __coatl_prof_report", ".text"].iter().map(|l| l.to_string()).collect()
+}
+
+/// When `--entry` names another function and the program has no `main`, defines `main` as
+/// an alias of the entry so the C runtime objects linked in still resolve it.
+fn main_alias(fns: &[IRNode], entry: &str) -> Vec<String> {
+    let has_main = fns.iter().any(|f| f.as_list().and_then(|f| f.get(1)).and_then(|n| n.as_atom()).map(|n| n == "main").unwrap_or(false));
+    if entry == "main" || has_main { return Vec::new(); }
+    vec![".globl main".to_string(), format!(".set main, {}", entry)]
+}
+
+/// The string pool from `STRING_POOL_BASE` to its end, NULs included, as `.ascii` data
+/// under `.L_string_pool` in `.rodata`; `__coatl_init_memory` copies it into place.
+fn string_pool_asm(pool: &[(i32, String)]) -> Vec<String> {
+    let end = pool.iter().map(|(off, s)| off + s.len() as i32 + 1).max().unwrap_or(STRING_POOL_BASE);
+    let mut image = vec![0u8; (end - STRING_POOL_BASE) as usize];
+    for (off, s) in pool {
+        let at = (off - STRING_POOL_BASE) as usize;
+        image[at..at + s.len()].copy_from_slice(s.as_bytes());
+    }
+    if image.is_empty() { return Vec::new(); }
+    let mut out = vec![".section .rodata".to_string(), ".L_string_pool:".to_string()];
+    for chunk in image.chunks(64) {
+        let text: String = chunk.iter().map(|&b| match b {
+            b'"' | b'\\' => format!("\\{}", b as char),
+            0x20..=0x7e => (b as char).to_string(),
+            _ => format!("\\{:03o}", b),
+        }).collect();
+        out.push(format!("  .ascii \"{}\"", text));
+    }
+    out.push(".text".to_string());
+    out
+}
+
+/// Where a backend writes its assembly: any `io::Write`, with the number of lines written
+/// so far kept for `Assembly::origins`. The first write error is kept and later writes
+/// are skipped.
+struct AsmSink<W: Write> {
+    out: W,
+    /// Line (from 1) the next entry starts on.
+    line: usize,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> AsmSink<W> {
+    fn new(out: W) -> Self { AsmSink { out, line: 1, error: None } }
+
+    /// Writes `s`, which may hold several lines, followed by a newline.
+    fn write(&mut self, s: &str) {
+        self.line += s.matches('\n').count() + 1;
+        if self.error.is_none() && let Err(e) = writeln!(self.out, "{}", s) { self.error = Some(e); }
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        match self.error.take() { Some(e) => Err(e), None => self.out.flush() }
+    }
+}
+
+/// Assembly for the `--custom-section` payloads; identical syntax on both architectures.
+fn custom_section_asm(sections: &[(String, Vec<u8>)]) -> Vec<String> {
+    let mut out = Vec::new();
+    for (name, data) in sections {
+        let name = if name.starts_with('.') { name.clone() } else { format!(".{}", name) };
+        out.push(format!(".section {},\"\",%progbits", name));
+        for chunk in data.chunks(16) {
+            out.push(format!("  .byte {}", chunk.iter().map(|b| b.to_string()).collect::<Vec<_>>().join(",")));
+        }
+    }
+    if !out.is_empty() { out.push(".text".to_string()); }
+    out
+}
+
+struct X86_64Backend<W: Write> {
+    ir: IRNode,
+    opts: CodegenOptions,
+    out: AsmSink<W>,
+    vars: HashMap<Symbol, (i32, Symbol)>,
+    /// Stack slots taken by the current function's params and the lets in scope.
+    slots: i32,
+    strings: HashMap<String, i32>,
+    structs: HashMap<Symbol, Vec<layout::Field>>,
+    externs: HashSet<Symbol>,
+    /// `static` tables: element type and length.
+    statics: HashMap<Symbol, (Symbol, usize)>,
+    label_count: i32,
+    current_fn: Symbol,
+    fn_times: Vec<(String, Duration)>,
+    /// Line of the output where the code of each function and `(loc ...)` statement begins.
+    origins: Vec<(usize, String)>,
+    /// Stack bytes each function takes, fixed frame included (`--stack-report`).
+    frames: Vec<(String, u32)>,
+    stack: stack::StackDepth,
+}
+
+impl<W: Write> X86_64Backend<W> {
+    fn new(ir: IRNode, opts: CodegenOptions, out: W) -> Self {
+        Self {
+            ir,
+            opts,
+            out: AsmSink::new(out),
+            vars: HashMap::new(),
+            slots: 0,
+            strings: HashMap::new(),
+            structs: HashMap::new(),
+            externs: HashSet::new(),
+            statics: HashMap::new(),
+            label_count: 0,
+            current_fn: Symbol::default(),
+            fn_times: Vec::new(),
+            origins: Vec::new(),
+            frames: Vec::new(),
+            stack: stack::StackDepth::default(),
+        }
+    }
+
+    fn emit(&mut self, s: String) { self.stack.x86_64(&s); self.out.write(&s); }
+    fn new_label(&mut self, prefix: &str) -> String {
+        self.label_count += 1;
+        format!(".{}{}", prefix, self.label_count)
+    }
+
+    fn collect_strings(&mut self, node: &IRNode) {
+        if let IRNode::List(l) = node {
+            if !l.is_empty()
+                && let Some(atom) = l[0].as_atom()
+                    && atom == "string_typed" && l.len() > 1
+                        && let Some(val) = l[1].as_str() {
+                            self.strings.insert(val.to_string(), 0);
+                        }
+            for child in l { self.collect_strings(child); }
+        }
+    }
+
+    fn lower(&mut self) {
+        let fns: Vec<IRNode> = ir_section(&self.ir, "functions").to_vec();
+        self.structs = layout::program_layouts(&self.ir).into_iter().map(|(name, (fields, _))| (name, fields)).collect();
+        self.statics = static_shapes(&self.ir);
+        for e in ir_section(&self.ir, "externs") {
+            if let Some(name) = e.as_list().and_then(|l| l.get(1)).and_then(|n| n.as_atom()) { self.externs.insert(*name); }
+        }
+
+        self.emit(".intel_syntax noprefix".to_string());
+        self.emit(".bss".to_string());
+        self.emit(".align 16".to_string());
+        if self.opts.global_memory() { self.emit(".globl __coatl_mem".to_string()); }
+        if !self.opts.import_memory {
+            self.emit("__coatl_mem:".to_string());
+            self.emit(format!("  .zero {}", MEM_SIZE));
+        }
+        self.emit("__coatl_mem_inited:".to_string());
+        self.emit("  .long 0".to_string());
+        if self.opts.stack_limit.is_some() {
+            self.emit(".align 8".to_string());
+            self.emit("__coatl_stack_limit:".to_string());
+            self.emit("  .quad 0".to_string());
+        }
+        self.emit(".text".to_string());
+        self.emit("__coatl_init_memory:".to_string());
+        self.emit("  push rbp; mov rbp, rsp".to_string());
+        self.emit("  mov eax, dword ptr [rip+__coatl_mem_inited]; test eax, eax; jne .L_mem_done".to_string());
+        self.emit("  mov dword ptr [rip+__coatl_mem_inited], 1".to_string());
+        if let Some(limit) = self.opts.stack_limit {
+            self.emit(format!("  mov rax, rsp; sub rax, {}; mov qword ptr [rip+__coatl_stack_limit], rax", limit));
+        }
+
+        for func in &fns { self.collect_strings(func); }
+        if self.opts.trace || self.opts.profile || self.opts.backtrace {
+            for name in fns.iter().filter_map(|f| f.as_list()?.get(1)?.as_atom()) { self.strings.insert(mangle::display(name), 0); }
+        }
+
+        let pool = layout_string_pool(&mut self.strings);
+        let pool_len = string_pool_end(&self.strings) - STRING_POOL_BASE;
+        if pool_len > 0 {
+            self.emit("  lea rdx, [rip+__coatl_mem]".to_string());
+            self.emit(format!("  lea rdi, [rdx+{}]; lea rsi, [rip+.L_string_pool]; mov ecx, {}; rep movsb", STRING_POOL_BASE, pool_len));
+        }
+
+        self.emit(".L_mem_done:".to_string());
+        self.emit("  pop rbp; ret".to_string());
+        for line in string_pool_asm(&pool) { self.emit(line); }
+
+        if self.opts.main_alias() {
+            for line in main_alias(&fns, self.opts.entry()) { self.emit(line); }
+        }
+        if self.opts.profile {
+            for line in profile_table_asm(&fns, &self.strings) { self.emit(line); }
+        }
+        for line in static_data_asm(&self.ir, ".long") { self.emit(line); }
+        for (index, func) in fns.into_iter().enumerate() {
+            let start = Instant::now();
+            self.lower_fn(&func, index);
+            self.fn_times.push((self.current_fn.to_string(), start.elapsed()));
+        }
+
+        if self.opts.syscalls == Syscalls::Libc || self.opts.shared {
+            // The C runtime's _start calls `main` (or the host loads the library); either
+            // way the pool is initialized from .init_array first.
+            for line in init_array_asm() { self.emit(line); }
+            if self.opts.profile { for line in fini_array_asm() { self.emit(line); } }
+        } else {
+            self.emit(format!(".globl {}", self.opts.start_symbol()));
+            self.emit(format!("{}:", self.opts.start_symbol()));
+            self.emit("  call __coatl_init_memory".to_string());
+            self.emit(format!("  call {}", self.opts.entry()));
+            if self.opts.profile { self.emit("  push rax; call __coatl_prof_report; pop rax".to_string()); }
+            self.emit(if self.opts.syscalls == Syscalls::Extern { "  mov edi, eax; call __coatl_exit" } else { "  mov edi, eax; mov eax, 60; syscall" }.to_string());
+        }
+        if self.opts.stack_limit.is_some() {
+            self.emit("__coatl_stack_overflow:".to_string());
+            self.emit(format!("  mov edi, 2; lea rsi, [rip+.L_stack_overflow_msg]; mov edx, {}; call __coatl_write", STACK_OVERFLOW_MSG.len()));
+            self.emit("  call __coatl_backtrace; mov edi, 134; call __coatl_exit".to_string());
+            self.emit(format!(".section .rodata\n.L_stack_overflow_msg:\n  .ascii \"{}\"\n.text", STACK_OVERFLOW_MSG.escape_default()));
+        }
+        self.origins.push((self.out.line, "the runtime intrinsics".to_string()));
+        self.emit(RUNTIME_MARKER.to_string());
+        self.emit(self.opts.runtime_asm(match self.opts.syscalls {
+            Syscalls::Linux => INTRINSICS_X86_64.to_string(),
+            Syscalls::Libc => INTRINSICS_X86_64_LIBC.to_string(),
+            Syscalls::Extern => intrinsics::intrinsics_x86_64_extern(),
+        }));
+        if self.opts.profile { self.emit(PROFILE_X86_64.to_string()); }
+        self.emit(if self.opts.backtrace { BACKTRACE_X86_64.to_string() } else { "__coatl_backtrace:\n  ret".to_string() });
+        self.emit(if self.opts.panic_hook { PANIC_HOOK_X86_64.to_string() } else { "__coatl_panic_hook:\n  ret".to_string() });
+        if self.opts.shared {
+            self.emit(SHARED_X86_64.to_string());
+            for line in hidden_runtime_asm() { self.emit(line); }
+        }
+        for line in custom_section_asm(&self.opts.custom_sections) { self.emit(line); }
+    }
+
+    /// Frame offset (below `rbp`) and layout of the field reached by `path`, a struct
+    /// variable followed by field names (`a.b.c` is `[a, b, c]`).
+    fn field_slot(&self, path: &[IRNode]) -> (i32, layout::Field) {
+        let (mut at, mut ty) = *self.vars.get(path[0].as_atom().unwrap()).unwrap();
+        let mut found = None;
+        for name in path[1..].iter().filter_map(|n| n.as_atom()) {
+            let field = self.structs.get(&ty).unwrap().iter().find(|f| &f.name == name).unwrap().clone();
+            at -= field.offset as i32;
+            ty = field.ty;
+            found = Some(field);
+        }
+        (at, found.unwrap())
+    }
+
+    /// Leaves in `rcx` the address of element `index` of the slice variable `name`, running
+    /// `bounds` (a panic) when the index is not below its length; returns the element type.
+    fn slice_element(&mut self, name: &IRNode, index: &IRNode, bounds: &IRNode) -> String {
+        let (off, ty) = *self.vars.get(name.as_atom().unwrap()).unwrap();
+        let elem = layout::slice_elem(&ty).unwrap_or("i32").to_string();
+        let l_ok = self.new_label("L_slice_ok");
+        // `len` is the upper half of the slot and `ptr` the lower; an unsigned compare also
+        // sends negative indexes to the panic.
+        self.lower_expr(index);
+        self.emit(format!("  mov ecx, dword ptr [rbp-{}]", off - 4));
+        self.emit(format!("  cmp rax, rcx; jb {}", l_ok));
+        self.lower_expr(bounds);
+        self.emit(l_ok + ":");
+        self.emit(format!("  mov ecx, dword ptr [rbp-{}]", off));
+        self.emit(if elem == "u8" { "  add rcx, rax" } else { "  lea rcx, [rcx+rax*4]" }.to_string());
+        self.emit("  lea rdx, [rip+__coatl_mem]; add rcx, rdx".to_string());
+        elem
+    }
+
+    /// Keeps the low `size` bytes of `rax`, zeroing the rest.
+    fn truncate_rax(&mut self, size: u32) {
+        match size {
+            1 => self.emit("  movzx eax, al".to_string()),
+            2 => self.emit("  movzx eax, ax".to_string()),
+            4 => self.emit("  mov eax, eax".to_string()),
+            8.. => {}
+            _ => self.emit(format!("  mov rcx, {}; and rax, rcx", (1u64 << (size * 8)) - 1)),
+        }
+    }
+
+    fn lower_fn(&mut self, n: &IRNode, index: usize) {
+        if let IRNode::List(l) = n {
+            let name = l[1].as_atom().unwrap();
+            self.current_fn = *name;
+            self.vars.clear();
+            self.origins.push((self.out.line, format!("fn `{}`", mangle::display(name))));
+            if fn_is_public(l, self.opts.entry()) { self.emit(format!(".global {}", name)); }
+            if self.opts.symbol_sizes { self.emit(format!(".type {}, @function", name)); }
+            self.emit(format!("{}:", name));
+            self.emit(format!("  push rbp; mov rbp, rsp; sub rsp, {}", 4096 + if self.opts.profile { PROFILE_FRAME_BYTES } else { 0 }));
+            if self.opts.stack_limit.is_some() {
+                self.emit("  cmp rsp, qword ptr [rip+__coatl_stack_limit]; jb __coatl_stack_overflow".to_string());
+            }
+            self.stack = stack::StackDepth::default();
+            
+            let regs = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+            if let IRNode::List(params) = &l[2] {
+                for (i, p) in params[1..].iter().enumerate() {
+                    if let IRNode::List(pl) = p {
+                        let p_name = pl[1].as_atom().unwrap();
+                        let p_type = pl[2].as_atom().unwrap();
+                        let off = (i as i32 + 1) * 8;
+                        self.vars.insert(*p_name, (off, *p_type));
+                        if i < 6 { self.emit(format!("  mov [rbp-{}], {}", off, regs[i])); }
+                        else {
+                            let stack_off = 16 + (i as i32 - 6) * 8;
+                            self.emit(format!("  mov rax, [rbp+{}]\n  mov [rbp-{}], rax", stack_off, off));
+                        }
+                    }
+                }
+            }
+
+            self.slots = self.vars.len() as i32;
+            let counters = format!("__coatl_prof_table+{}", index * 32);
+            if self.opts.profile {
+                self.emit(format!("  inc qword ptr [rip+{}]", counters));
+                self.emit(format!("  rdtsc; shl rdx, 32; or rax, rdx; mov [rbp-{}], rax", PROFILE_SLOT));
+            }
+            if self.opts.backtrace {
+                let off = self.strings[&mangle::display(name)];
+                self.emit("  mov rax, qword ptr [rip+__coatl_bt_depth]; and eax, 63; lea rcx, [rip+__coatl_bt_stack]".to_string());
+                self.emit(format!("  mov dword ptr [rcx+rax*4], {}; inc qword ptr [rip+__coatl_bt_depth]", off));
+            }
+            let trace_name = self.strings.get(&mangle::display(name)).copied().filter(|_| self.opts.trace);
+            if let Some(off) = trace_name { self.emit(format!("  mov edi, 0; mov esi, {}; call __coatl_trace", off)); }
+
+            if let IRNode::List(body) = &l[4] {
+                for stmt in &body[1..] { self.lower_stmt(stmt); }
+            }
+            self.emit(format!(".Lret_{}:", name));
+            if let Some(off) = trace_name { self.emit(format!("  push rax; mov edi, 1; mov esi, {}; call __coatl_trace; pop rax", off)); }
+            if self.opts.backtrace { self.emit("  dec qword ptr [rip+__coatl_bt_depth]".to_string()); }
+            if self.opts.profile {
+                self.emit(format!("  push rax; rdtsc; shl rdx, 32; or rax, rdx; sub rax, [rbp-{}]; add qword ptr [rip+{}+8], rax; pop rax", PROFILE_SLOT, counters));
+            }
+            self.emit("  leave; ret".to_string());
+            self.frames.push((name.to_string(), stack::FRAME_BYTES + if self.opts.profile { PROFILE_FRAME_BYTES as u32 } else { 0 } + self.stack.peak()));
+            if self.opts.symbol_sizes { self.emit(format!(".size {0}, . - {0}", name)); }
+        }
+    }
+
+    fn lower_stmt(&mut self, n: &IRNode) {
+        let l = n.as_list().unwrap();
+        let head = l[0].as_atom().unwrap();
+        match head.as_str() {
+            "let" => {
+                let name = l[1].as_atom().unwrap();
+                let vtype = l[2].as_atom().unwrap();
+                // The initializer still sees any outer `name` this let shadows.
+                self.lower_expr(&l[3]);
+                self.slots += 1;
+                let off = self.slots * 8;
+                self.vars.insert(*name, (off, *vtype));
+                self.emit(format!("  mov [rbp-{}], rax", off));
+            }
+            "assign" => {
+                let name = l[1].as_atom().unwrap();
+                let off = self.vars.get(name).unwrap().0;
+                self.lower_expr(&l[2]);
+                self.emit(format!("  mov [rbp-{}], rax", off));
+            }
+            "slice_assign" => {
+                let elem = self.slice_element(&l[1], &l[2], &l[4]);
+                self.emit("  push rcx".to_string());
+                self.lower_expr(&l[3]);
+                self.emit("  pop rcx".to_string());
+                self.emit(if elem == "u8" { "  mov byte ptr [rcx], al" } else { "  mov dword ptr [rcx], eax" }.to_string());
+            }
+            "field_assign" => {
+                let (at, field) = self.field_slot(&l[1..l.len() - 1]);
+                self.lower_expr(&l[l.len() - 1]);
+                // Stored in power-of-two pieces, low bytes first, so no neighbouring field
+                // of the enclosing struct is overwritten.
+                if field.size == 8 { self.emit(format!("  mov qword ptr [rbp-{}], rax", at)); }
+                let mut done = 0;
+                while field.size < 8 && done < field.size {
+                    let (width, reg, ptr) = match field.size - done { 4.. => (4, "eax", "dword"), 2 | 3 => (2, "ax", "word"), _ => (1, "al", "byte") };
+                    self.emit(format!("  mov {} ptr [rbp-{}], {}", ptr, at - done as i32, reg));
+                    done += width;
+                    // Move the next piece down to the low bytes.
+                    if done < field.size { self.emit(format!("  shr rax, {}", width * 8)); }
+                }
+            }
+            "if" => {
+                let l_else = self.new_label("L_else");
+                let l_end = self.new_label("L_end");
+                self.lower_expr(&l[1]);
+                self.emit("  cmp rax, 0; je ".to_string() + &l_else);
+                self.lower_stmt(&l[2]);
+                self.emit("  jmp ".to_string() + &l_end);
+                self.emit(l_else + ":");
+                if l.len() > 3 { self.lower_stmt(&l[3].as_list().unwrap()[1]); }
+                self.emit(l_end + ":");
+            }
+            "while" => {
+                let l_start = self.new_label("L_while_start");
+                let l_end = self.new_label("L_while_end");
+                self.emit(l_start.clone() + ":");
+                self.lower_expr(&l[1]);
+                self.emit("  cmp rax, 0; je ".to_string() + &l_end);
+                self.lower_stmt(&l[2]);
+                self.emit("  jmp ".to_string() + &l_start);
+                self.emit(l_end + ":");
+            }
+            "match" => {
+                let m = MatchArms::new(l);
+                let labels: Vec<String> = m.arms.iter().map(|_| self.new_label("L_arm")).collect();
+                let l_default = self.new_label("L_match_default");
+                let l_end = self.new_label("L_match_end");
+                // Arms compare the low 32 bits of the scrutinee.
+                self.lower_expr(&l[1]);
+                if let Some((low, high)) = m.table_range() {
+                    let table = self.new_label("L_jump_table");
+                    self.emit(format!("  sub eax, {}; cmp eax, {}; ja {}", low, high as i64 - low as i64, l_default));
+                    self.emit(format!("  lea rdx, [rip+{}]; movsxd rax, dword ptr [rdx+rax*4]; add rax, rdx; jmp rax", table));
+                    self.emit(".section .rodata".to_string());
+                    self.emit(".balign 4".to_string());
+                    self.emit(format!("{}:", table));
+                    for entry in m.table(low, high) {
+                        self.emit(format!("  .long {} - {}", entry.map(|i| &labels[i]).unwrap_or(&l_default), table));
+                    }
+                    self.emit(".text".to_string());
+                } else {
+                    for ((v, _), label) in m.arms.iter().zip(&labels) { self.emit(format!("  cmp eax, {}; je {}", v, label)); }
+                    self.emit(format!("  jmp {}", l_default));
+                }
+                for ((_, body), label) in m.arms.iter().zip(labels) {
+                    self.emit(label + ":");
+                    self.lower_stmt(body);
+                    self.emit(format!("  jmp {}", l_end));
+                }
+                self.emit(l_default + ":");
+                if let Some(body) = m.default { self.lower_stmt(body); }
+                self.emit(l_end + ":");
+            }
+            "block" => {
+                // Lets are scoped to their block; its slots are reused once it ends.
+                let (vars, slots) = (self.vars.clone(), self.slots);
+                for s in &l[1..] { self.lower_stmt(s); }
+                (self.vars, self.slots) = (vars, slots);
+            }
+            "return" => {
+                self.lower_expr(&l[1]);
+                let label = format!(".Lret_{}", self.current_fn);
+                self.emit(format!("  jmp {}", label));
+            }
+            "expr" => { self.lower_expr(&l[1]); }
+            "asm" => {
+                let template = l[1].as_str().unwrap();
+                let lines = expand_asm_template(template, &l[2].as_list().unwrap()[1..], &self.vars, |off| format!("qword ptr [rbp-{}]", off));
+                for line in lines { self.emit(line); }
+            }
+            "loc" => {
+                let (at, text) = (l[1].as_str().unwrap(), l[2].as_str().unwrap());
+                self.origins.push((self.out.line, format!("{}: {}", at, text)));
+                if self.opts.listing { self.emit(format!("# {}: {}", at, text)); }
+            }
+            _ => {}
+        }
+    }
+
+    fn lower_expr(&mut self, n: &IRNode) {
+        let l = n.as_list().unwrap();
+        let head = l[0].as_atom().unwrap();
+        match head.as_str() {
+            "uninit" => self.emit("  xor eax, eax".to_string()),
+            "int" | "int_i64" | "bool" | "f32" | "f64" => {
+                let val = l[1].as_atom().unwrap();
+                if head == "f32" {
+                    let f: f32 = val.parse().unwrap();
+                    self.emit(format!("  mov eax, {}; movd xmm0, eax; movss rax, xmm0", f.to_bits()));
+                } else if head == "f64" {
+                    let f: f64 = val.parse().unwrap();
+                    self.emit(format!("  mov rax, {}; movd xmm0, rax; movsd rax, xmm0", f.to_bits()));
+                } else {
+                    self.emit(format!("  mov rax, {}", val));
+                }
+            }
+            "ident" => {
+                let name = l[1].as_atom().unwrap();
+                let off = self.vars.get(name).unwrap().0;
+                self.emit(format!("  mov rax, [rbp-{}]", off));
+            }
+            "static_index" => {
+                // An unsigned compare also sends negative indexes to the panic.
+                let name = l[1].as_atom().unwrap();
+                let (elem, len) = *self.statics.get(name).unwrap();
+                let l_ok = self.new_label("L_static_ok");
+                self.lower_expr(&l[2]);
+                self.emit(format!("  cmp rax, {}; jb {}", len, l_ok));
+                self.lower_expr(&l[3]);
+                self.emit(l_ok + ":");
+                self.emit(format!("  lea rcx, [rip+.Lstatic_{}]", name));
+                self.emit(if elem == "u8" { "  movzx eax, byte ptr [rcx+rax]" } else { "  movsxd rax, dword ptr [rcx+rax*4]" }.to_string());
+            }
+            "slice_range" => {
+                // Both bounds are compared unsigned, so a negative start or end panics too.
+                let (off, ty) = *self.vars.get(l[1].as_atom().unwrap()).unwrap();
+                let (l_bad, l_ok) = (self.new_label("L_range_bad"), self.new_label("L_range_ok"));
+                self.lower_expr(&l[2]);
+                self.emit("  mov rcx, rax; shr rcx, 32; mov edx, eax".to_string());
+                self.emit(format!("  cmp edx, ecx; ja {}", l_bad));
+                self.emit(format!("  cmp ecx, dword ptr [rbp-{}]; jbe {}", off - 4, l_ok));
+                self.emit(l_bad + ":");
+                self.lower_expr(&l[3]);
+                self.emit(l_ok + ":");
+                self.emit("  sub ecx, edx; shl rcx, 32".to_string());
+                self.emit(format!("  mov eax, dword ptr [rbp-{}]", off));
+                self.emit(if layout::slice_elem(&ty) == Some("u8") { "  add eax, edx" } else { "  lea eax, [rax+rdx*4]" }.to_string());
+                self.emit("  or rax, rcx".to_string());
+            }
+            "slice_index" => {
+                let elem = self.slice_element(&l[1], &l[2], &l[3]);
+                self.emit(if elem == "u8" { "  movzx eax, byte ptr [rcx]" } else { "  movsxd rax, dword ptr [rcx]" }.to_string());
+            }
+            "field" => {
+                let (at, field) = self.field_slot(&l[1..]);
+                match field.size {
+                    1 => self.emit(format!("  movzx eax, byte ptr [rbp-{}]", at)),
+                    2 => self.emit(format!("  movzx eax, word ptr [rbp-{}]", at)),
+                    4 if self.structs.contains_key(&field.ty) => self.emit(format!("  mov eax, dword ptr [rbp-{}]", at)),
+                    4 => self.emit(format!("  movsxd rax, dword ptr [rbp-{}]", at)),
+                    8 => self.emit(format!("  mov rax, qword ptr [rbp-{}]", at)),
+                    // A struct of 3, 5, 6 or 7 bytes: load the eight bytes at the field (still
+                    // within the frame) and keep its own.
+                    size => {
+                        self.emit(format!("  mov rax, qword ptr [rbp-{}]", at));
+                        self.truncate_rax(size);
+                    }
+                }
+            }
+            "struct_lit" => {
+                // Values are given in field order; each is truncated to its field's size
+                // and or-ed into place.
+                let fields = self.structs.get(l[1].as_atom().unwrap()).cloned().unwrap_or_default();
+                self.emit("  push 0".to_string());
+                for (arg, field) in l[2..].iter().zip(&fields) {
+                    self.lower_expr(arg);
+                    self.truncate_rax(field.size);
+                    if field.offset > 0 { self.emit(format!("  shl rax, {}", field.offset * 8)); }
+                    self.emit("  or [rsp], rax".to_string());
+                }
+                self.emit("  pop rax".to_string());
+            }
+            // Multiplying or dividing by a power of two shifts; a signed divide first adds
+            // `2^k - 1` to a negative dividend so it still rounds toward zero.
+            "binary" if l[1].as_atom().is_some_and(|op| op == "mul" || op == "div") && pow2_shift(&l[3]).is_some() => {
+                let k = pow2_shift(&l[3]).unwrap();
+                self.lower_expr(&l[2]);
+                if l[1].as_atom().unwrap() == "mul" { self.emit(format!("  shl rax, {}", k)); }
+                else { self.emit(format!("  mov rcx, rax; sar rcx, 63; shr rcx, {}; add rax, rcx; sar rax, {}", 64 - k, k)); }
+            }
+            "binary" if l[1].as_atom().is_some_and(|op| op == "mul") && pow2_shift(&l[2]).is_some() => {
+                self.lower_expr(&l[3]);
+                self.emit(format!("  shl rax, {}", pow2_shift(&l[2]).unwrap()));
+            }
+            "binary" => {
+                let op = l[1].as_atom().unwrap();
+                self.lower_expr(&l[2]); self.emit("  push rax".to_string());
+                self.lower_expr(&l[3]); self.emit("  mov rcx, rax; pop rax".to_string());
+                match op.as_str() {
+                    "add" => self.emit("  add rax, rcx".to_string()),
+                    "sub" => self.emit("  sub rax, rcx".to_string()),
+                    "mul" => self.emit("  imul rax, rcx".to_string()),
+                    "div" => self.emit("  cqo; idiv rcx".to_string()),
+                    "and" => self.emit("  and rax, rcx".to_string()),
+                    "or" => self.emit("  or rax, rcx".to_string()),
+                    _ => {
+                        let cond = match op.as_str() { "eq"=>"e", "ne"=>"ne", "lt"=>"l", "gt"=>"g", "le"=>"le", "ge"=>"ge", _=>"e" };
+                        self.emit(format!("  cmp rax, rcx; set{} al; movzx rax, al", cond));
+                    }
+                }
+            }
+            // A debug trap right at the call site rather than inside a runtime routine.
+            "call" if l[1].as_atom().is_some_and(|n| n == "__breakpoint") => self.emit("  int3; xor eax, eax".to_string()),
+            "call" => {
+                let name = l[1].as_atom().unwrap();
+                let regs = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+                let args = &l[2..];
+                // Foreign code expects a 16-byte aligned stack at the call; Coatl code may be
+                // mid-expression with values pushed, so realign and restore rsp afterwards.
+                let is_extern = self.externs.contains(name);
+                let stack_args = args.len().saturating_sub(6);
+                if is_extern {
+                    self.emit("  mov rax, rsp; and rsp, -16; push rax; push rax".to_string());
+                    if stack_args % 2 == 1 { self.emit("  sub rsp, 8".to_string()); }
+                }
+                for i in (6..args.len()).rev() {
+                    self.lower_expr(&args[i]);
+                    self.emit("  push rax".to_string());
+                }
+                for arg in args.iter().take(6) {
+                    self.lower_expr(arg);
+                    self.emit("  push rax".to_string());
+                }
+                for i in (0..std::cmp::min(args.len(), 6)).rev() {
+                    self.emit(format!("  pop {}", regs[i]));
+                }
+                if is_extern {
+                    self.emit(format!("  call {}@PLT", name));
+                    self.emit(format!("  add rsp, {}; mov rsp, [rsp]", (stack_args + stack_args % 2) * 8));
+                } else {
+                    self.emit(format!("  call {}", name));
+                    if args.len() > 6 { self.emit(format!("  add rsp, {}", (args.len() - 6) * 8)); }
+                }
+            }
+            "string_typed" => {
+                let val = l[1].as_str().unwrap();
+                let off = self.strings.get(val).unwrap();
+                self.emit(format!("  mov rax, {}", off));
+            }
+            "syscall" => self.emit("  syscall".to_string()),
+            _ => {}
+        }
+    }
+}
+
+struct AArch64Backend<W: Write> {
+    ir: IRNode,
+    opts: CodegenOptions,
+    out: AsmSink<W>,
+    vars: HashMap<Symbol, (i32, Symbol)>,
+    /// Stack slots taken by the current function's params and the lets in scope.
+    slots: i32,
+    strings: HashMap<String, i32>,
+    structs: HashMap<Symbol, Vec<layout::Field>>,
+    /// `static` tables: element type and length.
+    statics: HashMap<Symbol, (Symbol, usize)>,
+    label_count: i32,
+    current_fn: Symbol,
+    fn_times: Vec<(String, Duration)>,
+    /// Line of the output where the code of each function and `(loc ...)` statement begins.
+    origins: Vec<(usize, String)>,
+    /// Stack bytes each function takes, fixed frame included (`--stack-report`).
+    frames: Vec<(String, u32)>,
+    stack: stack::StackDepth,
+}
+
+impl<W: Write> AArch64Backend<W> {
+    fn new(ir: IRNode, opts: CodegenOptions, out: W) -> Self {
+        Self {
+            ir,
+            opts,
+            out: AsmSink::new(out),
+            vars: HashMap::new(),
+            slots: 0,
+            strings: HashMap::new(),
+            structs: HashMap::new(),
+            statics: HashMap::new(),
+            label_count: 0,
+            current_fn: Symbol::default(),
+            fn_times: Vec::new(),
+            origins: Vec::new(),
+            frames: Vec::new(),
+            stack: stack::StackDepth::default(),
+        }
+    }
+
+    fn emit(&mut self, s: String) { self.stack.aarch64(&s); self.out.write(&s); }
+    fn new_label(&mut self, prefix: &str) -> String {
+        self.label_count += 1;
+        format!(".{}{}", prefix, self.label_count)
+    }
+
+    fn collect_strings(&mut self, node: &IRNode) {
+        if let IRNode::List(l) = node {
+            if !l.is_empty()
+                && let Some(atom) = l[0].as_atom()
+                    && atom == "string_typed" && l.len() > 1
+                        && let Some(val) = l[1].as_str() {
+                            self.strings.insert(val.to_string(), 0);
+                        }
+            for child in l { self.collect_strings(child); }
+        }
+    }
+
+    /// Loads the address of function `index`'s `--instrument=profile` counters into `reg`.
+    fn prof_counters(&mut self, reg: &str, index: usize) {
+        self.emit(format!("  adrp {0}, __coatl_prof_table; add {0}, {0}, :lo12:__coatl_prof_table", reg));
+        if index > 0 {
+            self.safe_mov_imm("x13", index as i64 * 32);
+            self.emit(format!("  add {0}, {0}, x13", reg));
+        }
+    }
+
+    fn safe_mov_imm(&mut self, reg: &str, val: i64) {
+        if (0..65536).contains(&val) {
+            self.emit(format!("  mov {}, #{}", reg, val));
+        } else {
+            self.emit(format!("  movz {}, #{}", reg, val & 0xffff));
+            if (val >> 16) & 0xffff != 0 { self.emit(format!("  movk {}, #{}", reg, (val >> 16) & 0xffff) + ", lsl #16"); }
+            if (val >> 32) & 0xffff != 0 { self.emit(format!("  movk {}, #{}", reg, (val >> 32) & 0xffff) + ", lsl #32"); }
+            if (val >> 48) & 0xffff != 0 { self.emit(format!("  movk {}, #{}", reg, (val >> 48) & 0xffff) + ", lsl #48"); }
+        }
+    }
+
+    fn ldr_x29(&mut self, reg: &str, off: i32) {
+        if (-256..=4095).contains(&off) { self.emit(format!("  ldr {}, [x29, #{}]", reg, off)); }
+        else { self.safe_mov_imm("x1", off as i64); self.emit(format!("  ldr {}, [x29, x1]", reg)); }
+    }
+
+    fn ldrsw_x29(&mut self, reg: &str, off: i32) {
+        if (-256..=4095).contains(&off) { self.emit(format!("  ldrsw {}, [x29, #{}]", reg, off)); }
+        else { self.safe_mov_imm("x1", off as i64); self.emit(format!("  ldrsw {}, [x29, x1]", reg)); }
+    }
+
+    fn str_x29(&mut self, reg: &str, off: i32) {
+        if (-256..=4095).contains(&off) { self.emit(format!("  str {}, [x29, #{}]", reg, off)); }
+        else { self.safe_mov_imm("x1", off as i64); self.emit(format!("  str {}, [x29, x1]", reg)); }
+    }
+
+    fn lower(&mut self) {
+        let fns: Vec<IRNode> = ir_section(&self.ir, "functions").to_vec();
+        self.structs = layout::program_layouts(&self.ir).into_iter().map(|(name, (fields, _))| (name, fields)).collect();
+        self.statics = static_shapes(&self.ir);
+
+        self.emit(".bss".to_string());
+        self.emit(".align 4".to_string());
+        if self.opts.global_memory() { self.emit(".globl __coatl_mem".to_string()); }
+        if !self.opts.import_memory {
+            self.emit("__coatl_mem:".to_string());
+            self.emit(format!("  .zero {}", MEM_SIZE));
+        }
+        self.emit("__coatl_mem_inited:".to_string());
+        self.emit("  .word 0".to_string());
+        if self.opts.stack_limit.is_some() {
+            self.emit(".balign 8".to_string());
+            self.emit("__coatl_stack_limit:".to_string());
+            self.emit("  .quad 0".to_string());
+        }
+        self.emit(".text".to_string());
+        self.emit("__coatl_init_memory:".to_string());
+        self.emit("  stp x29, x30, [sp, #-16]!".to_string());
+        self.emit("  mov x29, sp".to_string());
+        self.emit("  adrp x0, __coatl_mem_inited; ldr w1, [x0, :lo12:__coatl_mem_inited]; cbnz w1, .L_mem_done".to_string());
+        self.emit("  mov w1, #1; str w1, [x0, :lo12:__coatl_mem_inited]".to_string());
+        if let Some(limit) = self.opts.stack_limit {
+            self.safe_mov_imm("x4", limit as i64);
+            self.emit("  mov x3, sp; sub x3, x3, x4; adrp x4, __coatl_stack_limit; str x3, [x4, :lo12:__coatl_stack_limit]".to_string());
+        }
+
+        for func in &fns { self.collect_strings(func); }
+        if self.opts.trace || self.opts.profile || self.opts.backtrace {
+            for name in fns.iter().filter_map(|f| f.as_list()?.get(1)?.as_atom()) { self.strings.insert(mangle::display(name), 0); }
+        }
+
+        let pool = layout_string_pool(&mut self.strings);
+        let pool_len = string_pool_end(&self.strings) - STRING_POOL_BASE;
+        if pool_len > 0 {
+            self.emit("  adrp x2, __coatl_mem; add x2, x2, :lo12:__coatl_mem".to_string());
+            self.safe_mov_imm("x1", STRING_POOL_BASE as i64);
+            self.emit("  add x4, x2, x1; adrp x3, .L_string_pool; add x3, x3, :lo12:.L_string_pool".to_string());
+            self.safe_mov_imm("x5", pool_len as i64);
+            self.emit(".L_string_pool_copy:".to_string());
+            self.emit("  ldrb w0, [x3], #1; strb w0, [x4], #1; subs x5, x5, #1; b.ne .L_string_pool_copy".to_string());
+        }
+
+        self.emit(".L_mem_done:".to_string());
+        self.emit("  ldp x29, x30, [sp], #16".to_string());
+        self.emit("  ret".to_string());
+        for line in string_pool_asm(&pool) { self.emit(line); }
+
+        if self.opts.main_alias() {
+            for line in main_alias(&fns, self.opts.entry()) { self.emit(line); }
+        }
+        if self.opts.profile {
+            for line in profile_table_asm(&fns, &self.strings) { self.emit(line); }
+        }
+        for line in static_data_asm(&self.ir, ".word") { self.emit(line); }
+        for (index, func) in fns.into_iter().enumerate() {
+            let start = Instant::now();
+            self.lower_fn(&func, index);
+            self.fn_times.push((self.current_fn.to_string(), start.elapsed()));
+        }
+
+        if self.opts.shared {
+            for line in init_array_asm() { self.emit(line); }
+            if self.opts.profile { for line in fini_array_asm() { self.emit(line); } }
+        } else {
+            self.emit(format!(".globl {}", self.opts.start_symbol()));
+            self.emit(format!("{}:", self.opts.start_symbol()));
+            self.emit("  stp x29, x30, [sp, #-16]!".to_string());
+            self.emit("  bl __coatl_init_memory".to_string());
+            self.emit(format!("  bl {}", self.opts.entry()));
+            if self.opts.profile { self.emit("  str x0, [sp, #-16]!; bl __coatl_prof_report; ldr x0, [sp], #16".to_string()); }
+            self.emit("  mov w0, w0; mov x8, #93; svc #0".to_string());
+        }
+        if self.opts.stack_limit.is_some() {
+            self.emit("__coatl_stack_overflow:".to_string());
+            self.emit(format!("  mov x0, #2; adrp x1, .L_stack_overflow_msg; add x1, x1, :lo12:.L_stack_overflow_msg; mov x2, #{}; mov x8, #64; svc #0", STACK_OVERFLOW_MSG.len()));
+            self.emit("  bl __coatl_backtrace; mov x0, #134; mov x8, #93; svc #0".to_string());
+            self.emit(format!(".section .rodata\n.L_stack_overflow_msg:\n  .ascii \"{}\"\n.text", STACK_OVERFLOW_MSG.escape_default()));
+        }
+        self.origins.push((self.out.line, "the runtime intrinsics".to_string()));
+        self.emit(RUNTIME_MARKER.to_string());
+        self.emit(self.opts.runtime_asm(INTRINSICS_AARCH64.to_string()));
+        if self.opts.profile { self.emit(PROFILE_AARCH64.to_string()); }
+        self.emit(if self.opts.backtrace { BACKTRACE_AARCH64.to_string() } else { "__coatl_backtrace:\n  ret".to_string() });
+        self.emit(if self.opts.panic_hook { PANIC_HOOK_AARCH64.to_string() } else { "__coatl_panic_hook:\n  ret".to_string() });
+        if self.opts.shared {
+            self.emit(SHARED_AARCH64.to_string());
+            for line in hidden_runtime_asm() { self.emit(line); }
+        }
+        for line in custom_section_asm(&self.opts.custom_sections) { self.emit(line); }
+    }
+
+    fn lower_fn(&mut self, n: &IRNode, index: usize) {
+        if let IRNode::List(l) = n {
+            let name = l[1].as_atom().unwrap();
+            self.current_fn = *name;
+            self.vars.clear();
+            self.origins.push((self.out.line, format!("fn `{}`", mangle::display(name))));
+            if fn_is_public(l, self.opts.entry()) { self.emit(format!(".global {}", name)); }
+            if self.opts.symbol_sizes { self.emit(format!(".type {}, %function", name)); }
+            self.emit(format!("{}:", name));
+            self.emit("  stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #4096".to_string());
+            if self.opts.profile { self.emit(format!("  sub sp, sp, #{}", PROFILE_FRAME_BYTES)); }
+            if self.opts.stack_limit.is_some() {
+                self.emit("  adrp x16, __coatl_stack_limit; ldr x16, [x16, :lo12:__coatl_stack_limit]; mov x17, sp; cmp x17, x16; b.lo __coatl_stack_overflow".to_string());
+            }
+            self.stack = stack::StackDepth::default();
+            
+            let mut o = 16;
+            if let IRNode::List(params) = &l[2] {
+                for (i, p) in params[1..].iter().enumerate() {
+                    if let IRNode::List(pl) = p {
+                        let p_name = pl[1].as_atom().unwrap();
+                        let p_type = pl[2].as_atom().unwrap();
+                        self.vars.insert(*p_name, (o, *p_type));
+                        if i < 8 { self.str_x29(&format!("x{}", i), -o); }
+                        else {
+                            let stack_off = 16 + (i as i32 - 8) * 8;
+                            self.ldr_x29("x0", stack_off);
+                            self.str_x29("x0", -o);
+                        }
+                        o += 8;
+                    }
+                }
+            }
+
+            self.slots = self.vars.len() as i32;
+            if self.opts.profile {
+                self.prof_counters("x9", index);
+                self.emit("  ldr x10, [x9]; add x10, x10, #1; str x10, [x9]".to_string());
+                self.emit(format!("  mrs x10, cntvct_el0; mov x11, #{}; sub x11, x29, x11; str x10, [x11]", PROFILE_SLOT));
+            }
+            if self.opts.backtrace {
+                let off = self.strings[&mangle::display(name)];
+                self.emit("  adrp x9, __coatl_bt_depth; add x9, x9, :lo12:__coatl_bt_depth; ldr x10, [x9]; and x11, x10, #63; add x10, x10, #1; str x10, [x9]".to_string());
+                self.safe_mov_imm("x12", off as i64);
+                self.emit("  adrp x9, __coatl_bt_stack; add x9, x9, :lo12:__coatl_bt_stack; str w12, [x9, x11, lsl #2]".to_string());
+            }
+            // x29-8 is never a local slot (they start at x29-16), so it holds the return value.
+            let trace_name = self.strings.get(&mangle::display(name)).copied().filter(|_| self.opts.trace);
+            if let Some(off) = trace_name {
+                self.emit("  mov x0, #0".to_string());
+                self.safe_mov_imm("x1", off as i64);
+                self.emit("  bl __coatl_trace".to_string());
+            }
+
+            if let IRNode::List(body) = &l[4] {
+                for stmt in &body[1..] { self.lower_stmt(stmt); }
+            }
+            self.emit(format!(".Lret_{}:", name));
+            if let Some(off) = trace_name {
+                self.emit("  str x0, [x29, #-8]; mov x0, #1".to_string());
+                self.safe_mov_imm("x1", off as i64);
+                self.emit("  bl __coatl_trace; ldr x0, [x29, #-8]".to_string());
+            }
+            if self.opts.backtrace {
+                self.emit("  adrp x9, __coatl_bt_depth; ldr x10, [x9, :lo12:__coatl_bt_depth]; sub x10, x10, #1; str x10, [x9, :lo12:__coatl_bt_depth]".to_string());
+            }
+            if self.opts.profile {
+                self.prof_counters("x9", index);
+                self.emit(format!("  mrs x10, cntvct_el0; mov x11, #{}; sub x11, x29, x11; ldr x11, [x11]; sub x10, x10, x11", PROFILE_SLOT));
+                self.emit("  ldr x12, [x9, #8]; add x12, x12, x10; str x12, [x9, #8]".to_string());
+            }
+            if self.opts.profile { self.emit(format!("  add sp, sp, #{}", PROFILE_FRAME_BYTES)); }
+            self.emit("  add sp, sp, #4096; ldp x29, x30, [sp], #16; ret".to_string());
+            self.frames.push((name.to_string(), stack::FRAME_BYTES + if self.opts.profile { PROFILE_FRAME_BYTES as u32 } else { 0 } + self.stack.peak()));
+            if self.opts.symbol_sizes { self.emit(format!(".size {0}, . - {0}", name)); }
+        }
+    }
+
+    fn lower_stmt(&mut self, n: &IRNode) {
+        let l = n.as_list().unwrap();
+        let head = l[0].as_atom().unwrap();
+        match head.as_str() {
+            "let" => {
+                let name = l[1].as_atom().unwrap();
+                let vtype = l[2].as_atom().unwrap();
+                // The initializer still sees any outer `name` this let shadows.
+                self.lower_expr(&l[3]);
+                self.slots += 1;
+                let off = (self.slots + 1) * 8;
+                self.vars.insert(*name, (off, *vtype));
+                self.str_x29("x0", -off);
+            }
+            "assign" => {
+                let name = l[1].as_atom().unwrap();
+                let off = self.vars.get(name).unwrap().0;
+                self.lower_expr(&l[2]);
+                self.str_x29("x0", -off);
+            }
+            "if" => {
+                let l_else = self.new_label("else");
+                let l_end = self.new_label("endif");
+                self.lower_expr(&l[1]);
+                self.emit(format!("  cbz x0, {}", l_else));
+                self.lower_stmt(&l[2]);
+                self.emit(format!("  b {}", l_end));
+                self.emit(format!("{}:", l_else));
+                if l.len() > 3 { self.lower_stmt(&l[3].as_list().unwrap()[1]); }
+                self.emit(format!("{}:", l_end));
+            }
+            "while" => {
+                let l_start = self.new_label("while");
+                let l_end = self.new_label("endwhile");
+                self.emit(format!("{}:", l_start));
+                self.lower_expr(&l[1]);
+                self.emit(format!("  cbz x0, {}", l_end));
+                self.lower_stmt(&l[2]);
+                self.emit(format!("  b {}", l_start));
+                self.emit(format!("{}:", l_end));
+            }
+            "match" => {
+                let m = MatchArms::new(l);
+                let labels: Vec<String> = m.arms.iter().map(|_| self.new_label("arm")).collect();
+                let l_default = self.new_label("match_default");
+                let l_end = self.new_label("match_end");
+                // Arms compare the low 32 bits of the scrutinee.
+                self.lower_expr(&l[1]);
+                if let Some((low, high)) = m.table_range() {
+                    let table = self.new_label("jump_table");
+                    self.safe_mov_imm("w1", low as u32 as i64);
+                    self.emit("  sub w0, w0, w1".to_string());
+                    self.safe_mov_imm("w1", (high as i64 - low as i64) as u32 as i64);
+                    self.emit(format!("  cmp w0, w1; b.hi {}", l_default));
+                    self.emit(format!("  adrp x1, {0}; add x1, x1, :lo12:{0}", table));
+                    self.emit("  ldrsw x2, [x1, w0, uxtw #2]; add x1, x1, x2; br x1".to_string());
+                    self.emit(".section .rodata".to_string());
+                    self.emit(".balign 4".to_string());
+                    self.emit(format!("{}:", table));
+                    for entry in m.table(low, high) {
+                        self.emit(format!("  .word {} - {}", entry.map(|i| &labels[i]).unwrap_or(&l_default), table));
+                    }
+                    self.emit(".text".to_string());
+                } else {
+                    for ((v, _), label) in m.arms.iter().zip(&labels) {
+                        self.safe_mov_imm("w1", *v as u32 as i64);
+                        self.emit(format!("  cmp w0, w1; b.eq {}", label));
+                    }
+                    self.emit(format!("  b {}", l_default));
+                }
+                for ((_, body), label) in m.arms.iter().zip(labels) {
+                    self.emit(format!("{}:", label));
+                    self.lower_stmt(body);
+                    self.emit(format!("  b {}", l_end));
+                }
+                self.emit(format!("{}:", l_default));
+                if let Some(body) = m.default { self.lower_stmt(body); }
+                self.emit(format!("{}:", l_end));
+            }
+            "block" => {
+                // Lets are scoped to their block; its slots are reused once it ends.
+                let (vars, slots) = (self.vars.clone(), self.slots);
+                for s in &l[1..] { self.lower_stmt(s); }
+                (self.vars, self.slots) = (vars, slots);
+            }
+            "return" => {
+                self.lower_expr(&l[1]);
+                let label = format!(".Lret_{}", self.current_fn);
+                self.emit(format!("  b {}", label));
+            }
+            "svc" => {
+                let args = &l[1..];
+                for arg in args {
+                    self.lower_expr(arg);
+                    self.emit("  str x0, [sp, #-16]!".to_string());
+                }
+                if !args.is_empty() { self.emit("  ldr x8, [sp], #16".to_string()); }
+                if args.len() > 1 { self.emit("  ldr x0, [sp], #16".to_string()); }
+                if args.len() > 2 { self.emit("  ldr x1, [sp], #16".to_string()); }
+                if args.len() > 3 { self.emit("  ldr x2, [sp], #16".to_string()); }
+                self.emit("  svc #0".to_string());
+            }
+            "expr" => { self.lower_expr(&l[1]); }
+            "asm" => {
+                let template = l[1].as_str().unwrap();
+                let lines = expand_asm_template(template, &l[2].as_list().unwrap()[1..], &self.vars, |off| format!("[x29, #-{}]", off));
+                for line in lines { self.emit(line); }
+            }
+            "loc" => {
+                let (at, text) = (l[1].as_str().unwrap(), l[2].as_str().unwrap());
+                self.origins.push((self.out.line, format!("{}: {}", at, text)));
+                if self.opts.listing { self.emit(format!("// {}: {}", at, text)); }
+            }
+            _ => {}
+        }
+    }
+
+    fn lower_expr(&mut self, n: &IRNode) {
+        let l = n.as_list().unwrap();
+        let head = l[0].as_atom().unwrap();
+        match head.as_str() {
+            "int" | "int_i64" | "bool" => {
+                let val: i64 = l[1].as_atom().unwrap().parse().unwrap();
+                self.safe_mov_imm("x0", val);
+            }
+            "uninit" => self.emit("  mov x0, #0".to_string()),
+            "ident" => {
+                let name = l[1].as_atom().unwrap();
+                let off = self.vars.get(name).unwrap().0;
+                self.ldrsw_x29("x0", -off);
+            }
+            "static_index" => {
+                // An unsigned compare also sends negative indexes to the panic.
+                let name = l[1].as_atom().unwrap();
+                let (elem, len) = *self.statics.get(name).unwrap();
+                let l_ok = self.new_label("static_ok");
+                self.lower_expr(&l[2]);
+                self.safe_mov_imm("x9", len as i64);
+                self.emit(format!("  cmp x0, x9; b.lo {}", l_ok));
+                self.lower_expr(&l[3]);
+                self.emit(format!("{}:", l_ok));
+                self.emit(format!("  adrp x9, .Lstatic_{0}; add x9, x9, :lo12:.Lstatic_{0}", name));
+                self.emit(if elem == "u8" { "  ldrb w0, [x9, x0]" } else { "  ldrsw x0, [x9, x0, lsl #2]" }.to_string());
+            }
+            "binary" if l[1].as_atom().is_some_and(|op| op == "mul" || op == "div") && pow2_shift(&l[3]).is_some() => {
+                let k = pow2_shift(&l[3]).unwrap();
+                self.lower_expr(&l[2]);
+                if l[1].as_atom().unwrap() == "mul" { self.emit(format!("  lsl x0, x0, #{}", k)); }
+                else { self.emit(format!("  asr x1, x0, #63; add x0, x0, x1, lsr #{}; asr x0, x0, #{}", 64 - k, k)); }
+            }
+            "binary" if l[1].as_atom().is_some_and(|op| op == "mul") && pow2_shift(&l[2]).is_some() => {
+                self.lower_expr(&l[3]);
+                self.emit(format!("  lsl x0, x0, #{}", pow2_shift(&l[2]).unwrap()));
+            }
+            "binary" => {
+                let op = l[1].as_atom().unwrap();
+                self.lower_expr(&l[2]); self.emit("  str x0, [sp, #-16]!".to_string());
+                self.lower_expr(&l[3]); self.emit("  mov x1, x0; ldr x0, [sp], #16".to_string());
+                match op.as_str() {
+                    "add" => self.emit("  add x0, x0, x1".to_string()),
+                    "sub" => self.emit("  sub x0, x0, x1".to_string()),
+                    "mul" => self.emit("  mul x0, x0, x1".to_string()),
+                    "div" => self.emit("  sdiv x0, x0, x1".to_string()),
+                    "and" => self.emit("  and x0, x0, x1".to_string()),
+                    "or" => self.emit("  orr x0, x0, x1".to_string()),
+                    _ => {
+                        let cond = match op.as_str() { "eq"=>"eq", "ne"=>"ne", "lt"=>"lt", "gt"=>"gt", "le"=>"le", "ge"=>"ge", _=>"eq" };
+                        self.emit(format!("  cmp x0, x1; cset w0, {}", cond));
+                    }
+                }
+            }
+            // `brk` leaves the pc on itself, so a debugger would trap on it again after `continue`;
+            // `kill(getpid(), SIGTRAP)` stops the same way and resumes after the `svc`.
+            "call" if l[1].as_atom().is_some_and(|n| n == "__breakpoint") => self.emit("  mov x8, #172; svc #0; mov x1, #5; mov x8, #129; svc #0; mov x0, #0".to_string()),
+            "call" => {
+                let name = l[1].as_atom().unwrap();
+                let args = &l[2..];
+                for i in (8..args.len()).rev() {
+                    self.lower_expr(&args[i]);
+                    self.emit("  str x0, [sp, #-16]!".to_string());
+                }
+                for arg in args.iter().take(8) {
+                    self.lower_expr(arg);
+                    self.emit("  str x0, [sp, #-16]!".to_string());
+                }
+                for i in (0..std::cmp::min(args.len(), 8)).rev() {
+                    self.emit(format!("  ldr x{}, [sp], #16", i));
+                }
+                self.emit(format!("  bl {}", name));
+                if args.len() > 8 {
+                    self.emit(format!("  add sp, sp, #{}", (args.len() - 8) * 16));
+                }
+            }
+            "string_typed" => {
+                let val = l[1].as_str().unwrap();
+                let off = self.strings.get(val).unwrap();
+                self.safe_mov_imm("x0", *off as i64);
+            }
+            "str_len" | "str_ptr" => self.lower_expr(&l[1]),
+            _ => {}
+        }
+    }
+}
+
+/// Source of the runtime library merged into every program unless `--no-runtime` is given.
+const RUNTIME_SOURCE: &str = include_str!("runtime.coatl");
+/// The `RuntimeArea` enum appended to the runtime's source: the offsets of its scratch
+/// memory, from the layout above, by name.
+fn runtime_area() -> String {
+    format!(
+        "\nenum RuntimeArea {{ LinePos = {}, LineEnd = {}, LineBuf = {}, LineBufSize = {}, Request = {}, Count = {}, FdOut = {} }}\n",
+        RUNTIME_SCRATCH, RUNTIME_SCRATCH + 4, RUNTIME_SCRATCH + 8, LINE_BUF_SIZE, RUNTIME_REQUEST, RUNTIME_REQUEST + 8, RUNTIME_REQUEST + 12,
+    )
+}
+/// Module of the bundled runtime's functions, so `mem_copy` is emitted as `runtime__mem_copy`.
+pub(crate) const RUNTIME_MODULE: &str = "runtime";
+/// Source of the prelude imported into every program unless `--no-prelude` is given.
+const PRELUDE_SOURCE: &str = include_str!("prelude.coatl");
+/// Module of the prelude's functions (`prelude__print`).
+pub(crate) const PRELUDE_MODULE: &str = "prelude";
+
+/// Front-end settings chosen on the command line.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Log each module parsed (`-v`).
+    pub verbose: bool,
+    /// Merge the bundled runtime library (`--no-runtime` turns it off).
+    pub runtime: bool,
+    /// Import the prelude into every file (`--no-prelude` turns it off).
+    pub prelude: bool,
+    /// Architecture matched by `#[cfg(target = "...")]` (`--arch`).
+    pub target: String,
+    /// Dependency packages by name (`--dep=NAME=PATH`), importable as `import "NAME"`.
+    pub dependencies: HashMap<String, PathBuf>,
+    /// Precede every statement with a `(loc "file:line" "text")` node (`--listing`).
+    pub listing: bool,
+    /// Check the memory accesses of the program's own code at run time (`--sanitize=memory`).
+    pub sanitize: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions { verbose: false, runtime: true, prelude: true, target: "x86_64".to_string(), dependencies: HashMap::new(), listing: false, sanitize: false }
+    }
+}
+
+/// Parses the `.coatl` file at `path` and every module it imports into a program IR,
+/// with module-qualified calls resolved. The bundled runtime library and prelude are
+/// merged in as `opts` asks, and the functions of them that the program never calls
+/// are stripped.
+pub fn parse_source(path: &Path, timings: &mut Timings, opts: &ParseOptions) -> Result<IRNode, Vec<String>> {
+    let mut program = Program::default();
+    parse_file_recursive(path.to_path_buf(), None, &opts.dependencies, &mut Loader::default(), &mut program, timings, opts);
+    finish_program(program, timings, opts)
+}
+
+/// [`parse_source`] for a root file given as text, such as a program read from standard
+/// input: locations name it `file`, and its relative imports are found in the current
+/// directory.
+pub fn parse_source_text(source: String, file: &str, timings: &mut Timings, opts: &ParseOptions) -> Result<IRNode, Vec<String>> {
+    let mut program = Program::default();
+    let dir = env::current_dir().map_err(|e| vec![format!("cannot read the current directory: {}", e)])?;
+    if opts.verbose { eprintln!("coatl: parsing {}", file); }
+    parse_text_recursive(SourceFile { text: source, name: file.to_string(), dir }, None, &opts.dependencies, &mut Loader::default(), &mut program, timings, opts);
+    finish_program(program, timings, opts)
+}
+
+/// Parses and checks one self-contained source file given as bytes, for fuzzers and for
+/// services compiling untrusted input. Unlike [`parse_source`] it touches no files: the
+/// bundled runtime and prelude are merged in, but an `import` is an error. It returns the
+/// checked program IR or the errors found; no input makes it panic, hang or overflow the stack,
+/// so it does not depend on `panic = "unwind"`.
+pub fn parse_and_check(input: &[u8]) -> Result<IRNode, Vec<typecheck::Diagnostic>> {
+    let source = std::str::from_utf8(input).map_err(|e| vec![typecheck::Diagnostic::new(format!("input is not valid UTF-8 at byte {}", e.valid_up_to()))])?;
+    let opts = ParseOptions::default();
+    let (mut program, mut timings) = (Program::default(), Timings::default());
+    let imports = parse_module(source.to_string(), "<input>".to_string(), None, &mut program, &mut timings, &opts);
+    program.errors.extend(imports.iter().map(|i| format!("cannot import \"{}\": the input must be a single file", i)));
+    let ir = finish_program(program, &mut timings, &opts).map_err(|errors| errors.into_iter().map(typecheck::Diagnostic::new).collect::<Vec<_>>())?;
+    typecheck::check_program(&ir)?;
+    Ok(ir)
+}
+
+/// Merges the bundled code into the parsed `program` and resolves it into a program IR.
+fn finish_program(mut program: Program, timings: &mut Timings, opts: &ParseOptions) -> Result<IRNode, Vec<String>> {
+    if !program.errors.is_empty() { return Err(program.errors); }
+    let bundled = [(opts.runtime, RUNTIME_MODULE, RUNTIME_SOURCE.to_string() + &runtime_area()), (opts.prelude, PRELUDE_MODULE, PRELUDE_SOURCE.to_string())];
+    for (_, module, source) in bundled.into_iter().filter(|b| b.0) {
+        if opts.verbose { eprintln!("coatl: parsing the bundled {}", module); }
+        parse_module(source, format!("{}.coatl", module), Some(module), &mut program, timings, opts);
+    }
+    program.resolve_enums()?;
+    program.resolve_aliases()?;
+    program.resolve_struct_inits()?;
+    program.resolve_destructures()?;
+    program.resolve_calls()?;
+    program.resolve_named_args()?;
+    program.strip_unused_bundled();
+    let mut ir = program.into_ir();
+    typecheck::resolve_overloads(&mut ir).map_err(|errors| errors.into_iter().map(|e| e.text()).collect::<Vec<_>>())?;
+    consteval::evaluate(&mut ir)?;
+    apply_symbol_attrs(&mut ir)?;
+    Ok(ir)
+}
+
+/// The `(attr name arg...)` marker `name` of a `(fn ...)` or `(struct ...)` node.
+pub(crate) fn item_attr<'a>(item: &'a [IRNode], name: &str) -> Option<&'a [IRNode]> {
+    item.iter().filter_map(|n| n.as_list()).find(|a| a.len() > 1 && a[0].as_atom().is_some_and(|h| h == "attr") && a[1].as_atom().is_some_and(|n| n == name)).map(|a| &a[2..])
+}
+
+/// Gives functions marked `#[export_name = "sym"]` the symbol `sym`, and those marked
+/// `#[no_mangle]` their name as written (without the module prefix or the overload
+/// suffix), renaming their calls to match. Both attributes also make the function global,
+/// like `pub fn`. A symbol that is not a valid identifier, or that two functions end up
+/// sharing, is an error.
+fn apply_symbol_attrs(ir: &mut IRNode) -> Result<(), Vec<String>> {
+    let mut renames: HashMap<Symbol, Symbol> = HashMap::new();
+    let mut errors = Vec::new();
+    let mut seen: HashMap<Symbol, usize> = HashMap::new();
+    for e in ir_section(ir, "externs") {
+        if let Some(name) = e.as_list().and_then(|l| l.get(1)).and_then(|n| n.as_atom()) { *seen.entry(*name).or_default() += 1; }
+    }
+    let IRNode::List(root) = ir else { return Ok(()) };
+    let Some(IRNode::List(fns)) = root.iter_mut().find(|c| c.as_list().and_then(|c| c.first()).and_then(|h| h.as_atom()).is_some_and(|h| h == "functions")) else { return Ok(()) };
+    for f in fns.iter_mut().skip(1) {
+        let IRNode::List(l) = f else { continue };
+        let Some(name) = l.get(1).and_then(|n| n.as_atom()).cloned() else { continue };
+        let symbol = if let Some(args) = item_attr(l, "export_name") {
+            match args.first().and_then(|a| a.as_atom()) {
+                Some(&sym) => sym,
+                None => { errors.push(format!("`#[export_name]` on `{}` needs a symbol, as in `#[export_name = \"name\"]`", mangle::display(&name))); continue }
+            }
+        } else if item_attr(l, "no_mangle").is_some() {
+            let base = mangle::demangle(&name).map(|(_, n)| n).unwrap_or(&name);
+            base.split(mangle::OVERLOAD_SEPARATOR).next().unwrap_or(base).into()
+        } else {
+            continue;
+        };
+        if symbol.is_empty() || symbol.starts_with(|c: char| c.is_ascii_digit()) || !symbol.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '.') {
+            errors.push(format!("`{}` is not a valid symbol name for `{}`", symbol, mangle::display(&name)));
+            continue;
+        }
+        if name == "main" && symbol != "main" {
+            errors.push(format!("`main` cannot be exported as `{}`; use `--entry` to start from another function", symbol));
+            continue;
+        }
+        if !l.iter().any(|m| m.as_list().and_then(|m| m.first()).and_then(|h| h.as_atom()).is_some_and(|h| h == "vis")) {
+            l.push(IRNode::List(vec![IRNode::atom("vis"), IRNode::atom("pub")]));
+        }
+        l[1] = IRNode::atom(symbol);
+        renames.insert(name, symbol);
+    }
+    for f in fns.iter().skip(1) {
+        if let Some(name) = f.as_list().and_then(|l| l.get(1)).and_then(|n| n.as_atom()) { *seen.entry(*name).or_default() += 1; }
+    }
+    for (from, to) in &renames {
+        if seen.get(to).copied().unwrap_or(0) > 1 { errors.push(format!("symbol `{}` of `{}` is already defined by another function or extern", to, mangle::display(from))); }
+    }
+    if !errors.is_empty() {
+        errors.sort();
+        errors.dedup();
+        return Err(errors);
+    }
+    for f in fns.iter_mut() { rename_calls(f, &mut |c| renames.get(c).cloned()); }
+    Ok(())
+}
+
+/// Parses the source text of one file, without its imports or the bundled code, for
+/// `coatl doc`. Calls are left as written and nothing is type checked.
+pub fn parse_items(source: &str, file: &str) -> Result<IRNode, Vec<String>> {
+    let mut program = Program::default();
+    parse_module(source.to_string(), file.to_string(), None, &mut program, &mut Timings::default(), &ParseOptions::default());
+    if !program.errors.is_empty() { return Err(program.errors); }
+    Ok(program.into_ir())
+}
+
+/// What [`lower_to`] reports besides the assembly it writes: the string pool layout and
+/// per-function codegen times shown by `-v` and `--timings=verbose`.
+pub struct Assembly {
+    pub strings: HashMap<String, i32>,
+    pub fn_times: Vec<(String, Duration)>,
+    /// First line of the text (from 1) emitted for each function and, when the IR has
+    /// `(loc ...)` statements, each statement, with a description of it.
+    pub origins: Vec<(usize, String)>,
+    /// Stack bytes each function takes while it runs, by symbol, for [`stack::report`].
+    pub frames: Vec<(String, u32)>,
+}
+
+impl Assembly {
+    /// What line `line` of the text was emitted for, if it follows the startup code.
+    pub fn origin_of(&self, line: usize) -> Option<&str> {
+        self.origins.iter().rev().find(|(start, _)| *start <= line).map(|(_, what)| what.as_str())
+    }
+}
+
+/// Lowers a type-checked program to assembly for `arch` (`x86_64` or `aarch64`), writing
+/// it to `out` as it is generated.
+pub fn lower_to<W: Write>(ir: IRNode, arch: &str, opts: &CodegenOptions, out: W) -> io::Result<Assembly> {
+    let (sink, strings, fn_times, origins, frames) = if arch == "aarch64" {
+        let mut backend = AArch64Backend::new(ir, opts.clone(), out);
+        backend.lower();
+        (backend.out, backend.strings, backend.fn_times, backend.origins, backend.frames)
+    } else {
+        let mut backend = X86_64Backend::new(ir, opts.clone(), out);
+        backend.lower();
+        (backend.out, backend.strings, backend.fn_times, backend.origins, backend.frames)
+    };
+    sink.finish()?;
+    Ok(Assembly { strings, fn_times, origins, frames })
+}
+
+/// [`lower_to`] into a string.
+pub fn lower(ir: IRNode, arch: &str, opts: &CodegenOptions) -> (String, Assembly) {
+    let mut text = Vec::new();
+    let assembly = lower_to(ir, arch, opts, &mut text).expect("writing to memory cannot fail");
+    (String::from_utf8(text).expect("assembly is UTF-8"), assembly)
+}
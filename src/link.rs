@@ -0,0 +1,105 @@
+//! Linking of separately compiled IR programs (`coatl link`).
+//!
+//! Each file can be compiled on its own with `--emit=ir`, declaring what it uses from the
+//! others as `extern fn`. Linking merges the programs into one: an extern that another
+//! program defines as a `pub fn` becomes a call to that function, and the remaining externs
+//! are left for the system linker. Runtime and prelude functions compiled into several of
+//! the programs are kept once; strings are pooled again when the linked program is lowered.
+
+use crate::{ir_section, is_bundled_symbol, IRNode, Symbol};
+use std::collections::HashMap;
+
+/// A function's parameter types and return type, as written in the IR.
+fn signature(item: &IRNode, params_at: usize) -> (Vec<Symbol>, Symbol) {
+    let Some(l) = item.as_list() else { return (Vec::new(), Symbol::default()) };
+    let params = l.get(params_at).and_then(|p| p.as_list()).map(|p| {
+        p[1..].iter().filter_map(|p| p.as_list()?.get(2)?.as_atom().cloned()).collect()
+    }).unwrap_or_default();
+    let ret = l.get(params_at + 1).and_then(|r| r.as_list()?.get(1)?.as_atom().cloned()).unwrap_or_default();
+    (params, ret)
+}
+
+fn show((params, ret): &(Vec<Symbol>, Symbol)) -> String {
+    format!("({}) returns {}", params.join(", "), ret)
+}
+
+fn name_of(item: &IRNode) -> Symbol {
+    item.as_list().and_then(|l| l.get(1)).and_then(|n| n.as_atom()).cloned().unwrap_or_default()
+}
+
+fn is_pub(f: &IRNode) -> bool {
+    f.as_list().is_some_and(|l| l.iter().any(|n| n.as_list().is_some_and(|v| v.len() == 2 && v[0].as_atom().is_some_and(|a| a == "vis") && v[1].as_atom().is_some_and(|a| a == "pub"))))
+}
+
+/// Merges the programs `(file, ir)` into one program, reporting a function or struct
+/// defined differently by two files and an extern that does not match its definition.
+pub fn link(programs: &[(String, IRNode)]) -> Result<IRNode, Vec<String>> {
+    let mut errors = Vec::new();
+    let mut fns: Vec<IRNode> = Vec::new();
+    let mut defined: HashMap<Symbol, &str> = HashMap::new();
+    let mut structs: Vec<IRNode> = Vec::new();
+    let mut struct_files: HashMap<Symbol, &str> = HashMap::new();
+    let mut statics: Vec<IRNode> = Vec::new();
+    let mut static_files: HashMap<Symbol, &str> = HashMap::new();
+    for (file, ir) in programs {
+        for f in ir_section(ir, "functions") {
+            let name = name_of(f);
+            match defined.get(&name) {
+                None => { defined.insert(name, file); fns.push(f.clone()); }
+                Some(_) if is_bundled_symbol(&name) && fns.contains(f) => {}
+                Some(other) => errors.push(format!("fn `{}` is defined in both {} and {}", name, other, file)),
+            }
+        }
+        for s in ir_section(ir, "structs") {
+            let name = name_of(s);
+            match struct_files.get(&name) {
+                None => { struct_files.insert(name, file); structs.push(s.clone()); }
+                Some(_) if structs.contains(s) => {}
+                Some(other) => errors.push(format!("struct `{}` has different fields in {} and {}", name, other, file)),
+            }
+        }
+        for s in ir_section(ir, "statics") {
+            let name = name_of(s);
+            match static_files.get(&name) {
+                None => { static_files.insert(name, file); statics.push(s.clone()); }
+                Some(other) => errors.push(format!("static `{}` is defined in both {} and {}", name, other, file)),
+            }
+        }
+    }
+
+    let mut externs: Vec<IRNode> = Vec::new();
+    let mut declared: HashMap<Symbol, (&str, (Vec<Symbol>, Symbol))> = HashMap::new();
+    for (file, ir) in programs {
+        for e in ir_section(ir, "externs") {
+            let name = name_of(e);
+            let sig = signature(e, 3);
+            if let Some(f) = fns.iter().find(|f| name_of(f) == name) {
+                let def = signature(f, 2);
+                if !is_pub(f) {
+                    errors.push(format!("{} declares extern fn `{}`, but it is not pub in {}", file, name, defined[&name]));
+                } else if def != sig {
+                    errors.push(format!("{} declares extern fn `{}{}`, but {} defines it as `{}{}`", file, name, show(&sig), defined[&name], name, show(&def)));
+                }
+                continue;
+            }
+            match declared.get(&name) {
+                None => { declared.insert(name, (file, sig)); externs.push(e.clone()); }
+                Some((other, prev)) if *prev != sig => errors.push(format!("extern fn `{}` is declared as `{}` in {} and `{}` in {}", name, show(prev), other, show(&sig), file)),
+                Some(_) => {}
+            }
+        }
+    }
+    if !errors.is_empty() { return Err(errors); }
+
+    let section = |name: &str, items: Vec<IRNode>| IRNode::List(std::iter::once(IRNode::atom(name.to_string())).chain(items).collect());
+    let mut root = vec![
+        IRNode::atom("coatl_ir"),
+        IRNode::atom("v1"),
+        section("imports", Vec::new()),
+        section("externs", externs),
+        section("structs", structs),
+        section("functions", fns),
+    ];
+    if !statics.is_empty() { root.push(section("statics", statics)); }
+    Ok(IRNode::List(root))
+}
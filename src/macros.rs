@@ -0,0 +1,121 @@
+//! Statement macros: `macro name(params) { body }` and `name!(args)`.
+//!
+//! A macro is local to the file defining it. After the file is parsed, each `name!(args)`
+//! statement is replaced by the statements of the body, with every use of a parameter
+//! replaced by the argument expression. Locals the body declares with `let` are renamed
+//! with a `__macroN` suffix unique to the expansion, so they never capture or shadow the
+//! caller's variables. A `return` in the body returns from the calling function.
+//!
+//! An expansion may nest the IR deeper than the parser lets source text nest, since an
+//! argument is spliced into the body; one whose statements, where the call was, end up
+//! more than [`MAX_NESTING`] lists deep is reported.
+
+use crate::{IRNode, Symbol, MAX_NESTING};
+use std::collections::HashMap;
+
+/// Expansions nested deeper than this are reported as runaway recursion.
+const MAX_DEPTH: usize = 32;
+/// Expansions in one file beyond this are reported, so macros invoking each other several
+/// times per level cannot grow the program exponentially.
+const MAX_EXPANSIONS: usize = 100_000;
+
+pub(crate) struct Macro {
+    pub params: Vec<Symbol>,
+    /// Statements of the body block.
+    pub body: Vec<IRNode>,
+}
+
+/// Expands every `(macro_call name "file:line:col" args...)` statement under `node`.
+pub(crate) fn expand(node: &mut IRNode, macros: &HashMap<Symbol, Macro>, expansions: &mut usize, errors: &mut Vec<String>) {
+    expand_in(node, macros, expansions, errors, 0, 1);
+}
+
+/// [`expand`] for `node`, which is `lists` lists deep, inside `depth` expansions.
+fn expand_in(node: &mut IRNode, macros: &HashMap<Symbol, Macro>, expansions: &mut usize, errors: &mut Vec<String>, depth: usize, lists: usize) {
+    let IRNode::List(items) = node else { return };
+    let mut out = Vec::with_capacity(items.len());
+    for mut item in items.drain(..) {
+        let Some((name, at, args)) = macro_call(&item) else {
+            expand_in(&mut item, macros, expansions, errors, depth, lists + 1);
+            out.push(item);
+            continue;
+        };
+        let Some(m) = macros.get(&name) else {
+            errors.push(format!("{}: unknown macro `{}`", at, name));
+            continue;
+        };
+        if m.params.len() != args.len() {
+            errors.push(format!("{}: macro `{}` takes {} argument(s), got {}", at, name, m.params.len(), args.len()));
+            continue;
+        }
+        if depth >= MAX_DEPTH {
+            errors.push(format!("{}: expansion of macro `{}` nests more than {} levels deep", at, name, MAX_DEPTH));
+            continue;
+        }
+        if *expansions >= MAX_EXPANSIONS {
+            errors.push(format!("{}: expanding macro `{}` exceeds the limit of {} expansions per file", at, name, MAX_EXPANSIONS));
+            continue;
+        }
+        *expansions += 1;
+        let mut renames = HashMap::new();
+        for stmt in &m.body { collect_lets(stmt, &mut renames, *expansions); }
+        let bound: HashMap<&str, &IRNode> = m.params.iter().map(|p| p.as_str()).zip(args).collect();
+        let mut body = IRNode::List(m.body.clone());
+        substitute(&mut body, &bound, &renames);
+        // The body's statements take the call's place, one list below `node`.
+        if lists + nesting(&body) - 1 > MAX_NESTING {
+            errors.push(format!("{}: Nesting deeper than {} levels in the expansion of macro `{}`", at, MAX_NESTING, name));
+            continue;
+        }
+        expand_in(&mut body, macros, expansions, errors, depth + 1, lists);
+        if let IRNode::List(stmts) = body { out.extend(stmts); }
+    }
+    *items = out;
+}
+
+/// How many lists deep `node` nests, counting itself.
+fn nesting(node: &IRNode) -> usize {
+    node.as_list().map_or(0, |l| 1 + l.iter().map(nesting).max().unwrap_or(0))
+}
+
+fn macro_call(item: &IRNode) -> Option<(Symbol, Symbol, &[IRNode])> {
+    let l = item.as_list()?;
+    if l.first()?.as_atom()? != "macro_call" { return None; }
+    Some((*l.get(1)?.as_atom()?, *l.get(2)?.as_atom()?, &l[3..]))
+}
+
+/// Maps each name the body declares with `let` to its renamed, expansion-unique form.
+fn collect_lets(node: &IRNode, renames: &mut HashMap<Symbol, Symbol>, expansion: usize) {
+    let Some(l) = node.as_list() else { return };
+    if l.first().and_then(|h| h.as_atom()).map(|h| h == "let").unwrap_or(false) && let Some(name) = l.get(1).and_then(|n| n.as_atom()) {
+        renames.insert(*name, format!("{}__macro{}", name, expansion).into());
+    }
+    for child in l { collect_lets(child, renames, expansion); }
+}
+
+/// Replaces parameter uses with their arguments and renames the body's own locals.
+/// Variables appear as `(ident x)` and as the bare name in the second slot of the nodes
+/// below; a parameter there can only be replaced by an argument that is itself a variable.
+fn substitute(node: &mut IRNode, args: &HashMap<&str, &IRNode>, renames: &HashMap<Symbol, Symbol>) {
+    let IRNode::List(l) = node else { return };
+    let head = l.first().and_then(|h| h.as_atom()).cloned().unwrap_or_default();
+    if head == "ident" && let Some(name) = l.get(1).and_then(|n| n.as_atom()) {
+        if let Some(arg) = args.get(name.as_str()) {
+            *node = (*arg).clone();
+            return;
+        }
+        if let Some(renamed) = renames.get(name) { l[1] = IRNode::atom(*renamed); }
+        return;
+    }
+    let rename = |atom: &mut IRNode| {
+        let Some(name) = atom.as_atom() else { return };
+        let var = args.get(name.as_str()).and_then(|a| a.as_list()).filter(|a| a.len() == 2 && a[0].as_atom().map(|h| h == "ident").unwrap_or(false));
+        if let Some(var) = var { *atom = var[1].clone(); } else if let Some(renamed) = renames.get(name) { *atom = IRNode::atom(*renamed); }
+    };
+    match head.as_str() {
+        "let" | "assign" | "array_assign" | "field_assign" | "field" | "array_index" | "slice_index" | "slice_assign" => { if let Some(a) = l.get_mut(1) { rename(a); } }
+        "operands" => { for a in l.iter_mut().skip(1) { rename(a); } }
+        _ => {}
+    }
+    for child in l.iter_mut() { substitute(child, args, renames); }
+}
@@ -1,1219 +1,726 @@
-mod intrinsics;
-
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 use std::process;
-use std::collections::{HashMap, HashSet};
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum IRNode {
-    Atom(String),
-    List(Vec<IRNode>),
+use std::time::Instant;
+
+use coatl::{callgraph, cfg, doc, link, manifest, mangle, map, stack, tags, typecheck, IRParser, CodegenOptions, ParseOptions, Syscalls, Timings};
+use coatl::{collect_intrinsic_calls, fold_constants, ir_section, json_string, layout_json, log_layout, lower, lower_to, parse_size, parse_source, parse_source_text, prefix_exports, unknown_calls};
+
+/// `--target` triples and the `--arch` each selects.
+const TARGETS: &[(&str, &str)] = &[("x86_64-linux", "x86_64"), ("aarch64-linux", "aarch64")];
+
+/// Stack budget `--profile=debug` checks unless `--stack-limit` is given: most of the
+/// usual 8 MiB main thread stack.
+const DEBUG_STACK_LIMIT: u32 = 7 * 1024 * 1024;
+
+/// Exit statuses, one per kind of failure, so build systems can tell them apart.
+/// Anything else, such as a file that cannot be read or written, is `EXIT_FAILURE`.
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_PARSE: i32 = 3;
+const EXIT_TYPE: i32 = 4;
+const EXIT_CODEGEN: i32 = 5;
+
+/// What the build did, printed on stdout as one JSON object when it ends (`--summary=json`).
+#[derive(Default)]
+struct Summary {
+    json: bool,
+    warnings: usize,
+    /// Paths of the artifacts written so far.
+    artifacts: Vec<String>,
 }
 
-impl IRNode {
-    pub fn is_list(&self) -> bool { matches!(self, IRNode::List(_)) }
-    pub fn as_list(&self) -> Option<&Vec<IRNode>> {
-        match self { IRNode::List(l) => Some(l), _ => None }
-    }
-    pub fn as_atom(&self) -> Option<&String> {
-        match self { IRNode::Atom(s) => Some(s), _ => None }
-    }
-    pub fn to_ir(&self) -> String {
-        match self {
-            IRNode::Atom(s) => {
-                if s.contains(' ') || s.is_empty() || s.contains('\n') || s.contains('\"') {
-                    format!("\"{}\"", s.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n").replace("\r", "\\r").replace("\t", "\\t"))
-                } else {
-                    s.clone()
-                }
-            }
-            IRNode::List(l) => {
-                let mut res = String::from("(");
-                for (i, item) in l.iter().enumerate() {
-                    if i > 0 { res.push(' '); }
-                    res.push_str(&item.to_ir());
-                }
-                res.push(')');
-                res
-            }
+impl Summary {
+    /// Exits with `code` after `errors` errors were reported, printing the summary first.
+    fn exit(&self, code: i32, errors: usize) -> ! {
+        if self.json {
+            let status = match code { 0 => "ok", EXIT_USAGE => "usage", EXIT_PARSE => "parse", EXIT_TYPE => "type", EXIT_CODEGEN => "codegen", _ => "failure" };
+            let artifacts: Vec<String> = self.artifacts.iter().map(|a| json_string(a)).collect();
+            println!("{{ \"status\": \"{}\", \"exit_code\": {}, \"errors\": {}, \"warnings\": {}, \"artifacts\": [{}] }}", status, code, errors, self.warnings, artifacts.join(", "));
         }
+        process::exit(code)
     }
 }
 
-pub struct IRParser {
-    tokens: Vec<String>,
-    pos: usize,
-}
+/// Build profile (`--profile`): a bundle of defaults for development or shipping.
+#[derive(Clone, Copy, PartialEq)]
+enum Profile { Debug, Release }
 
-impl IRParser {
-    pub fn new(input: &str) -> Self {
-        let mut tokens = Vec::new();
-        let mut chars = input.chars().peekable();
-        while let Some(&c) = chars.peek() {
-            if c.is_whitespace() { chars.next(); }
-            else if c == '(' || c == ')' { tokens.push(c.to_string()); chars.next(); }
-            else if c == '"' {
-                chars.next();
-                let mut s = String::new();
-                while let Some(nc) = chars.next() {
-                    if nc == '"' { break; }
-                    if nc == '\\' {
-                        if let Some(esc) = chars.next() {
-                            s.push(match esc {
-                                'n' => '\n', 'r' => '\r', 't' => '\t', _ => esc,
-                            });
-                        }
-                    } else { s.push(nc); }
-                }
-                tokens.push(format!("\"{}\"", s));
-            } else {
-                let mut s = String::new();
-                while let Some(&nc) = chars.peek() {
-                    if nc.is_whitespace() || nc == '(' || nc == ')' { break; }
-                    s.push(chars.next().unwrap());
-                }
-                tokens.push(s);
-            }
+/// Artifact to produce, from `--emit` or else the extension of the `-o` path.
+#[derive(Clone, Copy, PartialEq)]
+enum Emit { Asm, Ir, CfgDot, CallGraph, CallGraphJson, Obj, Exe, Shared }
+
+impl Emit {
+    fn parse(kind: &str) -> Option<Emit> {
+        match kind {
+            "asm" => Some(Emit::Asm),
+            "ir" => Some(Emit::Ir),
+            "cfg-dot" => Some(Emit::CfgDot),
+            "callgraph" => Some(Emit::CallGraph),
+            "callgraph-json" => Some(Emit::CallGraphJson),
+            "obj" => Some(Emit::Obj),
+            "exe" => Some(Emit::Exe),
+            "so" => Some(Emit::Shared),
+            _ => None,
         }
-        Self { tokens, pos: 0 }
     }
 
-    pub fn parse(&mut self) -> Option<IRNode> {
-        if self.pos >= self.tokens.len() { return None; }
-        let token = &self.tokens[self.pos];
-        self.pos += 1;
-        if token == "(" {
-            let mut list = Vec::new();
-            while self.pos < self.tokens.len() && self.tokens[self.pos] != ")" {
-                if let Some(node) = self.parse() { list.push(node); }
-            }
-            if self.pos < self.tokens.len() { self.pos += 1; }
-            Some(IRNode::List(list))
-        } else if token.starts_with('"') && token.ends_with('"') {
-            Some(IRNode::Atom(token[1..token.len()-1].to_string()))
-        } else {
-            Some(IRNode::Atom(token.clone()))
+    /// File name of this artifact of the input named `stem`, as written into `--out-dir`.
+    fn file_name(self, stem: &str) -> String {
+        match self {
+            Emit::Asm => format!("{}.s", stem),
+            Emit::Ir => format!("{}.ir", stem),
+            Emit::CfgDot => format!("{}.dot", stem),
+            Emit::CallGraph => format!("{}.callgraph.dot", stem),
+            Emit::CallGraphJson => format!("{}.callgraph.json", stem),
+            Emit::Obj => format!("{}.o", stem),
+            Emit::Exe => stem.to_string(),
+            Emit::Shared => format!("lib{}.so", stem),
         }
     }
-}
-
-#[derive(Debug, Clone, PartialEq)]
-enum TokenKind { Ident, Num, Str, Sym, Eof }
-
-#[derive(Debug, Clone)]
-struct Token {
-    kind: TokenKind,
-    value: String,
-    line: usize,
-    col: usize,
-}
 
-struct Lexer {
-    source: Vec<char>,
-    pos: usize,
-    line: usize,
-    col: usize,
-}
-
-impl Lexer {
-    fn new(source: String) -> Self {
-        Self { source: source.chars().collect(), pos: 0, line: 1, col: 1 }
-    }
-    fn peek(&self, n: usize) -> Option<char> {
-        if self.pos + n < self.source.len() { Some(self.source[self.pos + n]) } else { None }
-    }
-    fn advance(&mut self) -> Option<char> {
-        let c = self.peek(0)?;
-        self.pos += 1;
-        if c == '\n' { self.line += 1; self.col = 1; } else { self.col += 1; }
-        Some(c)
-    }
-    fn tokenize(&mut self) -> Vec<Token> {
-        let mut tokens = Vec::new();
-        while self.pos < self.source.len() {
-            let c = self.peek(0).unwrap();
-            if c.is_whitespace() { self.advance(); }
-            else if c == '/' && self.peek(1) == Some('/') {
-                while self.peek(0).is_some() && self.peek(0) != Some('\n') { self.advance(); }
-            } else if c.is_alphabetic() || c == '_' {
-                let (sl, sc) = (self.line, self.col);
-                let mut val = String::new();
-                while let Some(nc) = self.peek(0) {
-                    if nc.is_alphanumeric() || nc == '_' { val.push(self.advance().unwrap()); } else { break; }
-                }
-                tokens.push(Token { kind: TokenKind::Ident, value: val, line: sl, col: sc });
-            } else if c.is_digit(10) {
-                let (sl, sc) = (self.line, self.col);
-                let mut val = String::new();
-                if c == '0' && self.peek(1) == Some('x') {
-                    val.push(self.advance().unwrap()); val.push(self.advance().unwrap());
-                    while let Some(nc) = self.peek(0) {
-                        if nc.is_digit(16) { val.push(self.advance().unwrap()); } else { break; }
-                    }
-                } else {
-                    while let Some(nc) = self.peek(0) {
-                        if nc.is_digit(10) || nc == '.' { val.push(self.advance().unwrap()); } else { break; }
-                    }
-                }
-                for suf in ["i64", "i32", "f64", "f32"] {
-                    let mut match_suf = true;
-                    for (j, sc) in suf.chars().enumerate() {
-                        if self.peek(j) != Some(sc) { match_suf = false; break; }
-                    }
-                    if match_suf {
-                        val.push_str(suf);
-                        for _ in 0..suf.len() { self.advance(); }
-                        break;
-                    }
-                }
-                tokens.push(Token { kind: TokenKind::Num, value: val, line: sl, col: sc });
-            } else if c == '"' {
-                let (sl, sc) = (self.line, self.col);
-                self.advance();
-                let mut val = String::new();
-                while let Some(nc) = self.peek(0) {
-                    if nc == '"' { break; }
-                    if nc == '\\' {
-                        self.advance();
-                        let esc = self.advance().unwrap();
-                        let char_to_push = match esc {
-                            'n' => '\n', 't' => '\t', 'r' => '\r', '"' => '"', '\\' => '\\',
-                            'x' => {
-                                let h1 = self.advance().unwrap(); let h2 = self.advance().unwrap();
-                                u8::from_str_radix(&format!("{}{}", h1, h2), 16).unwrap() as char
-                            }
-                            _ => { val.push('\\'); esc }
-                        };
-                        val.push(char_to_push);
-                    } else { val.push(self.advance().unwrap()); }
-                }
-                self.advance();
-                tokens.push(Token { kind: TokenKind::Str, value: val, line: sl, col: sc });
-            } else {
-                let (sl, sc) = (self.line, self.col);
-                let mut sym = String::new();
-                for s in ["==", "!=", "<=", ">=", "->", "&&", "||"] {
-                    let mut match_s = true;
-                    for (j, sc) in s.chars().enumerate() {
-                        if self.peek(j) != Some(sc) { match_s = false; break; }
-                    }
-                    if match_s { sym = s.to_string(); break; }
-                }
-                if !sym.is_empty() {
-                    for _ in 0..sym.len() { self.advance(); }
-                } else {
-                    sym.push(self.advance().unwrap());
-                }
-                tokens.push(Token { kind: TokenKind::Sym, value: sym, line: sl, col: sc });
-            }
+    fn from_output(path: &str) -> Emit {
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            _ if path.is_empty() || path == "-" => Emit::Asm,
+            Some("s") => Emit::Asm,
+            Some("ir") => Emit::Ir,
+            Some("dot") => Emit::CfgDot,
+            Some("o") => Emit::Obj,
+            Some("so") => Emit::Shared,
+            _ => Emit::Exe,
         }
-        tokens.push(Token { kind: TokenKind::Eof, value: String::new(), line: self.line, col: self.col });
-        tokens
     }
 }
 
-struct Parser {
-    tokens: Vec<Token>,
-    pos: usize,
+/// Expands `coatl build [flags]` into the arguments given by the nearest `coatl.toml`,
+/// followed by `flags`, which override them. `coatl build - [flags]` compiles standard
+/// input in place of the package's root file, or is `coatl - [flags]` outside a package.
+fn build_args(args: &[String]) -> Vec<String> {
+    let stdin = args.get(2).is_some_and(|a| a == "-");
+    let cwd = env::current_dir().expect("Failed to read the current directory");
+    let Some(path) = manifest::find(&cwd) else {
+        if stdin { return args[..1].iter().chain(&args[2..]).cloned().collect(); }
+        eprintln!("error: no {} found in {} or any parent directory", manifest::FILE_NAME, cwd.display());
+        process::exit(EXIT_USAGE);
+    };
+    let manifest = manifest::Manifest::load(&path).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(EXIT_USAGE)
+    });
+    let mut build = manifest.to_args();
+    if stdin { build[0] = "-".to_string(); }
+    if let Some(dir) = Path::new(&build[2]).parent() { let _ = fs::create_dir_all(dir); }
+    args[..1].iter().cloned().chain(build).chain(args[2 + stdin as usize..].iter().cloned()).collect()
 }
 
-impl Parser {
-    fn new(tokens: Vec<Token>) -> Self { Self { tokens, pos: 0 } }
-    fn peek(&self, n: usize) -> &Token {
-        if self.pos + n < self.tokens.len() { &self.tokens[self.pos + n] } else { &self.tokens[self.tokens.len() - 1] }
-    }
-    fn consume(&mut self, kind: Option<TokenKind>, val: Option<&str>) -> Token {
-        let t = self.peek(0).clone();
-        if let Some(k) = kind { if t.kind != k { panic!("Expected {:?}, got {:?} at {}:{}", k, t.kind, t.line, t.col); } }
-        if let Some(v) = val { if t.value != v { panic!("Expected {}, got {} at {}:{}", v, t.value, t.line, t.col); } }
-        self.pos += 1;
-        t
-    }
-    fn parse_type(&mut self) -> String {
-        let t = self.peek(0);
-        if t.value == "[" {
-            self.consume(None, Some("["));
-            let ty = self.parse_type();
-            let sz = self.consume(Some(TokenKind::Num), None).value;
-            self.consume(None, Some("]"));
-            format!("[{} {}]", ty, sz)
-        } else if t.value == "*" {
-            self.consume(None, Some("*"));
-            format!("*{}", self.parse_type())
-        } else { self.consume(Some(TokenKind::Ident), None).value }
-    }
-    fn parse_struct(&mut self) -> IRNode {
-        self.consume(Some(TokenKind::Ident), Some("struct"));
-        let name = self.consume(Some(TokenKind::Ident), None).value;
-        let mut fields = vec![IRNode::Atom("struct".to_string()), IRNode::Atom(name)];
-        if self.peek(0).value == "{" {
-            self.consume(None, Some("{"));
-            while self.peek(0).value != "}" {
-                let fn_name = self.consume(Some(TokenKind::Ident), None).value;
-                self.consume(None, Some(":"));
-                let ft = self.parse_type();
-                fields.push(IRNode::List(vec![IRNode::Atom("field".to_string()), IRNode::Atom(fn_name), IRNode::Atom(ft)]));
-                if self.peek(0).value == "," { self.consume(None, Some(",")); }
-            }
-            self.consume(None, Some("}"));
-        }
-        IRNode::List(fields)
-    }
-    fn parse_fn(&mut self) -> IRNode {
-        self.consume(Some(TokenKind::Ident), Some("fn"));
-        let name = self.consume(Some(TokenKind::Ident), None).value;
-        self.consume(None, Some("("));
-        let mut params = vec![IRNode::Atom("params".to_string())];
-        while self.peek(0).value != ")" {
-            let pn = self.consume(Some(TokenKind::Ident), None).value;
-            self.consume(None, Some(":"));
-            let pt = self.parse_type();
-            params.push(IRNode::List(vec![IRNode::Atom("param".to_string()), IRNode::Atom(pn), IRNode::Atom(pt)]));
-            if self.peek(0).value == "," { self.consume(None, Some(",")); }
-        }
-        self.consume(None, Some(")"));
-        let mut rt = "i32".to_string();
-        if self.peek(0).value == "returns" || self.peek(0).value == "->" {
-            self.consume(None, None);
-            rt = self.parse_type();
-        }
-        let mut block = vec![IRNode::Atom("block".to_string())];
-        if self.peek(0).value == "{" {
-            self.consume(None, Some("{"));
-            while self.peek(0).value != "}" { block.push(self.parse_stmt()); }
-            self.consume(None, Some("}"));
-        }
-        IRNode::List(vec![IRNode::Atom("fn".to_string()), IRNode::Atom(name), IRNode::List(params), IRNode::List(vec![IRNode::Atom("ret".to_string()), IRNode::Atom(rt)]), IRNode::List(block)])
-    }
-    fn parse_stmt(&mut self) -> IRNode {
-        let t = self.peek(0);
-        if t.value == "let" {
-            self.consume(None, Some("let"));
-            let n = self.consume(Some(TokenKind::Ident), None).value;
-            self.consume(None, Some(":"));
-            let ty = self.parse_type();
-            self.consume(None, Some("="));
-            let e = self.parse_expr();
-            if self.peek(0).value == ";" { self.consume(None, Some(";")); }
-            IRNode::List(vec![IRNode::Atom("let".to_string()), IRNode::Atom(n), IRNode::Atom(ty), e])
-        } else if t.value == "return" {
-            self.consume(None, Some("return"));
-            let e = self.parse_expr();
-            if self.peek(0).value == ";" { self.consume(None, Some(";")); }
-            IRNode::List(vec![IRNode::Atom("return".to_string()), e])
-        } else if t.value == "svc" {
-            self.consume(None, Some("svc"));
-            let mut args = vec![IRNode::Atom("svc".to_string())];
-            if self.peek(0).value == "(" {
-                self.consume(None, Some("("));
-                while self.peek(0).value != ")" {
-                    args.push(self.parse_expr());
-                    if self.peek(0).value == "," { self.consume(None, Some(",")); }
-                }
-                self.consume(None, Some(")"));
-            } else {
-                while self.peek(0).kind != TokenKind::Sym && self.peek(0).kind != TokenKind::Eof {
-                    args.push(self.parse_expr());
-                    if self.peek(0).value == "," { self.consume(None, Some(",")); }
-                }
-            }
-            if self.peek(0).value == ";" { self.consume(None, Some(";")); }
-            IRNode::List(args)
-        } else if t.value == "syscall" {
-            self.consume(None, Some("syscall"));
-            let mut args = vec![IRNode::Atom("syscall".to_string())];
-            if self.peek(0).value == "(" {
-                self.consume(None, Some("("));
-                while self.peek(0).value != ")" {
-                    args.push(self.parse_expr());
-                    if self.peek(0).value == "," { self.consume(None, Some(",")); }
-                }
-                self.consume(None, Some(")"));
-            }
-            if self.peek(0).value == ";" { self.consume(None, Some(";")); }
-            IRNode::List(args)
-        } else if t.value == "if" {
-            self.consume(None, Some("if"));
-            let c = self.parse_expr();
-            self.consume(None, Some("{"));
-            let mut th = vec![IRNode::Atom("block".to_string())];
-            while self.peek(0).value != "}" { th.push(self.parse_stmt()); }
-            self.consume(None, Some("}"));
-            let mut res = vec![IRNode::Atom("if".to_string()), c, IRNode::List(th)];
-            if self.peek(0).value == "else" {
-                self.consume(None, Some("else"));
-                self.consume(None, Some("{"));
-                let mut el = vec![IRNode::Atom("block".to_string())];
-                while self.peek(0).value != "}" { el.push(self.parse_stmt()); }
-                self.consume(None, Some("}"));
-                res.push(IRNode::List(vec![IRNode::Atom("else".to_string()), IRNode::List(el)]));
-            }
-            IRNode::List(res)
-        } else if t.value == "while" {
-            self.consume(None, Some("while"));
-            let c = self.parse_expr();
-            self.consume(None, Some("{"));
-            let mut b = vec![IRNode::Atom("block".to_string())];
-            while self.peek(0).value != "}" { b.push(self.parse_stmt()); }
-            self.consume(None, Some("}"));
-            IRNode::List(vec![IRNode::Atom("while".to_string()), c, IRNode::List(b)])
-        } else if t.kind == TokenKind::Ident && self.peek(1).value == "[" {
-            let n = self.consume(Some(TokenKind::Ident), None).value;
-            self.consume(None, Some("["));
-            let idx = self.parse_expr();
-            self.consume(None, Some("]"));
-            self.consume(None, Some("="));
-            let e = self.parse_expr();
-            if self.peek(0).value == ";" { self.consume(None, Some(";")); }
-            IRNode::List(vec![IRNode::Atom("array_assign".to_string()), IRNode::Atom(n), idx, e])
-        } else if t.kind == TokenKind::Ident && self.peek(1).value == "=" {
-            let n = self.consume(Some(TokenKind::Ident), None).value;
-            self.consume(None, Some("="));
-            let e = self.parse_expr();
-            if self.peek(0).value == ";" { self.consume(None, Some(";")); }
-            IRNode::List(vec![IRNode::Atom("assign".to_string()), IRNode::Atom(n), e])
-        } else if t.kind == TokenKind::Ident && self.peek(1).value == "." && self.peek(3).value == "=" {
-            let v = self.consume(Some(TokenKind::Ident), None).value;
-            self.consume(None, Some("."));
-            let f = self.consume(Some(TokenKind::Ident), None).value;
-            self.consume(None, Some("="));
-            let e = self.parse_expr();
-            if self.peek(0).value == ";" { self.consume(None, Some(";")); }
-            IRNode::List(vec![IRNode::Atom("field_assign".to_string()), IRNode::Atom(v), IRNode::Atom(f), e])
-        } else {
-            let e = self.parse_expr();
-            if self.peek(0).value == ";" { self.consume(None, Some(";")); }
-            IRNode::List(vec![IRNode::Atom("expr".to_string()), e])
-        }
-    }
-    fn parse_expr(&mut self) -> IRNode { self.parse_or() }
-    fn parse_or(&mut self) -> IRNode {
-        let mut l = self.parse_and();
-        while self.peek(0).value == "||" || self.peek(0).value == "|" {
-            self.consume(None, None);
-            l = IRNode::List(vec![IRNode::Atom("binary".to_string()), IRNode::Atom("or".to_string()), l, self.parse_and()]);
+/// `.coatl` files under `dir`, skipping hidden directories and `target`, in path order.
+fn source_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+    paths.sort();
+    for path in paths {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" { source_files(&path, out); }
+        } else if name.ends_with(".coatl") {
+            out.push(path);
         }
-        l
-    }
-    fn parse_and(&mut self) -> IRNode {
-        let mut l = self.parse_cmp();
-        while self.peek(0).value == "&&" || self.peek(0).value == "&" {
-            self.consume(None, None);
-            l = IRNode::List(vec![IRNode::Atom("binary".to_string()), IRNode::Atom("and".to_string()), l, self.parse_cmp()]);
-        }
-        l
-    }
-    fn parse_cmp(&mut self) -> IRNode {
-        let mut l = self.parse_add();
-        let ops: HashMap<&str, &str> = [("==", "eq"), ("!=", "ne"), ("<", "lt"), (">", "gt"), ("<=", "le"), (">=", "ge")].iter().cloned().collect();
-        let val = self.peek(0).value.as_str();
-        if let Some(&op) = ops.get(val) {
-            self.consume(None, None);
-            l = IRNode::List(vec![IRNode::Atom("binary".to_string()), IRNode::Atom(op.to_string()), l, self.parse_add(), IRNode::Atom("bool".to_string())]);
-        }
-        l
-    }
-    fn parse_add(&mut self) -> IRNode {
-        let mut l = self.parse_mul();
-        while self.peek(0).value == "+" || self.peek(0).value == "-" {
-            let op = if self.consume(None, None).value == "+" { "add" } else { "sub" };
-            l = IRNode::List(vec![IRNode::Atom("binary".to_string()), IRNode::Atom(op.to_string()), l, self.parse_mul()]);
-        }
-        l
-    }
-    fn parse_mul(&mut self) -> IRNode {
-        let mut l = self.parse_term();
-        while self.peek(0).value == "*" || self.peek(0).value == "/" {
-            let op = if self.consume(None, None).value == "*" { "mul" } else { "div" };
-            l = IRNode::List(vec![IRNode::Atom("binary".to_string()), IRNode::Atom(op.to_string()), l, self.parse_term()]);
-        }
-        l
-    }
-    fn parse_term(&mut self) -> IRNode {
-        let t = self.peek(0).clone();
-        if t.value == "!" {
-            self.consume(None, None);
-            IRNode::List(vec![IRNode::Atom("binary".to_string()), IRNode::Atom("eq".to_string()), self.parse_term(), IRNode::List(vec![IRNode::Atom("int".to_string()), IRNode::Atom("0".to_string())]), IRNode::Atom("bool".to_string())])
-        } else if t.value == "svc" {
-            self.consume(None, None);
-            let imm = self.consume(Some(TokenKind::Num), None).value;
-            if self.peek(0).value == ";" { self.consume(None, Some(";")); }
-            IRNode::List(vec![IRNode::Atom("svc".to_string()), IRNode::Atom(imm)])
-        } else if t.value == "syscall" {
-            self.consume(None, None);
-            if self.peek(0).value == ";" { self.consume(None, Some(";")); }
-            IRNode::List(vec![IRNode::Atom("syscall".to_string())])
-        } else if t.value == "(" {
-            self.consume(None, Some("("));
-            let e = self.parse_expr();
-            self.consume(None, Some(")"));
-            e
-        } else if t.value == "[" {
-            self.consume(None, Some("["));
-            let val = self.parse_expr();
-            let sz = self.consume(Some(TokenKind::Num), None).value;
-            self.consume(None, Some("]"));
-            IRNode::List(vec![IRNode::Atom("array_lit".to_string()), val, IRNode::Atom(sz)])
-        } else if t.kind == TokenKind::Num {
-            let v = self.consume(Some(TokenKind::Num), None).value;
-            if v.ends_with("i64") { IRNode::List(vec![IRNode::Atom("int_i64".to_string()), IRNode::Atom(v[..v.len()-3].to_string())]) }
-            else if v.ends_with("f32") { IRNode::List(vec![IRNode::Atom("f32".to_string()), IRNode::Atom(v[..v.len()-3].to_string())]) }
-            else if v.ends_with("f64") { IRNode::List(vec![IRNode::Atom("f64".to_string()), IRNode::Atom(v[..v.len()-3].to_string())]) }
-            else if v.ends_with("i32") { IRNode::List(vec![IRNode::Atom("int".to_string()), IRNode::Atom(v[..v.len()-3].to_string())]) }
-            else { IRNode::List(vec![IRNode::Atom("int".to_string()), IRNode::Atom(v)]) }
-        } else if t.kind == TokenKind::Str {
-            IRNode::List(vec![IRNode::Atom("string_typed".to_string()), IRNode::Atom(self.consume(Some(TokenKind::Str), None).value)])
-        } else if t.kind == TokenKind::Ident {
-            let n = self.consume(Some(TokenKind::Ident), None).value;
-            if n == "true" || n == "false" { return IRNode::List(vec![IRNode::Atom("bool".to_string()), IRNode::Atom(if n == "true" { "1" } else { "0" }.to_string())]); }
-            if self.peek(0).value == "{" {
-                self.consume(None, Some("{"));
-                let mut fields = vec![IRNode::Atom("struct_lit".to_string()), IRNode::Atom(n)];
-                while self.peek(0).value != "}" {
-                    self.consume(Some(TokenKind::Ident), None); self.consume(None, Some(":"));
-                    fields.push(self.parse_expr());
-                    if self.peek(0).value == "," { self.consume(None, Some(",")); }
-                }
-                self.consume(None, Some("}"));
-                return IRNode::List(fields);
-            }
-            if self.peek(0).value == "(" {
-                self.consume(None, Some("("));
-                let mut args = Vec::new();
-                while self.peek(0).value != ")" {
-                    args.push(self.parse_expr());
-                    if self.peek(0).value == "," { self.consume(None, Some(",")); }
-                }
-                self.consume(None, Some(")"));
-                if n == "str_len" { return IRNode::List(vec![IRNode::Atom("str_len".to_string()), args[0].clone()]); }
-                if n == "str_ptr" { return IRNode::List(vec![IRNode::Atom("str_ptr".to_string()), args[0].clone()]); }
-                let mut call = vec![IRNode::Atom("call".to_string()), IRNode::Atom(n)];
-                call.extend(args);
-                return IRNode::List(call);
-            }
-            if self.peek(0).value == "." {
-                self.consume(None, Some("."));
-                return IRNode::List(vec![IRNode::Atom("field".to_string()), IRNode::Atom(n), IRNode::Atom(self.consume(Some(TokenKind::Ident), None).value)]);
-            }
-            if self.peek(0).value == "[" {
-                self.consume(None, Some("["));
-                let idx = self.parse_expr();
-                self.consume(None, Some("]"));
-                return IRNode::List(vec![IRNode::Atom("array_index".to_string()), IRNode::Atom(n), idx]);
-            }
-            IRNode::List(vec![IRNode::Atom("ident".to_string()), IRNode::Atom(n)])
-        } else { panic!("Unexpected token {:?}", t) }
     }
 }
 
-fn parse_file_recursive(filepath: PathBuf, visited: &mut HashSet<PathBuf>, all_structs: &mut Vec<IRNode>, all_fns: &mut Vec<IRNode>, _all_imports: &mut Vec<IRNode>) {
-    let filepath = fs::canonicalize(filepath).expect("Failed to canonicalize path");
-    if visited.contains(&filepath) { return; }
-    visited.insert(filepath.clone());
-    let source = fs::read_to_string(&filepath).expect("Failed to read file");
-    let mut lexer = Lexer::new(source);
-    let tokens = lexer.tokenize();
-    let mut parser = Parser::new(tokens);
-    
-    let mut imports = Vec::new();
-    let mut structs = Vec::new();
-    let mut fns = Vec::new();
-    
-    while parser.peek(0).kind != TokenKind::Eof {
-        let t = parser.peek(0);
-        if t.value == "import" {
-            parser.consume(None, None);
-            let imp = parser.consume(Some(TokenKind::Str), None).value;
-            imports.push(imp);
-        } else if t.value == "struct" { structs.push(parser.parse_struct()); }
-        else if t.value == "fn" { fns.push(parser.parse_fn()); }
-        else { parser.pos += 1; }
-    }
-    
-    all_structs.extend(structs);
-    all_fns.extend(fns);
-    for imp in imports {
-        let mut imp_path = filepath.parent().unwrap().to_path_buf();
-        imp_path.push(format!("{}.coatl", imp));
-        parse_file_recursive(imp_path, visited, all_structs, all_fns, _all_imports);
-    }
-}
-
-use intrinsics::{INTRINSICS_X86_64, INTRINSICS_AARCH64};
-
-struct X86_64Backend {
-    ir: IRNode,
-    output: Vec<String>,
-    vars: HashMap<String, (i32, String)>,
-    strings: HashMap<String, i32>,
-    structs: HashMap<String, Vec<String>>,
-    label_count: i32,
-    current_fn: String,
-}
-
-impl X86_64Backend {
-    fn new(ir: IRNode) -> Self {
-        Self {
-            ir,
-            output: Vec::new(),
-            vars: HashMap::new(),
-            strings: HashMap::new(),
-            structs: HashMap::new(),
-            label_count: 0,
-            current_fn: String::new(),
+/// `coatl tags [--format=ctags|json] [-o PATH] [FILE...]`: writes a symbol index of the
+/// given files, or of every `.coatl` file under the current directory, to `tags` (ctags)
+/// or `tags.json`, or to standard output for `-o -`.
+fn tags_command(args: &[String]) {
+    let (mut json, mut output, mut files) = (false, None, Vec::new());
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-o" if i + 1 < args.len() => { output = Some(args[i + 1].clone()); i += 1; }
+            "--format=ctags" => json = false,
+            "--format=json" => json = true,
+            arg if arg.starts_with('-') => { eprintln!("error: unknown tags option `{}` (expected --format=ctags|json or -o PATH)", arg); process::exit(EXIT_USAGE); }
+            arg => files.push(PathBuf::from(arg)),
         }
+        i += 1;
     }
-
-    fn emit(&mut self, s: String) { self.output.push(s); }
-    fn new_label(&mut self, prefix: &str) -> String {
-        self.label_count += 1;
-        format!(".{}{}", prefix, self.label_count)
+    if files.is_empty() {
+        source_files(Path::new("."), &mut files);
+        for f in &mut files { if let Ok(rel) = f.strip_prefix(".") { *f = rel.to_path_buf(); } }
     }
-
-    fn collect_strings(&mut self, node: &IRNode) {
-        if let IRNode::List(l) = node {
-            if !l.is_empty() {
-                if let Some(atom) = l[0].as_atom() {
-                    if atom == "string_typed" && l.len() > 1 {
-                        if let Some(val) = l[1].as_atom() {
-                            self.strings.insert(val.clone(), 0);
-                        }
-                    }
-                }
-            }
-            for child in l { self.collect_strings(child); }
-        }
-    }
-
-    fn lower(&mut self) {
-        let mut fns: Vec<IRNode> = Vec::new();
-        let mut structs_list: Vec<IRNode> = Vec::new();
-
-        if let IRNode::List(root) = &self.ir {
-            for child in root {
-                if let IRNode::List(c) = child {
-                    if !c.is_empty() {
-                        if c[0].as_atom().map(|s| s == "functions").unwrap_or(false) {
-                            fns = c[1..].to_vec();
-                        } else if c[0].as_atom().map(|s| s == "structs").unwrap_or(false) {
-                            structs_list = c[1..].to_vec();
-                        }
-                    }
-                }
-            }
-        }
-
-        for s in structs_list {
-            if let IRNode::List(sl) = s {
-                let name = sl[1].as_atom().unwrap().clone();
-                let fields = sl[2..].iter().map(|f| f.as_list().unwrap()[1].as_atom().unwrap().clone()).collect();
-                self.structs.insert(name, fields);
-            }
-        }
-
-        self.emit(".intel_syntax noprefix".to_string());
-        self.emit(".bss".to_string());
-        self.emit(".align 16".to_string());
-        self.emit(".globl __coatl_mem".to_string());
-        self.emit("__coatl_mem:".to_string());
-        self.emit("  .zero 1048576".to_string());
-        self.emit("__coatl_mem_inited:".to_string());
-        self.emit("  .long 0".to_string());
-        self.emit(".text".to_string());
-        self.emit("__coatl_init_memory:".to_string());
-        self.emit("  push rbp; mov rbp, rsp".to_string());
-        self.emit("  mov eax, dword ptr [rip+__coatl_mem_inited]; test eax, eax; jne .L_mem_done".to_string());
-        self.emit("  mov dword ptr [rip+__coatl_mem_inited], 1".to_string());
-        self.emit("  lea rdx, [rip+__coatl_mem]".to_string());
-
-        for func in &fns { self.collect_strings(func); }
-
-        let mut off: i32 = 65536;
-        let mut sorted_strings: Vec<_> = self.strings.keys().cloned().collect();
-        sorted_strings.sort();
-        for s in sorted_strings {
-            let bytes = s.as_bytes().to_vec();
-            for (i, &b) in bytes.iter().enumerate() {
-                self.emit(format!("  mov byte ptr [rdx+{}], {}", off + i as i32, b));
-            }
-            self.emit(format!("  mov byte ptr [rdx+{}], 0", off + bytes.len() as i32));
-            self.strings.insert(s, off);
-            off += bytes.len() as i32 + 1;
-        }
-
-        self.emit(".L_mem_done:".to_string());
-        self.emit("  pop rbp; ret".to_string());
-
-        for func in fns { self.lower_fn(&func); }
-
-        self.emit(".globl coatl_start".to_string());
-        self.emit("coatl_start:".to_string());
-        self.emit("  call __coatl_init_memory".to_string());
-        self.emit("  call main".to_string());
-        self.emit("  mov edi, eax; mov eax, 60; syscall".to_string());
-        self.emit(INTRINSICS_X86_64.to_string());
+    let mut index = Vec::new();
+    for file in &files {
+        let source = fs::read_to_string(file).unwrap_or_else(|e| { eprintln!("error: cannot read {}: {}", file.display(), e); process::exit(EXIT_FAILURE) });
+        index.extend(tags::scan(&source, &file.display().to_string()));
     }
-
-    fn lower_fn(&mut self, n: &IRNode) {
-        if let IRNode::List(l) = n {
-            let name = l[1].as_atom().unwrap();
-            self.current_fn = name.clone();
-            self.vars.clear();
-            self.emit(format!(".global {}\n{}:", name, name));
-            self.emit("  push rbp; mov rbp, rsp; sub rsp, 4096".to_string());
-            
-            let regs = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
-            if let IRNode::List(params) = &l[2] {
-                for (i, p) in params[1..].iter().enumerate() {
-                    if let IRNode::List(pl) = p {
-                        let p_name = pl[1].as_atom().unwrap();
-                        let p_type = pl[2].as_atom().unwrap();
-                        let off = (i as i32 + 1) * 8;
-                        self.vars.insert(p_name.clone(), (off, p_type.clone()));
-                        if i < 6 { self.emit(format!("  mov [rbp-{}], {}", off, regs[i])); }
-                        else {
-                            let stack_off = 16 + (i as i32 - 6) * 8;
-                            self.emit(format!("  mov rax, [rbp+{}]\n  mov [rbp-{}], rax", stack_off, off));
-                        }
-                    }
-                }
-            }
-
-            if let IRNode::List(body) = &l[4] {
-                for stmt in &body[1..] { self.lower_stmt(stmt); }
-            }
-            self.emit(format!(".Lret_{}:; leave; ret", name));
-        }
+    let text = if json { tags::json(&index) } else { tags::ctags(&index) };
+    match output.as_deref().unwrap_or(if json { "tags.json" } else { "tags" }) {
+        "-" => print!("{}", text),
+        path => fs::write(path, text).unwrap_or_else(|e| { eprintln!("error: cannot write {}: {}", path, e); process::exit(EXIT_FAILURE) }),
     }
+}
 
-    fn lower_stmt(&mut self, n: &IRNode) {
-        let l = n.as_list().unwrap();
-        let head = l[0].as_atom().unwrap();
-        match head.as_str() {
-            "let" => {
-                let name = l[1].as_atom().unwrap();
-                let vtype = l[2].as_atom().unwrap();
-                let off = (self.vars.len() as i32 + 1) * 8;
-                self.vars.insert(name.clone(), (off, vtype.clone()));
-                self.lower_expr(&l[3]);
-                self.emit(format!("  mov [rbp-{}], rax", off));
-            }
-            "assign" => {
-                let name = l[1].as_atom().unwrap();
-                let off = self.vars.get(name).unwrap().0;
-                self.lower_expr(&l[2]);
-                self.emit(format!("  mov [rbp-{}], rax", off));
-            }
-            "field_assign" => {
-                let var_name = l[1].as_atom().unwrap();
-                let field_name = l[2].as_atom().unwrap();
-                let (off, ty) = self.vars.get(var_name).unwrap().clone();
-                let fi = self.structs.get(&ty).unwrap().iter().position(|f| f == field_name).unwrap();
-                self.lower_expr(&l[3]);
-                self.emit(format!("  mov dword ptr [rbp-{}], eax", off - (fi as i32 * 4)));
-            }
-            "if" => {
-                let l_else = self.new_label("L_else");
-                let l_end = self.new_label("L_end");
-                self.lower_expr(&l[1]);
-                self.emit("  cmp rax, 0; je ".to_string() + &l_else);
-                self.lower_stmt(&l[2]);
-                self.emit("  jmp ".to_string() + &l_end);
-                self.emit(l_else + ":");
-                if l.len() > 3 { self.lower_stmt(&l[3].as_list().unwrap()[1]); }
-                self.emit(l_end + ":");
-            }
-            "while" => {
-                let l_start = self.new_label("L_while_start");
-                let l_end = self.new_label("L_while_end");
-                self.emit(l_start.clone() + ":");
-                self.lower_expr(&l[1]);
-                self.emit("  cmp rax, 0; je ".to_string() + &l_end);
-                self.lower_stmt(&l[2]);
-                self.emit("  jmp ".to_string() + &l_start);
-                self.emit(l_end + ":");
-            }
-            "block" => { for s in &l[1..] { self.lower_stmt(s); } }
-            "return" => {
-                self.lower_expr(&l[1]);
-                let label = format!(".Lret_{}", self.current_fn);
-                self.emit(format!("  jmp {}", label));
-            }
-            "expr" => { self.lower_expr(&l[1]); }
-            _ => {}
-        }
+/// `coatl doc [--format=markdown|html] [-o DIR] [FILE...]`: writes an API page for each
+/// given file, or for every `.coatl` file under the current directory, into `DIR`
+/// (default `doc`), named after the file: `math.coatl` becomes `doc/math.md`.
+fn doc_command(args: &[String]) {
+    let (mut html, mut output, mut files) = (false, PathBuf::from("doc"), Vec::new());
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-o" if i + 1 < args.len() => { output = PathBuf::from(&args[i + 1]); i += 1; }
+            "--format=markdown" => html = false,
+            "--format=html" => html = true,
+            arg if arg.starts_with('-') => { eprintln!("error: unknown doc option `{}` (expected --format=markdown|html or -o DIR)", arg); process::exit(EXIT_USAGE); }
+            arg => files.push(PathBuf::from(arg)),
+        }
+        i += 1;
+    }
+    if files.is_empty() { source_files(Path::new("."), &mut files); }
+    fs::create_dir_all(&output).unwrap_or_else(|e| { eprintln!("error: cannot create {}: {}", output.display(), e); process::exit(EXIT_FAILURE) });
+    for file in &files {
+        let source = fs::read_to_string(file).unwrap_or_else(|e| { eprintln!("error: cannot read {}: {}", file.display(), e); process::exit(EXIT_FAILURE) });
+        let ir = coatl::parse_items(&source, &file.display().to_string()).unwrap_or_else(|errors| {
+            for e in errors { eprintln!("error: {}", e); }
+            process::exit(EXIT_PARSE)
+        });
+        let module = file.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let page = doc::page(&module, &ir);
+        let (text, ext) = if html { (doc::html(&page), "html") } else { (doc::markdown(&page), "md") };
+        let path = output.join(format!("{}.{}", module, ext));
+        fs::write(&path, text).unwrap_or_else(|e| { eprintln!("error: cannot write {}: {}", path.display(), e); process::exit(EXIT_FAILURE) });
     }
+}
 
-    fn lower_expr(&mut self, n: &IRNode) {
-        let l = n.as_list().unwrap();
-        let head = l[0].as_atom().unwrap();
-        match head.as_str() {
-            "int" | "int_i64" | "bool" | "f32" | "f64" => {
-                let val = l[1].as_atom().unwrap();
-                if head == "f32" {
-                    let f: f32 = val.parse().unwrap();
-                    self.emit(format!("  mov eax, {}; movd xmm0, eax; movss rax, xmm0", f.to_bits()));
-                } else if head == "f64" {
-                    let f: f64 = val.parse().unwrap();
-                    self.emit(format!("  mov rax, {}; movd xmm0, rax; movsd rax, xmm0", f.to_bits()));
-                } else {
-                    self.emit(format!("  mov rax, {}", val));
-                }
-            }
-            "ident" => {
-                let name = l[1].as_atom().unwrap();
-                let off = self.vars.get(name).unwrap().0;
-                self.emit(format!("  mov rax, [rbp-{}]", off));
-            }
-            "field" => {
-                let var_name = l[1].as_atom().unwrap();
-                let field_name = l[2].as_atom().unwrap();
-                let (off, ty) = self.vars.get(var_name).unwrap().clone();
-                let fi = self.structs.get(&ty).unwrap().iter().position(|f| f == field_name).unwrap();
-                self.emit(format!("  movsxd rax, dword ptr [rbp-{}]", off - (fi as i32 * 4)));
-            }
-            "struct_lit" => {
-                for (i, arg) in l[2..4].iter().enumerate() {
-                    self.lower_expr(arg);
-                    if i == 0 {
-                        self.emit("  push rax".to_string());
-                    } else {
-                        self.emit("  shl rax, 32; pop rcx; or rax, rcx".to_string());
-                    }
-                }
-            }
-            "binary" => {
-                let op = l[1].as_atom().unwrap();
-                self.lower_expr(&l[2]); self.emit("  push rax".to_string());
-                self.lower_expr(&l[3]); self.emit("  mov rcx, rax; pop rax".to_string());
-                match op.as_str() {
-                    "add" => self.emit("  add rax, rcx".to_string()),
-                    "sub" => self.emit("  sub rax, rcx".to_string()),
-                    "mul" => self.emit("  imul rax, rcx".to_string()),
-                    "div" => self.emit("  cqo; idiv rcx".to_string()),
-                    "and" => self.emit("  and rax, rcx".to_string()),
-                    "or" => self.emit("  or rax, rcx".to_string()),
-                    _ => {
-                        let cond = match op.as_str() { "eq"=>"e", "ne"=>"ne", "lt"=>"l", "gt"=>"g", "le"=>"le", "ge"=>"ge", _=>"e" };
-                        self.emit(format!("  cmp rax, rcx; set{} al; movzx rax, al", cond));
-                    }
-                }
-            }
-            "call" => {
-                let name = l[1].as_atom().unwrap();
-                let regs = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
-                let args = &l[2..];
-                for i in (6..args.len()).rev() {
-                    self.lower_expr(&args[i]);
-                    self.emit("  push rax".to_string());
-                }
-                for arg in args.iter().take(6) {
-                    self.lower_expr(arg);
-                    self.emit("  push rax".to_string());
-                }
-                for i in (0..std::cmp::min(args.len(), 6)).rev() {
-                    self.emit(format!("  pop {}", regs[i]));
-                }
-                self.emit(format!("  call {}", name));
-                if args.len() > 6 { self.emit(format!("  add rsp, {}", (args.len() - 6) * 8)); }
-            }
-            "string_typed" => {
-                let val = l[1].as_atom().unwrap();
-                let off = self.strings.get(val).unwrap();
-                self.emit(format!("  mov rax, {}", off));
-            }
-            "syscall" => self.emit("  syscall".to_string()),
-            _ => {}
+/// The C compiler that assembles and links for `arch`: `$CC` (default `cc`), or the
+/// `aarch64-linux-gnu-gcc` cross compiler for aarch64 on another host when it exists.
+fn c_compiler(arch: &str) -> String {
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    if arch == "aarch64" {
+        let machine = process::Command::new("uname").arg("-m").output().map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string()).unwrap_or_default();
+        let cross_cc = "aarch64-linux-gnu-gcc";
+        if machine != "aarch64" && process::Command::new("command").args(["-v", cross_cc]).status().map(|s| s.success()).unwrap_or(false) {
+            return cross_cc.to_string();
         }
     }
+    cc
 }
 
-struct AArch64Backend {
-    ir: IRNode,
-    output: Vec<String>,
-    vars: HashMap<String, (i32, String)>,
-    strings: HashMap<String, i32>,
-    structs: HashMap<String, Vec<String>>,
-    label_count: i32,
-    current_fn: String,
+/// Assembles `text` for `arch` with `$CC -c`, returning the object or the assembler's
+/// messages. `stem` names the scratch files.
+fn assemble(arch: &str, text: &str, stem: &str) -> Result<Vec<u8>, String> {
+    let (tmp_s, tmp_o) = (env::temp_dir().join(format!("coatl_{}_{}.s", stem, process::id())), env::temp_dir().join(format!("coatl_{}_{}.o", stem, process::id())));
+    fs::write(&tmp_s, text).expect("Failed to write temp assembly");
+    let result = match process::Command::new(c_compiler(arch)).arg("-c").arg(&tmp_s).arg("-o").arg(&tmp_o).output() {
+        Ok(out) if out.status.success() => fs::read(&tmp_o).map_err(|e| e.to_string()),
+        Ok(out) => Err(String::from_utf8_lossy(&out.stderr).into_owned()),
+        Err(e) => Err(format!("cannot run {}: {}\n", c_compiler(arch), e)),
+    };
+    let _ = fs::remove_file(&tmp_s);
+    let _ = fs::remove_file(&tmp_o);
+    result
 }
 
-impl AArch64Backend {
-    fn new(ir: IRNode) -> Self {
-        Self {
-            ir,
-            output: Vec::new(),
-            vars: HashMap::new(),
-            strings: HashMap::new(),
-            structs: HashMap::new(),
-            label_count: 0,
-            current_fn: String::new(),
-        }
-    }
-
-    fn emit(&mut self, s: String) { self.output.push(s); }
-    fn new_label(&mut self, prefix: &str) -> String {
-        self.label_count += 1;
-        format!(".{}{}", prefix, self.label_count)
-    }
-
-    fn collect_strings(&mut self, node: &IRNode) {
-        if let IRNode::List(l) = node {
-            if !l.is_empty() {
-                if let Some(atom) = l[0].as_atom() {
-                    if atom == "string_typed" && l.len() > 1 {
-                        if let Some(val) = l[1].as_atom() {
-                            self.strings.insert(val.clone(), 0);
-                        }
-                    }
-                }
-            }
-            for child in l { self.collect_strings(child); }
-        }
-    }
-
-    fn safe_mov_imm(&mut self, reg: &str, val: i64) {
-        if (0..65536).contains(&val) {
-            self.emit(format!("  mov {}, #{}", reg, val));
-        } else {
-            self.emit(format!("  movz {}, #{}", reg, val & 0xffff));
-            if (val >> 16) & 0xffff != 0 { self.emit(format!("  movk {}, #{}", reg, (val >> 16) & 0xffff) + ", lsl #16"); }
-            if (val >> 32) & 0xffff != 0 { self.emit(format!("  movk {}, #{}", reg, (val >> 32) & 0xffff) + ", lsl #32"); }
-            if (val >> 48) & 0xffff != 0 { self.emit(format!("  movk {}, #{}", reg, (val >> 48) & 0xffff) + ", lsl #48"); }
-        }
-    }
-
-    fn ldr_x29(&mut self, reg: &str, off: i32) {
-        if (-256..=4095).contains(&off) { self.emit(format!("  ldr {}, [x29, #{}]", reg, off)); }
-        else { self.safe_mov_imm("x1", off as i64); self.emit(format!("  ldr {}, [x29, x1]", reg)); }
-    }
-
-    fn ldrsw_x29(&mut self, reg: &str, off: i32) {
-        if (-256..=4095).contains(&off) { self.emit(format!("  ldrsw {}, [x29, #{}]", reg, off)); }
-        else { self.safe_mov_imm("x1", off as i64); self.emit(format!("  ldrsw {}, [x29, x1]", reg)); }
-    }
-
-    fn str_x29(&mut self, reg: &str, off: i32) {
-        if (-256..=4095).contains(&off) { self.emit(format!("  str {}, [x29, #{}]", reg, off)); }
-        else { self.safe_mov_imm("x1", off as i64); self.emit(format!("  str {}, [x29, x1]", reg)); }
-    }
-
-    fn lower(&mut self) {
-        let mut fns: Vec<IRNode> = Vec::new();
-        let mut structs_list: Vec<IRNode> = Vec::new();
-
-        if let IRNode::List(root) = &self.ir {
-            for child in root {
-                if let IRNode::List(c) = child {
-                    if !c.is_empty() {
-                        if c[0].as_atom().map(|s| s == "functions").unwrap_or(false) {
-                            fns = c[1..].to_vec();
-                        } else if c[0].as_atom().map(|s| s == "structs").unwrap_or(false) {
-                            structs_list = c[1..].to_vec();
-                        }
-                    }
-                }
-            }
-        }
-
-        for s in structs_list {
-            if let IRNode::List(sl) = s {
-                let name = sl[1].as_atom().unwrap().clone();
-                let fields = sl[2..].iter().map(|f| f.as_list().unwrap()[1].as_atom().unwrap().clone()).collect();
-                self.structs.insert(name, fields);
-            }
-        }
-
-        self.emit(".bss".to_string());
-        self.emit(".align 4".to_string());
-        self.emit(".globl __coatl_mem".to_string());
-        self.emit("__coatl_mem:".to_string());
-        self.emit("  .zero 1048576".to_string());
-        self.emit("__coatl_mem_inited:".to_string());
-        self.emit("  .word 0".to_string());
-        self.emit(".text".to_string());
-        self.emit("__coatl_init_memory:".to_string());
-        self.emit("  stp x29, x30, [sp, #-16]!".to_string());
-        self.emit("  mov x29, sp".to_string());
-        self.emit("  adrp x0, __coatl_mem_inited; ldr w1, [x0, :lo12:__coatl_mem_inited]; cbnz w1, .L_mem_done".to_string());
-        self.emit("  mov w1, #1; str w1, [x0, :lo12:__coatl_mem_inited]".to_string());
-        self.emit("  adrp x2, __coatl_mem; add x2, x2, :lo12:__coatl_mem".to_string());
-
-        for func in &fns { self.collect_strings(func); }
-
-        let mut off: i32 = 65536;
-        let mut sorted_strings: Vec<_> = self.strings.keys().cloned().collect();
-        sorted_strings.sort();
-        for s in sorted_strings {
-            let bytes = s.as_bytes().to_vec();
-            for (i, &b) in bytes.iter().enumerate() {
-                self.safe_mov_imm("x1", (off + i as i32) as i64);
-                self.emit(format!("  mov w0, #{}; strb w0, [x2, x1]", b));
-            }
-            self.safe_mov_imm("x1", (off + bytes.len() as i32) as i64);
-            self.emit("  strb wzr, [x2, x1]".to_string());
-            self.strings.insert(s, off);
-            off += bytes.len() as i32 + 1;
-        }
-
-        self.emit(".L_mem_done:".to_string());
-        self.emit("  ldp x29, x30, [sp], #16".to_string());
-        self.emit("  ret".to_string());
-
-        for func in fns { self.lower_fn(&func); }
-
-        self.emit(".globl coatl_start".to_string());
-        self.emit("coatl_start:".to_string());
-        self.emit("  stp x29, x30, [sp, #-16]!".to_string());
-        self.emit("  bl __coatl_init_memory".to_string());
-        self.emit("  bl main".to_string());
-        self.emit("  mov w0, w0; mov x8, #93; svc #0".to_string());
-        self.emit(INTRINSICS_AARCH64.to_string());
-    }
-
-    fn lower_fn(&mut self, n: &IRNode) {
-        if let IRNode::List(l) = n {
-            let name = l[1].as_atom().unwrap();
-            self.current_fn = name.clone();
-            self.vars.clear();
-            self.emit(format!(".global {}\n{}:", name, name));
-            self.emit("  stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #4096".to_string());
-            
-            let mut o = 16;
-            if let IRNode::List(params) = &l[2] {
-                for (i, p) in params[1..].iter().enumerate() {
-                    if let IRNode::List(pl) = p {
-                        let p_name = pl[1].as_atom().unwrap();
-                        let p_type = pl[2].as_atom().unwrap();
-                        self.vars.insert(p_name.clone(), (o, p_type.clone()));
-                        if i < 8 { self.str_x29(&format!("x{}", i), -o); }
-                        else {
-                            let stack_off = 16 + (i as i32 - 8) * 8;
-                            self.ldr_x29("x0", stack_off);
-                            self.str_x29("x0", -o);
-                        }
-                        o += 8;
-                    }
-                }
-            }
-
-            if let IRNode::List(body) = &l[4] {
-                for stmt in &body[1..] { self.lower_stmt(stmt); }
-            }
-            self.emit(format!(".Lret_{}:; add sp, sp, #4096; ldp x29, x30, [sp], #16; ret", name));
-        }
-    }
-
-    fn lower_stmt(&mut self, n: &IRNode) {
-        let l = n.as_list().unwrap();
-        let head = l[0].as_atom().unwrap();
-        match head.as_str() {
-            "let" => {
-                let name = l[1].as_atom().unwrap();
-                let vtype = l[2].as_atom().unwrap();
-                let off = (self.vars.len() as i32 + 2) * 8;
-                self.vars.insert(name.clone(), (off, vtype.clone()));
-                self.lower_expr(&l[3]);
-                self.str_x29("x0", -off);
-            }
-            "assign" => {
-                let name = l[1].as_atom().unwrap();
-                let off = self.vars.get(name).unwrap().0;
-                self.lower_expr(&l[2]);
-                self.str_x29("x0", -off);
-            }
-            "if" => {
-                let l_else = self.new_label("else");
-                let l_end = self.new_label("endif");
-                self.lower_expr(&l[1]);
-                self.emit(format!("  cbz x0, {}", l_else));
-                self.lower_stmt(&l[2]);
-                self.emit(format!("  b {}", l_end));
-                self.emit(format!("{}:", l_else));
-                if l.len() > 3 { self.lower_stmt(&l[3].as_list().unwrap()[1]); }
-                self.emit(format!("{}:", l_end));
-            }
-            "while" => {
-                let l_start = self.new_label("while");
-                let l_end = self.new_label("endwhile");
-                self.emit(format!("{}:", l_start));
-                self.lower_expr(&l[1]);
-                self.emit(format!("  cbz x0, {}", l_end));
-                self.lower_stmt(&l[2]);
-                self.emit(format!("  b {}", l_start));
-                self.emit(format!("{}:", l_end));
-            }
-            "block" => { for s in &l[1..] { self.lower_stmt(s); } }
-            "return" => {
-                self.lower_expr(&l[1]);
-                let label = format!(".Lret_{}", self.current_fn);
-                self.emit(format!("  b {}", label));
-            }
-            "svc" => {
-                let args = &l[1..];
-                for arg in args {
-                    self.lower_expr(arg);
-                    self.emit("  str x0, [sp, #-16]!".to_string());
-                }
-                if !args.is_empty() { self.emit("  ldr x8, [sp], #16".to_string()); }
-                if args.len() > 1 { self.emit("  ldr x0, [sp], #16".to_string()); }
-                if args.len() > 2 { self.emit("  ldr x1, [sp], #16".to_string()); }
-                if args.len() > 3 { self.emit("  ldr x2, [sp], #16".to_string()); }
-                self.emit("  svc #0".to_string());
-            }
-            "expr" => { self.lower_expr(&l[1]); }
-            _ => {}
-        }
-    }
-
-    fn lower_expr(&mut self, n: &IRNode) {
-        let l = n.as_list().unwrap();
-        let head = l[0].as_atom().unwrap();
-        match head.as_str() {
-            "int" | "int_i64" | "bool" => {
-                let val: i64 = l[1].as_atom().unwrap().parse().unwrap();
-                self.safe_mov_imm("x0", val);
-            }
-            "ident" => {
-                let name = l[1].as_atom().unwrap();
-                let off = self.vars.get(name).unwrap().0;
-                self.ldrsw_x29("x0", -off);
-            }
-            "binary" => {
-                let op = l[1].as_atom().unwrap();
-                self.lower_expr(&l[2]); self.emit("  str x0, [sp, #-16]!".to_string());
-                self.lower_expr(&l[3]); self.emit("  mov x1, x0; ldr x0, [sp], #16".to_string());
-                match op.as_str() {
-                    "add" => self.emit("  add x0, x0, x1".to_string()),
-                    "sub" => self.emit("  sub x0, x0, x1".to_string()),
-                    "mul" => self.emit("  mul x0, x0, x1".to_string()),
-                    "div" => self.emit("  sdiv x0, x0, x1".to_string()),
-                    "and" => self.emit("  and x0, x0, x1".to_string()),
-                    "or" => self.emit("  orr x0, x0, x1".to_string()),
-                    _ => {
-                        let cond = match op.as_str() { "eq"=>"eq", "ne"=>"ne", "lt"=>"lt", "gt"=>"gt", "le"=>"le", "ge"=>"ge", _=>"eq" };
-                        self.emit(format!("  cmp x0, x1; cset w0, {}", cond));
-                    }
-                }
-            }
-            "call" => {
-                let name = l[1].as_atom().unwrap();
-                let args = &l[2..];
-                for i in (8..args.len()).rev() {
-                    self.lower_expr(&args[i]);
-                    self.emit("  str x0, [sp, #-16]!".to_string());
-                }
-                for arg in args.iter().take(8) {
-                    self.lower_expr(arg);
-                    self.emit("  str x0, [sp, #-16]!".to_string());
-                }
-                for i in (0..std::cmp::min(args.len(), 8)).rev() {
-                    self.emit(format!("  ldr x{}, [sp], #16", i));
-                }
-                self.emit(format!("  bl {}", name));
-                if args.len() > 8 {
-                    self.emit(format!("  add sp, sp, #{}", (args.len() - 8) * 16));
-                }
-            }
-            "string_typed" => {
-                let val = l[1].as_atom().unwrap();
-                let off = self.strings.get(val).unwrap();
-                self.safe_mov_imm("x0", *off as i64);
-            }
-            "str_len" | "str_ptr" => self.lower_expr(&l[1]),
-            _ => {}
-        }
-    }
+/// The line and message of an assembler error, as `file.s:LINE: Error: message` (GNU as)
+/// or `file.s:LINE:COL: error: message` (clang).
+fn assembler_error(line: &str) -> Option<(usize, &str)> {
+    let (_, rest) = line.split_once(".s:")?;
+    let (number, rest) = rest.split_once(':')?;
+    let rest = match rest.split_once(':') { Some((col, after)) if col.parse::<usize>().is_ok() => after, _ => rest };
+    let message = rest.trim_start().strip_prefix("Error:").or_else(|| rest.trim_start().strip_prefix("error:"))?;
+    Some((number.parse().ok()?, message.trim()))
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    if args.len() >= 2 && args[1] == "tags" {
+        tags_command(&args[2..]);
+        return;
+    }
+    if args.len() >= 2 && args[1] == "doc" {
+        doc_command(&args[2..]);
+        return;
+    }
+    if args.len() >= 2 && args[1] == "build" { args = build_args(&args); }
+    // `coatl link a.ir b.ir ...` merges separately compiled programs before lowering them.
+    let linking = args.len() >= 2 && args[1] == "link";
+    if linking { args.remove(1); }
     if args.len() >= 2 && (args[1] == "-V" || args[1] == "--version") {
         println!("coatl {}", env!("CARGO_PKG_VERSION"));
         process::exit(0);
     }
-    if args.len() < 2 { println!("Usage: coatl <input.coatl|input.ir> [-o output.s] [--arch=<arch>]"); process::exit(1); }
+    if args.len() < 2 { println!("Usage: coatl build [-] [flags] | coatl link <a.ir> <b.ir>... [flags] | coatl tags [--format=ctags|json] [-o PATH] [files...] | coatl doc [--format=markdown|html] [-o DIR] [files...] | coatl <input.coatl|input.ir|-> [-o output] [--target=<triple>|--arch=<arch>] [--emit=asm|obj|ir|cfg-dot|callgraph[-json]|exe|so[,...]] [--out-dir=DIR] [--profile=debug|release] [--timings[=verbose]] [-v|--verbose] [--libc] [--syscalls=linux|libc|extern] [--freestanding] [--import-memory] [--no-runtime] [--no-prelude] [--dep=NAME=PATH] [--entry=NAME] [--gc-functions] [--export-prefix=PREFIX] [--stack-limit=SIZE] [--stack-report[=LIMIT]] [--layout-map=PATH] [--map=PATH] [--size-report] [--listing] [--verify] [--instrument=trace,profile] [--backtrace] [--panic=exit|hook] [--sanitize=memory] [--custom-section=NAME=VALUE] [--summary=json]"); process::exit(EXIT_USAGE); }
+    let mut summary = Summary { json: args.iter().any(|a| a == "--summary=json"), ..Summary::default() };
     let mut input_path = String::new();
+    let mut link_inputs = Vec::new();
     let mut output_path = String::new();
     let mut arch = "x86_64".to_string();
+    let mut timings = Timings::default();
+    let mut verbose = false;
+    let mut parse_opts = ParseOptions::default();
+    let mut opts = CodegenOptions::default();
+    let mut custom_sections: Vec<(String, String)> = Vec::new();
+    let mut emits: Vec<Emit> = Vec::new();
+    let mut out_dir: Option<PathBuf> = None;
+    let mut profile = None;
+    let mut layout_map = None;
+    let mut map_path = None;
+    let mut verify = false;
+    let mut size_report = false;
+    let mut gc_functions = false;
+    let mut export_prefix: Option<String> = None;
+    // `--stack-report`, with the limit in bytes of `--stack-report=LIMIT`.
+    let mut stack_report: Option<Option<u32>> = None;
 
     let mut i = 1;
     while i < args.len() {
         if args[i] == "-o" { output_path = args[i+1].clone(); i += 2; }
         else if args[i].starts_with("--arch=") { arch = args[i][7..].to_string(); i += 1; }
+        else if let Some(triple) = args[i].strip_prefix("--target=") {
+            let Some((_, a)) = TARGETS.iter().find(|(t, _)| *t == triple) else {
+                let known: Vec<&str> = TARGETS.iter().map(|(t, _)| *t).collect();
+                eprintln!("error: unknown --target `{}` (expected one of {})", triple, known.join(", "));
+                summary.exit(EXIT_USAGE, 1);
+            };
+            arch = a.to_string();
+            i += 1;
+        }
+        else if let Some(name) = args[i].strip_prefix("--profile=") {
+            profile = Some(match name {
+                "debug" => Profile::Debug,
+                "release" => Profile::Release,
+                _ => { eprintln!("error: unknown --profile `{}` (expected debug or release)", name); summary.exit(EXIT_USAGE, 1); }
+            });
+            i += 1;
+        }
+        else if let Some(kinds) = args[i].strip_prefix("--emit=") {
+            for kind in kinds.split(',') {
+                let e = Emit::parse(kind).unwrap_or_else(|| {
+                    eprintln!("error: unknown --emit kind `{}` (expected asm, obj, ir, cfg-dot, callgraph, callgraph-json, exe or so)", kind);
+                    summary.exit(EXIT_USAGE, 1)
+                });
+                if !emits.contains(&e) { emits.push(e); }
+            }
+            i += 1;
+        }
+        else if let Some(dir) = args[i].strip_prefix("--out-dir=") { out_dir = Some(PathBuf::from(dir)); i += 1; }
+        else if args[i] == "--out-dir" && i + 1 < args.len() { out_dir = Some(PathBuf::from(&args[i + 1])); i += 2; }
+        else if args[i] == "--timings" { timings.enabled = true; i += 1; }
+        else if args[i] == "--timings=verbose" { timings.enabled = true; timings.verbose = true; i += 1; }
+        else if args[i] == "-v" || args[i] == "--verbose" { verbose = true; i += 1; }
+        else if args[i] == "--libc" { opts.syscalls = Syscalls::Libc; i += 1; }
+        else if let Some(name) = args[i].strip_prefix("--syscalls=") {
+            opts.syscalls = Syscalls::parse(name).unwrap_or_else(|| {
+                eprintln!("error: unknown --syscalls personality `{}` (expected linux, libc or extern)", name);
+                summary.exit(EXIT_USAGE, 1)
+            });
+            i += 1;
+        }
+        else if args[i] == "--freestanding" { opts.freestanding = true; i += 1; }
+        else if args[i] == "--import-memory" { opts.import_memory = true; i += 1; }
+        else if args[i] == "--no-runtime" { parse_opts.runtime = false; i += 1; }
+        else if args[i] == "--no-prelude" { parse_opts.prelude = false; i += 1; }
+        else if let Some(dep) = args[i].strip_prefix("--dep=") {
+            let Some((name, dir)) = dep.split_once('=') else { eprintln!("--dep expects NAME=PATH, got `{}`", dep); summary.exit(EXIT_USAGE, 1); };
+            parse_opts.dependencies.insert(name.to_string(), PathBuf::from(dir));
+            i += 1;
+        }
+        else if let Some(kinds) = args[i].strip_prefix("--instrument=") {
+            for kind in kinds.split(',') {
+                match kind {
+                    "trace" => opts.trace = true,
+                    "profile" => opts.profile = true,
+                    _ => { eprintln!("error: unknown --instrument kind `{}` (expected trace or profile)", kind); summary.exit(EXIT_USAGE, 1); }
+                }
+            }
+            i += 1;
+        }
+        else if args[i] == "--backtrace" { opts.backtrace = true; i += 1; }
+        else if let Some(mode) = args[i].strip_prefix("--panic=") {
+            match mode {
+                "exit" => opts.panic_hook = false,
+                "hook" => opts.panic_hook = true,
+                _ => { eprintln!("error: unknown panic mode `{}` (expected exit or hook)", mode); summary.exit(EXIT_USAGE, 1); }
+            }
+            i += 1;
+        }
+        else if let Some(kind) = args[i].strip_prefix("--sanitize=") {
+            if kind != "memory" { eprintln!("error: unknown sanitizer `{}` (expected memory)", kind); summary.exit(EXIT_USAGE, 1); }
+            parse_opts.sanitize = true;
+            i += 1;
+        }
+        else if let Some(size) = args[i].strip_prefix("--stack-limit=") {
+            let Some(limit) = parse_size(size) else {
+                eprintln!("error: invalid --stack-limit `{}` (expected bytes, optionally with a K or M suffix)", size);
+                summary.exit(EXIT_USAGE, 1);
+            };
+            opts.stack_limit = Some(limit);
+            i += 1;
+        }
+        else if args[i] == "--stack-report" { stack_report = Some(None); i += 1; }
+        else if let Some(size) = args[i].strip_prefix("--stack-report=") {
+            let Some(limit) = parse_size(size) else {
+                eprintln!("error: invalid --stack-report limit `{}` (expected bytes, optionally with a K or M suffix)", size);
+                summary.exit(EXIT_USAGE, 1);
+            };
+            stack_report = Some(Some(limit));
+            i += 1;
+        }
+        else if args[i] == "--gc-functions" { gc_functions = true; i += 1; }
+        else if let Some(prefix) = args[i].strip_prefix("--export-prefix=") {
+            if prefix.is_empty() || prefix.starts_with(|c: char| c.is_ascii_digit()) || !prefix.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '.') {
+                eprintln!("error: invalid --export-prefix `{}` (expected the start of a symbol name, e.g. `mee_`)", prefix);
+                summary.exit(EXIT_USAGE, 1);
+            }
+            export_prefix = Some(prefix.to_string());
+            i += 1;
+        }
+        else if args[i] == "--size-report" { size_report = true; opts.symbol_sizes = true; i += 1; }
+        else if args[i] == "--verify" { verify = true; parse_opts.listing = true; i += 1; }
+        else if args[i] == "--listing" { parse_opts.listing = true; opts.listing = true; i += 1; }
+        else if let Some(path) = args[i].strip_prefix("--layout-map=") { layout_map = Some(path.to_string()); i += 1; }
+        else if let Some(path) = args[i].strip_prefix("--map=") { map_path = Some(path.to_string()); opts.symbol_sizes = true; i += 1; }
+        else if let Some(name) = args[i].strip_prefix("--entry=") { opts.entry = Some(name.to_string()); i += 1; }
+        else if args[i] == "--entry" && i + 1 < args.len() { opts.entry = Some(args[i + 1].clone()); i += 2; }
+        else if let Some(spec) = args[i].strip_prefix("--custom-section=") {
+            let Some((name, value)) = spec.split_once('=') else {
+                eprintln!("error: --custom-section expects NAME=VALUE, NAME=@FILE or NAME=@ir");
+                summary.exit(EXIT_USAGE, 1);
+            };
+            // The name is spliced into a `.section` directive, so it must stay a plain identifier.
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+                eprintln!("error: --custom-section name `{}` may only contain letters, digits, `_` and `.`", name);
+                summary.exit(EXIT_USAGE, 1);
+            }
+            custom_sections.push((name.to_string(), value.to_string()));
+            i += 1;
+        }
+        else if args[i] == "--summary=json" { i += 1; }
+        else if let Some(kind) = args[i].strip_prefix("--summary=") { eprintln!("error: unknown --summary format `{}` (expected json)", kind); summary.exit(EXIT_USAGE, 1); }
+        else if args[i].starts_with("--") { eprintln!("error: unknown option `{}`", args[i]); summary.exit(EXIT_USAGE, 1); }
+        else if linking { link_inputs.push(args[i].clone()); i += 1; }
         else { input_path = args[i].clone(); i += 1; }
     }
 
-    let ir = if input_path.ends_with(".ir") {
-        let source = fs::read_to_string(&input_path).expect("Failed to read input file");
+    if parse_opts.sanitize && !parse_opts.runtime {
+        eprintln!("error: --sanitize=memory checks accesses with the runtime library; it cannot be combined with --no-runtime");
+        summary.exit(EXIT_USAGE, 1);
+    }
+    if !linking && input_path.is_empty() {
+        eprintln!("error: no input file");
+        summary.exit(EXIT_USAGE, 1);
+    }
+    // The parser stops at the first syntax error it meets by panicking, which reports it.
+    let ir = if linking {
+        let start = Instant::now();
+        let programs: Vec<(String, _)> = link_inputs.iter().map(|path| {
+            let source = fs::read_to_string(path).unwrap_or_else(|e| { eprintln!("error: cannot read {}: {}", path, e); summary.exit(EXIT_FAILURE, 1) });
+            if verbose { eprintln!("coatl: reading IR from {}", path); }
+            let ir = IRParser::new(&source).parse().unwrap_or_else(|| { eprintln!("error: {}: not a coatl IR program", path); summary.exit(EXIT_PARSE, 1) });
+            (path.clone(), ir)
+        }).collect();
+        timings.record("parse", start.elapsed());
+        let start = Instant::now();
+        let ir = link::link(&programs).unwrap_or_else(|errors| {
+            for e in &errors { eprintln!("error: {}", e); }
+            summary.exit(EXIT_PARSE, errors.len())
+        });
+        timings.record("link", start.elapsed());
+        ir
+    } else if input_path.ends_with(".ir") {
+        let source = fs::read_to_string(&input_path).unwrap_or_else(|e| { eprintln!("error: cannot read {}: {}", input_path, e); summary.exit(EXIT_FAILURE, 1) });
+        if verbose { eprintln!("coatl: reading IR from {}", input_path); }
+        let start = Instant::now();
         let mut parser = IRParser::new(&source);
-        parser.parse().expect("Failed to parse IR")
+        let ir = parser.parse().unwrap_or_else(|| { eprintln!("error: {}: not a coatl IR program", input_path); summary.exit(EXIT_PARSE, 1) });
+        timings.record("parse", start.elapsed());
+        ir
+    } else if input_path == "-" {
+        // `coatl -` compiles a program read from standard input.
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).unwrap_or_else(|e| { eprintln!("error: cannot read standard input: {}", e); summary.exit(EXIT_FAILURE, 1) });
+        parse_source_text(source, "<stdin>", &mut timings, &ParseOptions { verbose, target: arch.clone(), ..parse_opts }).unwrap_or_else(|errors| {
+            for e in &errors { eprintln!("error: {}", e); }
+            summary.exit(EXIT_PARSE, errors.len())
+        })
     } else {
-        let mut all_structs = Vec::new();
-        let mut all_fns = Vec::new();
-        let mut all_imports = Vec::new();
-        let mut visited = HashSet::new();
-        parse_file_recursive(PathBuf::from(&input_path), &mut visited, &mut all_structs, &mut all_fns, &mut all_imports);
-        IRNode::List(vec![
-            IRNode::Atom("coatl_ir".to_string()),
-            IRNode::Atom("v1".to_string()),
-            IRNode::List(vec![IRNode::Atom("imports".to_string())]), // Simplification: imports already resolved
-            IRNode::List(vec![IRNode::Atom("structs".to_string())].into_iter().chain(all_structs).collect()),
-            IRNode::List(vec![IRNode::Atom("functions".to_string())].into_iter().chain(all_fns).collect()),
-        ])
+        parse_source(Path::new(&input_path), &mut timings, &ParseOptions { verbose, target: arch.clone(), ..parse_opts }).unwrap_or_else(|errors| {
+            for e in &errors { eprintln!("error: {}", e); }
+            summary.exit(EXIT_PARSE, errors.len())
+        })
     };
 
-    if output_path.ends_with(".ir") {
-        fs::write(output_path, ir.to_ir()).expect("Failed to write IR output");
-        return;
-    }
+    summary.warnings = timings.warnings;
 
-    let output = if arch == "aarch64" {
-        let mut backend = AArch64Backend::new(ir);
-        backend.lower();
-        backend.output.join("\n") + "\n"
+    // Every artifact to write, with its path; an empty path is standard output.
+    let artifacts: Vec<(Emit, String)> = if let Some(dir) = &out_dir {
+        if !output_path.is_empty() {
+            eprintln!("error: -o cannot be combined with --out-dir, which names each artifact after the input");
+            summary.exit(EXIT_USAGE, 1);
+        }
+        let first = if linking { link_inputs.first().cloned().unwrap_or_default() } else { input_path.clone() };
+        let stem = if first == "-" { "stdin".to_string() } else { Path::new(&first).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| "out".to_string()) };
+        fs::create_dir_all(dir).unwrap_or_else(|e| { eprintln!("error: cannot create {}: {}", dir.display(), e); summary.exit(EXIT_FAILURE, 1) });
+        let kinds = if emits.is_empty() { vec![Emit::Exe] } else { emits };
+        kinds.into_iter().map(|e| (e, dir.join(e.file_name(&stem)).display().to_string())).collect()
     } else {
-        let mut backend = X86_64Backend::new(ir);
-        backend.lower();
-        backend.output.join("\n") + "\n"
+        if emits.len() > 1 {
+            eprintln!("error: more than one --emit kind needs --out-dir, which names each artifact after the input");
+            summary.exit(EXIT_USAGE, 1);
+        }
+        // `-o -` is standard output, as when `-o` is not given.
+        if output_path == "-" { output_path.clear(); }
+        vec![(emits.first().copied().unwrap_or_else(|| Emit::from_output(&output_path)), output_path)]
     };
-
-    if !output_path.is_empty() {
-        if output_path.ends_with(".s") || output_path.ends_with(".ir") {
-            fs::write(output_path, output).expect("Failed to write output");
+    if summary.json && artifacts.iter().any(|(_, path)| path.is_empty()) {
+        eprintln!("error: --summary=json prints on standard output; write the output with -o or --out-dir");
+        summary.exit(EXIT_USAGE, 1);
+    }
+    let emitting = |kind: Emit| artifacts.iter().any(|(e, _)| *e == kind);
+    if profile == Some(Profile::Debug) && opts.stack_limit.is_none() { opts.stack_limit = Some(DEBUG_STACK_LIMIT); }
+    if artifacts.iter().any(|(e, path)| path.is_empty() && matches!(e, Emit::Obj | Emit::Exe | Emit::Shared)) {
+        eprintln!("error: --emit=obj, exe and so need an output path (-o)");
+        summary.exit(EXIT_USAGE, 1);
+    }
+    opts.shared = emitting(Emit::Shared);
+    if opts.shared && (emitting(Emit::Asm) || emitting(Emit::Obj) || emitting(Emit::Exe)) {
+        eprintln!("error: --emit=so generates position-independent library code and cannot be combined with asm, obj or exe");
+        summary.exit(EXIT_USAGE, 1);
+    }
+    if opts.freestanding && (opts.syscalls == Syscalls::Libc || opts.shared) {
+        eprintln!("error: --freestanding cannot be combined with --libc or a .so output");
+        summary.exit(EXIT_USAGE, 1);
+    }
+    if opts.import_memory && (emitting(Emit::Exe) || opts.shared) {
+        eprintln!("error: --import-memory leaves `__coatl_mem` for the host to define; emit asm or obj and link them into the host");
+        summary.exit(EXIT_USAGE, 1);
+    }
+    if opts.syscalls != Syscalls::Linux && arch != "x86_64" {
+        eprintln!("error: --libc and --syscalls are only supported for --arch=x86_64");
+        summary.exit(EXIT_USAGE, 1);
+    }
+
+    if let Some(entry) = &opts.entry {
+        let entry = match entry.split_once("::") { Some((m, n)) => mangle::mangle(m, n), None => entry.clone() };
+        if !ir_section(&ir, "functions").iter().any(|f| f.as_list().and_then(|f| f.get(1)).and_then(|n| n.as_atom()).is_some_and(|n| *n == entry)) {
+            eprintln!("error: entry function `{}` not found", mangle::display(&entry));
+            summary.exit(EXIT_USAGE, 1);
+        }
+        opts.entry = Some(entry);
+    }
+
+    let start = Instant::now();
+    if let Err(errors) = typecheck::check_program(&ir) {
+        for e in &errors { eprintln!("{}", e); }
+        eprintln!("coatl: {} error(s) found", errors.len());
+        summary.exit(EXIT_TYPE, errors.len());
+    }
+    timings.record("typecheck", start.elapsed());
+    if verbose { eprintln!("coatl: typecheck passed"); }
+    let mut ir = ir;
+    if gc_functions {
+        let removed = callgraph::strip_unreachable(&mut ir, opts.entry.as_deref().unwrap_or("main"));
+        if verbose {
+            let names: Vec<String> = removed.iter().map(|n| mangle::display(n)).collect();
+            eprintln!("coatl: unreachable functions removed: {}", if names.is_empty() { "(none)".to_string() } else { names.join(", ") });
+        }
+    }
+    if let Some(prefix) = &export_prefix {
+        opts.entry = Some(prefix_exports(&mut ir, prefix, opts.entry.as_deref().unwrap_or("main")));
+        opts.export_prefix = Some(prefix.clone());
+    }
+    if profile == Some(Profile::Release) {
+        let start = Instant::now();
+        fold_constants(&mut ir);
+        timings.record("optimize", start.elapsed());
+    }
+
+    for (name, value) in custom_sections {
+        let data = if value == "@ir" {
+            ir.to_ir().into_bytes()
+        } else if let Some(path) = value.strip_prefix('@') {
+            fs::read(path).unwrap_or_else(|e| { eprintln!("error: cannot read custom section file {}: {}", path, e); summary.exit(EXIT_FAILURE, 1) })
         } else {
-            // Need to assemble and link
-            let mut tmp_s = env::temp_dir();
-            tmp_s.push("coatl_tmp.s");
-            fs::write(&tmp_s, output).expect("Failed to write temp assembly");
-            
-            let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
-            let mut cmd = process::Command::new(&cc);
-            cmd.args(&["-fPIE", "-pie", "-e", "coatl_start", tmp_s.to_str().unwrap(), "-o", &output_path]);
-            
-            // Special handling for aarch64 cross-compilation match
-            if arch == "aarch64" {
-                let machine = process::Command::new("uname").arg("-m").output().map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string()).unwrap_or_default();
-                if machine != "aarch64" {
-                    // Try to find cross compiler
-                    let cross_cc = "aarch64-linux-gnu-gcc";
-                    if process::Command::new("command").args(&["-v", cross_cc]).status().map(|s| s.success()).unwrap_or(false) {
-                        cmd = process::Command::new(cross_cc);
-                        cmd.args(&["-fPIE", "-pie", "-e", "coatl_start", tmp_s.to_str().unwrap(), "-o", &output_path]);
-                    }
-                }
-            }
-
-            let status = cmd.status().expect("Failed to run linker");
-            if !status.success() {
-                eprintln!("Linker failed");
-                process::exit(1);
-            }
-            let _ = fs::remove_file(tmp_s);
-        }
-    } else {
-        print!("{}", output);
-    }
+            value.into_bytes()
+        };
+        opts.custom_sections.push((name, data));
+    }
+
+    // The IR and the graphs drawn from it are written before code generation; when they
+    // are all that was asked for, the build ends there.
+    let is_ir = |e: &Emit| matches!(e, Emit::Ir | Emit::CfgDot | Emit::CallGraph | Emit::CallGraphJson);
+    let from_ir = artifacts.iter().all(|(e, _)| is_ir(e));
+    for (emit, path) in artifacts.iter().filter(|(e, _)| is_ir(e)) {
+        if from_ir && (layout_map.is_some() || map_path.is_some() || size_report || stack_report.is_some() || verify) {
+            eprintln!("error: --layout-map, --map, --size-report, --stack-report and --verify work on generated code and cannot be used with --emit=ir, cfg-dot or callgraph");
+            summary.exit(EXIT_USAGE, 1);
+        }
+        // In --out-dir, each function's graph gets its own `<function>.dot`.
+        if *emit == Emit::CfgDot && let Some(dir) = &out_dir {
+            for (name, graph) in cfg::render_each(&ir) {
+                let path = dir.join(format!("{}.dot", name)).display().to_string();
+                fs::write(&path, graph).unwrap_or_else(|e| { eprintln!("error: cannot write {}: {}", path, e); summary.exit(EXIT_FAILURE, 1) });
+                summary.artifacts.push(path);
+            }
+            continue;
+        }
+        let text = match emit {
+            Emit::Ir => ir.to_ir(),
+            Emit::CfgDot => cfg::render(&ir),
+            Emit::CallGraph => callgraph::dot(&callgraph::build(&ir)),
+            _ => callgraph::json(&callgraph::build(&ir)),
+        };
+        if path.is_empty() && *emit == Emit::Ir { println!("{}", text); }
+        else if path.is_empty() { print!("{}", text); } else {
+            fs::write(path, text).unwrap_or_else(|e| { eprintln!("error: cannot write {}: {}", path, e); summary.exit(EXIT_FAILURE, 1) });
+            summary.artifacts.push(path.clone());
+        }
+    }
+    if from_ir {
+        timings.report();
+        summary.exit(0, 0);
+    }
+
+    if verbose {
+        let mut intrinsics = Vec::new();
+        collect_intrinsic_calls(&ir, &mut intrinsics);
+        intrinsics.sort();
+        eprintln!("coatl: lowering for {}", arch);
+        eprintln!("coatl: intrinsics referenced: {}", if intrinsics.is_empty() { "(none)".to_string() } else { intrinsics.join(", ") });
+    }
+    // Calls the program cannot resolve itself, explained if the system linker rejects them.
+    let unknown = if emitting(Emit::Exe) || emitting(Emit::Shared) { unknown_calls(&ir) } else { Vec::new() };
+    // The assembly is streamed to the `.s` artifact, or else to a scratch file that objects,
+    // executables and libraries are assembled from. --verify, --map and --size-report check
+    // it before anything is written, and standard output takes it last, so then it is kept
+    // in memory.
+    let scratch = env::temp_dir().join(format!("coatl_tmp_{}.s", process::id()));
+    let call_graph = stack_report.map(|_| callgraph::build(&ir));
+    let asm_path = artifacts.iter().find(|(e, _)| *e == Emit::Asm).map(|(_, p)| p.clone()).unwrap_or_else(|| scratch.display().to_string());
+    let start = Instant::now();
+    let lowered = panic::catch_unwind(AssertUnwindSafe(|| {
+        if verify || map_path.is_some() || size_report || asm_path.is_empty() {
+            let (text, assembly) = lower(ir, &arch, &opts);
+            Ok((Some(text), assembly))
+        } else {
+            let file = fs::File::create(&asm_path)?;
+            lower_to(ir, &arch, &opts, io::BufWriter::new(file)).map(|assembly| (None, assembly))
+        }
+    }));
+    let (text, mut assembly) = match lowered {
+        Ok(Ok(lowered)) => lowered,
+        Ok(Err(e)) => { eprintln!("error: cannot write {}: {}", asm_path, e); summary.exit(EXIT_FAILURE, 1) }
+        Err(_) => summary.exit(EXIT_CODEGEN, 1),
+    };
+    if verbose { log_layout(&assembly.strings); }
+    if let Some(path) = &layout_map {
+        fs::write(path, layout_json(&assembly.strings)).unwrap_or_else(|e| { eprintln!("error: cannot write {}: {}", path, e); summary.exit(EXIT_FAILURE, 1) });
+    }
+    timings.functions = std::mem::take(&mut assembly.fn_times);
+    timings.record("codegen", start.elapsed());
+    if let (Some(limit), Some(graph)) = (stack_report, &call_graph) {
+        let (report, warnings) = stack::report(&assembly.frames, graph, limit);
+        eprint!("{}", report);
+        for w in &warnings { eprintln!("warning: {}", w); }
+        summary.warnings += warnings.len();
+    }
+
+    if verify && let Some(output) = &text {
+        let start = Instant::now();
+        if let Err(messages) = assemble(&arch, output, "verify") {
+            let errors: Vec<(usize, &str)> = messages.lines().filter_map(assembler_error).collect();
+            if errors.is_empty() { eprint!("{}", messages); }
+            for (line, message) in &errors {
+                eprintln!("error: generated assembly line {}: {}", line, message);
+                eprintln!("  note: emitted for {}", assembly.origin_of(*line).unwrap_or("the startup code"));
+            }
+            eprintln!("coatl: the generated assembly does not assemble for {}; this is a compiler bug unless it comes from an asm statement", arch);
+            summary.exit(EXIT_CODEGEN, errors.len().max(1));
+        }
+        timings.record("verify", start.elapsed());
+        if verbose { eprintln!("coatl: generated assembly verified"); }
+    }
+
+    if (map_path.is_some() || size_report) && let Some(output) = &text {
+        // Sizes come from the symbol table of the program assembled on its own.
+        let start = Instant::now();
+        let Some(sizes) = assemble(&arch, output, "map").ok().and_then(|bytes| map::read_elf(&bytes)) else {
+            eprintln!("error: cannot assemble the program for {} to measure it for {}", arch, if map_path.is_some() { "--map" } else { "--size-report" });
+            summary.exit(EXIT_CODEGEN, 1);
+        };
+        if let Some(path) = &map_path {
+            fs::write(path, map::render(&sizes, &assembly.strings)).unwrap_or_else(|e| { eprintln!("error: cannot write {}: {}", path, e); summary.exit(EXIT_FAILURE, 1) });
+        }
+        if size_report { eprint!("{}", map::size_report(&sizes)); }
+        timings.record("map", start.elapsed());
+    }
+
+    if let Some(output) = &text {
+        if asm_path.is_empty() { print!("{}", output); } else {
+            fs::write(&asm_path, output).unwrap_or_else(|e| { eprintln!("error: cannot write {}: {}", asm_path, e); summary.exit(EXIT_FAILURE, 1) });
+        }
+    }
+    if emitting(Emit::Asm) && !asm_path.is_empty() { summary.artifacts.push(asm_path.clone()); }
+    for (emit, path) in &artifacts {
+        if !matches!(emit, Emit::Obj | Emit::Exe | Emit::Shared) { continue; }
+        // With the `object` feature, x86_64 objects come from the built-in assembler rather
+        // than `$CC -c`. Debug builds still use `$CC` for the line info it adds.
+        #[cfg(feature = "object")]
+        if *emit == Emit::Obj && arch == "x86_64" && profile != Some(Profile::Debug) {
+            let start = Instant::now();
+            let source = match &text {
+                Some(text) => text.clone(),
+                None => fs::read_to_string(&asm_path).unwrap_or_else(|e| { eprintln!("error: cannot read {}: {}", asm_path, e); summary.exit(EXIT_FAILURE, 1) }),
+            };
+            let bytes = coatl::obj::assemble_x86_64(&source).unwrap_or_else(|(line, message)| {
+                if line == 0 { eprintln!("error: {}", message); } else {
+                    eprintln!("error: generated assembly line {}: {}", line, message);
+                    eprintln!("  note: emitted for {}", assembly.origin_of(line).unwrap_or("the startup code"));
+                }
+                eprintln!("coatl: the built-in assembler cannot assemble the program; this is a compiler bug unless it comes from an asm statement");
+                let _ = fs::remove_file(&scratch);
+                summary.exit(EXIT_CODEGEN, 1)
+            });
+            fs::write(path, bytes).unwrap_or_else(|e| { eprintln!("error: cannot write {}: {}", path, e); summary.exit(EXIT_FAILURE, 1) });
+            timings.record("assemble", start.elapsed());
+            summary.artifacts.push(path.clone());
+            continue;
+        }
+        let start = opts.start_symbol();
+        let link_args: &[&str] = if *emit == Emit::Obj { &["-c"] }
+            else if opts.shared { &["-shared", "-fPIC"] }
+            else if opts.freestanding { &["-nostdlib", "-static", "-e", &start] }
+            else if opts.syscalls == Syscalls::Libc { &["-fPIE", "-pie"] }
+            // A prefixed program defines no `main` for the C startup objects to call, and its
+            // own startup code does not need them.
+            else if opts.export_prefix.is_some() { &["-fPIE", "-pie", "-nostartfiles", "-e", &start] }
+            else { &["-fPIE", "-pie", "-e", &start] };
+        // Debug builds carry line info for the generated assembly; release builds drop
+        // the symbol table.
+        let profile_args: &[&str] = match profile {
+            Some(Profile::Debug) => &["-g"],
+            Some(Profile::Release) if *emit != Emit::Obj => &["-s"],
+            _ => &[],
+        };
+        let mut cmd = process::Command::new(c_compiler(&arch));
+        cmd.args(link_args).args(profile_args).args([asm_path.as_str(), "-o", path]);
+
+        if verbose { eprintln!("coatl: linking with {:?}", cmd); }
+        let start = Instant::now();
+        let status = cmd.status().expect("Failed to run linker");
+        timings.record("link", start.elapsed());
+        if !status.success() {
+            for e in &unknown { eprintln!("{}", e); }
+            eprintln!("Linker failed");
+            let _ = fs::remove_file(&scratch);
+            summary.exit(EXIT_CODEGEN, 1);
+        }
+        summary.artifacts.push(path.clone());
+    }
+    let _ = fs::remove_file(&scratch);
+    timings.report();
+    summary.exit(0, 0);
 }
@@ -0,0 +1,81 @@
+//! Symbol names for module-qualified functions.
+//!
+//! A function `name` defined in an imported module `module` (the import's file stem) is
+//! emitted as `module__name` in the IR and in both native backends. Functions of the
+//! root source file keep their plain names, so `main` stays `main`.
+//!
+//! Overloads of one name get the parameter types appended after `.`, so `print(i32)` and
+//! `print(str)` become `print.i32` and `print.str`. Names that are not overloaded are
+//! left alone.
+
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+
+use crate::Symbol;
+
+/// Separator between the module and function parts of a mangled symbol.
+pub const SEPARATOR: &str = "__";
+
+/// The modules symbols have been mangled for in this process, starting with the runtime and
+/// prelude compiled into `coatl`.
+fn modules() -> &'static Mutex<HashSet<String>> {
+    static MODULES: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    MODULES.get_or_init(|| Mutex::new([crate::RUNTIME_MODULE, crate::PRELUDE_MODULE].map(String::from).into()))
+}
+
+/// Makes `demangle` split symbols at `module`. `mangle` registers its module itself; tools
+/// that only read symbols register the modules they know of.
+pub fn register_module(module: &str) {
+    let mut modules = modules().lock().unwrap();
+    if !modules.contains(module) { modules.insert(module.to_string()); }
+}
+
+/// Returns the symbol for `name` defined in `module`.
+pub fn mangle(module: &str, name: &str) -> String {
+    register_module(module);
+    format!("{}{}{}", module, SEPARATOR, name)
+}
+
+/// Separator between a function name and the parameter types of an overload.
+pub const OVERLOAD_SEPARATOR: char = '.';
+
+/// Returns the symbol for the overload of `name` taking parameters of types `params`.
+/// Pointer and array types are spelled `ptr_T` and `arrN_T`; no parameters is `void`.
+pub fn overload(name: &str, params: &[Symbol]) -> String {
+    fn encode(ty: &str) -> String {
+        if let Some(inner) = ty.strip_prefix('*') { return format!("ptr_{}", encode(inner)); }
+        if let Some((elem, len)) = ty.strip_prefix('[').and_then(|t| t.strip_suffix(']')).and_then(|t| t.rsplit_once(' ')) {
+            return format!("arr{}_{}", len, encode(elem));
+        }
+        ty.to_string()
+    }
+    let types: Vec<String> = params.iter().map(|t| encode(t)).collect();
+    format!("{}{}{}", name, OVERLOAD_SEPARATOR, if types.is_empty() { "void".to_string() } else { types.join(".") })
+}
+
+/// Splits a mangled symbol into its module and function name at the first `__` preceded by a
+/// registered module, so `m__my__fn` is `m`'s `my__fn` while a root-file `my__fn` stays
+/// whole. Returns `None` for plain names and for `__`-prefixed runtime intrinsics.
+pub fn demangle(symbol: &str) -> Option<(&str, &str)> {
+    let modules = modules().lock().unwrap();
+    let at = (1..symbol.len()).find(|&at| symbol.as_bytes()[at..].starts_with(SEPARATOR.as_bytes()) && symbol.len() > at + SEPARATOR.len() && modules.contains(&symbol[..at]))?;
+    Some((&symbol[..at], &symbol[at + SEPARATOR.len()..]))
+}
+
+/// Human-readable form of a symbol: `module::name` for mangled symbols and `name(i32, str)`
+/// for overloads, unchanged otherwise.
+pub fn display(symbol: &str) -> String {
+    let (base, params) = match symbol.split_once(OVERLOAD_SEPARATOR) {
+        Some((base, params)) => (base, Some(params)),
+        None => (symbol, None),
+    };
+    let base = match demangle(base) {
+        Some((module, name)) => format!("{}::{}", module, name),
+        None => base.to_string(),
+    };
+    match params {
+        Some("void") => format!("{}()", base),
+        Some(params) => format!("{}({})", base, params.split(OVERLOAD_SEPARATOR).collect::<Vec<_>>().join(", ")),
+        None => base,
+    }
+}
@@ -0,0 +1,190 @@
+//! `coatl.toml` project manifests, read by `coatl build`.
+//!
+//! A manifest is a small subset of TOML: `[section]` headers and `key = value` lines whose
+//! values are strings, integers or booleans, with `#` comments. `[package]` names the
+//! project, `[build]` holds the settings otherwise given on the command line, and
+//! `[dependencies]` maps package names to the directories of other Coatl packages:
+//!
+//! ```toml
+//! [package]
+//! name = "snake"
+//!
+//! [build]
+//! main = "src/main.coatl"
+//! target = "x86_64-linux"
+//! output = "build/snake"
+//!
+//! [dependencies]
+//! tty = "../tty"
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File name `coatl build` looks for in the current directory and its parents.
+pub const FILE_NAME: &str = "coatl.toml";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+}
+
+/// `[build]` settings; `None` leaves the command line default in place.
+#[derive(Debug, Clone, Default)]
+pub struct Build {
+    /// Root source file, relative to the manifest. Defaults to `main.coatl`.
+    pub main: Option<String>,
+    pub target: Option<String>,
+    /// Output path, relative to the manifest. Defaults to the package name.
+    pub output: Option<String>,
+    pub emit: Option<String>,
+    /// `debug` or `release` (`--profile`).
+    pub profile: Option<String>,
+    pub entry: Option<String>,
+    pub stack_limit: Option<String>,
+    pub libc: Option<bool>,
+    /// `linux`, `libc` or `extern` (`--syscalls`).
+    pub syscalls: Option<String>,
+    pub freestanding: Option<bool>,
+    pub runtime: Option<bool>,
+    pub prelude: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    /// Directory holding the manifest; relative paths are resolved against it.
+    pub dir: PathBuf,
+    pub name: String,
+    pub build: Build,
+    /// `[dependencies]`: package name and directory, relative to `dir`, in file order.
+    pub dependencies: Vec<(String, String)>,
+}
+
+/// Returns the manifest in `start` or the nearest parent directory that has one.
+pub fn find(start: &Path) -> Option<PathBuf> {
+    start.ancestors().map(|d| d.join(FILE_NAME)).find(|p| p.is_file())
+}
+
+/// Splits manifest text into `(section, key, value, line)` entries.
+fn parse_entries(text: &str) -> Result<Vec<(String, String, Value, usize)>, String> {
+    let mut section = String::new();
+    let mut entries = Vec::new();
+    for (i, raw) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let line = strip_comment(raw).trim();
+        if line.is_empty() { continue; }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("{}:{}: expected `key = value`", FILE_NAME, line_no));
+        };
+        let value = value.trim();
+        let value = if let Some(s) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Value::Str(s.to_string())
+        } else if value == "true" || value == "false" {
+            Value::Bool(value == "true")
+        } else if let Ok(n) = value.replace('_', "").parse() {
+            Value::Int(n)
+        } else {
+            return Err(format!("{}:{}: `{}` is not a string, integer or boolean", FILE_NAME, line_no, value));
+        };
+        entries.push((section.clone(), key.trim().to_string(), value, line_no));
+    }
+    Ok(entries)
+}
+
+/// Drops a `#` comment, leaving `#` inside a string alone.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+impl Manifest {
+    /// Reads and parses the manifest at `path`.
+    pub fn load(path: &Path) -> Result<Manifest, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let dir = path.parent().map(|d| d.to_path_buf()).unwrap_or_default();
+        Manifest::parse(&text, dir)
+    }
+
+    pub fn parse(text: &str, dir: PathBuf) -> Result<Manifest, String> {
+        let mut name = None;
+        let mut build = Build::default();
+        let mut dependencies = Vec::new();
+        for (section, key, value, line) in parse_entries(text)? {
+            let at = format!("{}:{}", FILE_NAME, line);
+            let string = |v: Value| match v {
+                Value::Str(s) => Ok(s),
+                Value::Int(n) => Ok(n.to_string()),
+                Value::Bool(_) => Err(format!("{}: `{}` must be a string", at, key)),
+            };
+            let flag = |v: Value| match v {
+                Value::Bool(b) => Ok(b),
+                _ => Err(format!("{}: `{}` must be true or false", at, key)),
+            };
+            match (section.as_str(), key.as_str()) {
+                ("package", "name") => name = Some(string(value)?),
+                ("package", "version") => {}
+                ("build", "main") => build.main = Some(string(value)?),
+                ("build", "target") => build.target = Some(string(value)?),
+                ("build", "output") => build.output = Some(string(value)?),
+                ("build", "emit") => build.emit = Some(string(value)?),
+                ("build", "profile") => build.profile = Some(string(value)?),
+                ("build", "entry") => build.entry = Some(string(value)?),
+                ("build", "stack-limit") => build.stack_limit = Some(string(value)?),
+                ("build", "libc") => build.libc = Some(flag(value)?),
+                ("build", "syscalls") => build.syscalls = Some(string(value)?),
+                ("build", "freestanding") => build.freestanding = Some(flag(value)?),
+                ("build", "runtime") => build.runtime = Some(flag(value)?),
+                ("build", "prelude") => build.prelude = Some(flag(value)?),
+                ("dependencies", _) => dependencies.push((key.clone(), string(value)?)),
+                ("", _) => return Err(format!("{}: `{}` must be inside a [package], [build] or [dependencies] section", at, key)),
+                _ => return Err(format!("{}: unknown key `{}` in [{}]", at, key, section)),
+            }
+        }
+        let name = name.ok_or_else(|| format!("{}: missing `name` in [package]", FILE_NAME))?;
+        Ok(Manifest { dir, name, build, dependencies })
+    }
+
+    /// Directories of the `[dependencies]` packages by name.
+    pub fn dependency_dirs(&self) -> HashMap<String, PathBuf> {
+        self.dependencies.iter().map(|(name, path)| (name.clone(), self.dir.join(path))).collect()
+    }
+
+    /// Root source file of the package.
+    pub fn main_path(&self) -> PathBuf {
+        self.dir.join(self.build.main.as_deref().unwrap_or("main.coatl"))
+    }
+
+    /// Command line arguments equivalent to the manifest, input file first.
+    pub fn to_args(&self) -> Vec<String> {
+        let b = &self.build;
+        let path = |p: &str| self.dir.join(p).to_string_lossy().into_owned();
+        let mut args = vec![self.main_path().to_string_lossy().into_owned()];
+        args.extend(["-o".to_string(), path(b.output.as_deref().unwrap_or(&self.name))]);
+        if let Some(t) = &b.target { args.push(format!("--target={}", t)); }
+        if let Some(e) = &b.emit { args.push(format!("--emit={}", e)); }
+        if let Some(p) = &b.profile { args.push(format!("--profile={}", p)); }
+        if let Some(e) = &b.entry { args.push(format!("--entry={}", e)); }
+        if let Some(s) = &b.stack_limit { args.push(format!("--stack-limit={}", s)); }
+        if b.libc == Some(true) { args.push("--libc".to_string()); }
+        if let Some(s) = &b.syscalls { args.push(format!("--syscalls={}", s)); }
+        if b.freestanding == Some(true) { args.push("--freestanding".to_string()); }
+        if b.runtime == Some(false) { args.push("--no-runtime".to_string()); }
+        if b.prelude == Some(false) { args.push("--no-prelude".to_string()); }
+        for (name, dir) in &self.dependencies { args.push(format!("--dep={}={}", name, path(dir))); }
+        args
+    }
+}
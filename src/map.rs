@@ -0,0 +1,99 @@
+//! Map files (`--map`): the size of every program function, the sizes of the output
+//! sections and the string pool offsets, for tracking the size of generated code. The same
+//! function sizes, largest first, make up the `--size-report`.
+//!
+//! Function and section sizes are read from the ELF symbol and section tables of the
+//! assembled program, so they are exact for either target. The backends give each
+//! program function a `.size` when [`crate::CodegenOptions::symbol_sizes`] is set; the
+//! assembly intrinsics only count toward the section totals.
+
+use crate::mangle;
+use std::collections::HashMap;
+
+/// Sections reported in the map, in output order.
+const SECTIONS: &[&str] = &[".text", ".rodata", ".data", ".bss"];
+
+/// Symbol table type of a function symbol (`STT_FUNC`).
+const STT_FUNC: u8 = 2;
+/// Section header type of the symbol table (`SHT_SYMTAB`).
+const SHT_SYMTAB: u32 = 2;
+
+/// Function and section sizes of a relocatable object.
+pub struct ObjectSizes {
+    /// Sized function symbols, in address order.
+    pub functions: Vec<(String, u64)>,
+    pub sections: HashMap<String, u64>,
+}
+
+fn u16_at(b: &[u8], at: usize) -> Option<u16> { Some(u16::from_le_bytes(b.get(at..at + 2)?.try_into().ok()?)) }
+fn u32_at(b: &[u8], at: usize) -> Option<u32> { Some(u32::from_le_bytes(b.get(at..at + 4)?.try_into().ok()?)) }
+fn u64_at(b: &[u8], at: usize) -> Option<u64> { Some(u64::from_le_bytes(b.get(at..at + 8)?.try_into().ok()?)) }
+
+/// The NUL-terminated name at `at` in a string table.
+fn name_at(table: &[u8], at: usize) -> String {
+    let bytes = table.get(at..).unwrap_or(&[]);
+    String::from_utf8_lossy(&bytes[..bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len())]).into_owned()
+}
+
+/// Reads the sizes from a little-endian ELF64 object, as produced for both targets.
+pub fn read_elf(bytes: &[u8]) -> Option<ObjectSizes> {
+    if bytes.get(..4)? != b"\x7fELF" || bytes[4] != 2 || bytes[5] != 1 { return None; }
+    let (shoff, shentsize, shnum, shstrndx) = (u64_at(bytes, 0x28)? as usize, u16_at(bytes, 0x3a)? as usize, u16_at(bytes, 0x3c)? as usize, u16_at(bytes, 0x3e)? as usize);
+    // (name offset, type, file offset, size, link) of each section header.
+    let headers: Vec<(usize, u32, usize, usize, usize)> = (0..shnum).map(|i| {
+        let h = shoff + i * shentsize;
+        Some((u32_at(bytes, h)? as usize, u32_at(bytes, h + 4)?, u64_at(bytes, h + 0x18)? as usize, u64_at(bytes, h + 0x20)? as usize, u32_at(bytes, h + 0x28)? as usize))
+    }).collect::<Option<_>>()?;
+    let table = |i: usize| headers.get(i).and_then(|&(_, _, off, size, _)| bytes.get(off..off + size));
+    let names = table(shstrndx)?;
+    let sections = headers.iter().map(|h| (name_at(names, h.0), h.3 as u64)).filter(|(n, _)| SECTIONS.contains(&n.as_str())).collect();
+
+    let mut functions = Vec::new();
+    if let Some(i) = headers.iter().position(|h| h.1 == SHT_SYMTAB) {
+        let (symbols, strings) = (table(i)?, table(headers[i].4)?);
+        for sym in symbols.chunks_exact(24) {
+            let (name, info, value, size) = (u32_at(sym, 0)? as usize, sym[4], u64_at(sym, 8)?, u64_at(sym, 16)?);
+            if info & 0xf == STT_FUNC && size > 0 { functions.push((value, name_at(strings, name), size)); }
+        }
+    }
+    functions.sort();
+    Some(ObjectSizes { functions: functions.into_iter().map(|(_, name, size)| (name, size)).collect(), sections })
+}
+
+/// Renders the map of a program assembled to `sizes`, whose string pool is `strings`.
+pub fn render(sizes: &ObjectSizes, strings: &HashMap<String, i32>) -> String {
+    let mut out = String::from("Functions\n");
+    let width = sizes.functions.iter().map(|(n, _)| mangle::display(n).len()).max().unwrap_or(0).max("total".len());
+    for (name, size) in &sizes.functions {
+        out.push_str(&format!("  {:<width$}  {:>6} bytes\n", mangle::display(name), size, width = width));
+    }
+    out.push_str(&format!("  {:<width$}  {:>6} bytes\n", "total", sizes.functions.iter().map(|(_, s)| s).sum::<u64>(), width = width));
+
+    out.push_str("\nSections\n");
+    for name in SECTIONS {
+        if let Some(size) = sizes.sections.get(*name) { out.push_str(&format!("  {:<8}  {:>8} bytes\n", name, size)); }
+    }
+
+    out.push_str("\nString pool (__coatl_mem offsets)\n");
+    let mut pooled: Vec<(&i32, &String)> = strings.iter().map(|(s, off)| (off, s)).collect();
+    pooled.sort();
+    for (off, s) in pooled {
+        out.push_str(&format!("  {:>7}  {:>5}  {:?}\n", off, s.len(), s));
+    }
+    out
+}
+
+/// The `--size-report`: every program function's machine code bytes and share of the total,
+/// largest first.
+pub fn size_report(sizes: &ObjectSizes) -> String {
+    let mut functions: Vec<(String, u64)> = sizes.functions.iter().map(|(n, s)| (mangle::display(n), *s)).collect();
+    functions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let total: u64 = functions.iter().map(|(_, s)| s).sum();
+    let width = functions.iter().map(|(n, _)| n.len()).max().unwrap_or(0).max("total".len());
+    let mut out = String::from("code size per function:\n");
+    for (name, size) in &functions {
+        out.push_str(&format!("  {:<width$}  {:>6} bytes  {:>5.1}%\n", name, size, *size as f64 * 100.0 / total.max(1) as f64, width = width));
+    }
+    out.push_str(&format!("  {:<width$}  {:>6} bytes\n", "total", total, width = width));
+    out
+}
@@ -0,0 +1,796 @@
+//! The built-in x86_64 assembler behind `--emit=obj` when coatl is built with the `object`
+//! feature, so objects are written without binutils. It reads the Intel-syntax assembly
+//! the x86_64 backend and its intrinsics emit — the instructions they use, labels and data
+//! directives in `.text`, `.rodata`, `.data`, `.bss`, the init and fini arrays and
+//! `--custom-section`s — and writes the relocatable ELF object.
+//!
+//! A reference from one section to a local label of the same section is resolved here;
+//! the rest become `R_X86_64_PC32` (`rip`-relative operands), `R_X86_64_PLT32` (calls and
+//! jumps) and `R_X86_64_64` (`.quad`) relocations. Branches always take a 32-bit
+//! displacement, so the code is a little larger than GNU as makes it.
+
+use object::write::{Object, Relocation, SectionId, Symbol, SymbolId, SymbolSection};
+use object::{Architecture, BinaryFormat, Endianness, RelocationFlags, SectionFlags, SectionKind, SymbolFlags, SymbolKind, SymbolScope, elf};
+use std::collections::{HashMap, HashSet};
+
+/// A register: its number in the encoding, width in bytes, and whether it is one of
+/// `ah`..`bh` (which cannot be encoded together with a REX prefix) or an `xmm` register.
+#[derive(Clone, Copy, PartialEq)]
+struct Reg { num: u8, size: u8, high: bool, xmm: bool }
+
+/// Stands for `rip` in an address, which only appears in `[rip+symbol]` operands.
+const RIP: Reg = Reg { num: 16, size: 8, high: false, xmm: false };
+
+fn register(name: &str) -> Option<Reg> {
+    const R64: [&str; 16] = ["rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15"];
+    const R32: [&str; 16] = ["eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d", "r12d", "r13d", "r14d", "r15d"];
+    const R16: [&str; 16] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di", "r8w", "r9w", "r10w", "r11w", "r12w", "r13w", "r14w", "r15w"];
+    const R8: [&str; 16] = ["al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil", "r8b", "r9b", "r10b", "r11b", "r12b", "r13b", "r14b", "r15b"];
+    let name = name.to_ascii_lowercase();
+    for (size, table) in [(8, &R64), (4, &R32), (2, &R16), (1, &R8)] {
+        if let Some(num) = table.iter().position(|r| *r == name) { return Some(Reg { num: num as u8, size, high: false, xmm: false }); }
+    }
+    if let Some(num) = ["ah", "ch", "dh", "bh"].iter().position(|r| *r == name) { return Some(Reg { num: num as u8 + 4, size: 1, high: true, xmm: false }); }
+    name.strip_prefix("xmm").and_then(|n| n.parse::<u8>().ok()).filter(|n| *n < 16).map(|num| Reg { num, size: 16, high: false, xmm: true })
+}
+
+/// A linear expression: a constant plus symbols and registers with their coefficients.
+#[derive(Default)]
+struct Expr { value: i64, syms: Vec<(String, i64)>, regs: Vec<(Reg, i64)> }
+
+impl Expr {
+    fn add(mut self, other: Expr, sign: i64) -> Expr {
+        self.value = self.value.wrapping_add(other.value.wrapping_mul(sign));
+        self.syms.extend(other.syms.into_iter().map(|(s, k)| (s, k * sign)));
+        self.regs.extend(other.regs.into_iter().map(|(r, k)| (r, k * sign)));
+        self
+    }
+    fn scaled(mut self, by: i64) -> Expr {
+        self.value = self.value.wrapping_mul(by);
+        for (_, k) in &mut self.syms { *k *= by; }
+        for (_, k) in &mut self.regs { *k *= by; }
+        self
+    }
+    fn constant(&self) -> Option<i64> { (self.syms.is_empty() && self.regs.is_empty()).then_some(self.value) }
+}
+
+#[derive(PartialEq)]
+enum Tok { Num(i64), Name(String), Op(char) }
+
+fn is_symbol_char(c: char) -> bool { c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$') }
+
+fn tokenize(text: &str) -> Result<Vec<Tok>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() { i += 1; continue; }
+        let start = i;
+        if c.is_ascii_digit() {
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() { i += 1; }
+            let digits: String = chars[start..i].iter().collect();
+            let lower = digits.to_ascii_lowercase();
+            let parsed = if let Some(hex) = lower.strip_prefix("0x") { u64::from_str_radix(hex, 16) }
+                else if let Some(bin) = lower.strip_prefix("0b") { u64::from_str_radix(bin, 2) }
+                else if lower.len() > 1 && lower.starts_with('0') { u64::from_str_radix(&lower[1..], 8) }
+                else { lower.parse() };
+            tokens.push(Tok::Num(parsed.map_err(|_| format!("bad number `{}`", digits))? as i64));
+        } else if is_symbol_char(c) {
+            // `name@PLT` stays one token, for branches to take the name from.
+            while i < chars.len() && (is_symbol_char(chars[i]) || chars[i] == '@') { i += 1; }
+            tokens.push(Tok::Name(chars[start..i].iter().collect()));
+        } else if "+-*~()".contains(c) {
+            tokens.push(Tok::Op(c));
+            i += 1;
+        } else {
+            return Err(format!("unexpected `{}` in `{}`", c, text));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses `text` as a sum of products of numbers, symbols and (in addresses) registers.
+fn expr(text: &str) -> Result<Expr, String> {
+    let tokens = tokenize(text)?;
+    let mut at = 0;
+    let e = sum(&tokens, &mut at)?;
+    if at < tokens.len() || tokens.is_empty() { return Err(format!("cannot parse `{}`", text.trim())); }
+    Ok(e)
+}
+
+fn sum(tokens: &[Tok], at: &mut usize) -> Result<Expr, String> {
+    let mut e = product(tokens, at)?;
+    while let Some(Tok::Op(op @ ('+' | '-'))) = tokens.get(*at) {
+        *at += 1;
+        e = e.add(product(tokens, at)?, if *op == '+' { 1 } else { -1 });
+    }
+    Ok(e)
+}
+
+fn product(tokens: &[Tok], at: &mut usize) -> Result<Expr, String> {
+    let mut e = unary(tokens, at)?;
+    while tokens.get(*at) == Some(&Tok::Op('*')) {
+        *at += 1;
+        let rhs = unary(tokens, at)?;
+        e = match (e.constant(), rhs.constant()) {
+            (Some(k), _) => rhs.scaled(k),
+            (_, Some(k)) => e.scaled(k),
+            _ => return Err("only a constant can multiply a register or symbol".to_string()),
+        };
+    }
+    Ok(e)
+}
+
+fn unary(tokens: &[Tok], at: &mut usize) -> Result<Expr, String> {
+    let token = tokens.get(*at).ok_or("expression ends early")?;
+    *at += 1;
+    match token {
+        Tok::Op('-') => Ok(unary(tokens, at)?.scaled(-1)),
+        Tok::Op('+') => unary(tokens, at),
+        Tok::Op('~') => unary(tokens, at)?.constant().map(|v| Expr { value: !v, ..Expr::default() }).ok_or_else(|| "`~` needs a constant".to_string()),
+        Tok::Op('(') => {
+            let e = sum(tokens, at)?;
+            if tokens.get(*at) != Some(&Tok::Op(')')) { return Err("missing `)`".to_string()); }
+            *at += 1;
+            Ok(e)
+        }
+        Tok::Num(n) => Ok(Expr { value: *n, ..Expr::default() }),
+        Tok::Name(name) if name.eq_ignore_ascii_case("rip") => Ok(Expr { regs: vec![(RIP, 1)], ..Expr::default() }),
+        Tok::Name(name) => Ok(match register(name) {
+            Some(r) => Expr { regs: vec![(r, 1)], ..Expr::default() },
+            None => Expr { syms: vec![(name.clone(), 1)], ..Expr::default() },
+        }),
+        Tok::Op(c) => Err(format!("unexpected `{}`", c)),
+    }
+}
+
+/// A memory operand: `[base + index*scale + disp]`, or `[rip + symbol + disp]`.
+struct Mem { size: Option<u8>, base: Option<u8>, index: Option<(u8, u8)>, rip: bool, disp: i64, sym: Option<String> }
+
+enum Operand { Reg(Reg), Mem(Mem), Imm(Expr) }
+
+fn operand(text: &str) -> Result<Operand, String> {
+    let text = text.trim();
+    let Some(open) = text.find('[') else {
+        if let Some(r) = register(text) { return Ok(Operand::Reg(r)); }
+        let e = expr(text)?;
+        if !e.regs.is_empty() { return Err(format!("cannot parse `{}`", text)); }
+        return Ok(Operand::Imm(e));
+    };
+    let close = text.rfind(']').filter(|c| *c == text.len() - 1).ok_or_else(|| format!("cannot parse `{}`", text))?;
+    let size = match text[..open].trim().trim_end_matches("ptr").trim().to_ascii_lowercase().as_str() {
+        "" => None,
+        "byte" => Some(1),
+        "word" => Some(2),
+        "dword" => Some(4),
+        "qword" => Some(8),
+        other => return Err(format!("unknown operand size `{}`", other)),
+    };
+    let e = expr(&text[open + 1..close])?;
+    let mut m = Mem { size, base: None, index: None, rip: false, disp: e.value, sym: None };
+    for (r, k) in e.regs {
+        if r == RIP && k == 1 && !m.rip { m.rip = true; continue; }
+        if r.size != 8 || r.xmm || r == RIP { return Err(format!("cannot address through `{}`", text)); }
+        if k == 1 && m.base.is_none() { m.base = Some(r.num); }
+        else if matches!(k, 1 | 2 | 4 | 8) && m.index.is_none() { m.index = Some((r.num, k as u8)); }
+        else { return Err(format!("cannot address through `{}`", text)); }
+    }
+    // `rsp` has no index encoding, so it has to be the base.
+    if let (Some(b), Some((4, 1))) = (m.base, m.index) { m.base = Some(4); m.index = Some((b, 1)); }
+    if m.index.is_some_and(|(i, _)| i == 4) || (m.rip && (m.base.is_some() || m.index.is_some())) { return Err(format!("cannot address through `{}`", text)); }
+    match e.syms.as_slice() {
+        [] => {}
+        [(s, 1)] if m.rip && !s.contains('@') => m.sym = Some(s.clone()),
+        _ => return Err(format!("a symbol can only be addressed relative to rip, in `{}`", text)),
+    }
+    Ok(Operand::Mem(m))
+}
+
+/// A 32-bit field of an instruction that is relative to the instruction's end and holds
+/// `symbol + addend`: a `rip`-relative displacement, or a branch target.
+struct Reference { at: usize, symbol: String, addend: i64, branch: bool }
+
+/// An encoded instruction.
+#[derive(Default)]
+struct Code { bytes: Vec<u8>, reference: Option<Reference> }
+
+fn opsize(size: u8) -> &'static [u8] { if size == 2 { &[0x66] } else { &[] } }
+
+fn byte_regs(ops: &[Operand]) -> Vec<Reg> {
+    ops.iter().filter_map(|o| match o { Operand::Reg(r) if r.size == 1 => Some(*r), _ => None }).collect()
+}
+
+impl Code {
+    /// Pushes a REX prefix when `rex` has bits set or a byte register needs one to be
+    /// `spl`..`dil` rather than `ah`..`bh`.
+    fn rex(&mut self, rex: u8, regs: &[Reg]) -> Result<(), String> {
+        if rex == 0 && !regs.iter().any(|r| !r.high && (4..8).contains(&r.num)) { return Ok(()); }
+        if regs.iter().any(|r| r.high) { return Err("`ah`, `ch`, `dh` and `bh` cannot be used in this instruction".to_string()); }
+        self.bytes.push(0x40 | rex);
+        Ok(())
+    }
+
+    /// Appends `prefix`, REX, `opcode` and the ModRM addressing of `rm`, with `reg` in the
+    /// ModRM reg field.
+    fn modrm(&mut self, prefix: &[u8], w: bool, opcode: &[u8], reg: u8, rm: &Operand, regs: &[Reg]) -> Result<(), String> {
+        self.bytes.extend(prefix);
+        let mut rex = if w { 8 } else { 0 } | if reg & 8 != 0 { 4 } else { 0 };
+        let mut tail = Vec::new();
+        let mut reference = None;
+        match rm {
+            Operand::Reg(r) => {
+                if r.num & 8 != 0 { rex |= 1; }
+                tail.push(0xc0 | (reg & 7) << 3 | r.num & 7);
+            }
+            Operand::Mem(m) if m.rip => {
+                tail.push((reg & 7) << 3 | 5);
+                match &m.sym {
+                    Some(s) => { reference = Some((tail.len(), s.clone(), m.disp)); tail.extend([0; 4]); }
+                    None => tail.extend(displacement(m.disp)?.to_le_bytes()),
+                }
+            }
+            Operand::Mem(m) => {
+                let disp = displacement(m.disp)?;
+                let (mode, width) = match m.base {
+                    Some(b) if disp == 0 && b & 7 != 5 => (0, 0),
+                    Some(_) if i8::try_from(disp).is_ok() => (1, 1),
+                    Some(_) => (2, 4),
+                    None => (0, 4),
+                };
+                if m.base.is_some_and(|b| b & 8 != 0) { rex |= 1; }
+                if m.index.is_some_and(|(i, _)| i & 8 != 0) { rex |= 2; }
+                match (m.base, m.index) {
+                    (Some(b), None) if b & 7 != 4 => tail.push(mode << 6 | (reg & 7) << 3 | b & 7),
+                    _ => {
+                        let (index, scale) = m.index.unwrap_or((4, 1));
+                        tail.push(mode << 6 | (reg & 7) << 3 | 4);
+                        tail.push((scale.trailing_zeros() as u8) << 6 | (index & 7) << 3 | m.base.map_or(5, |b| b & 7));
+                    }
+                }
+                tail.extend(&disp.to_le_bytes()[..width]);
+            }
+            Operand::Imm(_) => return Err("expected a register or memory operand".to_string()),
+        }
+        self.rex(rex, regs)?;
+        self.bytes.extend(opcode);
+        if let Some((at, symbol, addend)) = reference { self.reference = Some(Reference { at: self.bytes.len() + at, symbol, addend, branch: false }); }
+        self.bytes.extend(tail);
+        Ok(())
+    }
+
+    /// Appends an instruction that encodes register `r` in the low bits of its opcode.
+    fn opreg(&mut self, prefix: &[u8], w: bool, opcode: u8, r: Reg) -> Result<(), String> {
+        self.bytes.extend(prefix);
+        self.rex(if w { 8 } else { 0 } | if r.num & 8 != 0 { 1 } else { 0 }, &[r].into_iter().filter(|r| r.size == 1).collect::<Vec<_>>())?;
+        self.bytes.push(opcode + (r.num & 7));
+        Ok(())
+    }
+
+    /// Appends `value` as a `width`-byte immediate.
+    fn imm(&mut self, value: i64, width: u8) -> Result<(), String> {
+        let fits = match width {
+            1 => (-0x80..=0xff).contains(&value),
+            2 => (-0x8000..=0xffff).contains(&value),
+            4 => (i32::MIN as i64..=u32::MAX as i64).contains(&value),
+            _ => true,
+        };
+        if !fits { return Err(format!("immediate {} does not fit in {} bytes", value, width)); }
+        self.bytes.extend(&value.to_le_bytes()[..width as usize]);
+        Ok(())
+    }
+
+    /// Appends a 32-bit branch displacement to `target`.
+    fn branch(&mut self, target: &Expr) -> Result<(), String> {
+        let [(symbol, 1)] = target.syms.as_slice() else { return Err("a branch needs a label".to_string()) };
+        // Branches get `R_X86_64_PLT32` relocations anyway.
+        let symbol = symbol.strip_suffix("@PLT").unwrap_or(symbol);
+        self.reference = Some(Reference { at: self.bytes.len(), symbol: symbol.to_string(), addend: target.value, branch: true });
+        self.bytes.extend([0; 4]);
+        Ok(())
+    }
+}
+
+fn displacement(disp: i64) -> Result<i32, String> { i32::try_from(disp).map_err(|_| format!("displacement {} does not fit in 32 bits", disp)) }
+
+fn immediate(e: &Expr) -> Result<i64, String> { e.constant().ok_or_else(|| "a symbol cannot be an immediate here".to_string()) }
+
+/// The immediate of a 64-bit instruction, which is sign-extended from 32 bits.
+fn imm32(value: i64, size: u8) -> Result<i64, String> {
+    if size == 8 && i32::try_from(value).is_err() { return Err(format!("immediate {} does not fit in 32 bits", value)); }
+    Ok(value)
+}
+
+/// The operand size that the register and sized memory operands agree on.
+fn width(ops: &[Operand]) -> Result<u8, String> {
+    let mut sizes = ops.iter().filter_map(|o| match o { Operand::Reg(r) => Some(r.size), Operand::Mem(m) => m.size, Operand::Imm(_) => None });
+    let size = sizes.next().ok_or("operand size is ambiguous; give the memory operand a `ptr` size")?;
+    if sizes.any(|s| s != size) { return Err("operand sizes do not match".to_string()); }
+    if size == 16 { return Err("unsupported xmm operand".to_string()); }
+    Ok(size)
+}
+
+fn condition(cc: &str) -> Option<u8> {
+    Some(match cc {
+        "o" => 0, "no" => 1, "b" | "c" | "nae" => 2, "ae" | "nb" | "nc" => 3, "e" | "z" => 4, "ne" | "nz" => 5,
+        "be" | "na" => 6, "a" | "nbe" => 7, "s" => 8, "ns" => 9, "p" | "pe" => 10, "np" | "po" => 11,
+        "l" | "nge" => 12, "ge" | "nl" => 13, "le" | "ng" => 14, "g" | "nle" => 15,
+        _ => return None,
+    })
+}
+
+/// Encodes one instruction.
+fn encode(mnemonic: &str, ops: &[Operand]) -> Result<Code, String> {
+    const ALU: [&str; 8] = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+    const GROUP3: [&str; 6] = ["not", "neg", "mul", "imul", "div", "idiv"];
+    const SHIFTS: [&str; 8] = ["rol", "ror", "rcl", "rcr", "shl", "shr", "sal", "sar"];
+    let mut c = Code::default();
+    let regs = byte_regs(ops);
+    let wide = |size: u8| (size == 1) as u8 ^ 1;
+    let bad = || Err(format!("unsupported operands for `{}`", mnemonic));
+    match (mnemonic, ops) {
+        (m, [dst, src]) if ALU.contains(&m) => {
+            let digit = ALU.iter().position(|a| *a == m).unwrap() as u8;
+            let size = width(ops)?;
+            match (dst, src) {
+                (Operand::Reg(_) | Operand::Mem(_), Operand::Reg(s)) => c.modrm(opsize(size), size == 8, &[digit * 8 + wide(size)], s.num, dst, &regs)?,
+                (Operand::Reg(d), Operand::Mem(_)) => c.modrm(opsize(size), size == 8, &[digit * 8 + 2 + wide(size)], d.num, src, &regs)?,
+                (_, Operand::Imm(e)) => {
+                    let v = imm32(immediate(e)?, size)?;
+                    if size == 1 { c.modrm(&[], false, &[0x80], digit, dst, &regs)?; c.imm(v, 1)?; }
+                    else if let Ok(b) = i8::try_from(v) { c.modrm(opsize(size), size == 8, &[0x83], digit, dst, &regs)?; c.imm(b as i64, 1)?; }
+                    else { c.modrm(opsize(size), size == 8, &[0x81], digit, dst, &regs)?; c.imm(v, size.min(4))?; }
+                }
+                _ => return bad(),
+            }
+        }
+        ("test", [dst, src]) => {
+            let size = width(ops)?;
+            match (dst, src) {
+                (_, Operand::Reg(s)) => c.modrm(opsize(size), size == 8, &[0x84 + wide(size)], s.num, dst, &regs)?,
+                (Operand::Reg(d), Operand::Mem(_)) => c.modrm(opsize(size), size == 8, &[0x84 + wide(size)], d.num, src, &regs)?,
+                (_, Operand::Imm(e)) => { c.modrm(opsize(size), size == 8, &[0xf6 + wide(size)], 0, dst, &regs)?; c.imm(imm32(immediate(e)?, size)?, size.min(4))?; }
+                _ => return bad(),
+            }
+        }
+        ("mov", [dst, src]) => {
+            let size = width(ops)?;
+            match (dst, src) {
+                (Operand::Reg(_) | Operand::Mem(_), Operand::Reg(s)) => c.modrm(opsize(size), size == 8, &[0x88 + wide(size)], s.num, dst, &regs)?,
+                (Operand::Reg(d), Operand::Mem(_)) => c.modrm(opsize(size), size == 8, &[0x8a + wide(size)], d.num, src, &regs)?,
+                (Operand::Reg(d), Operand::Imm(e)) => {
+                    let v = immediate(e)?;
+                    match size {
+                        1 => { c.opreg(&[], false, 0xb0, *d)?; c.imm(v, 1)?; }
+                        8 if i32::try_from(v).is_ok() => { c.modrm(&[], true, &[0xc7], 0, dst, &regs)?; c.imm(v, 4)?; }
+                        // A 32-bit move zero-extends into the whole register.
+                        8 if u32::try_from(v).is_ok() => { c.opreg(&[], false, 0xb8, *d)?; c.imm(v, 4)?; }
+                        _ => { c.opreg(opsize(size), size == 8, 0xb8, *d)?; c.imm(v, size)?; }
+                    }
+                }
+                (Operand::Mem(_), Operand::Imm(e)) => { c.modrm(opsize(size), size == 8, &[0xc6 + wide(size)], 0, dst, &regs)?; c.imm(imm32(immediate(e)?, size)?, size.min(4))?; }
+                _ => return bad(),
+            }
+        }
+        ("lea", [Operand::Reg(d), src @ Operand::Mem(_)]) if d.size >= 2 && !d.xmm => c.modrm(opsize(d.size), d.size == 8, &[0x8d], d.num, src, &regs)?,
+        ("movzx" | "movsx", [Operand::Reg(d), src]) if d.size >= 2 && !d.xmm => {
+            let from = match src { Operand::Reg(r) => r.size, Operand::Mem(m) => m.size.ok_or("operand size is ambiguous; give the memory operand a `ptr` size")?, Operand::Imm(_) => return bad() };
+            let op = match (mnemonic, from) { ("movzx", 1) => 0xb6, ("movzx", 2) => 0xb7, ("movsx", 1) => 0xbe, ("movsx", 2) => 0xbf, _ => return bad() };
+            c.modrm(opsize(d.size), d.size == 8, &[0x0f, op], d.num, src, &regs)?;
+        }
+        ("movsxd", [Operand::Reg(d), src]) if d.size == 8 && !d.xmm && matches!(src, Operand::Reg(Reg { size: 4, .. }) | Operand::Mem(Mem { size: Some(4) | None, .. })) => c.modrm(&[], true, &[0x63], d.num, src, &regs)?,
+        ("inc" | "dec", [dst]) => { let size = width(ops)?; c.modrm(opsize(size), size == 8, &[0xfe + wide(size)], (mnemonic == "dec") as u8, dst, &regs)?; }
+        (m, [dst]) if GROUP3.contains(&m) => {
+            let size = width(ops)?;
+            c.modrm(opsize(size), size == 8, &[0xf6 + wide(size)], GROUP3.iter().position(|g| *g == m).unwrap() as u8 + 2, dst, &regs)?;
+        }
+        ("imul", [Operand::Reg(d), src]) if d.size >= 2 => { let size = width(ops)?; c.modrm(opsize(size), size == 8, &[0x0f, 0xaf], d.num, src, &regs)?; }
+        ("imul", [Operand::Reg(d), src, Operand::Imm(e)]) if d.size >= 2 => {
+            let size = width(&ops[..2])?;
+            let v = imm32(immediate(e)?, size)?;
+            if let Ok(b) = i8::try_from(v) { c.modrm(opsize(size), size == 8, &[0x6b], d.num, src, &regs)?; c.imm(b as i64, 1)?; }
+            else { c.modrm(opsize(size), size == 8, &[0x69], d.num, src, &regs)?; c.imm(v, size.min(4))?; }
+        }
+        (m, [dst, count]) if SHIFTS.contains(&m) => {
+            let size = width(&ops[..1])?;
+            let digit = [0, 1, 2, 3, 4, 5, 4, 7][SHIFTS.iter().position(|s| *s == m).unwrap()];
+            match count {
+                Operand::Imm(e) if immediate(e)? == 1 => c.modrm(opsize(size), size == 8, &[0xd0 + wide(size)], digit, dst, &regs)?,
+                Operand::Imm(e) => { c.modrm(opsize(size), size == 8, &[0xc0 + wide(size)], digit, dst, &regs)?; c.imm(immediate(e)?, 1)?; }
+                Operand::Reg(Reg { num: 1, size: 1, high: false, .. }) => c.modrm(opsize(size), size == 8, &[0xd2 + wide(size)], digit, dst, &byte_regs(&ops[..1]))?,
+                _ => return bad(),
+            }
+        }
+        ("push", [Operand::Reg(r)]) if r.size == 8 && !r.xmm => c.opreg(&[], false, 0x50, *r)?,
+        ("pop", [Operand::Reg(r)]) if r.size == 8 && !r.xmm => c.opreg(&[], false, 0x58, *r)?,
+        ("push", [Operand::Imm(e)]) => {
+            let v = imm32(immediate(e)?, 8)?;
+            if let Ok(b) = i8::try_from(v) { c.bytes.push(0x6a); c.imm(b as i64, 1)?; } else { c.bytes.push(0x68); c.imm(v, 4)?; }
+        }
+        ("push", [m @ Operand::Mem(_)]) => c.modrm(&[], false, &[0xff], 6, m, &regs)?,
+        ("pop", [m @ Operand::Mem(_)]) => c.modrm(&[], false, &[0x8f], 0, m, &regs)?,
+        ("call" | "jmp", [Operand::Imm(target)]) => { c.bytes.push(if mnemonic == "call" { 0xe8 } else { 0xe9 }); c.branch(target)?; }
+        ("call" | "jmp", [target]) => c.modrm(&[], false, &[0xff], if mnemonic == "call" { 2 } else { 4 }, target, &regs)?,
+        (m, [Operand::Imm(target)]) if m.strip_prefix('j').and_then(condition).is_some() => {
+            c.bytes.extend([0x0f, 0x80 + m.strip_prefix('j').and_then(condition).unwrap()]);
+            c.branch(target)?;
+        }
+        (m, [dst]) if m.strip_prefix("set").and_then(condition).is_some() && width(ops)? == 1 => c.modrm(&[], false, &[0x0f, 0x90 + m.strip_prefix("set").and_then(condition).unwrap()], 0, dst, &regs)?,
+        (m, [Operand::Reg(d), src]) if m.strip_prefix("cmov").and_then(condition).is_some() && d.size >= 2 => {
+            let size = width(ops)?;
+            c.modrm(opsize(size), size == 8, &[0x0f, 0x40 + m.strip_prefix("cmov").and_then(condition).unwrap()], d.num, src, &regs)?;
+        }
+        ("movd" | "movq", [Operand::Reg(x), src]) if x.xmm && !matches!(src, Operand::Imm(_) | Operand::Reg(Reg { xmm: true, .. })) => {
+            let size = width(&ops[1..])?;
+            c.modrm(&[0x66], size == 8, &[0x0f, 0x6e], x.num, src, &regs)?;
+        }
+        ("movd" | "movq", [dst, Operand::Reg(x)]) if x.xmm && !matches!(dst, Operand::Imm(_) | Operand::Reg(Reg { xmm: true, .. })) => {
+            let size = width(&ops[..1])?;
+            c.modrm(&[0x66], size == 8, &[0x0f, 0x7e], x.num, dst, &regs)?;
+        }
+        (m, []) => c.bytes.extend(match m {
+            "ret" => &[0xc3][..], "leave" => &[0xc9], "nop" => &[0x90], "hlt" => &[0xf4], "int3" => &[0xcc], "ud2" => &[0x0f, 0x0b],
+            "syscall" => &[0x0f, 0x05], "rdtsc" => &[0x0f, 0x31], "cpuid" => &[0x0f, 0xa2], "pause" => &[0xf3, 0x90],
+            "cqo" => &[0x48, 0x99], "cdq" => &[0x99], "cdqe" => &[0x48, 0x98], "cwd" => &[0x66, 0x99],
+            "movsb" => &[0xa4], "movsw" => &[0x66, 0xa5], "movsd" => &[0xa5], "movsq" => &[0x48, 0xa5],
+            "stosb" => &[0xaa], "stosw" => &[0x66, 0xab], "stosd" => &[0xab], "stosq" => &[0x48, 0xab],
+            "lodsb" => &[0xac], "cmpsb" => &[0xa6], "scasb" => &[0xae],
+            _ => return Err(format!("unsupported instruction `{}`", m)),
+        }),
+        _ => return Err(format!("unsupported instruction `{}` with {} operand{}", mnemonic, ops.len(), if ops.len() == 1 { "" } else { "s" })),
+    }
+    Ok(c)
+}
+
+/// A section being assembled; `.bss`-like sections only count their size.
+struct Section { name: String, kind: SectionKind, flags: Option<u64>, data: Vec<u8>, bss: u64, align: u64 }
+
+impl Section {
+    fn here(&self) -> u64 { if self.nobits() { self.bss } else { self.data.len() as u64 } }
+    fn nobits(&self) -> bool { self.kind == SectionKind::UninitializedData }
+    fn code(&self) -> bool { self.kind == SectionKind::Text || self.flags.is_some_and(|f| f & elf::SHF_EXECINSTR as u64 != 0) }
+}
+
+/// What is subtracted from the symbol of a fixup: nothing, the address of the field
+/// itself (a PC-relative value), or another label.
+enum Minus { None, Here, Label(String) }
+
+/// A `size`-byte field holding `symbol - minus + addend`, resolved once every label is known.
+struct Fixup { section: usize, offset: u64, size: u8, symbol: Option<String>, minus: Minus, addend: i64, branch: bool, line: usize }
+
+#[derive(Default)]
+struct Assembler {
+    sections: Vec<Section>,
+    current: usize,
+    line: usize,
+    /// Section and offset of every label, and the labels in definition order.
+    labels: HashMap<String, (usize, u64)>,
+    order: Vec<String>,
+    globals: HashSet<String>,
+    weak: HashSet<String>,
+    hidden: HashSet<String>,
+    sizes: HashMap<String, u64>,
+    /// `.set` aliases as (alias, target, line).
+    aliases: Vec<(String, String, usize)>,
+    fixups: Vec<Fixup>,
+}
+
+/// Splits a line into its `;`-separated statements, dropping `#` and `/* */` comments;
+/// `comment` carries an open `/*` over to the next line.
+fn statements(line: &str, comment: &mut bool) -> Vec<String> {
+    let (mut out, mut current, mut quoted) = (Vec::new(), String::new(), false);
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if *comment {
+            if c == '*' && chars.peek() == Some(&'/') { chars.next(); *comment = false; }
+            continue;
+        }
+        if quoted {
+            current.push(c);
+            if c == '\\' { if let Some(n) = chars.next() { current.push(n); } } else if c == '"' { quoted = false; }
+            continue;
+        }
+        match c {
+            '"' => { quoted = true; current.push(c); }
+            '#' => break,
+            '/' if chars.peek() == Some(&'*') => { chars.next(); *comment = true; }
+            ';' => out.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    out.push(current);
+    out.into_iter().map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+}
+
+/// Splits directive or instruction operands on the commas outside strings and brackets.
+fn split_operands(text: &str) -> Vec<String> {
+    let (mut out, mut current, mut depth, mut quoted, mut escaped) = (Vec::new(), String::new(), 0, false, false);
+    for c in text.chars() {
+        if quoted {
+            if escaped { escaped = false; } else if c == '\\' { escaped = true; } else if c == '"' { quoted = false; }
+        } else if c == '"' { quoted = true; }
+        else if c == '[' || c == '(' { depth += 1; }
+        else if c == ']' || c == ')' { depth -= 1; }
+        else if c == ',' && depth == 0 { out.push(std::mem::take(&mut current).trim().to_string()); continue; }
+        current.push(c);
+    }
+    if !current.trim().is_empty() || !out.is_empty() { out.push(current.trim().to_string()); }
+    out
+}
+
+/// The bytes of a quoted string, with GNU as escapes.
+fn string_literal(text: &str) -> Result<Vec<u8>, String> {
+    let inner = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')).ok_or_else(|| format!("expected a string, found `{}`", text))?;
+    let (mut out, bytes, mut i) = (Vec::new(), inner.as_bytes(), 0);
+    while i < bytes.len() {
+        let b = bytes[i];
+        i += 1;
+        if b != b'\\' { out.push(b); continue; }
+        let e = *bytes.get(i).ok_or("string ends in `\\`")?;
+        i += 1;
+        out.push(match e {
+            b'n' => b'\n', b't' => b'\t', b'r' => b'\r', b'b' => 8, b'f' => 12, b'v' => 11, b'a' => 7,
+            b'0'..=b'7' => {
+                let mut v = (e - b'0') as u32;
+                for _ in 0..2 { if let Some(d @ b'0'..=b'7') = bytes.get(i) { v = v * 8 + (d - b'0') as u32; i += 1; } }
+                v as u8
+            }
+            b'x' => {
+                let mut v = 0u32;
+                while let Some(d) = bytes.get(i).and_then(|d| (*d as char).to_digit(16)) { v = v * 16 + d; i += 1; }
+                v as u8
+            }
+            other => other,
+        });
+    }
+    Ok(out)
+}
+
+impl Assembler {
+    fn section(&mut self) -> &mut Section { &mut self.sections[self.current] }
+
+    /// Makes `name` the current section, creating it with `flags` (`.section NAME,"aw"`)
+    /// and `kind` (`%progbits`/`%nobits`) on first use.
+    fn switch(&mut self, name: &str, flags: Option<&str>, kind: Option<&str>) {
+        if let Some(i) = self.sections.iter().position(|s| s.name == name) { self.current = i; return; }
+        let (kind, flags) = match name {
+            ".text" => (SectionKind::Text, None),
+            ".data" => (SectionKind::Data, None),
+            ".bss" => (SectionKind::UninitializedData, None),
+            ".rodata" => (SectionKind::ReadOnlyData, None),
+            ".init_array" => (SectionKind::Elf(elf::SHT_INIT_ARRAY), Some((elf::SHF_ALLOC | elf::SHF_WRITE) as u64)),
+            ".fini_array" => (SectionKind::Elf(elf::SHT_FINI_ARRAY), Some((elf::SHF_ALLOC | elf::SHF_WRITE) as u64)),
+            _ => {
+                let flags = flags.unwrap_or("").chars().map(|f| match f { 'a' => elf::SHF_ALLOC, 'w' => elf::SHF_WRITE, 'x' => elf::SHF_EXECINSTR, _ => 0 }).fold(0, |a, b| a | b);
+                let kind = if kind.is_some_and(|k| k.ends_with("nobits")) { SectionKind::UninitializedData } else { SectionKind::Elf(elf::SHT_PROGBITS) };
+                (kind, Some(flags as u64))
+            }
+        };
+        self.sections.push(Section { name: name.to_string(), kind, flags, data: Vec::new(), bss: 0, align: 1 });
+        self.current = self.sections.len() - 1;
+    }
+
+    fn define(&mut self, name: &str) -> Result<(), String> {
+        if self.labels.contains_key(name) { return Err(format!("symbol `{}` is already defined", name)); }
+        let at = (self.current, self.section().here());
+        self.labels.insert(name.to_string(), at);
+        self.order.push(name.to_string());
+        Ok(())
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let section = self.section();
+        if !section.nobits() { section.data.extend(bytes); }
+        else if bytes.iter().all(|b| *b == 0) { section.bss += bytes.len() as u64; }
+        else { return Err(format!("data in the uninitialized section `{}`", section.name)); }
+        Ok(())
+    }
+
+    fn align(&mut self, to: u64) -> Result<(), String> {
+        if !to.is_power_of_two() { return Err(format!("alignment {} is not a power of two", to)); }
+        let section = self.section();
+        section.align = section.align.max(to);
+        let pad = section.here().next_multiple_of(to) - section.here();
+        if section.nobits() { section.bss += pad; } else {
+            let fill = if section.code() { 0x90 } else { 0 };
+            section.data.extend(std::iter::repeat_n(fill, pad as usize));
+        }
+        Ok(())
+    }
+
+    /// Appends a `size`-byte data value: a constant, a symbol plus a constant, or the
+    /// difference of two labels.
+    fn data(&mut self, size: u8, text: &str) -> Result<(), String> {
+        let e = expr(text)?;
+        if !e.regs.is_empty() || e.syms.iter().any(|(s, _)| s.contains('@')) { return Err(format!("cannot parse `{}`", text)); }
+        let (symbol, minus) = match e.syms.as_slice() {
+            [] => (None, Minus::None),
+            [(s, 1)] => (Some(s.clone()), Minus::None),
+            [(s, 1), (m, -1)] => (Some(s.clone()), Minus::Label(m.clone())),
+            _ => return Err(format!("cannot relocate `{}`", text)),
+        };
+        let offset = self.section().here();
+        if symbol.is_some() {
+            if self.section().nobits() { return Err(format!("a relocation in the uninitialized section `{}`", self.section().name)); }
+            self.fixups.push(Fixup { section: self.current, offset, size, symbol, minus, addend: e.value, branch: false, line: self.line });
+        }
+        self.emit(&e.value.to_le_bytes()[..size as usize])
+    }
+
+    fn directive(&mut self, name: &str, rest: &str) -> Result<(), String> {
+        let args = split_operands(rest);
+        let number = |text: &str| expr(text)?.constant().ok_or_else(|| format!("`{}` is not a constant", text));
+        match name {
+            ".intel_syntax" | ".file" | ".ident" | ".type" | ".local" => {}
+            ".text" | ".data" | ".bss" => self.switch(name, None, None),
+            ".section" => {
+                let section = args.first().ok_or("`.section` needs a name")?;
+                self.switch(section, args.get(1).map(|f| f.trim_matches('"')), args.get(2).map(String::as_str));
+            }
+            ".globl" | ".global" => self.globals.extend(args),
+            ".weak" => self.weak.extend(args),
+            ".hidden" => self.hidden.extend(args),
+            ".set" | ".equ" => {
+                let [alias, target] = args.as_slice() else { return Err(format!("`{}` needs a name and a symbol", name)) };
+                self.aliases.push((alias.clone(), target.clone(), self.line));
+            }
+            ".size" => {
+                let [symbol, size] = args.as_slice() else { return Err("`.size` needs a symbol and a size".to_string()) };
+                let size = match size.strip_prefix(". -").map(str::trim) {
+                    Some(from) if from == symbol => {
+                        let &(section, start) = self.labels.get(symbol).filter(|(s, _)| *s == self.current).ok_or_else(|| format!("`{}` is not defined in this section", symbol))?;
+                        self.sections[section].here() - start
+                    }
+                    _ => number(size)? as u64,
+                };
+                self.sizes.insert(symbol.clone(), size);
+            }
+            ".align" | ".balign" => self.align(number(args.first().ok_or("alignment missing")?)? as u64)?,
+            ".p2align" => self.align(1 << number(args.first().ok_or("alignment missing")?)?)?,
+            ".zero" | ".skip" | ".space" => {
+                let n = number(args.first().ok_or("size missing")?)? as u64;
+                let fill = args.get(1).map(|f| number(f)).transpose()?.unwrap_or(0) as u8;
+                let section = self.section();
+                if section.nobits() && fill == 0 { section.bss += n; } else { self.emit(&vec![fill; n as usize])?; }
+            }
+            ".byte" | ".word" | ".short" | ".value" | ".long" | ".int" | ".quad" => {
+                let size = match name { ".byte" => 1, ".word" | ".short" | ".value" => 2, ".long" | ".int" => 4, _ => 8 };
+                for arg in &args { self.data(size, arg)?; }
+            }
+            ".ascii" | ".asciz" | ".string" => for arg in &args {
+                let mut bytes = string_literal(arg)?;
+                if name != ".ascii" { bytes.push(0); }
+                self.emit(&bytes)?;
+            },
+            _ => return Err(format!("unsupported directive `{}`", name)),
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, text: &str) -> Result<(), String> {
+        let mut text = text;
+        // Leading `label:`s.
+        while let Some((label, rest)) = text.split_once(':') && !label.is_empty() && label.chars().all(is_symbol_char) {
+            self.define(label)?;
+            text = rest.trim_start();
+        }
+        if text.is_empty() { return Ok(()); }
+        let (head, rest) = text.split_once(char::is_whitespace).map(|(h, r)| (h, r.trim())).unwrap_or((text, ""));
+        if head.starts_with('.') { return self.directive(head, rest); }
+        let head = head.to_ascii_lowercase();
+        // `rep movsb` and the like: a prefix byte before the string instruction.
+        let (prefix, mnemonic, rest) = match head.as_str() {
+            "rep" | "repe" | "repz" | "repne" | "repnz" | "lock" => {
+                let (m, r) = rest.split_once(char::is_whitespace).map(|(m, r)| (m, r.trim())).unwrap_or((rest, ""));
+                (Some(match head.as_str() { "lock" => 0xf0, "repne" | "repnz" => 0xf2, _ => 0xf3 }), m.to_ascii_lowercase(), r)
+            }
+            _ => (None, head, rest),
+        };
+        let ops = split_operands(rest).iter().map(|o| operand(o)).collect::<Result<Vec<_>, _>>()?;
+        let mut code = encode(&mnemonic, &ops)?;
+        if let Some(p) = prefix {
+            code.bytes.insert(0, p);
+            if let Some(r) = &mut code.reference { r.at += 1; }
+        }
+        let offset = self.section().here();
+        if let Some(r) = code.reference.take() {
+            // The field is relative to the end of the instruction, past any immediate.
+            let addend = r.addend - (code.bytes.len() - r.at) as i64;
+            self.fixups.push(Fixup { section: self.current, offset: offset + r.at as u64, size: 4, symbol: Some(r.symbol), minus: Minus::Here, addend, branch: r.branch, line: self.line });
+        }
+        self.emit(&code.bytes)
+    }
+
+    fn finish(self) -> Result<Vec<u8>, (usize, String)> {
+        let Assembler { mut sections, mut labels, mut order, globals, weak, hidden, sizes, aliases, fixups, .. } = self;
+        for (alias, target, line) in aliases {
+            let &at = labels.get(&target).ok_or_else(|| (line, format!("`{}` is not defined", target)))?;
+            if labels.insert(alias.clone(), at).is_some() { return Err((line, format!("symbol `{}` is already defined", alias))); }
+            order.push(alias);
+        }
+        let mut obj = Object::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+        let ids: Vec<SectionId> = sections.iter().map(|s| {
+            let id = obj.add_section(Vec::new(), s.name.as_bytes().to_vec(), s.kind);
+            if let Some(sh_flags) = s.flags { obj.section_mut(id).flags = SectionFlags::Elf { sh_flags }; }
+            id
+        }).collect();
+        // Marks the stack non-executable, which linkers otherwise warn about.
+        let stack = obj.add_section(Vec::new(), b".note.GNU-stack".to_vec(), SectionKind::Elf(elf::SHT_PROGBITS));
+        obj.section_mut(stack).flags = SectionFlags::Elf { sh_flags: 0 };
+        let exported = |name: &str| globals.contains(name) || weak.contains(name);
+        let scope = |name: &str| if !exported(name) { SymbolScope::Compilation } else if hidden.contains(name) { SymbolScope::Linkage } else { SymbolScope::Dynamic };
+        let mut symbols: HashMap<String, SymbolId> = HashMap::new();
+        for name in order.iter().filter(|n| !n.starts_with(".L")) {
+            let (section, value) = labels[name];
+            let kind = if sections[section].code() { SymbolKind::Text } else { SymbolKind::Data };
+            let symbol = Symbol {
+                name: name.as_bytes().to_vec(), value, size: sizes.get(name).copied().unwrap_or(0), kind, scope: scope(name),
+                weak: weak.contains(name), section: SymbolSection::Section(ids[section]), flags: SymbolFlags::None,
+            };
+            symbols.insert(name.clone(), obj.add_symbol(symbol));
+        }
+
+        let mut relocations = Vec::new();
+        for f in fixups {
+            let fail = |message: String| (f.line, message);
+            let target = f.symbol.as_ref().map(|s| (s, labels.get(s).copied()));
+            let local = |t: &Option<(&String, Option<(usize, u64)>)>| match t { Some((s, Some(at))) if !exported(s) => Some(*at), _ => None };
+            let (mut addend, mut minus, mut value) = (f.addend, f.minus, None);
+            if let Minus::Label(m) = &minus {
+                let &(section, offset) = labels.get(m).ok_or_else(|| fail(format!("`{}` is not defined", m)))?;
+                match local(&target) {
+                    _ if section == f.section => { addend += f.offset as i64 - offset as i64; minus = Minus::Here; }
+                    Some((s, at)) if s == section => value = Some(at as i64 - offset as i64 + addend),
+                    _ => return Err(fail(format!("cannot subtract `{}` from a symbol in another section", m))),
+                }
+            }
+            if let (Minus::Here, Some((section, at))) = (&minus, local(&target)) && section == f.section { value = Some(at as i64 + addend - f.offset as i64); }
+            if let Some(value) = value {
+                let fits = match f.size { 1 => i8::try_from(value).is_ok(), 2 => i16::try_from(value).is_ok(), 4 => i32::try_from(value).is_ok(), _ => true };
+                if !fits { return Err(fail(format!("value {} does not fit in {} bytes", value, f.size))); }
+                let data = &mut sections[f.section].data;
+                data[f.offset as usize..f.offset as usize + f.size as usize].copy_from_slice(&value.to_le_bytes()[..f.size as usize]);
+                continue;
+            }
+            let Some((name, at)) = target else { continue };
+            let (symbol, addend) = match (local(&target), at) {
+                (Some((section, offset)), _) => (obj.section_symbol(ids[section]), addend + offset as i64),
+                (None, Some(_)) => (symbols[name], addend),
+                (None, None) => {
+                    let id = *symbols.entry(name.clone()).or_insert_with(|| obj.add_symbol(Symbol {
+                        name: name.as_bytes().to_vec(), value: 0, size: 0, kind: SymbolKind::Unknown, scope: if hidden.contains(name) { SymbolScope::Linkage } else { SymbolScope::Dynamic },
+                        weak: weak.contains(name), section: SymbolSection::Undefined, flags: SymbolFlags::None,
+                    }));
+                    (id, addend)
+                }
+            };
+            let r_type = match (&minus, f.size) {
+                (Minus::Here, 4) if f.branch => elf::R_X86_64_PLT32,
+                (Minus::Here, 4) => elf::R_X86_64_PC32,
+                (Minus::None, 8) => elf::R_X86_64_64,
+                (Minus::None, 4) => elf::R_X86_64_32,
+                _ => return Err(fail(format!("cannot relocate a {}-byte reference to `{}`", f.size, name))),
+            };
+            relocations.push((ids[f.section], Relocation { offset: f.offset, symbol, addend, flags: RelocationFlags::Elf { r_type } }));
+        }
+
+        for (section, &id) in sections.into_iter().zip(&ids) {
+            if section.nobits() { obj.append_section_bss(id, section.bss, section.align); } else { obj.set_section_data(id, section.data, section.align); }
+        }
+        for (section, relocation) in relocations { obj.add_relocation(section, relocation).map_err(|e| (0, e.to_string()))?; }
+        obj.write().map_err(|e| (0, e.to_string()))
+    }
+}
+
+/// Assembles x86_64 assembly as the backend emits it into a relocatable ELF object, or
+/// returns the line (1-based, 0 for the object as a whole) and message of the first error.
+pub fn assemble_x86_64(text: &str) -> Result<Vec<u8>, (usize, String)> {
+    let mut asm = Assembler::default();
+    asm.switch(".text", None, None);
+    let mut comment = false;
+    for (i, line) in text.lines().enumerate() {
+        asm.line = i + 1;
+        for statement in statements(line, &mut comment) { asm.statement(&statement).map_err(|e| (i + 1, e))?; }
+    }
+    asm.finish()
+}
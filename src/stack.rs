@@ -0,0 +1,106 @@
+//! Stack usage (`--stack-report`): the bytes of stack each function takes while it runs,
+//! and the most any call to it can take with everything it calls below it.
+//!
+//! Every function has a fixed frame, [`FRAME_BYTES`], and pushes temporaries below it while
+//! evaluating expressions and passing arguments. The backends count those pushes in the
+//! code they emit for each function with a [`StackDepth`]. The deepest use of a call adds
+//! the deepest of the functions it calls, from the call graph; a function that can reach a
+//! recursive call has no bound. Runtime intrinsics and `extern` functions are not counted.
+
+use crate::callgraph;
+use crate::mangle;
+use std::collections::HashMap;
+
+/// Fixed stack bytes of every function: the return address and saved frame pointer (x29
+/// and x30 on AArch64), and the 4 KiB frame its locals live in.
+pub const FRAME_BYTES: u32 = 16 + 4096;
+
+/// Bytes pushed below a function's frame so far, and the most at any point, tracked from
+/// the instructions the backend emits.
+#[derive(Debug, Default)]
+pub(crate) struct StackDepth {
+    depth: i64,
+    peak: i64,
+    /// Depths saved by an `and rsp, -16` realignment, restored by `mov rsp, [rsp]`.
+    saved: Vec<i64>,
+}
+
+/// The immediate `N` of `prefix N` (or `prefix #N`) at the start of `ins`.
+fn imm(ins: &str, prefix: &str) -> Option<i64> {
+    ins.strip_prefix(prefix)?.trim().trim_start_matches('#').parse().ok()
+}
+
+impl StackDepth {
+    pub(crate) fn peak(&self) -> u32 { self.peak as u32 }
+
+    fn push(&mut self, bytes: i64) {
+        self.depth += bytes;
+        self.peak = self.peak.max(self.depth);
+    }
+
+    /// Accounts for the x86_64 instructions of one emitted entry.
+    pub(crate) fn x86_64(&mut self, s: &str) {
+        for ins in s.split([';', '\n']).map(str::trim) {
+            if ins.starts_with("push ") { self.push(8); }
+            else if ins.starts_with("pop ") { self.push(-8); }
+            else if let Some(n) = imm(ins, "sub rsp,") { self.push(n); }
+            else if let Some(n) = imm(ins, "add rsp,") { self.push(-n); }
+            // Realigning for a foreign call drops rsp by at most 8 bytes.
+            else if ins == "and rsp, -16" { self.saved.push(self.depth); self.push(8); }
+            else if ins == "mov rsp, [rsp]" { self.depth = self.saved.pop().unwrap_or(0); }
+        }
+    }
+
+    /// Accounts for the AArch64 instructions of one emitted entry.
+    pub(crate) fn aarch64(&mut self, s: &str) {
+        for ins in s.split([';', '\n']).map(str::trim) {
+            if let Some(n) = ins.strip_suffix("]!").and_then(|i| i.rsplit_once("[sp, #-")).and_then(|(_, n)| n.parse::<i64>().ok()) { self.push(n); }
+            else if let Some(n) = ins.rsplit_once("[sp], #").and_then(|(_, n)| n.parse::<i64>().ok()) { self.push(-n); }
+            else if let Some(n) = imm(ins, "sub sp, sp,") { self.push(n); }
+            else if let Some(n) = imm(ins, "add sp, sp,") { self.push(-n); }
+        }
+    }
+}
+
+/// The deepest stack a call to `name` can use, or `None` when it can reach a recursive call.
+fn deepest(name: &str, frames: &HashMap<&str, u32>, calls: &HashMap<&str, &[crate::Symbol]>, memo: &mut HashMap<String, Option<u32>>, active: &mut Vec<String>) -> Option<u32> {
+    if let Some(d) = memo.get(name) { return *d; }
+    if active.iter().any(|a| a == name) { return None; }
+    active.push(name.to_string());
+    let mut below = Some(0);
+    for c in calls.get(name).copied().unwrap_or_default() {
+        below = match (below, deepest(c, frames, calls, memo, active)) {
+            (Some(b), Some(d)) => Some(b.max(d)),
+            _ => None,
+        };
+    }
+    active.pop();
+    let d = below.map(|b| frames.get(name).copied().unwrap_or(0) + b);
+    memo.insert(name.to_string(), d);
+    d
+}
+
+/// The report of the stack used by each function in `frames` (its symbol and frame bytes),
+/// deepest first, and with a `limit`, a warning for each whose deepest use is unbounded or
+/// over it.
+pub fn report(frames: &[(String, u32)], graph: &[callgraph::Function], limit: Option<u32>) -> (String, Vec<String>) {
+    let frame_of: HashMap<&str, u32> = frames.iter().map(|(n, f)| (n.as_str(), *f)).collect();
+    let calls: HashMap<&str, &[crate::Symbol]> = graph.iter().map(|f| (f.name.as_str(), f.calls.as_slice())).collect();
+    let mut memo = HashMap::new();
+    let mut rows: Vec<(String, u32, Option<u32>)> = frames.iter().map(|(n, f)| (mangle::display(n), *f, deepest(n, &frame_of, &calls, &mut memo, &mut Vec::new()))).collect();
+    rows.sort_by_key(|r| (std::cmp::Reverse(r.2.map_or(u64::MAX, u64::from)), r.0.clone()));
+
+    let width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0).max("function".len());
+    let mut out = format!("stack usage (bytes):\n  {:<width$}  {:>6}  {:>9}\n", "function", "frame", "deepest", width = width);
+    let mut warnings = Vec::new();
+    for (name, frame, deep) in &rows {
+        let over = match (deep, limit) {
+            (None, Some(_)) => { warnings.push(format!("the stack use of `{}` has no bound: it can reach a recursive call", name)); true }
+            (Some(d), Some(l)) if *d > l => { warnings.push(format!("`{}` can use {} bytes of stack, over the limit of {}", name, d, l)); true }
+            _ => false,
+        };
+        let deep = deep.map(|d| d.to_string()).unwrap_or_else(|| "recursive".to_string());
+        out.push_str(&format!("  {:<width$}  {:>6}  {:>9}{}\n", name, frame, deep, if over { "  over limit" } else { "" }, width = width));
+    }
+    (out, warnings)
+}
@@ -0,0 +1,120 @@
+//! Interned names.
+//!
+//! Identifiers, type names and the other atoms of the IR are interned once per process: a
+//! [`Symbol`] points at the single copy of its text, so it is `Copy`, clones without
+//! allocating and compares by address. It hashes like its text, so maps keyed by symbols
+//! can still be looked up with a `&str`. Interned text is never freed, so string literals,
+//! doc comments and other payloads stay owned `IRNode::Str`s instead.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Mutex, OnceLock};
+
+/// An interned string. Its text lives until the process exits.
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static str);
+
+fn table() -> &'static Mutex<HashSet<&'static str>> {
+    static TABLE: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    TABLE.get_or_init(Default::default)
+}
+
+impl Symbol {
+    /// The symbol for `s`, adding it to the table the first time it is seen.
+    pub fn intern(s: &str) -> Symbol {
+        let mut table = table().lock().unwrap();
+        if let Some(&text) = table.get(s) { return Symbol(text); }
+        let text: &'static str = Box::leak(s.into());
+        table.insert(text);
+        Symbol(text)
+    }
+
+    pub fn as_str(&self) -> &'static str { self.0 }
+}
+
+impl Default for Symbol {
+    fn default() -> Self { Symbol::intern("") }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+    fn deref(&self) -> &str { self.0 }
+}
+
+impl Borrow<str> for Symbol {
+    fn borrow(&self) -> &str { self.0 }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str { self.0 }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool { std::ptr::eq(self.0, other.0) }
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool { self.0 == other }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool { self.0 == *other }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool { self.0 == other }
+}
+
+impl PartialEq<Symbol> for str {
+    fn eq(&self, other: &Symbol) -> bool { self == other.0 }
+}
+
+impl PartialEq<Symbol> for &str {
+    fn eq(&self, other: &Symbol) -> bool { *self == other.0 }
+}
+
+impl PartialEq<Symbol> for String {
+    fn eq(&self, other: &Symbol) -> bool { self == other.0 }
+}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) { self.0.hash(state) }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Symbol) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Symbol) -> Ordering { self.0.cmp(other.0) }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str(self.0) }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::Debug::fmt(self.0, f) }
+}
+
+impl From<&str> for Symbol {
+    fn from(s: &str) -> Symbol { Symbol::intern(s) }
+}
+
+impl From<&String> for Symbol {
+    fn from(s: &String) -> Symbol { Symbol::intern(s) }
+}
+
+impl From<String> for Symbol {
+    fn from(s: String) -> Symbol { Symbol::intern(&s) }
+}
+
+impl From<Symbol> for String {
+    fn from(s: Symbol) -> String { s.0.to_string() }
+}
@@ -0,0 +1,103 @@
+//! Symbol index (`coatl tags`): the functions, structs and struct fields declared in
+//! source files, with the line of each, so editors can jump to definitions.
+//!
+//! Files are only lexed, not parsed or resolved, so an index can be built for code that
+//! does not compile yet. Names are as written in the source, without module prefixes.
+
+use crate::{json_string, Lexer, Symbol, TokenKind};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TagKind {
+    Function,
+    Struct,
+    Field,
+}
+
+impl TagKind {
+    /// The one-letter ctags kind.
+    fn letter(self) -> char {
+        match self { TagKind::Function => 'f', TagKind::Struct => 's', TagKind::Field => 'm' }
+    }
+    fn name(self) -> &'static str {
+        match self { TagKind::Function => "function", TagKind::Struct => "struct", TagKind::Field => "field" }
+    }
+}
+
+/// One declaration. `scope` is the struct a field belongs to.
+#[derive(Debug, Clone)]
+pub struct Tag {
+    pub name: String,
+    pub kind: TagKind,
+    pub file: String,
+    pub line: usize,
+    pub scope: Option<String>,
+}
+
+/// The declarations of the source text of `file`: every top-level `fn` (also `pub` or
+/// `const`), every `struct` and its fields. `extern fn` declarations are not indexed,
+/// and a file the lexer rejects has none.
+pub fn scan(source: &str, file: &str) -> Vec<Tag> {
+    let Ok(tokens) = Lexer::new(source.to_string()).tokenize() else { return Vec::new() };
+    let tag = |name: &str, kind, line, scope: Option<&str>| Tag { name: name.to_string(), kind, file: file.to_string(), line, scope: scope.map(|s| s.to_string()) };
+    let mut tags = Vec::new();
+    let mut depth = 0usize;
+    // The struct whose `{ }` is open, with the depth inside it.
+    let mut open_struct: Option<(Symbol, usize)> = None;
+    for (i, t) in tokens.iter().enumerate() {
+        let next = |n: usize| tokens.get(i + n);
+        match (t.kind.clone(), t.value.as_str()) {
+            (TokenKind::Sym, "{") => depth += 1,
+            (TokenKind::Sym, "}") => {
+                depth = depth.saturating_sub(1);
+                if open_struct.as_ref().is_some_and(|(_, d)| depth < *d) { open_struct = None; }
+            }
+            (TokenKind::Ident, "fn") if depth == 0 && (i == 0 || tokens[i - 1].value != "extern") => {
+                if let Some(n) = next(1).filter(|n| n.kind == TokenKind::Ident) { tags.push(tag(&n.value, TagKind::Function, n.line, None)); }
+            }
+            (TokenKind::Ident, "struct") if depth == 0 => {
+                let Some(n) = next(1).filter(|n| n.kind == TokenKind::Ident) else { continue };
+                tags.push(tag(&n.value, TagKind::Struct, n.line, None));
+                if next(2).is_some_and(|b| b.value == "{") { open_struct = Some((n.value, depth + 1)); }
+            }
+            // A field is `name: type` directly inside the struct's braces; its default, if
+            // any, follows `=` and holds no `:` at that depth.
+            (TokenKind::Ident, _) if open_struct.as_ref().is_some_and(|(_, d)| *d == depth) && next(1).is_some_and(|c| c.value == ":") => {
+                let prev = tokens[i - 1].value.as_str();
+                if prev == "{" || prev == "," || tokens[i - 1].line < t.line {
+                    let scope = open_struct.as_ref().map(|(s, _)| s.as_str());
+                    tags.push(tag(&t.value, TagKind::Field, t.line, scope));
+                }
+            }
+            _ => {}
+        }
+    }
+    tags
+}
+
+/// Tags sorted by name, then file and line, as the ctags format expects.
+fn sorted(tags: &[Tag]) -> Vec<&Tag> {
+    let mut out: Vec<&Tag> = tags.iter().collect();
+    out.sort_by(|a, b| (&a.name, &a.file, a.line).cmp(&(&b.name, &b.file, b.line)));
+    out
+}
+
+/// A ctags file (extended format, sorted): `name<TAB>file<TAB>line;"<TAB>kind`, with
+/// `struct:Name` after the kind of a field.
+pub fn ctags(tags: &[Tag]) -> String {
+    let mut out = String::from("!_TAG_FILE_FORMAT\t2\t/extended format/\n!_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted, 2=foldcase/\n!_TAG_PROGRAM_NAME\tcoatl\t//\n");
+    for t in sorted(tags) {
+        out.push_str(&format!("{}\t{}\t{};\"\t{}", t.name, t.file, t.line, t.kind.letter()));
+        if let Some(scope) = &t.scope { out.push_str(&format!("\tstruct:{}", scope)); }
+        out.push('\n');
+    }
+    out
+}
+
+/// A JSON array of `{ "name", "kind", "file", "line" }` objects, with `"struct"` for fields.
+pub fn json(tags: &[Tag]) -> String {
+    let entries: Vec<String> = sorted(tags).iter().map(|t| {
+        let scope = t.scope.as_ref().map(|s| format!(", \"struct\": {}", json_string(s))).unwrap_or_default();
+        format!("  {{ \"name\": {}, \"kind\": \"{}\", \"file\": {}, \"line\": {}{} }}", json_string(&t.name), t.kind.name(), json_string(&t.file), t.line, scope)
+    }).collect();
+    if entries.is_empty() { "[]\n".to_string() } else { format!("[\n{}\n]\n", entries.join(",\n")) }
+}
@@ -0,0 +1,549 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::layout::{self, Field, MAX_STRUCT_SIZE};
+use crate::{IRNode, Symbol};
+
+/// Signature of a runtime intrinsic implemented in `intrinsics.rs`. Every intrinsic
+/// argument is an integer register; pointers are offsets into `__coatl_mem`.
+pub struct Intrinsic {
+    pub name: &'static str,
+    pub params: &'static [&'static str],
+}
+
+pub const INTRINSICS: &[Intrinsic] = &[
+    Intrinsic { name: "__mem_store", params: &["addr", "value"] },
+    Intrinsic { name: "__mem_store8", params: &["addr", "value"] },
+    Intrinsic { name: "__mem_load", params: &["addr"] },
+    Intrinsic { name: "__mem_load8", params: &["addr"] },
+    Intrinsic { name: "__fd_write", params: &["fd", "iovs", "iovs_len", "nwritten"] },
+    Intrinsic { name: "__fd_read", params: &["fd", "iovs", "iovs_len", "nread"] },
+    Intrinsic { name: "__fd_pread", params: &["fd", "iovs", "iovs_len", "offset", "nread"] },
+    Intrinsic { name: "__fd_pwrite", params: &["fd", "iovs", "iovs_len", "offset", "nwritten"] },
+    Intrinsic { name: "__fd_close", params: &["fd"] },
+    Intrinsic { name: "__path_open", params: &["dirfd", "dirflags", "path", "path_len", "oflags", "rights_base", "rights_inheriting", "fdflags", "fd_out"] },
+    Intrinsic { name: "__path_create", params: &["path", "fd_out"] },
+    Intrinsic { name: "__print", params: &["msg"] },
+    Intrinsic { name: "__get_argc", params: &[] },
+    Intrinsic { name: "__get_argv", params: &["index"] },
+    Intrinsic { name: "__tty_get_mode", params: &["fd", "out_ptr"] },
+    Intrinsic { name: "__tty_set_raw", params: &["fd", "mode_ptr", "vmin", "vtime"] },
+    Intrinsic { name: "__tty_restore", params: &["fd", "mode_ptr"] },
+    Intrinsic { name: "__tty_get_size", params: &["fd", "out_ptr"] },
+    Intrinsic { name: "__tty_has_input", params: &["fd", "timeout_ms"] },
+    Intrinsic { name: "__sleep_ms", params: &["ms"] },
+    Intrinsic { name: "__panic", params: &["msg", "location"] },
+    Intrinsic { name: "__dbg", params: &["value", "label"] },
+    Intrinsic { name: "__breakpoint", params: &[] },
+    Intrinsic { name: "__str_eq", params: &["a", "b"] },
+    Intrinsic { name: "__itoa", params: &["value", "buf"] },
+    Intrinsic { name: "__print_int", params: &["value"] },
+    Intrinsic { name: "__atoi", params: &["ptr", "len", "out_ptr"] },
+];
+
+pub fn lookup_intrinsic(name: &str) -> Option<&'static Intrinsic> {
+    INTRINSICS.iter().find(|i| i.name == name)
+}
+
+/// Levenshtein distance between two identifiers.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let sub = prev[j] + if ca == *cb { 0 } else { 1 };
+            cur.push(sub.min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Returns the candidate closest to `name`, if it is close enough to be a likely typo.
+pub(crate) fn closest_match<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    // A name of one or two characters is not corrected into a different one.
+    let limit = (name.len() / 3).max(2).min(name.len().saturating_sub(1));
+    candidates.map(|c| (edit_distance(name, c), c)).filter(|(d, _)| *d > 0 && *d <= limit).min_by_key(|(d, _)| *d).map(|(_, c)| c)
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    /// A known name close to the unknown one the error is about, shown as
+    /// ``; did you mean `name`?``.
+    pub suggestion: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: String) -> Self {
+        Diagnostic { message, suggestion: None }
+    }
+
+    /// An error about the unknown `name`, suggesting the closest of `candidates`.
+    pub fn unknown<'a>(message: String, name: &str, candidates: impl Iterator<Item = &'a str>) -> Self {
+        Diagnostic { message, suggestion: closest_match(name, candidates).map(|s| s.to_string()) }
+    }
+
+    /// The message, followed by the suggestion if there is one.
+    pub fn text(&self) -> String {
+        match &self.suggestion {
+            Some(s) => format!("{}; did you mean `{}`?", self.message, s),
+            None => self.message.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error: {}", self.text())
+    }
+}
+
+/// Types that can be passed where an intrinsic expects an integer register.
+fn is_integer_like(ty: &str) -> bool {
+    matches!(ty, "i32" | "i64" | "u8" | "char" | "bool" | "str") || ty.starts_with('*')
+}
+
+struct Checker {
+    structs: HashMap<Symbol, (Vec<Field>, u32)>,
+    fn_rets: HashMap<Symbol, Symbol>,
+    fn_arity: HashMap<Symbol, usize>,
+    vars: HashMap<Symbol, Symbol>,
+    current_fn: Symbol,
+    errors: Vec<Diagnostic>,
+    /// Overload sets by source name: each overload's symbol and parameter types.
+    overloads: HashMap<Symbol, Vec<(Symbol, Vec<Symbol>)>>,
+    /// Call tags (see [`resolve_overloads`]) mapped to the chosen overload or an error.
+    resolved: HashMap<Symbol, Result<Symbol, String>>,
+    /// `static` tables: element type and length.
+    statics: HashMap<Symbol, (Symbol, usize)>,
+}
+
+impl Checker {
+    fn new(ir: &IRNode) -> Self {
+        let mut checker = Checker {
+            structs: layout::program_layouts(ir),
+            fn_rets: HashMap::new(),
+            fn_arity: HashMap::new(),
+            vars: HashMap::new(),
+            current_fn: Symbol::default(),
+            errors: Vec::new(),
+            overloads: HashMap::new(),
+            resolved: HashMap::new(),
+            statics: HashMap::new(),
+        };
+        for st in crate::ir_section(ir, "statics").iter().filter_map(|s| s.as_list()) {
+            let (Some(name), Some(ty)) = (st.get(1).and_then(|n| n.as_atom()), st.get(2).and_then(|t| t.as_atom())) else { continue };
+            if let Some((elem, len)) = static_type(ty) { checker.statics.insert(*name, (elem, len)); }
+        }
+        // Functions are `(fn name (params ...) (ret ty) block)`, externs `(extern name abi (params ...) (ret ty))`.
+        let fns = crate::ir_section(ir, "functions").iter().map(|f| (f, 2, 3));
+        let sigs = fns.chain(crate::ir_section(ir, "externs").iter().map(|e| (e, 3, 4)));
+        for (f, pi, ri) in sigs {
+            let Some(f) = f.as_list() else { continue };
+            let Some(name) = f.get(1).and_then(|n| n.as_atom()) else { continue };
+            if let Some(params) = f.get(pi).and_then(|p| p.as_list()) { checker.fn_arity.insert(*name, params.len() - 1); }
+            if let Some(ret) = f.get(ri).and_then(|r| r.as_list()).and_then(|r| r.get(1)).and_then(|t| t.as_atom()) {
+                checker.fn_rets.insert(*name, *ret);
+            }
+        }
+        checker
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(Diagnostic::new(format!("in fn {}: {}", crate::mangle::display(&self.current_fn), message)));
+    }
+
+    /// Reports `message` about the unknown `name`, suggesting the closest of `candidates`.
+    fn error_unknown<'a>(&mut self, message: String, name: &str, candidates: impl Iterator<Item = &'a str>) {
+        let message = format!("in fn {}: {}", crate::mangle::display(&self.current_fn), message);
+        self.errors.push(Diagnostic::unknown(message, name, candidates));
+    }
+
+    fn check_fn(&mut self, l: &[IRNode]) {
+        self.current_fn = l[1].as_atom().copied().unwrap_or_default();
+        self.vars.clear();
+        if let Some(params) = l.get(2).and_then(|p| p.as_list()) {
+            for p in &params[1..] {
+                if let Some(pl) = p.as_list() && pl.len() > 2 {
+                    self.vars.insert(pl[1].as_atom().copied().unwrap_or_default(), pl[2].as_atom().copied().unwrap_or_default());
+                }
+            }
+        }
+        if let Some(body) = l.get(4).and_then(|b| b.as_list()) {
+            for stmt in &body[1..] { self.check_stmt(stmt); }
+        }
+    }
+
+    /// Returns the type of the variable named by `name`, reporting it if not in scope.
+    fn check_var(&mut self, name: Option<&IRNode>) -> Symbol {
+        let name = name.and_then(|n| n.as_atom()).copied().unwrap_or_default();
+        match self.vars.get(&name) {
+            Some(&ty) => ty,
+            None => {
+                let vars: Vec<Symbol> = self.vars.keys().copied().collect();
+                self.error_unknown(format!("use of undeclared variable `{}`", name), &name, vars.iter().map(|v| v.as_str()));
+                "i32".into()
+            }
+        }
+    }
+
+    /// True when `name` is a static not shadowed by a local.
+    fn is_static(&self, name: Option<&IRNode>) -> bool {
+        name.and_then(|n| n.as_atom()).is_some_and(|n| self.statics.contains_key(n) && !self.vars.contains_key(n))
+    }
+
+    /// Returns the type of the field reached from struct variable `var` through the field
+    /// names in `path` (`a.b.c` is `a` and `[b, c]`), reporting unknown fields.
+    fn check_field(&mut self, var: Option<&IRNode>, path: &[IRNode]) -> Symbol {
+        let mut ty = self.check_var(var);
+        for name in path.iter().filter_map(|n| n.as_atom()) {
+            let Some((fields, _)) = self.structs.get(&ty) else {
+                self.error(format!("field access `.{}` on `{}`, which is not a struct", name, ty));
+                return "i32".into();
+            };
+            ty = match fields.iter().find(|f| &f.name == name) {
+                Some(f) => f.ty,
+                None => {
+                    let names: Vec<Symbol> = fields.iter().map(|f| f.name).collect();
+                    self.error_unknown(format!("struct `{}` has no field `{}`", ty, name), name, names.iter().map(|n| n.as_str()));
+                    return "i32".into();
+                }
+            };
+        }
+        ty
+    }
+
+    /// Returns the element type of the slice variable `name`, reporting it if it is not
+    /// a slice of `u8` or `i32`.
+    fn check_slice(&mut self, name: Option<&IRNode>) -> Symbol {
+        let ty = self.check_var(name);
+        match layout::slice_elem(&ty) {
+            Some(elem) if self.structs.contains_key(&ty) => elem.into(),
+            Some(_) => {
+                self.error(format!("slice type `{}` is not supported; slices hold `u8` or `i32`", ty));
+                "i32".into()
+            }
+            None => {
+                let name = name.and_then(|n| n.as_atom()).cloned().unwrap_or_default();
+                self.error(format!("`{}` is indexed as a slice, but has type `{}`", name, ty));
+                "i32".into()
+            }
+        }
+    }
+
+    fn check_stmt(&mut self, n: &IRNode) {
+        let Some(l) = n.as_list() else { return };
+        let Some(head) = l.first().and_then(|h| h.as_atom()) else { return };
+        match head.as_str() {
+            "let" if l.len() > 3 => {
+                let value = self.check_expr(&l[3]);
+                let name = l[1].as_atom().copied().unwrap_or_default();
+                let ty = l[2].as_atom().copied().unwrap_or_default();
+                // A `for` loop over anything but a literal range binds it to a hidden `__forN`.
+                if ty == layout::RANGE_TYPE && value != ty {
+                    if name.starts_with("__for") { self.error(format!("`for` loops over a range, not `{}`", value)); }
+                    else { self.error(format!("`{}` is a range, but is given a `{}`", name, value)); }
+                }
+                self.vars.insert(name, ty);
+            }
+            "else" => { for s in &l[1..] { self.check_stmt(s); } }
+            "block" => {
+                // Lets declared in a block go out of scope at its end.
+                let outer = self.vars.clone();
+                for s in &l[1..] { self.check_stmt(s); }
+                self.vars = outer;
+            }
+            "if" | "while" => {
+                self.check_expr(&l[1]);
+                for s in &l[2..] { self.check_stmt(s); }
+            }
+            "match" if l.len() > 1 => {
+                self.check_expr(&l[1]);
+                let mut seen: Vec<&str> = Vec::new();
+                for arm in l[2..].iter().filter_map(|a| a.as_list()) {
+                    if let Some(v) = arm.get(1).and_then(|v| v.as_atom()) {
+                        if v.parse::<i32>().is_err() {
+                            self.error(format!("match arm `{}` is not an i32 literal", v));
+                        } else if seen.contains(&v.as_str()) {
+                            self.error(format!("duplicate match arm `{}`", v));
+                        }
+                        seen.push(v);
+                    }
+                    if let Some(body) = arm.last() { self.check_stmt(body); }
+                }
+            }
+            "asm" if l.len() > 2 => {
+                let template = l[1].as_str().unwrap_or_default();
+                let operands: Vec<Symbol> = l[2].as_list().map(|o| o[1..].iter().filter_map(|a| a.as_atom().copied()).collect()).unwrap_or_default();
+                for op in &operands {
+                    if !self.vars.contains_key(op) { self.error(format!("asm operand `{}` is not a local variable", op)); }
+                }
+                let mut rest = template;
+                while let Some(open) = rest.find('{') {
+                    let Some(close) = rest[open..].find('}') else { break };
+                    let name = &rest[open + 1..open + close];
+                    if !operands.iter().any(|o| o == name) {
+                        self.error(format!("asm placeholder `{{{}}}` does not name an operand", name));
+                    }
+                    rest = &rest[open + close + 1..];
+                }
+            }
+            "field_assign" if l.len() > 3 => {
+                self.check_field(l.get(1), &l[2..l.len() - 1]);
+                for child in &l[1..] { if child.is_list() { self.check_expr(child); } }
+            }
+            "array_assign" if self.is_static(l.get(1)) => {
+                let name = l[1].as_atom().cloned().unwrap_or_default();
+                self.error(format!("cannot assign to static `{}`; statics are read-only", crate::mangle::display(&name)));
+            }
+            "slice_assign" => {
+                self.check_slice(l.get(1));
+                if self.check_expr(&l[2]) == layout::RANGE_TYPE {
+                    let name = l[1].as_atom().cloned().unwrap_or_default();
+                    self.error(format!("cannot assign to a range of slice `{}`; assign its elements one by one", name));
+                }
+                for child in &l[3..] { self.check_expr(child); }
+            }
+            "assign" | "array_assign" => {
+                self.check_var(l.get(1));
+                for child in &l[1..] { if child.is_list() { self.check_expr(child); } }
+            }
+            "return" | "expr" | "svc" | "syscall" => {
+                for child in &l[1..] { if child.is_list() { self.check_expr(child); } }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_expr(&mut self, n: &IRNode) -> Symbol {
+        let Some(l) = n.as_list() else { return "i32".into() };
+        let Some(head) = l.first().and_then(|h| h.as_atom()) else { return "i32".into() };
+        match head.as_str() {
+            "int" => "i32".into(),
+            "int_i64" => "i64".into(),
+            "f32" | "f64" | "bool" => *head,
+            "string_typed" => "str".into(),
+            "ident" => self.check_var(l.get(1)),
+            "field" => self.check_field(l.get(1), &l[2..]),
+            "array_index" => {
+                self.check_var(l.get(1));
+                for child in &l[2..] { if child.is_list() { self.check_expr(child); } }
+                "i32".into()
+            }
+            "static_index" => {
+                let name = l.get(1).and_then(|n| n.as_atom()).copied().unwrap_or_default();
+                for child in &l[2..] { self.check_expr(child); }
+                match self.statics.get(&name) {
+                    Some(&(elem, _)) => elem,
+                    None => {
+                        self.error(format!("use of undeclared static `{}`", crate::mangle::display(&name)));
+                        "i32".into()
+                    }
+                }
+            }
+            "slice_index" => {
+                for child in &l[2..] { self.check_expr(child); }
+                self.check_slice(l.get(1))
+            }
+            "slice_range" => {
+                for child in &l[2..] { self.check_expr(child); }
+                self.check_slice(l.get(1));
+                self.vars.get(l[1].as_atom().map(|n| n.as_str()).unwrap_or_default()).copied().unwrap_or_else(|| "i32".into())
+            }
+            "struct_lit" => {
+                for child in &l[2..] { self.check_expr(child); }
+                let name = l.get(1).and_then(|a| a.as_atom()).copied().unwrap_or_default();
+                let fields = self.structs.get(&name).map(|s| s.0.len());
+                if let Some(n) = fields && n != l.len() - 2 {
+                    self.error(format!("struct literal `{}` has {} value(s), but the struct has {} field(s)", name, l.len() - 2, n));
+                }
+                name
+            }
+            "binary" => {
+                for child in &l[2..] { if child.is_list() { self.check_expr(child); } }
+                if l.get(4).and_then(|a| a.as_atom()).map(|a| a == "bool").unwrap_or(false) { "bool".into() } else { "i32".into() }
+            }
+            "call" => {
+                let name = l.get(1).and_then(|a| a.as_atom()).copied().unwrap_or_default();
+                let arg_tys: Vec<Symbol> = l[2..].iter().map(|a| self.check_expr(a)).collect();
+                if let Some((base, _)) = name.split_once('#') && let Some(candidates) = self.overloads.get(base) {
+                    let picked = select_overload(candidates, &arg_tys).ok_or_else(|| {
+                        let shown: Vec<String> = candidates.iter().map(|c| crate::mangle::display(&c.0)).collect();
+                        format!("in fn {}: no overload of `{}` takes ({}); candidates are {}",
+                            crate::mangle::display(&self.current_fn), crate::mangle::display(base), arg_tys.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(", "), shown.join(", "))
+                    });
+                    let ret = picked.as_ref().ok().and_then(|s| self.fn_rets.get(s)).copied();
+                    self.resolved.insert(name, picked);
+                    return ret.unwrap_or_else(|| "i32".into());
+                }
+                if let Some(intr) = lookup_intrinsic(&name) {
+                    if arg_tys.len() != intr.params.len() {
+                        self.error(format!("intrinsic `{}` expects {} argument(s) ({}), got {}", name, intr.params.len(), intr.params.join(", "), arg_tys.len()));
+                    }
+                    for (i, ty) in arg_tys.iter().enumerate().take(intr.params.len()) {
+                        if !is_integer_like(ty) {
+                            self.error(format!("intrinsic `{}` argument `{}` must be an integer, got `{}`", name, intr.params[i], ty));
+                        }
+                    }
+                    // `__dbg` evaluates to its argument.
+                    if name == "__dbg" && let Some(&ty) = arg_tys.first() { return ty; }
+                    return "i32".into();
+                }
+                if name.starts_with("__") {
+                    match closest_match(&name, INTRINSICS.iter().map(|i| i.name)) {
+                        Some(_) => self.error_unknown(format!("unknown intrinsic `{}`", name), &name, INTRINSICS.iter().map(|i| i.name)),
+                        None => self.error(format!("unknown intrinsic `{}` (the `__` prefix is reserved for runtime intrinsics)", name)),
+                    }
+                    return "i32".into();
+                }
+                if let Some(&arity) = self.fn_arity.get(&name) && arity != arg_tys.len() {
+                    self.error(format!("fn `{}` expects {} argument(s), got {}", name, arity, arg_tys.len()));
+                }
+                self.fn_rets.get(&name).copied().unwrap_or_else(|| "i32".into())
+            }
+            _ => {
+                for child in &l[1..] { if child.is_list() { self.check_expr(child); } }
+                "i32".into()
+            }
+        }
+    }
+}
+
+/// Picks the overload for a call with arguments of types `args`: the only one of that
+/// arity, otherwise the one whose parameter types match exactly.
+fn select_overload(candidates: &[(Symbol, Vec<Symbol>)], args: &[Symbol]) -> Option<Symbol> {
+    let arity: Vec<&(Symbol, Vec<Symbol>)> = candidates.iter().filter(|c| c.1.len() == args.len()).collect();
+    match arity.as_slice() {
+        [one] => Some(one.0),
+        _ => arity.iter().find(|c| c.1 == args).map(|c| c.0),
+    }
+}
+
+/// Gives each overload of a function name its own symbol (see [`crate::mangle::overload`])
+/// and binds every call of that name to the overload matching its argument types.
+pub fn resolve_overloads(ir: &mut IRNode) -> Result<(), Vec<Diagnostic>> {
+    let IRNode::List(root) = ir else { return Ok(()) };
+    let Some(IRNode::List(fns)) = root.iter_mut().find(|s| s.as_list().and_then(|l| l.first()).and_then(|h| h.as_atom()).map(|h| h == "functions").unwrap_or(false)) else {
+        return Ok(());
+    };
+    let signature = |f: &IRNode| -> Option<(Symbol, Vec<Symbol>)> {
+        let f = f.as_list()?;
+        let params = f.get(2)?.as_list()?[1..].iter().filter_map(|p| p.as_list()?.get(2)?.as_atom().copied()).collect();
+        Some((*f.get(1)?.as_atom()?, params))
+    };
+    let sigs: Vec<Option<(Symbol, Vec<Symbol>)>> = fns.iter().map(signature).collect();
+    let mut overloads: HashMap<Symbol, Vec<(Symbol, Vec<Symbol>)>> = HashMap::new();
+    for (name, params) in sigs.iter().flatten() {
+        overloads.entry(*name).or_default().push((crate::mangle::overload(name, params).into(), params.clone()));
+    }
+    overloads.retain(|_, set| set.len() > 1);
+    if overloads.is_empty() { return Ok(()); }
+
+    let mut errors = Vec::new();
+    let mut names: Vec<&Symbol> = overloads.keys().collect();
+    names.sort();
+    for name in names {
+        let set = &overloads[name];
+        if name == "main" {
+            errors.push(Diagnostic::new("fn `main` cannot be overloaded".to_string()));
+        }
+        for (i, (symbol, params)) in set.iter().enumerate() {
+            if set[..i].iter().any(|(s, _)| s == symbol) {
+                errors.push(Diagnostic::new(format!("fn `{}` is defined twice with parameters ({})", crate::mangle::display(name), params.iter().map(|p| p.as_str()).collect::<Vec<_>>().join(", "))));
+            }
+        }
+    }
+    if !errors.is_empty() { return Err(errors); }
+    for (f, sig) in fns.iter_mut().zip(&sigs) {
+        if let (IRNode::List(l), Some((name, params))) = (f, sig) && overloads.contains_key(name) {
+            l[1] = IRNode::atom(crate::mangle::overload(name, params));
+        }
+    }
+
+    // Tag every call to an overloaded name so the checker can report its choice per call.
+    let mut tags = 0;
+    for f in fns.iter_mut() {
+        crate::rename_calls(f, &mut |c| {
+            if !overloads.contains_key(c) { return None; }
+            tags += 1;
+            Some(format!("{}#{}", c, tags).into())
+        });
+    }
+    let mut checker = Checker::new(ir);
+    checker.overloads = overloads;
+    for f in crate::ir_section(ir, "functions").iter().filter_map(|f| f.as_list()) { checker.check_fn(f); }
+    let resolved = checker.resolved;
+    let mut tagged: Vec<(usize, &Result<Symbol, String>)> = resolved.iter().filter_map(|(tag, r)| Some((tag.split_once('#')?.1.parse().ok()?, r))).collect();
+    tagged.sort_by_key(|(k, _)| *k);
+    let errors: Vec<Diagnostic> = tagged.into_iter().filter_map(|(_, r)| r.clone().err()).map(Diagnostic::new).collect();
+    if !errors.is_empty() { return Err(errors); }
+    let IRNode::List(root) = ir else { return Ok(()) };
+    for section in root.iter_mut() {
+        crate::rename_calls(section, &mut |c| resolved.get(c).and_then(|r| r.clone().ok()));
+    }
+    Ok(())
+}
+
+/// Element type and length of a static's `[T N]` type.
+pub(crate) fn static_type(ty: &str) -> Option<(Symbol, usize)> {
+    let (elem, len) = ty.strip_prefix('[')?.strip_suffix(']')?.rsplit_once(' ')?;
+    Some((elem.into(), len.parse().ok()?))
+}
+
+/// Reports statics that are not an `i32` or `u8` array, or whose values do not fit it.
+fn check_statics(ir: &IRNode, errors: &mut Vec<Diagnostic>) {
+    let mut seen: Vec<&Symbol> = Vec::new();
+    for st in crate::ir_section(ir, "statics").iter().filter_map(|s| s.as_list()) {
+        let (Some(name), Some(ty)) = (st.get(1).and_then(|n| n.as_atom()), st.get(2).and_then(|t| t.as_atom())) else { continue };
+        let shown = crate::mangle::display(name);
+        if seen.contains(&name) {
+            errors.push(Diagnostic::new(format!("static `{}` is defined twice", shown)));
+        }
+        seen.push(name);
+        let Some((elem, len)) = static_type(ty).filter(|(e, _)| e == "i32" || e == "u8") else {
+            errors.push(Diagnostic::new(format!("static `{}` has type {}; statics are arrays of i32 or u8", shown, ty)));
+            continue;
+        };
+        let values = &st[3..];
+        if values.len() != len {
+            errors.push(Diagnostic::new(format!("static `{}` has {} value(s), but its type {} holds {}", shown, values.len(), ty, len)));
+        }
+        for v in values.iter().filter_map(|v| v.as_atom()) {
+            let fits = match elem.as_str() {
+                "u8" => v.parse::<u8>().is_ok(),
+                _ => v.parse::<i32>().is_ok(),
+            };
+            if !fits { errors.push(Diagnostic::new(format!("static `{}` value {} does not fit in {}", shown, v, elem))); }
+        }
+    }
+}
+
+/// Typechecks a whole `(coatl_ir ...)` program, reporting every error found.
+pub fn check_program(ir: &IRNode) -> Result<(), Vec<Diagnostic>> {
+    let fns: Vec<&[IRNode]> = crate::ir_section(ir, "functions").iter().filter_map(|f| f.as_list()).map(|f| f.as_slice()).collect();
+    let mut checker = Checker::new(ir);
+    for f in &fns {
+        if let Some(name) = f.get(1).and_then(|n| n.as_atom()) && name.starts_with("__") {
+            checker.errors.push(Diagnostic::new(format!("fn `{}`: the `__` prefix is reserved for runtime intrinsics", name)));
+        }
+    }
+    for s in crate::ir_section(ir, "structs").iter().filter_map(|s| s.as_list()) {
+        if let Err(e) = layout::struct_repr(s) { checker.errors.push(Diagnostic::new(e)); }
+    }
+    for name in layout::recursive_structs(ir) {
+        checker.errors.push(Diagnostic::new(format!("struct `{}` contains itself; a field's struct type cannot lead back to it", name)));
+    }
+    let mut sizes: Vec<(&Symbol, u32)> = checker.structs.iter().map(|(name, s)| (name, s.1)).collect();
+    sizes.sort();
+    for (name, size) in sizes {
+        if size > MAX_STRUCT_SIZE {
+            checker.errors.push(Diagnostic::new(format!("struct `{}` is {} bytes; struct values are limited to {} bytes", name, size, MAX_STRUCT_SIZE)));
+        }
+    }
+    check_statics(ir, &mut checker.errors);
+    for f in &fns { checker.check_fn(f); }
+    checker.errors.extend(crate::definite::check(&fns, &checker.structs));
+    if checker.errors.is_empty() { Ok(()) } else { Err(checker.errors) }
+}
@@ -0,0 +1,138 @@
+.bss
+.align 4
+.globl __coatl_mem
+__coatl_mem:
+  .zero 1048576
+__coatl_mem_inited:
+  .word 0
+.text
+__coatl_init_memory:
+  stp x29, x30, [sp, #-16]!
+  mov x29, sp
+  adrp x0, __coatl_mem_inited; ldr w1, [x0, :lo12:__coatl_mem_inited]; cbnz w1, .L_mem_done
+  mov w1, #1; str w1, [x0, :lo12:__coatl_mem_inited]
+  adrp x2, __coatl_mem; add x2, x2, :lo12:__coatl_mem
+  movz x1, #0
+  movk x1, #1, lsl #16
+  add x4, x2, x1; adrp x3, .L_string_pool; add x3, x3, :lo12:.L_string_pool
+  mov x5, #61
+.L_string_pool_copy:
+  ldrb w0, [x3], #1; strb w0, [x4], #1; subs x5, x5, #1; b.ne .L_string_pool_copy
+.L_mem_done:
+  ldp x29, x30, [sp], #16
+  ret
+.section .rodata
+.L_string_pool:
+  .ascii "testdata/control_flow.coatl:21:3\000assertion failed: total > 0\000"
+.text
+collatz_steps:
+  stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #4096
+  str x0, [x29, #-16]
+  mov x0, #0
+  str x0, [x29, #-24]
+.while1:
+  ldrsw x0, [x29, #-16]
+  str x0, [sp, #-16]!
+  mov x0, #1
+  mov x1, x0; ldr x0, [sp], #16
+  cmp x0, x1; cset w0, ne
+  cbz x0, .endwhile2
+  ldrsw x0, [x29, #-16]
+  asr x1, x0, #63; add x0, x0, x1, lsr #63; asr x0, x0, #1
+  lsl x0, x0, #1
+  str x0, [sp, #-16]!
+  ldrsw x0, [x29, #-16]
+  mov x1, x0; ldr x0, [sp], #16
+  cmp x0, x1; cset w0, eq
+  cbz x0, .else3
+  ldrsw x0, [x29, #-16]
+  asr x1, x0, #63; add x0, x0, x1, lsr #63; asr x0, x0, #1
+  str x0, [x29, #-16]
+  b .endif4
+.else3:
+  ldrsw x0, [x29, #-16]
+  str x0, [sp, #-16]!
+  mov x0, #3
+  mov x1, x0; ldr x0, [sp], #16
+  mul x0, x0, x1
+  str x0, [sp, #-16]!
+  mov x0, #1
+  mov x1, x0; ldr x0, [sp], #16
+  add x0, x0, x1
+  str x0, [x29, #-16]
+.endif4:
+  ldrsw x0, [x29, #-24]
+  str x0, [sp, #-16]!
+  mov x0, #1
+  mov x1, x0; ldr x0, [sp], #16
+  add x0, x0, x1
+  str x0, [x29, #-24]
+  b .while1
+.endwhile2:
+  ldrsw x0, [x29, #-24]
+  b .Lret_collatz_steps
+.Lret_collatz_steps:
+  add sp, sp, #4096; ldp x29, x30, [sp], #16; ret
+.global main
+main:
+  stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #4096
+  mov x0, #0
+  str x0, [x29, #-16]
+  mov x0, #1
+  str x0, [x29, #-24]
+.while5:
+  ldrsw x0, [x29, #-24]
+  str x0, [sp, #-16]!
+  mov x0, #10
+  mov x1, x0; ldr x0, [sp], #16
+  cmp x0, x1; cset w0, le
+  cbz x0, .endwhile6
+  ldrsw x0, [x29, #-16]
+  str x0, [sp, #-16]!
+  ldrsw x0, [x29, #-24]
+  str x0, [sp, #-16]!
+  ldr x0, [sp], #16
+  bl collatz_steps
+  mov x1, x0; ldr x0, [sp], #16
+  add x0, x0, x1
+  str x0, [x29, #-16]
+  ldrsw x0, [x29, #-24]
+  str x0, [sp, #-16]!
+  mov x0, #1
+  mov x1, x0; ldr x0, [sp], #16
+  add x0, x0, x1
+  str x0, [x29, #-24]
+  b .while5
+.endwhile6:
+  ldrsw x0, [x29, #-16]
+  str x0, [sp, #-16]!
+  mov x0, #0
+  mov x1, x0; ldr x0, [sp], #16
+  cmp x0, x1; cset w0, gt
+  str x0, [sp, #-16]!
+  mov x0, #0
+  mov x1, x0; ldr x0, [sp], #16
+  cmp x0, x1; cset w0, eq
+  cbz x0, .else7
+  movz x0, #33
+  movk x0, #1, lsl #16
+  str x0, [sp, #-16]!
+  movz x0, #0
+  movk x0, #1, lsl #16
+  str x0, [sp, #-16]!
+  ldr x1, [sp], #16
+  ldr x0, [sp], #16
+  bl __panic
+  b .endif8
+.else7:
+.endif8:
+  ldrsw x0, [x29, #-16]
+  b .Lret_main
+.Lret_main:
+  add sp, sp, #4096; ldp x29, x30, [sp], #16; ret
+.globl coatl_start
+coatl_start:
+  stp x29, x30, [sp, #-16]!
+  bl __coatl_init_memory
+  bl main
+  mov w0, w0; mov x8, #93; svc #0
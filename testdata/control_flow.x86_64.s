@@ -0,0 +1,129 @@
+.intel_syntax noprefix
+.bss
+.align 16
+.globl __coatl_mem
+__coatl_mem:
+  .zero 1048576
+__coatl_mem_inited:
+  .long 0
+.text
+__coatl_init_memory:
+  push rbp; mov rbp, rsp
+  mov eax, dword ptr [rip+__coatl_mem_inited]; test eax, eax; jne .L_mem_done
+  mov dword ptr [rip+__coatl_mem_inited], 1
+  lea rdx, [rip+__coatl_mem]
+  lea rdi, [rdx+65536]; lea rsi, [rip+.L_string_pool]; mov ecx, 61; rep movsb
+.L_mem_done:
+  pop rbp; ret
+.section .rodata
+.L_string_pool:
+  .ascii "testdata/control_flow.coatl:21:3\000assertion failed: total > 0\000"
+.text
+collatz_steps:
+  push rbp; mov rbp, rsp; sub rsp, 4096
+  mov [rbp-8], rdi
+  mov rax, 0
+  mov [rbp-16], rax
+.L_while_start1:
+  mov rax, [rbp-8]
+  push rax
+  mov rax, 1
+  mov rcx, rax; pop rax
+  cmp rax, rcx; setne al; movzx rax, al
+  cmp rax, 0; je .L_while_end2
+  mov rax, [rbp-8]
+  mov rcx, rax; sar rcx, 63; shr rcx, 63; add rax, rcx; sar rax, 1
+  shl rax, 1
+  push rax
+  mov rax, [rbp-8]
+  mov rcx, rax; pop rax
+  cmp rax, rcx; sete al; movzx rax, al
+  cmp rax, 0; je .L_else3
+  mov rax, [rbp-8]
+  mov rcx, rax; sar rcx, 63; shr rcx, 63; add rax, rcx; sar rax, 1
+  mov [rbp-8], rax
+  jmp .L_end4
+.L_else3:
+  mov rax, [rbp-8]
+  push rax
+  mov rax, 3
+  mov rcx, rax; pop rax
+  imul rax, rcx
+  push rax
+  mov rax, 1
+  mov rcx, rax; pop rax
+  add rax, rcx
+  mov [rbp-8], rax
+.L_end4:
+  mov rax, [rbp-16]
+  push rax
+  mov rax, 1
+  mov rcx, rax; pop rax
+  add rax, rcx
+  mov [rbp-16], rax
+  jmp .L_while_start1
+.L_while_end2:
+  mov rax, [rbp-16]
+  jmp .Lret_collatz_steps
+.Lret_collatz_steps:
+  leave; ret
+.global main
+main:
+  push rbp; mov rbp, rsp; sub rsp, 4096
+  mov rax, 0
+  mov [rbp-8], rax
+  mov rax, 1
+  mov [rbp-16], rax
+.L_while_start5:
+  mov rax, [rbp-16]
+  push rax
+  mov rax, 10
+  mov rcx, rax; pop rax
+  cmp rax, rcx; setle al; movzx rax, al
+  cmp rax, 0; je .L_while_end6
+  mov rax, [rbp-8]
+  push rax
+  mov rax, [rbp-16]
+  push rax
+  pop rdi
+  call collatz_steps
+  mov rcx, rax; pop rax
+  add rax, rcx
+  mov [rbp-8], rax
+  mov rax, [rbp-16]
+  push rax
+  mov rax, 1
+  mov rcx, rax; pop rax
+  add rax, rcx
+  mov [rbp-16], rax
+  jmp .L_while_start5
+.L_while_end6:
+  mov rax, [rbp-8]
+  push rax
+  mov rax, 0
+  mov rcx, rax; pop rax
+  cmp rax, rcx; setg al; movzx rax, al
+  push rax
+  mov rax, 0
+  mov rcx, rax; pop rax
+  cmp rax, rcx; sete al; movzx rax, al
+  cmp rax, 0; je .L_else7
+  mov rax, 65569
+  push rax
+  mov rax, 65536
+  push rax
+  pop rsi
+  pop rdi
+  call __panic
+  jmp .L_end8
+.L_else7:
+.L_end8:
+  mov rax, [rbp-8]
+  jmp .Lret_main
+.Lret_main:
+  leave; ret
+.globl coatl_start
+coatl_start:
+  call __coatl_init_memory
+  call main
+  mov edi, eax; mov eax, 60; syscall
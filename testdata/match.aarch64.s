@@ -0,0 +1,109 @@
+.bss
+.align 4
+.globl __coatl_mem
+__coatl_mem:
+  .zero 1048576
+__coatl_mem_inited:
+  .word 0
+.text
+__coatl_init_memory:
+  stp x29, x30, [sp, #-16]!
+  mov x29, sp
+  adrp x0, __coatl_mem_inited; ldr w1, [x0, :lo12:__coatl_mem_inited]; cbnz w1, .L_mem_done
+  mov w1, #1; str w1, [x0, :lo12:__coatl_mem_inited]
+.L_mem_done:
+  ldp x29, x30, [sp], #16
+  ret
+token_kind:
+  stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #4096
+  str x0, [x29, #-16]
+  mov x0, #0
+  str x0, [x29, #-24]
+  ldrsw x0, [x29, #-16]
+  mov w1, #40
+  sub w0, w0, w1
+  mov w1, #5
+  cmp w0, w1; b.hi .match_default5
+  adrp x1, .jump_table7; add x1, x1, :lo12:.jump_table7
+  ldrsw x2, [x1, w0, uxtw #2]; add x1, x1, x2; br x1
+.section .rodata
+.balign 4
+.jump_table7:
+  .word .arm1 - .jump_table7
+  .word .arm2 - .jump_table7
+  .word .match_default5 - .jump_table7
+  .word .arm3 - .jump_table7
+  .word .match_default5 - .jump_table7
+  .word .arm4 - .jump_table7
+.text
+.arm1:
+  mov x0, #1
+  str x0, [x29, #-24]
+  b .match_end6
+.arm2:
+  mov x0, #2
+  str x0, [x29, #-24]
+  b .match_end6
+.arm3:
+  mov x0, #3
+  str x0, [x29, #-24]
+  b .match_end6
+.arm4:
+  mov x0, #4
+  str x0, [x29, #-24]
+  b .match_end6
+.match_default5:
+  mov x0, #9
+  str x0, [x29, #-24]
+.match_end6:
+  ldrsw x0, [x29, #-24]
+  b .Lret_token_kind
+.Lret_token_kind:
+  add sp, sp, #4096; ldp x29, x30, [sp], #16; ret
+sign_name:
+  stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #4096
+  str x0, [x29, #-16]
+  ldrsw x0, [x29, #-16]
+  movz w1, #65535
+  movk w1, #65535, lsl #16
+  cmp w0, w1; b.eq .arm8
+  mov w1, #1
+  cmp w0, w1; b.eq .arm9
+  b .match_default10
+.arm8:
+  mov x0, #1
+  b .Lret_sign_name
+  b .match_end11
+.arm9:
+  mov x0, #2
+  b .Lret_sign_name
+  b .match_end11
+.match_default10:
+.match_end11:
+  mov x0, #0
+  b .Lret_sign_name
+.Lret_sign_name:
+  add sp, sp, #4096; ldp x29, x30, [sp], #16; ret
+.global main
+main:
+  stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #4096
+  mov x0, #43
+  str x0, [sp, #-16]!
+  ldr x0, [sp], #16
+  bl token_kind
+  str x0, [sp, #-16]!
+  mov x0, #1
+  str x0, [sp, #-16]!
+  ldr x0, [sp], #16
+  bl sign_name
+  mov x1, x0; ldr x0, [sp], #16
+  add x0, x0, x1
+  b .Lret_main
+.Lret_main:
+  add sp, sp, #4096; ldp x29, x30, [sp], #16; ret
+.globl coatl_start
+coatl_start:
+  stp x29, x30, [sp, #-16]!
+  bl __coatl_init_memory
+  bl main
+  mov w0, w0; mov x8, #93; svc #0
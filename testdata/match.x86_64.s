@@ -0,0 +1,100 @@
+.intel_syntax noprefix
+.bss
+.align 16
+.globl __coatl_mem
+__coatl_mem:
+  .zero 1048576
+__coatl_mem_inited:
+  .long 0
+.text
+__coatl_init_memory:
+  push rbp; mov rbp, rsp
+  mov eax, dword ptr [rip+__coatl_mem_inited]; test eax, eax; jne .L_mem_done
+  mov dword ptr [rip+__coatl_mem_inited], 1
+.L_mem_done:
+  pop rbp; ret
+token_kind:
+  push rbp; mov rbp, rsp; sub rsp, 4096
+  mov [rbp-8], rdi
+  mov rax, 0
+  mov [rbp-16], rax
+  mov rax, [rbp-8]
+  sub eax, 40; cmp eax, 5; ja .L_match_default5
+  lea rdx, [rip+.L_jump_table7]; movsxd rax, dword ptr [rdx+rax*4]; add rax, rdx; jmp rax
+.section .rodata
+.balign 4
+.L_jump_table7:
+  .long .L_arm1 - .L_jump_table7
+  .long .L_arm2 - .L_jump_table7
+  .long .L_match_default5 - .L_jump_table7
+  .long .L_arm3 - .L_jump_table7
+  .long .L_match_default5 - .L_jump_table7
+  .long .L_arm4 - .L_jump_table7
+.text
+.L_arm1:
+  mov rax, 1
+  mov [rbp-16], rax
+  jmp .L_match_end6
+.L_arm2:
+  mov rax, 2
+  mov [rbp-16], rax
+  jmp .L_match_end6
+.L_arm3:
+  mov rax, 3
+  mov [rbp-16], rax
+  jmp .L_match_end6
+.L_arm4:
+  mov rax, 4
+  mov [rbp-16], rax
+  jmp .L_match_end6
+.L_match_default5:
+  mov rax, 9
+  mov [rbp-16], rax
+.L_match_end6:
+  mov rax, [rbp-16]
+  jmp .Lret_token_kind
+.Lret_token_kind:
+  leave; ret
+sign_name:
+  push rbp; mov rbp, rsp; sub rsp, 4096
+  mov [rbp-8], rdi
+  mov rax, [rbp-8]
+  cmp eax, -1; je .L_arm8
+  cmp eax, 1; je .L_arm9
+  jmp .L_match_default10
+.L_arm8:
+  mov rax, 1
+  jmp .Lret_sign_name
+  jmp .L_match_end11
+.L_arm9:
+  mov rax, 2
+  jmp .Lret_sign_name
+  jmp .L_match_end11
+.L_match_default10:
+.L_match_end11:
+  mov rax, 0
+  jmp .Lret_sign_name
+.Lret_sign_name:
+  leave; ret
+.global main
+main:
+  push rbp; mov rbp, rsp; sub rsp, 4096
+  mov rax, 43
+  push rax
+  pop rdi
+  call token_kind
+  push rax
+  mov rax, 1
+  push rax
+  pop rdi
+  call sign_name
+  mov rcx, rax; pop rax
+  add rax, rcx
+  jmp .Lret_main
+.Lret_main:
+  leave; ret
+.globl coatl_start
+coatl_start:
+  call __coatl_init_memory
+  call main
+  mov edi, eax; mov eax, 60; syscall
@@ -0,0 +1,55 @@
+.bss
+.align 4
+.globl __coatl_mem
+__coatl_mem:
+  .zero 1048576
+__coatl_mem_inited:
+  .word 0
+.text
+__coatl_init_memory:
+  stp x29, x30, [sp, #-16]!
+  mov x29, sp
+  adrp x0, __coatl_mem_inited; ldr w1, [x0, :lo12:__coatl_mem_inited]; cbnz w1, .L_mem_done
+  mov w1, #1; str w1, [x0, :lo12:__coatl_mem_inited]
+  adrp x2, __coatl_mem; add x2, x2, :lo12:__coatl_mem
+  movz x1, #0
+  movk x1, #1, lsl #16
+  add x4, x2, x1; adrp x3, .L_string_pool; add x3, x3, :lo12:.L_string_pool
+  mov x5, #22
+.L_string_pool_copy:
+  ldrb w0, [x3], #1; strb w0, [x4], #1; subs x5, x5, #1; b.ne .L_string_pool_copy
+.L_mem_done:
+  ldp x29, x30, [sp], #16
+  ret
+.section .rodata
+.L_string_pool:
+  .ascii "Hello from a module!\012\000"
+.text
+.global main
+main:
+  stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #4096
+  movz x0, #0
+  movk x0, #1, lsl #16
+  str x0, [sp, #-16]!
+  ldr x0, [sp], #16
+  bl io__print
+  mov x0, #0
+  b .Lret_main
+.Lret_main:
+  add sp, sp, #4096; ldp x29, x30, [sp], #16; ret
+io__print:
+  stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #4096
+  str x0, [x29, #-16]
+  ldrsw x0, [x29, #-16]
+  str x0, [sp, #-16]!
+  ldr x0, [sp], #16
+  bl __print
+  b .Lret_io__print
+.Lret_io__print:
+  add sp, sp, #4096; ldp x29, x30, [sp], #16; ret
+.globl coatl_start
+coatl_start:
+  stp x29, x30, [sp, #-16]!
+  bl __coatl_init_memory
+  bl main
+  mov w0, w0; mov x8, #93; svc #0
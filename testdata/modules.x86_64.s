@@ -0,0 +1,47 @@
+.intel_syntax noprefix
+.bss
+.align 16
+.globl __coatl_mem
+__coatl_mem:
+  .zero 1048576
+__coatl_mem_inited:
+  .long 0
+.text
+__coatl_init_memory:
+  push rbp; mov rbp, rsp
+  mov eax, dword ptr [rip+__coatl_mem_inited]; test eax, eax; jne .L_mem_done
+  mov dword ptr [rip+__coatl_mem_inited], 1
+  lea rdx, [rip+__coatl_mem]
+  lea rdi, [rdx+65536]; lea rsi, [rip+.L_string_pool]; mov ecx, 22; rep movsb
+.L_mem_done:
+  pop rbp; ret
+.section .rodata
+.L_string_pool:
+  .ascii "Hello from a module!\012\000"
+.text
+.global main
+main:
+  push rbp; mov rbp, rsp; sub rsp, 4096
+  mov rax, 65536
+  push rax
+  pop rdi
+  call io__print
+  mov rax, 0
+  jmp .Lret_main
+.Lret_main:
+  leave; ret
+io__print:
+  push rbp; mov rbp, rsp; sub rsp, 4096
+  mov [rbp-8], rdi
+  mov rax, [rbp-8]
+  push rax
+  pop rdi
+  call __print
+  jmp .Lret_io__print
+.Lret_io__print:
+  leave; ret
+.globl coatl_start
+coatl_start:
+  call __coatl_init_memory
+  call main
+  mov edi, eax; mov eax, 60; syscall
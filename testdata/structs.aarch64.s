@@ -0,0 +1,67 @@
+.bss
+.align 4
+.globl __coatl_mem
+__coatl_mem:
+  .zero 1048576
+__coatl_mem_inited:
+  .word 0
+.text
+__coatl_init_memory:
+  stp x29, x30, [sp, #-16]!
+  mov x29, sp
+  adrp x0, __coatl_mem_inited; ldr w1, [x0, :lo12:__coatl_mem_inited]; cbnz w1, .L_mem_done
+  mov w1, #1; str w1, [x0, :lo12:__coatl_mem_inited]
+.L_mem_done:
+  ldp x29, x30, [sp], #16
+  ret
+make_point:
+  stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #4096
+  str x0, [x29, #-16]
+  str x1, [x29, #-24]
+  b .Lret_make_point
+.Lret_make_point:
+  add sp, sp, #4096; ldp x29, x30, [sp], #16; ret
+shift_point:
+  stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #4096
+  str x0, [x29, #-16]
+  b .Lret_shift_point
+.Lret_shift_point:
+  add sp, sp, #4096; ldp x29, x30, [sp], #16; ret
+sum_point:
+  stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #4096
+  str x0, [x29, #-16]
+  str x0, [sp, #-16]!
+  mov x1, x0; ldr x0, [sp], #16
+  add x0, x0, x1
+  b .Lret_sum_point
+.Lret_sum_point:
+  add sp, sp, #4096; ldp x29, x30, [sp], #16; ret
+.global main
+main:
+  stp x29, x30, [sp, #-16]!; mov x29, sp; sub sp, sp, #4096
+  mov x0, #1
+  str x0, [sp, #-16]!
+  mov x0, #2
+  str x0, [sp, #-16]!
+  ldr x1, [sp], #16
+  ldr x0, [sp], #16
+  bl make_point
+  str x0, [x29, #-16]
+  ldrsw x0, [x29, #-16]
+  str x0, [sp, #-16]!
+  ldr x0, [sp], #16
+  bl shift_point
+  str x0, [x29, #-24]
+  ldrsw x0, [x29, #-24]
+  str x0, [sp, #-16]!
+  ldr x0, [sp], #16
+  bl sum_point
+  b .Lret_main
+.Lret_main:
+  add sp, sp, #4096; ldp x29, x30, [sp], #16; ret
+.globl coatl_start
+coatl_start:
+  stp x29, x30, [sp, #-16]!
+  bl __coatl_init_memory
+  bl main
+  mov w0, w0; mov x8, #93; svc #0
@@ -0,0 +1,93 @@
+.intel_syntax noprefix
+.bss
+.align 16
+.globl __coatl_mem
+__coatl_mem:
+  .zero 1048576
+__coatl_mem_inited:
+  .long 0
+.text
+__coatl_init_memory:
+  push rbp; mov rbp, rsp
+  mov eax, dword ptr [rip+__coatl_mem_inited]; test eax, eax; jne .L_mem_done
+  mov dword ptr [rip+__coatl_mem_inited], 1
+.L_mem_done:
+  pop rbp; ret
+make_point:
+  push rbp; mov rbp, rsp; sub rsp, 4096
+  mov [rbp-8], rdi
+  mov [rbp-16], rsi
+  push 0
+  mov rax, [rbp-8]
+  mov eax, eax
+  or [rsp], rax
+  mov rax, [rbp-16]
+  mov eax, eax
+  shl rax, 32
+  or [rsp], rax
+  pop rax
+  jmp .Lret_make_point
+.Lret_make_point:
+  leave; ret
+shift_point:
+  push rbp; mov rbp, rsp; sub rsp, 4096
+  mov [rbp-8], rdi
+  push 0
+  movsxd rax, dword ptr [rbp-8]
+  push rax
+  mov rax, 1
+  mov rcx, rax; pop rax
+  add rax, rcx
+  mov eax, eax
+  or [rsp], rax
+  movsxd rax, dword ptr [rbp-4]
+  push rax
+  mov rax, 2
+  mov rcx, rax; pop rax
+  add rax, rcx
+  mov eax, eax
+  shl rax, 32
+  or [rsp], rax
+  pop rax
+  jmp .Lret_shift_point
+.Lret_shift_point:
+  leave; ret
+sum_point:
+  push rbp; mov rbp, rsp; sub rsp, 4096
+  mov [rbp-8], rdi
+  movsxd rax, dword ptr [rbp-8]
+  push rax
+  movsxd rax, dword ptr [rbp-4]
+  mov rcx, rax; pop rax
+  add rax, rcx
+  jmp .Lret_sum_point
+.Lret_sum_point:
+  leave; ret
+.global main
+main:
+  push rbp; mov rbp, rsp; sub rsp, 4096
+  mov rax, 1
+  push rax
+  mov rax, 2
+  push rax
+  pop rsi
+  pop rdi
+  call make_point
+  mov [rbp-8], rax
+  mov rax, [rbp-8]
+  push rax
+  pop rdi
+  call shift_point
+  mov [rbp-16], rax
+  mov rax, [rbp-16]
+  push rax
+  pop rdi
+  call sum_point
+  jmp .Lret_main
+.Lret_main:
+  leave; ret
+.globl coatl_start
+coatl_start:
+  call __coatl_init_memory
+  call main
+  mov edi, eax; mov eax, 60; syscall
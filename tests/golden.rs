@@ -0,0 +1,111 @@
+//! Golden-file tests: every `testdata/*.coatl` program is compiled through the library to
+//! IR and to assembly for each backend, and compared against the snapshots checked in
+//! next to it (`name.ir`, `name.x86_64.s`, `name.aarch64.s`). The bundled runtime is
+//! left out of the assembly snapshots. Run with `COATL_BLESS=1` to rewrite them. The same
+//! programs also check that streaming with `lower_to` writes what `lower` returns.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use coatl::{typecheck, CodegenOptions, ParseOptions, Timings, RUNTIME_MARKER};
+
+const ARCHES: &[&str] = &["x86_64", "aarch64"];
+
+fn testdata_sources() -> Vec<PathBuf> {
+    let mut sources: Vec<PathBuf> = fs::read_dir("testdata")
+        .expect("Failed to read testdata")
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().map(|e| e == "coatl").unwrap_or(false))
+        .collect();
+    sources.sort();
+    sources
+}
+
+/// Compares `actual` with the snapshot at `path`, or rewrites it when blessing.
+/// Returns a description of the mismatch, if any.
+fn check_snapshot(path: &Path, actual: &str, bless: bool) -> Option<String> {
+    if bless {
+        fs::write(path, actual).expect("Failed to write snapshot");
+        return None;
+    }
+    let expected = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(_) => return Some(format!("{}: missing snapshot", path.display())),
+    };
+    if expected == actual { return None; }
+    let line = expected.lines().zip(actual.lines()).position(|(e, a)| e != a)
+        .unwrap_or_else(|| expected.lines().count().min(actual.lines().count()));
+    Some(format!(
+        "{}: differs at line {}\n  expected: {}\n    actual: {}",
+        path.display(),
+        line + 1,
+        expected.lines().nth(line).unwrap_or("<eof>"),
+        actual.lines().nth(line).unwrap_or("<eof>"),
+    ))
+}
+
+#[test]
+fn test_golden_snapshots() {
+    let bless = env::var_os("COATL_BLESS").is_some();
+    let sources = testdata_sources();
+    assert!(!sources.is_empty(), "no testdata/*.coatl programs found");
+    let mut failures = Vec::new();
+    for source in &sources {
+        let ir = coatl::parse_source(source, &mut Timings::default(), &ParseOptions::default())
+            .unwrap_or_else(|e| panic!("{}: {}", source.display(), e.join("; ")));
+        if let Err(errors) = typecheck::check_program(&ir) {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            panic!("{}: {}", source.display(), errors.join("; "));
+        }
+        failures.extend(check_snapshot(&source.with_extension("ir"), &ir.to_ir(), bless));
+        for arch in ARCHES {
+            let (asm, _) = coatl::lower(ir.clone(), arch, &CodegenOptions::default());
+            let program = asm.split(RUNTIME_MARKER).next().unwrap();
+            failures.extend(check_snapshot(&source.with_extension(format!("{}.s", arch)), program, bless));
+        }
+    }
+    assert!(failures.is_empty(), "{} snapshot(s) out of date (rerun with COATL_BLESS=1 to update):\n{}", failures.len(), failures.join("\n"));
+}
+
+/// Takes at most a few bytes per `write`, so the backends' streaming is exercised on
+/// partial writes; fails once `limit` bytes have been taken.
+struct Trickle {
+    taken: Vec<u8>,
+    limit: usize,
+}
+
+impl Write for Trickle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.taken.len() >= self.limit { return Err(io::Error::other("sink full")); }
+        let n = buf.len().min(7).min(self.limit - self.taken.len());
+        self.taken.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+#[test]
+fn test_lower_to_sink() {
+    for source in &testdata_sources() {
+        let ir = coatl::parse_source(source, &mut Timings::default(), &ParseOptions::default())
+            .unwrap_or_else(|e| panic!("{}: {}", source.display(), e.join("; ")));
+        for arch in ARCHES {
+            let (asm, expected) = coatl::lower(ir.clone(), arch, &CodegenOptions::default());
+            let mut sink = Trickle { taken: Vec::new(), limit: usize::MAX };
+            let assembly = coatl::lower_to(ir.clone(), arch, &CodegenOptions::default(), &mut sink).unwrap();
+            assert_eq!(String::from_utf8(sink.taken).unwrap(), asm, "{} {}", source.display(), arch);
+            assert_eq!(assembly.origins, expected.origins, "{} {}", source.display(), arch);
+            assert_eq!(assembly.frames, expected.frames, "{} {}", source.display(), arch);
+            assert_eq!(assembly.strings, expected.strings, "{} {}", source.display(), arch);
+
+            // A sink that fails partway reports the error instead of the assembly.
+            let mut sink = Trickle { taken: Vec::new(), limit: asm.len() / 2 };
+            let err = coatl::lower_to(ir.clone(), arch, &CodegenOptions::default(), &mut sink).err().expect("write error");
+            assert_eq!(err.to_string(), "sink full");
+            assert_eq!(sink.taken, asm.as_bytes()[..asm.len() / 2]);
+        }
+    }
+}
@@ -35,6 +35,19 @@ fn build_bin(src: &str, bin_name: &str, arch: &str) -> Option<PathBuf> {
     }
 }
 
+fn compile_source(src: &str, name: &str, extra_args: &[&str]) -> std::process::Output {
+    let tmp_dir = env::temp_dir().join(format!("coatl-test-{}", name));
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src_path = tmp_dir.join(format!("{}.coatl", name));
+    fs::write(&src_path, src).unwrap();
+    Command::new(get_coatl_bin())
+        .arg(&src_path)
+        .arg("-o")
+        .arg(tmp_dir.join(format!("{}.s", name)))
+        .args(extra_args)
+        .output().unwrap()
+}
+
 fn assert_rc(expected: i32, got: i32, label: &str) {
     assert_eq!(expected, got, "[FAIL] {} expected rc={} got rc={}", label, expected, got);
 }
@@ -82,7 +95,85 @@ fn test_ir_smoke() {
     let content = fs::read_to_string(&mod_ir).unwrap();
     assert!(content.contains("(imports"));
     assert!(content.contains("(fn main"));
-    assert!(content.contains("(fn print"));
+    assert!(content.contains("(fn io__print"));
+}
+
+#[test]
+fn test_timings_report() {
+    let root_dir = env::current_dir().unwrap();
+    let out = env::temp_dir().join("coatl-timings.s");
+    let output = Command::new(get_coatl_bin())
+        .arg(root_dir.join("tests/struct_chain_calls.coatl"))
+        .arg("-o")
+        .arg(&out)
+        .arg("--timings=verbose")
+        .output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for phase in ["lex", "parse", "codegen", "total", "make_point"] {
+        assert!(stderr.contains(phase), "missing `{}` in timings output:\n{}", phase, stderr);
+    }
+}
+
+#[test]
+fn test_intrinsic_typecheck_errors() {
+    let output = compile_source("fn main() returns i32 {\n  let f: f32 = 1.5f32\n  __fd_close(1, 2)\n  return __mem_load(f)\n}\n", "intrinsic-typecheck", &[]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("intrinsic `__fd_close` expects 1 argument(s)"), "{}", stderr);
+    assert!(stderr.contains("intrinsic `__mem_load` argument `addr` must be an integer, got `f32`"), "{}", stderr);
+
+    let output = compile_source("fn main() returns i32 {\n  return __fd_wrte(1, 0, 1, 0)\n}\n", "unknown-intrinsic", &[]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unknown intrinsic `__fd_wrte`; did you mean `__fd_write`?"), "{}", stderr);
+}
+
+#[test]
+fn test_extern_c_call() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-extern");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("extern.coatl");
+    fs::write(&src, "extern \"C\" fn abs(x: i32) -> i32;\n\nfn main() returns i32 {\n  return 1 + abs(0 - 41)\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "extern", "x86_64").expect("Build extern failed");
+    let status = Command::new(&bin).status().unwrap();
+    assert_rc(42, status.code().unwrap_or(-1), "extern");
+}
+
+#[test]
+fn test_libc_mode() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let root_dir = env::current_dir().unwrap();
+    let tmp_dir = env::temp_dir().join("coatl-test-libc");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let bin = tmp_dir.join("hello");
+    let status = Command::new(get_coatl_bin())
+        .arg(root_dir.join("examples/hello.coatl"))
+        .arg("--libc")
+        .arg("-o")
+        .arg(&bin)
+        .status().unwrap();
+    assert!(status.success());
+    let output = Command::new(&bin).output().unwrap();
+    assert_rc(0, output.status.code().unwrap_or(-1), "libc-hello");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Hello, world!"));
+}
+
+#[test]
+fn test_inline_asm() {
+    let output = compile_source("fn main() returns i32 {\n  let x: i32 = 20\n  asm(\"mov rax, {x}; add rax, 1; mov {x}, rax\", x)\n  return x\n}\n", "inline-asm", &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let asm = fs::read_to_string(env::temp_dir().join("coatl-test-inline-asm/inline-asm.s")).unwrap();
+    assert!(asm.contains("  mov rax, qword ptr [rbp-8]\n  add rax, 1\n  mov qword ptr [rbp-8], rax"), "{}", asm);
+
+    let output = compile_source("fn main() returns i32 {\n  asm(\"nop {y}\")\n  return 0\n}\n", "inline-asm-bad", &[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("asm placeholder `{y}` does not name an operand"));
 }
 
 #[test]
@@ -169,7 +260,1941 @@ fn test_snake_smoke() {
     }
 
     // Test aarch64 (build only)
-    if Command::new("command").args(&["-v", "aarch64-linux-gnu-gcc"]).status().map(|s| s.success()).unwrap_or(false) || env::consts::ARCH == "aarch64" {
+    if Command::new("command").args(["-v", "aarch64-linux-gnu-gcc"]).status().map(|s| s.success()).unwrap_or(false) || env::consts::ARCH == "aarch64" {
         let _bin_path_aarch64 = build_bin(src_path.to_str().unwrap(), "snake_aarch64", "aarch64").expect("Build snake aarch64 failed");
     }
 }
+
+#[test]
+fn test_custom_section() {
+    let output = compile_source("fn main() returns i32 {\n  return 0\n}\n", "custom-section", &["--custom-section=coatl.note=hi", "--custom-section=.coatl.ir=@ir"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let asm = fs::read_to_string(env::temp_dir().join("coatl-test-custom-section/custom-section.s")).unwrap();
+    assert!(asm.contains(".section .coatl.note,\"\",%progbits\n  .byte 104,105\n"), "{}", asm);
+    assert!(asm.contains(".section .coatl.ir,\"\",%progbits"), "{}", asm);
+
+    for bad in ["--custom-section==x", "--custom-section=a,b=x", "--custom-section=a\n.text=x"] {
+        let output = compile_source("fn main() returns i32 {\n  return 0\n}\n", "custom-section", &[bad]);
+        assert_eq!(output.status.code(), Some(2), "{}", bad);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("may only contain letters, digits, `_` and `.`"));
+    }
+}
+
+#[test]
+fn test_pub_fn_visibility() {
+    let output = compile_source("pub fn add(a: i32, b: i32) returns i32 {\n  return a + b\n}\nfn helper() returns i32 {\n  return 1\n}\nfn main() returns i32 {\n  return add(helper(), 1)\n}\n", "pub-fn", &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let asm = fs::read_to_string(env::temp_dir().join("coatl-test-pub-fn/pub-fn.s")).unwrap();
+    assert!(asm.contains(".global add\nadd:"), "{}", asm);
+    assert!(asm.contains(".global main\nmain:"), "{}", asm);
+    assert!(!asm.contains(".global helper"), "{}", asm);
+}
+
+#[test]
+fn test_module_mangling() {
+    let tmp_dir = env::temp_dir().join("coatl-test-mangling");
+    let _ = fs::create_dir_all(&tmp_dir);
+    fs::write(tmp_dir.join("math.coatl"), "fn abs(x: i32) returns i32 {\n  if x < 0 {\n    return 0 - x\n  }\n  return x\n}\nfn twice(x: i32) returns i32 {\n  return abs(x) + abs(x)\n}\n").unwrap();
+    fs::write(tmp_dir.join("other.coatl"), "fn abs(x: i32) returns i32 {\n  return x\n}\n").unwrap();
+    let output = compile_source("import \"math\"\nfn main() returns i32 {\n  return math::twice(0 - 20) + twice(1)\n}\n", "mangling", &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let asm = fs::read_to_string(tmp_dir.join("mangling.s")).unwrap();
+    assert!(asm.contains("math__twice:") && asm.contains("call math__abs"), "{}", asm);
+
+    let output = compile_source("import \"math\"\nimport \"other\"\nfn main() returns i32 {\n  return abs(1)\n}\n", "mangling", &[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("call to `abs` is ambiguous between math::abs, other::abs"));
+
+    // `__` inside a function name is not mistaken for a module separator.
+    fs::write(tmp_dir.join("m.coatl"), "fn my__fn() returns i32 {\n  return 1\n}\n").unwrap();
+    fs::write(tmp_dir.join("names.coatl"), "import \"m\"\nfn my__fn() returns i32 {\n  return 2\n}\nfn main() returns i32 {\n  return m::my__fn() + my__fn()\n}\n").unwrap();
+    let output = Command::new(get_coatl_bin()).arg(tmp_dir.join("names.coatl")).args(["--emit=callgraph-json", "-o", "-"]).output().unwrap();
+    let graph = String::from_utf8_lossy(&output.stdout);
+    assert!(graph.contains("\"name\": \"m::my__fn\"") && graph.contains("\"name\": \"my__fn\""), "{}", graph);
+    assert_eq!(coatl::mangle::demangle("my__fn"), None);
+    assert_eq!(coatl::mangle::demangle(&coatl::mangle::mangle("m", "my__fn")), Some(("m", "my__fn")));
+}
+
+#[test]
+fn test_entry_point() {
+    let output = compile_source("fn start_here() returns i32 {\n  return 9\n}\n", "entry", &["--entry=start_here"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let asm = fs::read_to_string(env::temp_dir().join("coatl-test-entry/entry.s")).unwrap();
+    assert!(asm.contains(".set main, start_here") && asm.contains("call start_here"), "{}", asm);
+
+    let output = compile_source("fn main() returns i32 {\n  return 0\n}\n", "entry", &["--entry=nope"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("entry function `nope` not found"));
+}
+
+#[test]
+fn test_shared_library() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-shared");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("mathlib.coatl");
+    fs::write(&src, "pub fn add(a: i32, b: i32) returns i32 {\n  return a + b\n}\nfn hidden() returns i32 {\n  return 0\n}\n").unwrap();
+    let status = Command::new(get_coatl_bin()).arg(&src).arg("-o").arg(tmp_dir.join("libmathlib.so")).status().unwrap();
+    assert!(status.success());
+    let host = tmp_dir.join("host.c");
+    fs::write(&host, "int add(int, int);\nint main(void) { return add(40, 2); }\n").unwrap();
+    let status = Command::new("cc").arg(&host).arg("-L").arg(&tmp_dir).arg("-lmathlib").arg("-o").arg(tmp_dir.join("host")).status().unwrap();
+    assert!(status.success());
+    let status = Command::new(tmp_dir.join("host")).env("LD_LIBRARY_PATH", &tmp_dir).status().unwrap();
+    assert_rc(42, status.code().unwrap_or(-1), "shared-library");
+}
+
+#[test]
+fn test_shared_library_strings() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-shared-strings");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("shout.coatl");
+    fs::write(&src, "pub fn shout(s: str) returns str {\n  let i: i32 = s\n  while (__mem_load8(i) != 0) {\n    let c: i32 = __mem_load8(i)\n    if (c >= 97 && c <= 122) { __mem_store8(i, c - 32) }\n    i = i + 1\n  }\n  return s\n}\n").unwrap();
+    let status = Command::new(get_coatl_bin()).arg(&src).arg("-o").arg(tmp_dir.join("libshout.so")).status().unwrap();
+    assert!(status.success());
+    let host = tmp_dir.join("host.c");
+    fs::write(&host, concat!(
+        "#include <stdio.h>\n#include <stdint.h>\n",
+        "uint8_t *coatl_memory(void);\nint32_t coatl_str(const char *, int64_t);\nvoid coatl_str_reset(void);\nint32_t shout(int32_t);\n",
+        "int main(void) {\n",
+        "  int32_t s = coatl_str(\"hi, coatl\", 9);\n",
+        "  printf(\"%s\\n\", (char *)coatl_memory() + shout(s));\n",
+        "  coatl_str_reset();\n",
+        "  if (coatl_str(\"again\", 5) != s) return 1;\n",
+        "  return coatl_str(\"x\", 1 << 20) == -1 ? 0 : 2;\n",
+        "}\n",
+    )).unwrap();
+    let status = Command::new("cc").arg(&host).arg("-L").arg(&tmp_dir).arg("-lshout").arg("-o").arg(tmp_dir.join("host")).status().unwrap();
+    assert!(status.success());
+    let output = Command::new(tmp_dir.join("host")).env("LD_LIBRARY_PATH", &tmp_dir).output().unwrap();
+    assert_rc(0, output.status.code().unwrap_or(-1), "shared-library-strings");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "HI, COATL\n");
+}
+
+#[test]
+fn test_extern_syscalls() {
+    let output = compile_source("fn main() returns i32 {\n  return 0\n}\n", "syscalls-arch", &["--syscalls=extern", "--arch=aarch64"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("only supported for --arch=x86_64"));
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let output = compile_source("fn main() returns i32 {\n  println(\"hi\")\n  return 3\n}\n", "syscalls-extern", &["--syscalls=extern"]);
+    assert!(output.status.success());
+    let tmp_dir = env::temp_dir().join("coatl-test-syscalls-extern");
+    let asm = fs::read_to_string(tmp_dir.join("syscalls-extern.s")).unwrap();
+    // Only the argv, terminal and sleep intrinsics still issue Linux syscalls directly.
+    assert!(asm.contains("call coatl_sys_write@PLT") && !asm.contains("call write@PLT") && !asm.contains("mov eax, 60"), "{}", asm);
+
+    let shim = tmp_dir.join("shim.c");
+    fs::write(&shim, concat!(
+        "#include <unistd.h>\n#include <fcntl.h>\n",
+        "long coatl_sys_write(int fd, const void *buf, unsigned long len) { write(fd, \"[\", 1); write(fd, buf, len); write(fd, \"]\", 1); return len; }\n",
+        "long coatl_sys_read(int fd, void *buf, unsigned long len) { return read(fd, buf, len); }\n",
+        "long coatl_sys_pread(int fd, void *buf, unsigned long len, long off) { return pread(fd, buf, len, off); }\n",
+        "long coatl_sys_pwrite(int fd, const void *buf, unsigned long len, long off) { return pwrite(fd, buf, len, off); }\n",
+        "int coatl_sys_open(const char *path, int flags, int mode) { return open(path, flags, mode); }\n",
+        "int coatl_sys_close(int fd) { return close(fd); }\n",
+        "void coatl_sys_exit(int status) { _exit(status + 40); }\n",
+    )).unwrap();
+    let bin = tmp_dir.join("syscalls-extern");
+    let status = Command::new("cc").arg(tmp_dir.join("syscalls-extern.s")).arg(&shim).args(["-fPIE", "-pie", "-e", "coatl_start", "-o"]).arg(&bin).status().unwrap();
+    assert!(status.success());
+    let output = Command::new(&bin).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "[hi][\n]");
+    assert_rc(43, output.status.code().unwrap_or(-1), "syscalls-extern");
+}
+
+#[test]
+fn test_freestanding() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let root_dir = env::current_dir().unwrap();
+    let tmp_dir = env::temp_dir().join("coatl-test-freestanding");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let bin = tmp_dir.join("hello");
+    let status = Command::new(get_coatl_bin())
+        .arg(root_dir.join("examples/hello.coatl"))
+        .arg("--freestanding")
+        .arg("-o")
+        .arg(&bin)
+        .status().unwrap();
+    assert!(status.success());
+    let output = Command::new(&bin).output().unwrap();
+    assert_rc(0, output.status.code().unwrap_or(-1), "freestanding-hello");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Hello, world!"));
+}
+
+#[test]
+fn test_stack_limit() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-stack-limit");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("recurse.coatl");
+    fs::write(&src, "fn down(n: i32) returns i32 {\n  if n == 0 {\n    return 0\n  }\n  return down(n - 1) + 1\n}\nfn main() returns i32 {\n  return down(__get_argc() * 100) - 100\n}\n").unwrap();
+    let bin = tmp_dir.join("recurse");
+    let status = Command::new(get_coatl_bin()).arg(&src).arg("--stack-limit=1M").arg("-o").arg(&bin).status().unwrap();
+    assert!(status.success());
+    let output = Command::new(&bin).output().unwrap();
+    assert_rc(0, output.status.code().unwrap_or(-1), "stack-limit-shallow");
+    let output = Command::new(&bin).args(["a"; 40]).output().unwrap();
+    assert_rc(134, output.status.code().unwrap_or(-1), "stack-limit-deep");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("coatl: stack overflow"));
+}
+
+#[test]
+fn test_panic() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-panic");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("panic.coatl");
+    fs::write(&src, "fn main() returns i32 {\n  if __get_argc() > 1 {\n    panic(\"too many arguments\")\n  }\n  return 0\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "panic", "x86_64").expect("Build panic failed");
+    let output = Command::new(&bin).output().unwrap();
+    assert_rc(0, output.status.code().unwrap_or(-1), "panic-not-taken");
+    let output = Command::new(&bin).arg("x").output().unwrap();
+    assert_rc(101, output.status.code().unwrap_or(-1), "panic");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("panic at ") && stderr.ends_with("panic.coatl:3:5: too many arguments\n"), "{}", stderr);
+}
+
+#[test]
+fn test_assert() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-assert");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("assert.coatl");
+    fs::write(&src, "fn main() returns i32 {\n  let n: i32 = __get_argc()\n  assert(n > 0)\n  assert_eq(n * 2, 2)\n  return 0\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "assert", "x86_64").expect("Build assert failed");
+    let output = Command::new(&bin).output().unwrap();
+    assert_rc(0, output.status.code().unwrap_or(-1), "assert-pass");
+    let output = Command::new(&bin).arg("x").output().unwrap();
+    assert_rc(101, output.status.code().unwrap_or(-1), "assert-fail");
+    assert!(String::from_utf8_lossy(&output.stderr).ends_with("assert.coatl:4:3: assertion failed: n * 2 == 2\n"));
+}
+
+#[test]
+fn test_dbg_intrinsic() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-dbg");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("dbg.coatl");
+    fs::write(&src, "fn main() returns i32 {\n  let x: i32 = 0 - 5\n  let y: i32 = __dbg(x * x) + 1\n  __dbg(x)\n  return y - 26\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "dbg", "x86_64").expect("Build dbg failed");
+    let output = Command::new(&bin).output().unwrap();
+    assert_rc(0, output.status.code().unwrap_or(-1), "dbg");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("dbg.coatl:3:16] x * x: i32 = 25\n"), "{}", stderr);
+    assert!(stderr.contains("dbg.coatl:4:3] x: i32 = -5\n"), "{}", stderr);
+}
+
+#[test]
+fn test_instrument_trace() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-trace");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("trace.coatl");
+    fs::write(&src, "fn inc(x: i32) returns i32 {\n  return x + 1\n}\nfn main() returns i32 {\n  return inc(41)\n}\n").unwrap();
+    let bin = tmp_dir.join("trace");
+    let status = Command::new(get_coatl_bin()).arg(&src).arg("--instrument=trace").arg("-o").arg(&bin).status().unwrap();
+    assert!(status.success());
+    let output = Command::new(&bin).output().unwrap();
+    assert_rc(42, output.status.code().unwrap_or(-1), "trace");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "-> main\n  -> inc\n  <- inc\n<- main\n");
+}
+
+#[test]
+fn test_instrument_profile() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-profile");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("profile.coatl");
+    fs::write(&src, "fn inc(x: i32) returns i32 {\n  return x + 1\n}\nfn main() returns i32 {\n  return inc(inc(40))\n}\n").unwrap();
+    let bin = tmp_dir.join("profile");
+    let status = Command::new(get_coatl_bin()).arg(&src).arg("--instrument=profile").arg("-o").arg(&bin).status().unwrap();
+    assert!(status.success());
+    let output = Command::new(&bin).output().unwrap();
+    assert_rc(42, output.status.code().unwrap_or(-1), "profile");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(lines[0], "coatl profile:");
+    assert!(lines[1].starts_with("  main calls=1 cycles="), "{}", stderr);
+    assert!(lines[2].starts_with("  inc calls=2 cycles="), "{}", stderr);
+
+    // The entry cycle count has its own slot inside the frame, not the 512th local's, and
+    // temporaries pushed below the frame do not overwrite it.
+    let locals: String = (1..=512).map(|i| format!("  let v{}: i32 = {}\n", i, i % 100)).collect();
+    fs::write(&src, format!("fn main() returns i32 {{\n{}  let x: i32 = v1 + v2\n  return v512 + v511 + x\n}}\n", locals)).unwrap();
+    let status = Command::new(get_coatl_bin()).arg(&src).arg("--instrument=profile").arg("-o").arg(&bin).status().unwrap();
+    assert!(status.success());
+    let output = Command::new(&bin).output().unwrap();
+    assert_rc(26, output.status.code().unwrap_or(-1), "profile-locals");
+}
+
+#[test]
+fn test_string_folding_and_pooling() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let src = "fn main() returns i32 {\n  __print(\"hello, \" + \"world\" + \"\\n\")\n  __print(\"world\\n\")\n  __print(\"lo, world\\n\")\n  return 0\n}\n";
+    let output = compile_source(src, "strpool", &["-v"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // "world\n" and "lo, world\n" share the tail of the folded "hello, world\n".
+    assert!(String::from_utf8_lossy(&output.stderr).contains("[65536, 65550)     string pool (3 string(s))"));
+    let tmp_dir = env::temp_dir().join("coatl-test-strpool");
+    let src_path = tmp_dir.join("strpool.coatl");
+    let bin = build_bin(src_path.to_str().unwrap(), "strpool", "x86_64").expect("Build strpool failed");
+    let output = Command::new(&bin).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello, world\nworld\nlo, world\n");
+}
+
+#[test]
+fn test_match_jump_table() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-match");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("match.coatl");
+    fs::write(&src, "fn dense(c: i32) returns i32 {\n  let r: i32 = 0\n  match c {\n    40 => { r = 1 }\n    41 => { r = 2 }\n    43 => { r = 3 }\n    44 => { r = 4 }\n    _ => { r = 9 }\n  }\n  return r\n}\nfn sparse(c: i32) returns i32 {\n  match c {\n    -7 => { return 1 }\n    1000 => { return 2 }\n  }\n  return 3\n}\nfn main() returns i32 {\n  return dense(40) + dense(42) * 10 + dense(44) * 100 + dense(0 - 1) * 1000 + sparse(0 - 7) * 10000 + sparse(1000) * 20000 + sparse(5) * 100000\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "match", "x86_64").expect("Build match failed");
+    let status = Command::new(&bin).status().unwrap();
+    // 1 + 90 + 400 + 9000 + 10000 + 40000 + 300000 = 359491, truncated to the exit status.
+    assert_rc(359491 % 256, status.code().unwrap_or(-1), "match");
+
+    let dup = compile_source("fn main() returns i32 {\n  match 1 {\n    1 => { return 1 }\n    1 => { return 2 }\n  }\n  return 0\n}\n", "match-dup", &[]);
+    assert!(!dup.status.success());
+    assert!(String::from_utf8_lossy(&dup.stderr).contains("duplicate match arm `1`"));
+}
+
+#[test]
+fn test_struct_destructuring() {
+    let output = compile_source("fn main() returns i32 {\n  let Nope { x } = 1\n  return x\n}\n", "destructure-unknown", &[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("destructuring `let` of unknown struct `Nope`"), "{}", String::from_utf8_lossy(&output.stderr));
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-destructure");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("destructure.coatl");
+    fs::write(&src, "struct Point {\n  x: i32,\n  y: i32\n}\nfn make(a: i32, b: i32) returns Point {\n  return Point { x: a, y: b }\n}\nfn main() returns i32 {\n  let p: Point = make(3, 4)\n  let Point { x, y: py } = p\n  let Point { x: a, y: b } = make(10, 20)\n  let Later { c } = later()\n  return x * 10 + py + a + b + c.v\n}\nstruct Later {\n  c: Cell\n}\nstruct Cell {\n  v: i32\n}\nfn later() returns Later {\n  return Later { c: Cell { v: 2 } }\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "destructure", "x86_64").expect("Build destructure failed");
+    let status = Command::new(&bin).status().unwrap();
+    assert_rc(66, status.code().unwrap_or(-1), "destructure");
+}
+
+#[test]
+fn test_block_scoping() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let out_of_scope = compile_source("fn main() returns i32 {\n  let total: i32 = 1\n  if (total > 0) {\n    let inner: i32 = 5\n    total = total + inner\n  }\n  return total + inner\n}\n", "scope-error", &[]);
+    assert!(!out_of_scope.status.success());
+    assert!(String::from_utf8_lossy(&out_of_scope.stderr).contains("in fn main: use of undeclared variable `inner`"));
+
+    let tmp_dir = env::temp_dir().join("coatl-test-scope");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("scope.coatl");
+    fs::write(&src, "fn main() returns i32 {\n  let total: i32 = 1\n  let i: i32 = 0\n  while (i < 3) {\n    let step: i32 = i * 10\n    total = total + step\n    i = i + 1\n  }\n  let after: i32 = 5\n  return total + after\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "scope", "x86_64").expect("Build scope failed");
+    let status = Command::new(&bin).status().unwrap();
+    assert_rc(36, status.code().unwrap_or(-1), "scope");
+}
+
+#[test]
+fn test_shadowing() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-shadow");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("shadow.coatl");
+    fs::write(&src, "fn main() returns i32 {\n  let x: i32 = 5\n  let y: i32 = 0\n  if (x > 0) {\n    let x: i32 = x * 10\n    y = x\n  }\n  let x: bool = x > 3\n  __dbg(x)\n  return y + x\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "shadow", "x86_64").expect("Build shadow failed");
+    let output = Command::new(&bin).output().unwrap();
+    assert_rc(51, output.status.code().unwrap_or(-1), "shadow");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("] x: bool = 1\n"));
+}
+
+#[test]
+fn test_string_equality() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-streq");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("streq.coatl");
+    fs::write(&src, "fn same(a: str, b: str) returns i32 {\n  if (a == b) {\n    return 1\n  }\n  return 0\n}\nfn main() returns i32 {\n  let s: str = \"hello\"\n  let t: str = \"hell\"\n  let r: i32 = 0\n  if (s == \"hello\") { r = r + 1 }\n  if (s != t) { r = r + 10 }\n  if (t == \"hello\") { r = r + 100 }\n  return r + same(\"\", \"\") * 50 + same(\"ab\", \"abc\") * 200\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "streq", "x86_64").expect("Build streq failed");
+    let status = Command::new(&bin).status().unwrap();
+    assert_rc(61, status.code().unwrap_or(-1), "streq");
+}
+
+#[test]
+fn test_struct_byte_fields() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-layout");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("layout.coatl");
+    fs::write(&src, "struct Cell {\n  alive: bool,\n  glyph: u8,\n  x: i32\n}\nstruct Pair {\n  a: i32,\n  b: i32\n}\nfn flip(c: Cell) returns Cell {\n  return Cell { alive: c.alive == false, glyph: c.glyph + 1, x: c.x - 1 }\n}\nfn main() returns i32 {\n  let c: Cell = flip(Cell { alive: true, glyph: 255, x: 0 - 3 })\n  let p: Pair = Pair { a: 0 - 1, b: 7 }\n  let wrapped: i32 = c.glyph\n  c.glyph = 40\n  return c.alive * 100 + wrapped + c.glyph + c.x + p.b\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "layout", "x86_64").expect("Build layout failed");
+    let status = Command::new(&bin).status().unwrap();
+    // glyph 255 + 1 wraps to 0 in its byte; x = -4; b survives the negative a beside it.
+    assert_rc(43, status.code().unwrap_or(-1), "layout");
+
+    let big = compile_source("struct Big {\n  a: i32,\n  b: i32,\n  c: i32\n}\nfn main() returns i32 {\n  return 0\n}\n", "layout-big", &[]);
+    assert!(!big.status.success());
+    assert!(String::from_utf8_lossy(&big.stderr).contains("struct `Big` is 12 bytes; struct values are limited to 8 bytes"));
+}
+
+#[test]
+fn test_unary_operators() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-unary");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("unary.coatl");
+    fs::write(&src, "fn neg(x: i32) returns i32 {\n  return -x\n}\nfn main() returns i32 {\n  let a: i32 = -5\n  let ok: bool = !(a > 0)\n  let b: i32 = neg(a) * -2 + --3\n  if (!ok) { return 1 }\n  __print(\"-\")\n  __print(\"!\")\n  return b + 20 - -1\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "unary", "x86_64").expect("Build unary failed");
+    let output = Command::new(&bin).output().unwrap();
+    assert_rc(14, output.status.code().unwrap_or(-1), "unary");
+    // String literals spelled like the operators are still strings.
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "-!");
+}
+
+#[test]
+fn test_type_aliases() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let src = "type Fd = i32\ntype Handle = Fd;\ntype Name = str\ntype Pt = Point\nstruct Point {\n  x: i32,\n  y: Fd\n}\nfn bump(n: Handle) returns Fd {\n  return n + 1\n}\nfn main() returns i32 {\n  let fd: Handle = bump(2)\n  let s: Name = \"abc\"\n  let p: Pt = Pt { x: 1, y: fd }\n  if (s == \"abc\") { return p.y + p.x }\n  return 0\n}\n";
+    let output = compile_source(src, "alias", &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let tmp_dir = env::temp_dir().join("coatl-test-alias");
+    let ir = tmp_dir.join("alias.ir");
+    let status = Command::new(get_coatl_bin()).arg(tmp_dir.join("alias.coatl")).arg("-o").arg(&ir).status().unwrap();
+    assert!(status.success());
+    let ir = fs::read_to_string(&ir).unwrap();
+    assert!(ir.contains("(fn bump (params (param n i32)) (ret i32)") && ir.contains("(let p Point (struct_lit Point"), "{}", ir);
+    let bin = build_bin(tmp_dir.join("alias.coatl").to_str().unwrap(), "alias", "x86_64").expect("Build alias failed");
+    assert_rc(4, Command::new(&bin).status().unwrap().code().unwrap_or(-1), "alias");
+
+    let cycle = compile_source("type A = B\ntype B = A\nfn main() returns i32 {\n  return 0\n}\n", "alias-cycle", &[]);
+    assert!(!cycle.status.success());
+    assert!(String::from_utf8_lossy(&cycle.stderr).contains("error: type alias `A` refers to itself"));
+}
+
+#[test]
+fn test_struct_field_defaults() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-defaults");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("defaults.coatl");
+    fs::write(&src, "struct Config {\n  retries: i32 = 3,\n  verbose: bool = false\n}\nstruct Point {\n  x: i32,\n  y: i32\n}\nfn main() returns i32 {\n  let c: Config = Config { verbose: true }\n  let d: Config = Config { }\n  let p: Point = Point { y: 20, x: 1 }\n  return c.retries * 10 + c.verbose + d.verbose * 100 + p.y - p.x\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "defaults", "x86_64").expect("Build defaults failed");
+    assert_rc(50, Command::new(&bin).status().unwrap().code().unwrap_or(-1), "defaults");
+
+    let missing = compile_source("struct P {\n  x: i32,\n  y: i32\n}\nfn main() returns i32 {\n  let p: P = P { x: 1, z: 2 }\n  return 0\n}\n", "defaults-missing", &[]);
+    assert!(!missing.status.success());
+    let stderr = String::from_utf8_lossy(&missing.stderr);
+    assert!(stderr.contains("error: struct `P` has no field `z`") && stderr.contains("error: struct literal `P` is missing field `y`"), "{}", stderr);
+}
+
+#[test]
+fn test_field_init_shorthand() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-shorthand");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("shorthand.coatl");
+    fs::write(&src, "struct Point {\n  x: i32,\n  y: i32\n}\nfn make(x: i32, y: i32) returns Point {\n  return Point { y, x }\n}\nfn main() returns i32 {\n  let done: bool = false\n  let p: Point = make(4, 9)\n  let x: i32 = 100\n  let q: Point = Point { x, y: p.y }\n  if done { return 1 }\n  return q.x + q.y - p.x\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "shorthand", "x86_64").expect("Build shorthand failed");
+    assert_rc(105, Command::new(&bin).status().unwrap().code().unwrap_or(-1), "shorthand");
+}
+
+#[test]
+fn test_named_arguments() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-named");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("named.coatl");
+    fs::write(&src, "fn area(width: i32, height: i32, scale: i32) returns i32 {\n  return width * height * scale\n}\nfn main() returns i32 {\n  __mem_store(addr: 64, value: 7)\n  let a: i32 = area(height: 3, scale: 1, width: 5)\n  let b: i32 = area(2, scale: 2, height: 4)\n  return a + b + __mem_load(64)\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "named", "x86_64").expect("Build named failed");
+    assert_rc(38, Command::new(&bin).status().unwrap().code().unwrap_or(-1), "named");
+
+    let bad = compile_source("fn f(a: i32, b: i32) returns i32 {\n  return a\n}\nfn main() returns i32 {\n  return f(1, a: 2, c: 3)\n}\n", "named-bad", &[]);
+    assert!(!bad.status.success());
+    let stderr = String::from_utf8_lossy(&bad.stderr);
+    for msg in ["argument `a` of `f` is given twice", "`f` has no parameter named `c`", "call to `f` is missing argument `b`"] {
+        assert!(stderr.contains(msg), "{}", stderr);
+    }
+}
+
+#[test]
+fn test_function_overloading() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-overload");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("overload.coatl");
+    fs::write(&src, "fn show(x: i32) returns i32 {\n  return x + 1\n}\nfn show(s: str) returns i32 {\n  return 100\n}\nfn show(a: i32, b: i32) returns i32 {\n  return a * b\n}\nfn show() returns i32 {\n  return 7\n}\nfn main() returns i32 {\n  let a: i32 = show(1)\n  let b: i32 = show(\"hi\")\n  return a + b + show(3, 4) + show()\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "overload", "x86_64").expect("Build overload failed");
+    assert_rc(121, Command::new(&bin).status().unwrap().code().unwrap_or(-1), "overload");
+
+    let bad = compile_source("fn f(a: i32) returns i32 {\n  return a\n}\nfn f(a: str) returns i32 {\n  return 0\n}\nfn g(a: i32) returns i32 {\n  return a\n}\nfn g(b: i32) returns i32 {\n  return b\n}\nfn main() returns i32 {\n  return f(true)\n}\n", "overload-bad", &[]);
+    assert!(!bad.status.success());
+    let stderr = String::from_utf8_lossy(&bad.stderr);
+    assert!(stderr.contains("fn `g` is defined twice with parameters (i32)"), "{}", stderr);
+
+    let bad = compile_source("fn f(a: i32) returns i32 {\n  return a\n}\nfn f(a: str) returns i32 {\n  return 0\n}\nfn main() returns i32 {\n  return f(true)\n}\n", "overload-nomatch", &[]);
+    assert!(!bad.status.success());
+    let stderr = String::from_utf8_lossy(&bad.stderr);
+    assert!(stderr.contains("no overload of `f` takes (bool); candidates are f(i32), f(str)"), "{}", stderr);
+}
+
+#[test]
+fn test_integer_formatting() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-itoa");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("itoa.coatl");
+    fs::write(&src, "fn main() returns i32 {\n  let n: i32 = __itoa(0 - 2147483647 - 1, 2000)\n  __print(2000)\n  print_int(n)\n  print_int(0)\n  print_int(0 - 42)\n  return __itoa(1234, 3000)\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "itoa", "x86_64").expect("Build itoa failed");
+    let output = Command::new(&bin).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "-2147483648110-42");
+    assert_rc(4, output.status.code().unwrap_or(-1), "itoa");
+}
+
+#[test]
+fn test_integer_parsing() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-atoi");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("atoi.coatl");
+    fs::write(&src, "fn parse(ptr: i32, len: i32) returns i32 {\n  if (__atoi(ptr, len, 200) != 0) {\n    __print(\"err \")\n    return 0\n  }\n  print_int(__mem_load(200))\n  __print(\" \")\n  return 0\n}\n\nfn main() returns i32 {\n  parse(\"123\\n\", 4)\n  parse(\"-77\", 3)\n  parse(\"+5\", 2)\n  parse(\"2147483647\", 10)\n  parse(\"-2147483648\", 11)\n  parse(\"2147483648\", 10)\n  parse(\"-2147483649\", 11)\n  parse(\"12x\", 3)\n  parse(\"-\", 1)\n  parse(\"7\", 0)\n  __mem_store(200, 9)\n  __atoi(\"x\", 1, 200)\n  return __mem_load(200)\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "atoi", "x86_64").expect("Build atoi failed");
+    let output = Command::new(&bin).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "123 -77 5 2147483647 -2147483648 err err err err err ");
+    assert_rc(9, output.status.code().unwrap_or(-1), "atoi");
+}
+
+#[test]
+fn test_bundled_runtime() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let source = "fn mem_set(dst: i32, value: i32, len: i32) returns i32 {\n  return 9\n}\nfn main() returns i32 {\n  cstr_copy(2000, \"hello\")\n  print_line(2000)\n  print_value(\"n = \", cstr_len(2000))\n  return runtime::cstr_len(\"abc\") + mem_set(0, 0, 0)\n}\n";
+    let tmp_dir = env::temp_dir().join("coatl-test-runtime");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("runtime.coatl");
+    fs::write(&src, source).unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "runtime", "x86_64").expect("Build runtime failed");
+    let output = Command::new(&bin).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello\nn = 5\n");
+    assert_rc(12, output.status.code().unwrap_or(-1), "runtime");
+
+    // Only the runtime functions the program reaches are kept; a root function of the same name wins.
+    let ir_path = tmp_dir.join("runtime.ir");
+    let status = Command::new(get_coatl_bin()).arg(&src).arg("-o").arg(&ir_path).status().unwrap();
+    assert!(status.success());
+    let ir = fs::read_to_string(&ir_path).unwrap();
+    assert!(ir.contains("(fn runtime__cstr_copy") && ir.contains("(fn runtime__mem_copy"), "{}", ir);
+    assert!(!ir.contains("runtime__mem_set"), "{}", ir);
+
+    let status = Command::new(get_coatl_bin()).arg(&src).arg("--no-runtime").arg("-o").arg(&ir_path).status().unwrap();
+    assert!(status.success());
+    assert!(!fs::read_to_string(&ir_path).unwrap().contains("(fn runtime__"));
+}
+
+#[test]
+fn test_prelude() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-prelude");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("prelude.coatl");
+    fs::write(&src, "fn main() returns i32 {\n  println(\"hi\")\n  print(\"there\\n\")\n  return 0\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "prelude", "x86_64").expect("Build prelude failed");
+    let output = Command::new(&bin).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hi\nthere\n");
+
+    let ir = compile_source("fn main() returns i32 {\n  return print(\"x\")\n}\n", "no-prelude", &["--no-prelude"]);
+    assert!(ir.status.success());
+    let asm = fs::read_to_string(env::temp_dir().join("coatl-test-no-prelude").join("no-prelude.s")).unwrap();
+    assert!(asm.contains("call print\n") && !asm.contains("prelude__"), "{}", asm);
+}
+
+#[test]
+fn test_cfg_target() {
+    let source = "#[cfg(target = \"x86_64\")]\nfn arch_id() returns i32 {\n  return 1\n}\n#[cfg(target = \"aarch64\")]\nfn arch_id() returns i32 {\n  return 2\n}\n#[cfg(target = \"wasm\")]\nimport \"missing\"\n\nfn main() returns i32 {\n  let r: i32 = arch_id()\n  #[cfg(not(target = \"x86_64\"))]\n  r = r + 100\n  #[cfg(any(target = \"wasm\", target = \"x86_64\"))]\n  r = r + 10\n  return r\n}\n";
+    let tmp_dir = env::temp_dir().join("coatl-test-cfg");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("cfg.coatl");
+    fs::write(&src, source).unwrap();
+    for (arch, body) in [("x86_64", "(return (int 1))"), ("aarch64", "(return (int 2))")] {
+        let ir_path = tmp_dir.join(format!("cfg-{}.ir", arch));
+        let status = Command::new(get_coatl_bin()).arg(&src).arg(format!("--arch={}", arch)).arg("-o").arg(&ir_path).status().unwrap();
+        assert!(status.success());
+        let ir = fs::read_to_string(&ir_path).unwrap();
+        assert_eq!(ir.matches("(fn arch_id").count(), 1, "{}", ir);
+        assert!(ir.contains(body), "{}", ir);
+    }
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let bin = build_bin(src.to_str().unwrap(), "cfg", "x86_64").expect("Build cfg failed");
+    assert_rc(11, Command::new(&bin).status().unwrap().code().unwrap_or(-1), "cfg");
+}
+
+#[test]
+fn test_macros() {
+    let source = "macro check(code) {\n  let rc: i32 = code\n  if (rc != 0) {\n    return rc + 40\n  }\n}\n\nmacro bump(var, by) {\n  var = var + by\n}\n\nmacro bump_twice(v) {\n  bump!(v, 1)\n  bump!(v, 1)\n}\n\nfn step(n: i32) returns i32 {\n  return n - 6\n}\n\nfn main() returns i32 {\n  let rc: i32 = 1\n  let total: i32 = 5\n  bump_twice!(total)\n  check!(step(6))\n  check!(step(total))\n  return rc\n}\n";
+    let output = compile_source(source, "macros", &["--emit=ir"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let ir = fs::read_to_string(env::temp_dir().join("coatl-test-macros").join("macros.s")).unwrap();
+    // The macro's `rc` is renamed per expansion and never touches main's `rc`.
+    assert!(ir.contains("(let rc__macro4 i32 (call step (int 6)))") && ir.contains("(return (ident rc))"), "{}", ir);
+    assert_eq!(ir.matches("(assign total (binary add (ident total) (int 1)))").count(), 2, "{}", ir);
+
+    let output = compile_source("macro one(a) {\n  a = 1\n}\nfn main() returns i32 {\n  let x: i32 = 0\n  one!(x, 2)\n  two!(x)\n  return x\n}\n", "macros-bad", &[]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("macros-bad.coatl:6:3: macro `one` takes 1 argument(s), got 2"), "{}", stderr);
+    assert!(stderr.contains("macros-bad.coatl:7:3: unknown macro `two`"), "{}", stderr);
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let src = env::temp_dir().join("coatl-test-macros").join("macros.coatl");
+    let bin = build_bin(src.to_str().unwrap(), "macros", "x86_64").expect("Build macros failed");
+    assert_rc(41, Command::new(&bin).status().unwrap().code().unwrap_or(-1), "macros");
+}
+
+#[test]
+fn test_const_fn() {
+    let source = "const fn fib(n: i32) returns i32 {\n  if (n < 2) {\n    return n\n  }\n  return fib(n - 1) + fib(n - 2)\n}\n\nconst fn is_even(n: i32) returns bool {\n  return n / 2 * 2 == n\n}\n\nconst fn big(n: i32) returns i32 {\n  return n * 1000000\n}\n\nfn main() returns i32 {\n  let x: i32 = fib(10)\n  let y: i32 = fib(x - 50)\n  if (is_even(4)) { x = x + 1 }\n  let z: i32 = big(5000)\n  return x + y\n}\n";
+    let output = compile_source(source, "const-fn", &["--emit=ir"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let ir = fs::read_to_string(env::temp_dir().join("coatl-test-const-fn").join("const-fn.s")).unwrap();
+    assert!(ir.contains("(let x i32 (int 55))") && ir.contains("(if (bool 1)"), "{}", ir);
+    // Non-constant arguments and results past i32 are left to run time.
+    assert!(ir.contains("(let y i32 (call fib (binary sub (ident x) (int 50))))") && ir.contains("(call big (int 5000))"), "{}", ir);
+
+    let output = compile_source("const fn greet(n: i32) returns i32 {\n  print_int(n)\n  return n\n}\nfn main() returns i32 {\n  return greet(1)\n}\n", "const-fn-bad", &[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("const fn `greet` calls `__print_int`, which is not a const fn"), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // A let in a nested block shadows only until the block ends, as at run time.
+    let shadow = "const fn shadow(n: i32) returns i32 {\n  let x: i32 = 1\n  if (n > 0) {\n    let x: i32 = 100\n    x = x + n\n  }\n  while (n > 0) {\n    let x: i32 = 50\n    n = n - 1\n  }\n  x = x + n\n  return x\n}\nfn main() returns i32 {\n  return shadow(3)\n}\n";
+    let output = compile_source(shadow, "const-fn-shadow", &["--emit=ir"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let ir = fs::read_to_string(env::temp_dir().join("coatl-test-const-fn-shadow").join("const-fn-shadow.s")).unwrap();
+    assert!(ir.contains("(return (int 1))"), "{}", ir);
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let src = env::temp_dir().join("coatl-test-const-fn").join("const-fn.coatl");
+    let bin = build_bin(src.to_str().unwrap(), "const-fn", "x86_64").expect("Build const-fn failed");
+    assert_rc(61, Command::new(&bin).status().unwrap().code().unwrap_or(-1), "const-fn");
+}
+
+#[test]
+fn test_static_arrays() {
+    let source = "static TABLE: [i32; 8] = [1, 2, 3, 5, 8, 13, 21, -34]\nstatic BYTES: [u8 4] = [10, 20, 30, 255]\n\nfn at(i: i32) returns i32 {\n  return TABLE[i]\n}\n\nfn main() returns i32 {\n  let sum: i32 = 0\n  let i: i32 = 0\n  while (i < 8) {\n    sum = sum + at(i)\n    i = i + 1\n  }\n  if (__get_argc() > 1) {\n    sum = at(8)\n  }\n  return sum + BYTES[3] - BYTES[0]\n}\n";
+    let output = compile_source(source, "statics", &["--emit=ir"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let ir = fs::read_to_string(env::temp_dir().join("coatl-test-statics").join("statics.s")).unwrap();
+    assert!(ir.contains("(statics (static TABLE \"[i32 8]\" 1 2 3 5 8 13 21 -34) (static BYTES \"[u8 4]\" 10 20 30 255))"), "{}", ir);
+    assert!(ir.contains("(static_index TABLE (ident i)"), "{}", ir);
+
+    let output = compile_source(source, "statics-asm", &["--emit=asm"]);
+    let asm = fs::read_to_string(env::temp_dir().join("coatl-test-statics-asm").join("statics-asm.s")).unwrap();
+    assert!(output.status.success() && asm.contains(".section .rodata") && asm.contains(".Lstatic_TABLE:") && asm.contains("  .byte 10, 20, 30, 255"), "{}", asm);
+
+    let bad = "static T: [i32 3] = [1, 2]\nstatic U: [u8 1] = [300]\nfn main() returns i32 {\n  T[0] = 4\n  return 0\n}\n";
+    let output = compile_source(bad, "statics-bad", &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("static `T` has 2 value(s), but its type [i32 3] holds 3"), "{}", stderr);
+    assert!(stderr.contains("static `U` value 300 does not fit in u8"), "{}", stderr);
+    assert!(stderr.contains("cannot assign to static `T`; statics are read-only"), "{}", stderr);
+
+    // Values are constant expressions, const fn calls included, folded before codegen.
+    let dir = env::temp_dir().join("coatl-test-statics-const");
+    let _ = fs::create_dir_all(&dir);
+    fs::write(dir.join("shapes.coatl"), "const fn sq(n: i32) returns i32 {\n  return n * n\n}\nstatic SIDES: [u8 2] = [sq(2), sq(3) - 1]\n").unwrap();
+    let output = compile_source("import \"shapes\"\nconst fn cube(n: i32) returns i32 {\n  return n * shapes::sq(n)\n}\nstatic POW: [i32 4] = [cube(1), cube(2), 3 * (4 + 5), -cube(3)]\nfn main() returns i32 {\n  return POW[1]\n}\n", "statics-const", &["--emit=ir"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let ir = fs::read_to_string(dir.join("statics-const.s")).unwrap();
+    assert!(ir.contains("(static POW \"[i32 4]\" 1 8 27 -27) (static shapes__SIDES \"[u8 2]\" 4 8)"), "{}", ir);
+    let output = compile_source("static V: [i32 2] = [1, __get_argc()]\nfn main() returns i32 {\n  return V[0]\n}\n", "statics-const", &[]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("static `V` has a value that is not a constant i32 expression"), "{}", String::from_utf8_lossy(&output.stderr));
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let src = env::temp_dir().join("coatl-test-statics").join("statics.coatl");
+    let bin = build_bin(src.to_str().unwrap(), "statics", "x86_64").expect("Build statics failed");
+    assert_rc(8, Command::new(&bin).status().unwrap().code().unwrap_or(-1), "statics");
+    let out = Command::new(&bin).arg("x").output().unwrap();
+    assert_rc(101, out.status.code().unwrap_or(-1), "statics out of bounds");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("index out of bounds of static `TABLE`"), "{}", String::from_utf8_lossy(&out.stderr));
+}
+
+#[test]
+fn test_layout_map() {
+    let map = env::temp_dir().join("coatl-test-layout-map").join("layout.json");
+    let map_arg = format!("--layout-map={}", map.display());
+    let output = compile_source("fn main() returns i32 {\n  print(\"say \\\"hi\\\"\\n\")\n  print(\"hi\\\"\\n\")\n  return 0\n}\n", "layout-map", &["--no-prelude", &map_arg]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json = fs::read_to_string(&map).unwrap();
+    assert!(json.contains("\"memory\": { \"symbol\": \"__coatl_mem\", \"size\": 1048576 }"), "{}", json);
+    assert!(json.contains("{ \"name\": \"strings\", \"start\": 65536, \"end\": "), "{}", json);
+    // The shorter string is a suffix of the longer one and shares its bytes.
+    assert!(json.contains("{ \"offset\": 65536, \"length\": 9, \"value\": \"say \\\"hi\\\"\\n\" }"), "{}", json);
+    assert!(json.contains("{ \"offset\": 65541, \"length\": 4, \"value\": \"hi\\\"\\n\" }"), "{}", json);
+
+    let output = compile_source("fn main() returns i32 {\n  return 0\n}\n", "layout-map-ir", &["--emit=ir", &map_arg]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_listing() {
+    let source = "fn main() returns i32 {\n  let x: i32 = 4\n  if (x > 3) {\n    x = x + 1\n  }\n  return x\n}\n";
+    let output = compile_source(source, "listing", &["--emit=asm", "--listing"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let asm = fs::read_to_string(env::temp_dir().join("coatl-test-listing").join("listing.s")).unwrap();
+    assert!(asm.contains("listing.coatl:2: let x: i32 = 4\n  mov rax, 4\n"), "{}", asm);
+    assert!(asm.contains("listing.coatl:4: x = x + 1\n"), "{}", asm);
+
+    let output = compile_source(source, "listing-off", &["--emit=asm"]);
+    assert!(output.status.success());
+    let asm = fs::read_to_string(env::temp_dir().join("coatl-test-listing-off").join("listing-off.s")).unwrap();
+    assert!(!asm.contains("listing-off.coatl:"), "{}", asm);
+}
+
+#[test]
+fn test_map_file() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let map = env::temp_dir().join("coatl-test-map").join("out.map");
+    let map_arg = format!("--map={}", map.display());
+    let source = "fn twice(n: i32) returns i32 {\n  return n * 2\n}\n\nfn main() returns i32 {\n  print(\"mapped\\n\")\n  return twice(4)\n}\n";
+    let output = compile_source(source, "map", &[&map_arg]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let text = fs::read_to_string(&map).unwrap();
+    let size_of = |name: &str| -> u64 {
+        let line = text.lines().find(|l| l.split_whitespace().next() == Some(name)).unwrap_or_else(|| panic!("no {} in\n{}", name, text));
+        line.split_whitespace().nth(1).unwrap().parse().unwrap()
+    };
+    assert!(size_of("main") > 0 && size_of("twice") > 0, "{}", text);
+    assert_eq!(size_of("total"), size_of("main") + size_of("twice") + size_of("prelude::print"), "{}", text);
+    assert!(size_of(".bss") >= 1048576 && size_of(".text") > size_of("total"), "{}", text);
+    assert!(text.contains("65536      7  \"mapped\\n\""), "{}", text);
+    // Program functions get sizes only when a map is asked for.
+    let asm = fs::read_to_string(env::temp_dir().join("coatl-test-map").join("map.s")).unwrap();
+    assert!(asm.contains(".size twice, . - twice"), "{}", asm);
+    let output = compile_source(source, "map-off", &[]);
+    assert!(output.status.success());
+    assert!(!fs::read_to_string(env::temp_dir().join("coatl-test-map-off").join("map-off.s")).unwrap().contains(".size twice"));
+}
+
+#[test]
+fn test_verify() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let output = compile_source("fn main() returns i32 {\n  let x: i32 = 3\n  asm(\"bogus_op {x}\", x)\n  return x\n}\n", "verify-bad", &["--verify"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("error: generated assembly line ") && stderr.contains("bogus_op"), "{}", stderr);
+    assert!(stderr.contains("note: emitted for ") && stderr.contains("verify-bad.coatl:3: asm(\"bogus_op {x}\", x)"), "{}", stderr);
+    assert!(!env::temp_dir().join("coatl-test-verify-bad").join("verify-bad.s").exists());
+
+    let output = compile_source("fn main() returns i32 {\n  return 7\n}\n", "verify-ok", &["--verify"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let asm = fs::read_to_string(env::temp_dir().join("coatl-test-verify-ok").join("verify-ok.s")).unwrap();
+    assert!(!asm.contains("verify-ok.coatl:"), "{}", asm);
+}
+
+#[test]
+fn test_nested_struct_fields() {
+    let source = "struct Pair {\n  lo: u8,\n  hi: u8\n}\n\nstruct Tri {\n  a: u8,\n  b: u8,\n  c: u8\n}\n\nstruct Rect {\n  tag: u8,\n  size: Pair,\n  w: i32\n}\n\nstruct Box {\n  t: Tri,\n  flag: bool,\n  n: i32\n}\n\nfn area(r: Rect) returns i32 {\n  return r.size.lo * r.size.hi + r.w\n}\n\nfn main() returns i32 {\n  let r: Rect = Rect { tag: 7, size: Pair { lo: 3, hi: 4 }, w: 100 }\n  r.size.hi = 5\n  let p: Pair = r.size\n  let b: Box = Box { t: Tri { a: 1, b: 2, c: 3 }, flag: true, n: 50 }\n  b.t.c = 9\n  let t: Tri = b.t\n  if (!b.flag) {\n    return 1\n  }\n  return area(r) + r.tag + p.hi + t.a + t.b + b.t.c + b.n\n}\n";
+    let output = compile_source(source, "nested-fields", &["--emit=ir"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let ir = fs::read_to_string(env::temp_dir().join("coatl-test-nested-fields").join("nested-fields.s")).unwrap();
+    assert!(ir.contains("(field_assign r size hi (int 5))") && ir.contains("(field r size lo)"), "{}", ir);
+
+    let bad = "struct A {\n  b: B\n}\nstruct B {\n  a: A\n}\nstruct P {\n  x: i32\n}\nfn main() returns i32 {\n  let p: P = P { x: 1 }\n  return p.x.y\n}\n";
+    let output = compile_source(bad, "nested-fields-bad", &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("struct `A` contains itself") && stderr.contains("struct `B` contains itself"), "{}", stderr);
+    assert!(stderr.contains("field access `.y` on `i32`, which is not a struct"), "{}", stderr);
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let src = env::temp_dir().join("coatl-test-nested-fields").join("nested-fields.coatl");
+    let bin = build_bin(src.to_str().unwrap(), "nested-fields", "x86_64").expect("Build nested-fields failed");
+    assert_rc(189, Command::new(&bin).status().unwrap().code().unwrap_or(-1), "nested-fields");
+
+    // Struct fields of 5 to 7 bytes are stored in 4-, 2- and 1-byte pieces.
+    let odd = concat!(
+        "struct Seven {\n  a: u8,\n  b: u8,\n  c: u8,\n  d: u8,\n  e: u8,\n  f: u8,\n  g: u8\n}\n",
+        "struct Five {\n  a: u8,\n  b: u8,\n  c: u8,\n  d: u8,\n  e: u8\n}\n",
+        "struct W {\n  tag: u8,\n  s: Seven\n}\n",
+        "struct V {\n  n: u8,\n  f: Five,\n  z: u8\n}\n",
+        "fn main() returns i32 {\n  let w: W = W { tag: 100, s: Seven { a: 0, b: 0, c: 0, d: 0, e: 0, f: 0, g: 0 } }\n",
+        "  w.s = Seven { a: 1, b: 2, c: 3, d: 4, e: 5, f: 6, g: 7 }\n",
+        "  let v: V = V { n: 1, f: Five { a: 0, b: 0, c: 0, d: 0, e: 0 }, z: 9 }\n",
+        "  v.f = Five { a: 1, b: 2, c: 3, d: 4, e: 5 }\n",
+        "  if (w.s.e != 5 || w.s.f != 6 || v.f.e != 5 || v.z != 9) { return 1 }\n",
+        "  return w.tag + w.s.a + w.s.d * 2 + w.s.g * 10 + v.f.d\n}\n",
+    );
+    let tmp_dir = env::temp_dir().join("coatl-test-odd-fields");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("odd-fields.coatl");
+    fs::write(&src, odd).unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "odd-fields", "x86_64").expect("Build odd-fields failed");
+    assert_rc(183, Command::new(&bin).status().unwrap().code().unwrap_or(-1), "odd-fields");
+}
+
+#[test]
+fn test_slices() {
+    let source = "fn sum(values: []i32) returns i32 {\n  let total: i32 = 0\n  let i: i32 = 0\n  while (i < values.len) {\n    total = total + values[i]\n    i = i + 1\n  }\n  return total\n}\n\nfn upper(b: []u8) returns i32 {\n  let i: i32 = 0\n  while (i < b.len) {\n    if (b[i] >= 97) { b[i] = b[i] - 32 }\n    i = i + 1\n  }\n  return b.len\n}\n\nfn main() returns i32 {\n  let squares: []i32 = []i32 { ptr: 4096, len: 3 }\n  squares[0] = 1\n  squares[1] = 4\n  squares[2] = 9\n  write_bytes(1, cstr_bytes(\"> \"))\n  let line: []u8 = []u8 { ptr: 1024, len: 64 }\n  let n: i32 = read_bytes(0, line)\n  write_bytes(1, []u8 { ptr: line.ptr, len: upper([]u8 { ptr: line.ptr, len: n }) })\n  if (__get_argc() > 1) { return squares[3] }\n  return sum(squares)\n}\n";
+    let output = compile_source(source, "slices", &["--emit=ir"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let ir = fs::read_to_string(env::temp_dir().join("coatl-test-slices").join("slices.s")).unwrap();
+    assert!(ir.contains("(param values []i32)") && ir.contains("(struct_lit []u8 (field line ptr) (ident n))"), "{}", ir);
+    assert!(ir.contains("(slice_assign b (ident i) (binary sub (slice_index b (ident i)"), "{}", ir);
+
+    let output = compile_source("fn main() returns i32 {\n  let n: i32 = 3\n  let w: []i64 = []i64 { ptr: 0, len: 1 }\n  return n[0] + w[0]\n}\n", "slices-bad", &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("struct literal of unknown struct `[]i64`"), "{}", stderr);
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let src = env::temp_dir().join("coatl-test-slices").join("slices.coatl");
+    let bin = build_bin(src.to_str().unwrap(), "slices", "x86_64").expect("Build slices failed");
+    use std::io::Write;
+    let run = |args: &[&str]| {
+        let mut child = Command::new(&bin).args(args).stdin(std::process::Stdio::piped()).stdout(std::process::Stdio::piped()).stderr(std::process::Stdio::piped()).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(b"hello, world\n").unwrap();
+        child.wait_with_output().unwrap()
+    };
+    let out = run(&[]);
+    assert_rc(14, out.status.code().unwrap_or(-1), "slices");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "> HELLO, WORLD\n");
+    let out = run(&["x"]);
+    assert_rc(101, out.status.code().unwrap_or(-1), "slices out of bounds");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("index out of bounds of slice `squares`"), "{}", String::from_utf8_lossy(&out.stderr));
+}
+
+#[test]
+fn test_ranges() {
+    let source = "fn sum(values: []i32) returns i32 {\n  let total: i32 = 0\n  for i in 0..values.len {\n    total = total + values[i]\n  }\n  return total\n}\n\nfn main() returns i32 {\n  let squares: []i32 = []i32 { ptr: 4096, len: 6 }\n  for i in 0..6 { squares[i] = i * i }\n  let middle: range = 2..5\n  let total: i32 = 0\n  for k in middle { total = total + k }\n  let word: []u8 = cstr_bytes(\"hello, world\\n\")\n  write_bytes(1, word[7..13])\n  if (range_contains(middle, 5)) { return 100 }\n  if (__get_argc() > 1) { return sum(squares[4..7]) }\n  return sum(squares[1..4]) + total * 10 + range_len(middle)\n}\n";
+    let output = compile_source(source, "ranges", &["--emit=ir"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let ir = fs::read_to_string(env::temp_dir().join("coatl-test-ranges").join("ranges.s")).unwrap();
+    assert!(ir.contains("(let middle range (struct_lit range (int 2) (int 5)))"), "{}", ir);
+    assert!(ir.contains("(slice_range word (struct_lit range (int 7) (int 13))"), "{}", ir);
+    // A literal range keeps its end in an i32 local; a range variable is read through its fields.
+    assert!(ir.contains("(let i i32 (int 0)) (while (binary lt (ident i) (ident __for"), "{}", ir);
+    assert!(ir.contains("(let k i32 (field __for"), "{}", ir);
+
+    let bad = "fn main() returns i32 {\n  let b: []u8 = []u8 { ptr: 0, len: 4 }\n  b[0..2] = 1\n  return 0\n}\n";
+    let output = compile_source(bad, "ranges-bad", &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("cannot assign to a range of slice `b`; assign its elements one by one"), "{}", stderr);
+
+    let bad = "struct S { a: i32 }\n\nfn main() returns i32 {\n  let s: S = S { a: 1 }\n  for i in 5 { }\n  for j in s { }\n  return 0\n}\n";
+    let output = compile_source(bad, "ranges-for-bad", &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("`for` loops over a range, not `i32`"), "{}", stderr);
+    assert!(stderr.contains("`for` loops over a range, not `S`"), "{}", stderr);
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let src = env::temp_dir().join("coatl-test-ranges").join("ranges.coatl");
+    let bin = build_bin(src.to_str().unwrap(), "ranges", "x86_64").expect("Build ranges failed");
+    let out = Command::new(&bin).output().unwrap();
+    assert_rc(107, out.status.code().unwrap_or(-1), "ranges");
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "world\n");
+    let out = Command::new(&bin).arg("x").output().unwrap();
+    assert_rc(101, out.status.code().unwrap_or(-1), "ranges out of bounds");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("range out of bounds of slice `squares`"), "{}", String::from_utf8_lossy(&out.stderr));
+}
+
+#[test]
+fn test_suggestions() {
+    let source = "struct Point { x: i32, y: i32 }\nfn main() returns i32 {\n  let count: i32 = 1\n  let p: Point = Point { x: 1, y: 2 }\n  let q: Point = Pont { x: 3, y: 4 }\n  return cuont + p.yy + p.z\n}\n";
+    let output = compile_source(source, "suggestions", &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("error: struct literal of unknown struct `Pont`; did you mean `Point`?"), "{}", stderr);
+
+    let output = compile_source(&source.replace("Pont", "Point"), "suggestions-fields", &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("use of undeclared variable `cuont`; did you mean `count`?"), "{}", stderr);
+    assert!(stderr.contains("struct `Point` has no field `yy`; did you mean `y`?"), "{}", stderr);
+    // A one-letter name is not corrected into another one.
+    assert!(stderr.contains("struct `Point` has no field `z`\n"), "{}", stderr);
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let output = compile_source("fn main() returns i32 {\n  prnt(\"x\")\n  return 0\n}\n", "suggestions-call", &["--emit=exe"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("error: unknown function `prnt`; did you mean `print`?"), "{}", stderr);
+}
+
+#[test]
+fn test_tags() {
+    let tmp_dir = env::temp_dir().join("coatl-test-tags");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    fs::create_dir_all(tmp_dir.join("lib")).unwrap();
+    fs::write(tmp_dir.join("main.coatl"), "import \"lib/geo\"\n\nextern fn puts(s: i32) returns i32\n\nstruct Config {\n  retries: i32 = 3,\n  origin: geo::Point = geo::Point { x: 0, y: 0 }\n}\n\npub fn main() returns i32 {\n  return undefined_yet()\n}\n").unwrap();
+    fs::write(tmp_dir.join("lib").join("geo.coatl"), "struct Point { x: i32, y: i32 }\n\nconst fn area(w: i32, h: i32) returns i32 {\n  return w * h\n}\n").unwrap();
+
+    let status = Command::new(get_coatl_bin()).arg("tags").current_dir(&tmp_dir).status().unwrap();
+    assert!(status.success());
+    let tags = fs::read_to_string(tmp_dir.join("tags")).unwrap();
+    assert!(tags.starts_with("!_TAG_FILE_FORMAT\t2\t/extended format/\n!_TAG_FILE_SORTED\t1\t"), "{}", tags);
+    let entries: Vec<&str> = tags.lines().filter(|l| !l.starts_with("!_")).collect();
+    assert_eq!(entries, [
+        "Config\tmain.coatl\t5;\"\ts",
+        "Point\tlib/geo.coatl\t1;\"\ts",
+        "area\tlib/geo.coatl\t3;\"\tf",
+        "main\tmain.coatl\t10;\"\tf",
+        "origin\tmain.coatl\t7;\"\tm\tstruct:Config",
+        "retries\tmain.coatl\t6;\"\tm\tstruct:Config",
+        "x\tlib/geo.coatl\t1;\"\tm\tstruct:Point",
+        "y\tlib/geo.coatl\t1;\"\tm\tstruct:Point",
+    ]);
+
+    let out = Command::new(get_coatl_bin()).args(["tags", "--format=json", "-o", "-", "lib/geo.coatl"]).current_dir(&tmp_dir).output().unwrap();
+    let json = String::from_utf8_lossy(&out.stdout);
+    assert!(json.starts_with("[\n  { \"name\": \"Point\", \"kind\": \"struct\", \"file\": \"lib/geo.coatl\", \"line\": 1 },"), "{}", json);
+    assert!(json.contains("{ \"name\": \"x\", \"kind\": \"field\", \"file\": \"lib/geo.coatl\", \"line\": 1, \"struct\": \"Point\" }"), "{}", json);
+}
+
+#[test]
+fn test_doc() {
+    let tmp_dir = env::temp_dir().join("coatl-test-doc");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    fs::create_dir_all(&tmp_dir).unwrap();
+    let src = tmp_dir.join("geom.coatl");
+    fs::write(&src, "/// A point on the grid.\n///\n/// Both coordinates are in cells.\nstruct Point {\n  x: i32,\n  y: i32\n}\n\n/// Adds <a> and `b`.\npub fn add(a: i32, b: i32) returns i32 {\n  return a + b\n}\n\n// not documented\nfn main() returns i32 {\n  return add(3, 4)\n}\n").unwrap();
+
+    let status = Command::new(get_coatl_bin()).arg("doc").arg("geom.coatl").current_dir(&tmp_dir).status().unwrap();
+    assert!(status.success());
+    let md = fs::read_to_string(tmp_dir.join("doc").join("geom.md")).unwrap();
+    assert!(md.starts_with("# Module `geom`\n\n## Structs\n\n### `Point`\n\n```coatl\nstruct Point {\n    x: i32,\n    y: i32\n}\n```\n\nA point on the grid.\n\nBoth coordinates are in cells.\n"), "{}", md);
+    assert!(md.contains("```coatl\npub fn add(a: i32, b: i32) returns i32\n```\n\nAdds <a> and `b`.\n"), "{}", md);
+    assert!(md.ends_with("```coatl\nfn main() returns i32\n```\n"), "{}", md);
+
+    let status = Command::new(get_coatl_bin()).args(["doc", "--format=html", "-o", "api", "geom.coatl"]).current_dir(&tmp_dir).status().unwrap();
+    assert!(status.success());
+    let html = fs::read_to_string(tmp_dir.join("api").join("geom.html")).unwrap();
+    assert!(html.contains("<p>A point on the grid.</p>\n<p>Both coordinates are in cells.</p>"), "{}", html);
+    assert!(html.contains("<p>Adds &lt;a&gt; and `b`.</p>"), "{}", html);
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    // Doc comments do not change the compiled program.
+    let bin = build_bin(src.to_str().unwrap(), "geom", "x86_64").expect("Build geom failed");
+    let status = Command::new(&bin).status().unwrap();
+    assert_rc(7, status.code().unwrap_or(-1), "geom");
+}
+
+#[test]
+fn test_attributes() {
+    let src = "#[repr(C)]\nstruct Pair { a: i32, b: i32 }\n\n#[inline]\n#[export(name = \"add_two\")]\nfn add(a: i32, b: i32) returns i32 { return a + b }\n\n#[inlin]\nfn sub(a: i32, b: i32) returns i32 { return a - b }\n\nfn main() returns i32 {\n  #[test]\n  let v: i32 = add(2, 3)\n  return sub(v, 1)\n}\n";
+    let out = compile_source(src, "attributes", &["--emit=ir"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("attributes.coatl:8:3: unknown attribute `inlin` is ignored; did you mean `inline`?"), "{}", stderr);
+    assert!(stderr.contains("attributes.coatl:13:3: attribute `test` only applies to functions and structs and is ignored"), "{}", stderr);
+    let ir = fs::read_to_string(env::temp_dir().join("coatl-test-attributes").join("attributes.s")).unwrap();
+    assert!(ir.contains("(attr repr C)"), "{}", ir);
+    assert!(ir.contains("(attr inline) (attr export (name add_two))"), "{}", ir);
+    assert!(!ir.contains("inlin)"), "{}", ir);
+    let out = compile_source("#[inline(a", "attributes-unterminated", &["--emit=asm"]);
+    assert_rc(3, out.status.code().unwrap_or(-1), "attributes-unterminated");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Unterminated attribute arguments of `inline` at 1:3"), "{}", String::from_utf8_lossy(&out.stderr));
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let src_path = env::temp_dir().join("coatl-test-attributes").join("attributes.coatl");
+    let bin = build_bin(src_path.to_str().unwrap(), "attributes", "x86_64").expect("Build attributes failed");
+    let status = Command::new(&bin).status().unwrap();
+    assert_rc(4, status.code().unwrap_or(-1), "attributes");
+}
+
+#[test]
+fn test_export_name() {
+    let tmp_dir = env::temp_dir().join("coatl-test-export-name");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    fs::create_dir_all(tmp_dir.join("lib")).unwrap();
+    fs::write(tmp_dir.join("lib").join("geo.coatl"), "#[no_mangle]\npub fn area(w: i32, h: i32) returns i32 { return w * h }\n\n#[export_name = \"geo_perimeter\"]\nfn perimeter(w: i32, h: i32) returns i32 { return 2 * (w + h) }\n").unwrap();
+    let src = tmp_dir.join("main.coatl");
+    fs::write(&src, "import \"lib/geo\"\n\n#[export_name = \"host_callback\"]\nfn cb(x: i32) returns i32 { return x + 1 }\n\nfn main() returns i32 {\n  return geo::area(2, 3) + geo::perimeter(1, 1) + cb(0)\n}\n").unwrap();
+
+    for arch in ["x86_64", "aarch64"] {
+        let out = Command::new(get_coatl_bin()).arg(&src).args([&format!("--arch={}", arch), "--emit=asm"]).output().unwrap();
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+        let asm = String::from_utf8_lossy(&out.stdout);
+        for sym in ["area", "geo_perimeter", "host_callback"] {
+            assert!(asm.contains(&format!(".global {}\n{}:", sym, sym)), "{}: {}", arch, sym);
+        }
+        assert!(!asm.contains("geo__area") && !asm.contains("geo__perimeter"), "{}", arch);
+    }
+
+    let out = compile_source("extern fn puts(s: i32) returns i32\n\n#[export_name = \"puts\"]\nfn a() returns i32 { return 1 }\n\nfn main() returns i32 { return a() }\n", "export-name-taken", &[]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("symbol `puts` of `a` is already defined by another function or extern"), "{}", String::from_utf8_lossy(&out.stderr));
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let bin = build_bin(src.to_str().unwrap(), "export-name", "x86_64").expect("Build export-name failed");
+    let status = Command::new(&bin).status().unwrap();
+    assert_rc(11, status.code().unwrap_or(-1), "export-name");
+}
+
+#[test]
+fn test_sanitize_memory() {
+    let src = "fn main() returns i32 {\n  __mem_store(100, 7)\n  let b: []u8 = cstr_bytes(\"hello\")\n  let x: i32 = __mem_load(100) + b[1]\n  if (__get_argc() == 2) {\n    __mem_store8(65536, 1)\n  }\n  if (__get_argc() == 3) {\n    b[0] = 88\n  }\n  if (__get_argc() == 4) {\n    let y: i32 = __mem_load(1048574)\n  }\n  return x\n}\n";
+    let out = compile_source(src, "sanitize", &["--sanitize=memory", "--emit=ir"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let tmp_dir = env::temp_dir().join("coatl-test-sanitize");
+    let src_path = tmp_dir.join("sanitize.coatl");
+    let bin = tmp_dir.join("sanitize");
+    let status = Command::new(get_coatl_bin()).arg(&src_path).args(["--sanitize=memory", "-o"]).arg(&bin).status().unwrap();
+    assert!(status.success());
+
+    let out = Command::new(&bin).output().unwrap();
+    assert_rc(108, out.status.code().unwrap_or(-1), "sanitize in bounds");
+    for (args, expected) in [
+        (&["a"][..], "panic at {}:6:5: memory sanitizer: write of 1 byte(s) by `__mem_store8` outside the user data region [0, 65536)"),
+        (&["a", "b"][..], "panic at {}:9:5: memory sanitizer: write of an element of slice `b` outside the user data region [0, 65536)"),
+        (&["a", "b", "c"][..], "panic at {}:12:18: memory sanitizer: read of 4 byte(s) by `__mem_load` outside __coatl_mem [0, 1048576)"),
+    ] {
+        let out = Command::new(&bin).args(args).output().unwrap();
+        assert_rc(101, out.status.code().unwrap_or(-1), "sanitize out of bounds");
+        assert_eq!(String::from_utf8_lossy(&out.stderr).trim_end(), expected.replace("{}", &src_path.display().to_string()));
+    }
+
+    // Without the flag the same stores go through unchecked.
+    let plain = build_bin(src_path.to_str().unwrap(), "sanitize-off", "x86_64").expect("Build sanitize-off failed");
+    let status = Command::new(&plain).arg("a").status().unwrap();
+    assert_rc(108, status.code().unwrap_or(-1), "sanitize off");
+
+    let out = compile_source(src, "sanitize-no-runtime", &["--sanitize=memory", "--no-runtime"]);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn test_definite_assignment() {
+    let ok = "struct P { x: i32, y: i32 }\n\nfn pick(c: i32) returns i32 {\n  let a: i32\n  if (c > 0) { a = 10 } else { a = 20 }\n  let p: P\n  p.x = a\n  p.y = 2\n  let m: i32\n  match c { 1 => { m = 5 }, _ => { panic(\"bad\") } }\n  return p.x + p.y + m\n}\n\nfn main() returns i32 {\n  let n: i32\n  n = pick(1)\n  return n\n}\n";
+    let out = compile_source(ok, "definite", &[]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+
+    let bad = "struct P { x: i32, y: i32 }\n\nfn g(p: P) returns i32 { return p.x }\n\nfn main() returns i32 {\n  let c: i32 = 1\n  let b: i32\n  if (c > 0) { b = 1 }\n  let d: i32\n  while (c > 5) { d = 1 }\n  let p: P\n  p.x = 3\n  let x: i32 = 4\n  if (c == 1) {\n    let x: i32\n  }\n  return b + d + p.x + p.y + g(p) + x\n}\n";
+    let out = compile_source(bad, "definite-bad", &[]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let reported: Vec<&str> = stderr.lines().filter(|l| l.contains("definitely assigned")).collect();
+    assert_eq!(reported.len(), 4, "{}", stderr);
+    for what in ["`b`", "`d`", "field `p.y`", "`p`"] {
+        assert!(stderr.contains(&format!("error: in fn main: {} is read before it is definitely assigned", what)), "{}: {}", what, stderr);
+    }
+
+    // A struct that contains itself is reported, not expanded field by field forever.
+    let out = compile_source("struct A { a: A }\n\nfn main() returns i32 {\n  let x: A\n  let y: A = x\n  return 0\n}\n", "definite-recursive", &[]);
+    assert_rc(4, out.status.code().unwrap_or(-1), "definite-recursive");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("struct `A` contains itself"), "{}", stderr);
+    assert!(stderr.contains("`x` is read before it is definitely assigned"), "{}", stderr);
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let src_path = env::temp_dir().join("coatl-test-definite").join("definite.coatl");
+    let bin = build_bin(src_path.to_str().unwrap(), "definite", "x86_64").expect("Build definite failed");
+    let status = Command::new(&bin).status().unwrap();
+    assert_rc(17, status.code().unwrap_or(-1), "definite");
+}
+
+#[test]
+fn test_stdin_source() {
+    use std::io::Write;
+    use std::process::Stdio;
+    let tmp_dir = env::temp_dir().join("coatl-test-stdin");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    fs::create_dir_all(tmp_dir.join("lib")).unwrap();
+    fs::write(tmp_dir.join("lib").join("geo.coatl"), "fn area(w: i32, h: i32) returns i32 { return w * h }\n").unwrap();
+    let run = |args: &[&str], src: &str| {
+        let mut child = Command::new(get_coatl_bin()).arg("-").args(args).current_dir(&tmp_dir)
+            .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(src.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    };
+    let src = "import \"lib/geo\"\n\nfn main() returns i32 {\n  if (__get_argc() > 1) { panic(\"boom\") }\n  return geo::area(2, 3)\n}\n";
+
+    let out = run(&["--emit=ir"], src);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("(coatl_ir v1"));
+    let out = run(&["-o", "-"], src);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("geo__area:"));
+
+    let out = run(&["-o", "stdin-bin"], src);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let status = Command::new(tmp_dir.join("stdin-bin")).status().unwrap();
+    assert_rc(6, status.code().unwrap_or(-1), "stdin");
+    let out = Command::new(tmp_dir.join("stdin-bin")).arg("x").output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&out.stderr).trim_end(), "panic at <stdin>:4:27: boom");
+}
+
+#[test]
+fn test_out_dir() {
+    let out = compile_source("fn main() returns i32 {\n  return 9\n}\n", "outdir", &[]);
+    assert!(out.status.success());
+    let tmp_dir = env::temp_dir().join("coatl-test-outdir");
+    let build = tmp_dir.join("build");
+    let _ = fs::remove_dir_all(&build);
+    let out = Command::new(get_coatl_bin()).current_dir(&tmp_dir).args(["outdir.coatl", "--emit=asm,ir,obj,exe", "--out-dir", "build"]).output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(fs::read_to_string(build.join("outdir.s")).unwrap().contains("main:"));
+    assert!(fs::read_to_string(build.join("outdir.ir")).unwrap().starts_with("(coatl_ir v1"));
+    assert!(build.join("outdir.o").exists());
+    let status = Command::new(build.join("outdir")).status().unwrap();
+    assert_rc(9, status.code().unwrap_or(-1), "out-dir exe");
+
+    let out = Command::new(get_coatl_bin()).current_dir(&tmp_dir).args(["outdir.coatl", "--emit=asm,ir"]).output().unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("needs --out-dir"));
+    let out = Command::new(get_coatl_bin()).current_dir(&tmp_dir).args(["outdir.coatl", "--emit=so,obj", "--out-dir=build"]).output().unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("cannot be combined with asm, obj or exe"));
+}
+
+#[test]
+fn test_exit_codes_and_summary() {
+    let tmp_dir = env::temp_dir().join("coatl-test-summary");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    fs::create_dir_all(&tmp_dir).unwrap();
+    let coatl = |src: &str, args: &[&str]| {
+        fs::write(tmp_dir.join("main.coatl"), src).unwrap();
+        Command::new(get_coatl_bin()).current_dir(&tmp_dir).arg("main.coatl").args(args).output().unwrap()
+    };
+    let ok = "#[frobnicate]\nfn main() returns i32 {\n  return 0\n}\n";
+
+    let out = coatl(ok, &["--emit=asm,exe", "--out-dir=build", "--summary=json"]);
+    assert_rc(0, out.status.code().unwrap_or(-1), "summary ok");
+    assert_eq!(String::from_utf8_lossy(&out.stdout).trim_end(), r#"{ "status": "ok", "exit_code": 0, "errors": 0, "warnings": 1, "artifacts": ["build/main.s", "build/main"] }"#);
+
+    let out = coatl(ok, &["--bogus"]);
+    assert_rc(2, out.status.code().unwrap_or(-1), "usage");
+    let out = coatl(ok, &["--summary=json"]);
+    assert_rc(2, out.status.code().unwrap_or(-1), "summary to stdout");
+    let out = coatl("fn main() returns i32 {\n  return (1\n}\n", &["-o", "main.s", "--summary=json"]);
+    assert_rc(3, out.status.code().unwrap_or(-1), "parse");
+    assert!(String::from_utf8_lossy(&out.stdout).contains(r#""status": "parse", "exit_code": 3, "errors": 1"#));
+    let out = coatl("fn main() returns i32 {\n  __fd_close()\n  return 0\n}\n", &["-o", "main.s", "--summary=json"]);
+    assert_rc(4, out.status.code().unwrap_or(-1), "type");
+    assert!(String::from_utf8_lossy(&out.stdout).contains(r#""status": "type", "exit_code": 4"#));
+    let out = coatl("fn main() returns i32 {\n  asm(\"bogus_instruction\")\n  return 0\n}\n", &["-o", "main"]);
+    assert_rc(5, out.status.code().unwrap_or(-1), "codegen");
+}
+
+/// `levels` macros, each passing its argument to the next plus `terms` ones, one per line,
+/// with `main` calling the first.
+fn macro_chain(levels: usize, terms: usize) -> String {
+    let mut src: String = (1..levels).map(|i| format!("macro m{}(x) {{ m{}!(x{}) }}\n", i, i + 1, " + 1".repeat(terms))).collect();
+    src += &format!("macro m{}(x) {{ let v: i32 = x }}\nfn main() returns i32 {{\n  let v: i32 = 0\n  m1!(v)\n  return 0\n}}\n", levels);
+    src
+}
+
+#[test]
+fn test_nesting_limit() {
+    let nested = |open: &str, inner: &str, close: &str, n: usize| format!("fn main() returns i32 {{\n  return {}{}{}\n}}\n", open.repeat(n), inner, close.repeat(n));
+    let out = compile_source(&nested("(", "1", ")", 200), "nesting_ok", &[]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    for (name, src) in [
+        ("nesting_parens", nested("(", "1", ")", 100_000)),
+        ("nesting_unary", nested("-", "1", "", 100_000)),
+        ("nesting_chain", nested("", &"1 + ".repeat(100_000), "1", 1)),
+        ("nesting_blocks", format!("fn main() returns i32 {{\n{}  return 0\n{}}}\n", "if true {\n".repeat(100_000), "}\n".repeat(100_000))),
+    ] {
+        let out = compile_source(&src, name, &[]);
+        assert_rc(3, out.status.code().unwrap_or(-1), name);
+        assert!(String::from_utf8_lossy(&out.stderr).contains("Nesting deeper than 256 levels"), "{}", name);
+    }
+    // Each macro passes its argument on inside a longer sum, so the expanded IR nests far
+    // deeper than any of the source text does.
+    let out = compile_source(&macro_chain(30, 200), "nesting_macros", &[]);
+    assert_rc(3, out.status.code().unwrap_or(-1), "nesting_macros");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("nesting_macros.coatl:1:15: Nesting deeper than 256 levels in the expansion of macro `m2`"), "{}", String::from_utf8_lossy(&out.stderr));
+}
+
+#[test]
+fn test_struct_repr() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-repr");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("repr.coatl");
+    // Unpacked, `len` would sit at offset 4 and the struct take 12 bytes.
+    fs::write(&src, "#[repr(packed)]\nstruct Header {\n  tag: u8,\n  len: i32,\n  flags: u8,\n  kind: u8\n}\nfn main() returns i32 {\n  let h: Header = Header { tag: 1, len: 300, flags: 2, kind: 3 }\n  h.len = h.len - 290\n  h.tag = 5\n  return h.tag + h.len + h.flags * h.kind\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "repr", "x86_64").expect("Build repr failed");
+    let status = Command::new(&bin).status().unwrap();
+    assert_rc(21, status.code().unwrap_or(-1), "repr packed");
+
+    let out = compile_source("#[repr(align(8))]\nstruct Wide { a: u8 }\nstruct Holder {\n  tag: u8,\n  w: Wide\n}\nfn main() returns i32 {\n  return 0\n}\n", "repr-align", &[]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("struct `Holder` is 16 bytes"), "{}", String::from_utf8_lossy(&out.stderr));
+    for (name, repr, message) in [
+        ("repr-odd", "align(3)", "`#[repr(align(3))]` needs a power of two"),
+        ("repr-both", "packed, align(4)", "cannot be both `#[repr(packed)]` and `#[repr(align(N))]`"),
+        ("repr-unknown", "transparent", "unknown repr `transparent`"),
+    ] {
+        let out = compile_source(&format!("#[repr({})]\nstruct S {{ a: i32 }}\nfn main() returns i32 {{\n  return 0\n}}\n", repr), name, &[]);
+        assert_rc(4, out.status.code().unwrap_or(-1), name);
+        assert!(String::from_utf8_lossy(&out.stderr).contains(message), "{}", String::from_utf8_lossy(&out.stderr));
+    }
+}
+
+#[test]
+fn test_enum_values() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-enum");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("enum.coatl");
+    // NoEnt is matched by its arm; Next follows NoEnt as 45.
+    fs::write(&src, "enum Errno { Success = 0, Badf = 8, NoEnt = 44, Next }\nfn check(e: Errno) returns i32 {\n  match e {\n    Errno::Success => { return 0 }\n    Errno::NoEnt => { return 1 }\n    _ => { return 2 }\n  }\n  return 3\n}\nfn main() returns i32 {\n  let e: Errno = Errno::NoEnt\n  return check(e) + Errno::Next + Errno::Badf\n}\n").unwrap();
+    let bin = build_bin(src.to_str().unwrap(), "enum", "x86_64").expect("Build enum failed");
+    let status = Command::new(&bin).status().unwrap();
+    assert_rc(54, status.code().unwrap_or(-1), "enum values");
+
+    let out = compile_source("enum E { Alpha, Beta }\nfn main() returns i32 {\n  return E::Betta\n}\n", "enum-unknown", &[]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("enum `E` has no variant `Betta`"), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Beta"), "{}", String::from_utf8_lossy(&out.stderr));
+}
+
+#[test]
+fn test_backtrace() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-backtrace");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("bt.coatl");
+    // `middle` has returned from its first call to `inner`, so only one `inner` frame remains.
+    fs::write(&src, "fn inner(n: i32) returns i32 {\n  if n > 2 {\n    panic(\"too deep\")\n  }\n  return n\n}\nfn middle(n: i32) returns i32 {\n  let a: i32 = inner(1)\n  return inner(n) + a\n}\nfn main() returns i32 {\n  return middle(5)\n}\n").unwrap();
+    let bin = tmp_dir.join("bt");
+    let status = Command::new(get_coatl_bin()).current_dir(&tmp_dir).arg("bt.coatl").arg("-o").arg(&bin).arg("--backtrace").status().unwrap();
+    assert!(status.success());
+    let out = Command::new(&bin).output().unwrap();
+    assert_rc(101, out.status.code().unwrap_or(-1), "backtrace panic");
+    assert_eq!(String::from_utf8_lossy(&out.stderr), "panic at bt.coatl:3:5: too deep\nstack backtrace:\n  0: inner\n  1: middle\n  2: main\n");
+
+    // Without --backtrace the panic message stands alone.
+    let status = Command::new(get_coatl_bin()).current_dir(&tmp_dir).arg("bt.coatl").arg("-o").arg(&bin).status().unwrap();
+    assert!(status.success());
+    let out = Command::new(&bin).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&out.stderr), "panic at bt.coatl:3:5: too deep\n");
+
+    // A stack overflow keeps only the innermost 64 frames.
+    fs::write(&src, "fn r(n: i32) returns i32 {\n  return r(n + 1) + 1\n}\nfn main() returns i32 {\n  return r(0)\n}\n").unwrap();
+    let status = Command::new(get_coatl_bin()).arg(&src).arg("-o").arg(&bin).arg("--backtrace").arg("--stack-limit=1M").status().unwrap();
+    assert!(status.success());
+    let out = Command::new(&bin).output().unwrap();
+    assert_rc(134, out.status.code().unwrap_or(-1), "backtrace overflow");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.starts_with("coatl: stack overflow\nstack backtrace:\n  0: r\n"), "{}", stderr);
+    assert!(stderr.ends_with("  63: r\n  ...\n"), "{}", stderr);
+}
+
+#[test]
+fn test_breakpoint() {
+    let src = "fn main() returns i32 {\n  let x: i32 = 4\n  __breakpoint()\n  return x\n}\n";
+    for (name, arch, trap) in [("breakpoint", "x86_64", "  int3"), ("breakpoint-aarch64", "aarch64", "mov x8, #172; svc #0; mov x1, #5; mov x8, #129; svc #0")] {
+        let out = compile_source(src, name, &["--emit=asm", &format!("--arch={}", arch)]);
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+        let asm = fs::read_to_string(env::temp_dir().join(format!("coatl-test-{0}/{0}.s", name))).unwrap();
+        assert!(asm.contains(trap), "{}", asm);
+    }
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    // Nothing is attached to catch the trap, so the kernel kills the program with SIGTRAP.
+    use std::os::unix::process::ExitStatusExt;
+    let tmp_dir = env::temp_dir().join("coatl-test-breakpoint");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let path = tmp_dir.join("bp.coatl");
+    fs::write(&path, src).unwrap();
+    let bin = build_bin(path.to_str().unwrap(), "bp", "x86_64").expect("Build breakpoint failed");
+    let status = Command::new(&bin).status().unwrap();
+    assert_eq!(status.signal(), Some(5), "{:?}", status);
+}
+
+#[test]
+fn test_emit_cfg_dot() {
+    let src = "fn classify(n: i32) returns i32 {\n  let i: i32 = 0\n  while i < n {\n    i = i + 1\n  }\n  match n {\n    1 => { return 10 }\n    _ => { panic(\"bad\") }\n  }\n  return 0\n}\nfn main() returns i32 {\n  return classify(1)\n}\n";
+    let out = compile_source(src, "cfg", &["--emit=cfg-dot"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let dot = fs::read_to_string(env::temp_dir().join("coatl-test-cfg/cfg.s")).unwrap();
+    let classify = &dot[dot.find("digraph \"classify\" {").expect("no graph for classify")..];
+    let classify = &classify[..classify.find("}\n").unwrap()];
+    for line in [
+        "b0 [label=\"entry\\l(let i i32 (int 0))\\l\"];",
+        "b2 [label=\"b2\\lwhile (binary lt (ident i) (ident n) bool)\\l\"];",
+        "b2 -> b3 [label=\"true\"];",
+        "b2 -> b4 [label=\"false\"];",
+        "b3 -> b2;",
+        "b4 -> b6 [label=\"1\"];",
+        "b4 -> b7 [label=\"_\"];",
+        "b6 -> b1;",
+    ] {
+        assert!(classify.contains(line), "missing `{}` in\n{}", line, classify);
+    }
+    // The `_` arm panics, so its block has no successor.
+    assert!(!classify.contains("b7 ->"), "{}", classify);
+    assert!(dot.contains("digraph \"main\" {"), "{}", dot);
+
+    // In --out-dir every function has its own file.
+    let out_dir = env::temp_dir().join("coatl-test-cfg").join("graphs");
+    let _ = fs::remove_dir_all(&out_dir);
+    let status = Command::new(get_coatl_bin()).arg(env::temp_dir().join("coatl-test-cfg/cfg.coatl")).arg("--emit=cfg-dot,ir").arg("--out-dir").arg(&out_dir).status().unwrap();
+    assert!(status.success());
+    assert!(fs::read_to_string(out_dir.join("classify.dot")).unwrap().starts_with("digraph \"classify\" {"));
+    assert!(fs::read_to_string(out_dir.join("main.dot")).unwrap().starts_with("digraph \"main\" {"));
+    assert!(out_dir.join("cfg.ir").exists() && !out_dir.join("cfg.dot").exists());
+}
+
+#[test]
+fn test_emit_callgraph() {
+    let tmp_dir = env::temp_dir().join("coatl-test-callgraph");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("calls.coatl");
+    fs::write(&src, "extern fn abs(x: i32) returns i32\nfn sq(x: i32) returns i32 {\n  return x * x\n}\nfn helper(x: i32) returns i32 {\n  return sq(x) + abs(x) + sq(1)\n}\nfn peek() returns i32 {\n  return __mem_load(0)\n}\nfn main() returns i32 {\n  return helper(2)\n}\n").unwrap();
+    let out_dir = tmp_dir.join("out");
+    let _ = fs::remove_dir_all(&out_dir);
+    let status = Command::new(get_coatl_bin()).arg(&src).arg("--emit=callgraph,callgraph-json").arg("--out-dir").arg(&out_dir).status().unwrap();
+    assert!(status.success());
+
+    let json = fs::read_to_string(out_dir.join("calls.callgraph.json")).unwrap();
+    for entry in [
+        "{ \"name\": \"sq\", \"calls\": [], \"intrinsics\": [], \"externs\": [] }",
+        "{ \"name\": \"helper\", \"calls\": [\"sq\"], \"intrinsics\": [], \"externs\": [\"abs\"] }",
+        "{ \"name\": \"peek\", \"calls\": [], \"intrinsics\": [\"__mem_load\"], \"externs\": [] }",
+        "{ \"name\": \"main\", \"calls\": [\"helper\"], \"intrinsics\": [], \"externs\": [] }",
+    ] {
+        assert!(json.contains(entry), "missing `{}` in\n{}", entry, json);
+    }
+    let dot = fs::read_to_string(out_dir.join("calls.callgraph.dot")).unwrap();
+    for line in ["\"main\" -> \"helper\";", "\"helper\" -> \"abs\" [style=dashed];", "\"__mem_load\" [shape=ellipse];"] {
+        assert!(dot.contains(line), "missing `{}` in\n{}", line, dot);
+    }
+}
+
+#[test]
+fn test_stack_report() {
+    // `leaf` takes eight arguments, so `mid` pushes two of them on the stack for the call.
+    let src = "fn leaf(a: i32, b: i32, c: i32, d: i32, e: i32, f: i32, g: i32, h: i32) returns i32 {\n  return a + b + c + d + e + f + g + h\n}\nfn mid(x: i32) returns i32 {\n  return leaf(x, 1, 2, 3, 4, 5, 6, 7)\n}\nfn fact(n: i32) returns i32 {\n  if n < 2 {\n    return 1\n  }\n  return n * fact(n - 1)\n}\nfn main() returns i32 {\n  return mid(1)\n}\n";
+    let out = compile_source(src, "stack-report", &["--stack-report"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let row = |name: &str| stderr.lines().find(|l| l.split_whitespace().next() == Some(name)).unwrap_or_else(|| panic!("no row for {} in\n{}", name, stderr)).split_whitespace().skip(1).map(|c| c.to_string()).collect::<Vec<_>>();
+    let (leaf, mid, main) = (row("leaf"), row("mid"), row("main"));
+    let frame = |r: &[String]| r[0].parse::<u32>().unwrap();
+    assert!(frame(&leaf) >= 4112 && frame(&mid) > frame(&leaf), "{}", stderr);
+    assert_eq!(mid[1].parse::<u32>().unwrap(), frame(&mid) + frame(&leaf), "{}", stderr);
+    assert_eq!(main[1].parse::<u32>().unwrap(), frame(&main) + frame(&mid) + frame(&leaf), "{}", stderr);
+    assert_eq!(row("fact")[1], "recursive", "{}", stderr);
+    assert!(!stderr.contains("warning"), "{}", stderr);
+
+    let out = compile_source(src, "stack-report-limit", &["--stack-report=10K"]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("warning: the stack use of `fact` has no bound: it can reach a recursive call"), "{}", stderr);
+    assert!(stderr.contains(&format!("warning: `main` can use {} bytes of stack, over the limit of 10240", main[1])), "{}", stderr);
+    assert!(!stderr.contains("`mid` can use"), "{}", stderr);
+}
+
+#[test]
+fn test_size_report() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let src = "fn big(a: i32, b: i32, c: i32) returns i32 {\n  let d: i32 = a * b + c\n  while d > 100 {\n    d = d / 2 - a * c\n  }\n  return d * d + a - b * c\n}\nfn small() returns i32 {\n  return 1\n}\nfn main() returns i32 {\n  return big(1, 2, 3) + small()\n}\n";
+    let out = compile_source(src, "size-report", &["--size-report"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    let rows: Vec<&str> = stderr.lines().skip_while(|l| *l != "code size per function:").skip(1).map(|l| l.split_whitespace().next().unwrap()).collect();
+    assert_eq!(rows.last(), Some(&"total"), "{}", stderr);
+    let position = |name: &str| rows.iter().position(|r| *r == name).unwrap_or_else(|| panic!("no {} in\n{}", name, stderr));
+    assert!(position("big") < position("small"), "{}", stderr);
+}
+
+#[test]
+fn test_gc_functions() {
+    let src = "fn unused(x: i32) returns i32 {\n  return x * 3\n}\nfn helper(x: i32) returns i32 {\n  return x + 1\n}\nfn only_exported() returns i32 {\n  return 7\n}\npub fn api() returns i32 {\n  return only_exported()\n}\nfn main() returns i32 {\n  return helper(41)\n}\n";
+    let out = compile_source(src, "gc-functions", &["--emit=ir", "--gc-functions"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let ir = fs::read_to_string(env::temp_dir().join("coatl-test-gc-functions").join("gc-functions.s")).unwrap();
+    for kept in ["(fn helper", "(fn only_exported", "(fn api", "(fn main"] { assert!(ir.contains(kept), "{} missing from\n{}", kept, ir); }
+    assert!(!ir.contains("(fn unused"), "{}", ir);
+
+    let out = compile_source(src, "gc-functions", &["--emit=ir"]);
+    assert!(out.status.success());
+    assert!(fs::read_to_string(env::temp_dir().join("coatl-test-gc-functions").join("gc-functions.s")).unwrap().contains("(fn unused"));
+}
+
+#[test]
+fn test_import_memory() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let src = "fn main() returns i32 {\n  println(\"hi\")\n  return __mem_load(0)\n}\n";
+    let output = compile_source(src, "import-memory", &["--import-memory"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let tmp_dir = env::temp_dir().join("coatl-test-import-memory");
+    let asm = fs::read_to_string(tmp_dir.join("import-memory.s")).unwrap();
+    assert!(asm.contains(".globl __coatl_mem") && !asm.contains("__coatl_mem:"), "{}", asm);
+
+    // The host defines the memory, with a value already in it for the program to read.
+    let host = tmp_dir.join("host.c");
+    fs::write(&host, "unsigned int __coatl_mem[1 << 18] __attribute__((aligned(16))) = { 9 };\n").unwrap();
+    let bin = tmp_dir.join("import-memory");
+    let status = Command::new("cc").arg(tmp_dir.join("import-memory.s")).arg(&host).args(["-fPIE", "-pie", "-e", "coatl_start", "-o"]).arg(&bin).status().unwrap();
+    assert!(status.success());
+    let output = Command::new(&bin).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hi\n");
+    assert_rc(9, output.status.code().unwrap_or(-1), "import-memory");
+
+    let output = Command::new(get_coatl_bin()).arg(tmp_dir.join("import-memory.coatl")).args(["--import-memory", "-o"]).arg(&bin).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_export_prefix() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let src = "pub fn api(x: i32) returns i32 {\n  return helper(x)\n}\nfn helper(x: i32) returns i32 {\n  return x + 1\n}\n#[no_mangle]\npub fn raw() returns i32 {\n  return 1\n}\nfn main() returns i32 {\n  println(\"hi\")\n  return api(40) + raw()\n}\n";
+    let tmp_dir = env::temp_dir().join("coatl-test-export-prefix");
+    let _ = fs::remove_dir_all(&tmp_dir);
+    fs::create_dir_all(&tmp_dir).unwrap();
+    fs::write(tmp_dir.join("export-prefix.coatl"), src).unwrap();
+
+    // Two prefixed modules, each with its own `api`, `main`, memory pool and runtime, link
+    // into one host.
+    for (module, body) in [("a", "x + 1"), ("b", "x * 10")] {
+        let path = tmp_dir.join(format!("{}.coatl", module));
+        fs::write(&path, format!("pub fn api(x: i32) returns i32 {{\n  return {}\n}}\nfn main() returns i32 {{\n  println(\"{}\")\n  return api(1)\n}}\n", body, module)).unwrap();
+        let out = Command::new(get_coatl_bin()).arg(&path).arg(format!("--export-prefix={}_", module)).args(["--emit=obj", "-o"]).arg(tmp_dir.join(format!("{}.o", module))).output().unwrap();
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    }
+    let host = tmp_dir.join("host.c");
+    fs::write(&host, "int a_api(int);\nint b_api(int);\nint main(void) { return a_api(1) + b_api(2); }\n").unwrap();
+    let out = Command::new("cc").arg(&host).arg(tmp_dir.join("a.o")).arg(tmp_dir.join("b.o")).arg("-o").arg(tmp_dir.join("host")).output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_rc(22, Command::new(tmp_dir.join("host")).status().unwrap().code().unwrap_or(-1), "export-prefix-host");
+
+    let bin = tmp_dir.join("export-prefix");
+    let out = Command::new(get_coatl_bin()).arg(tmp_dir.join("export-prefix.coatl")).args(["--export-prefix=mee_", "-o"]).arg(&bin).output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_rc(42, Command::new(&bin).status().unwrap().code().unwrap_or(-1), "export-prefix");
+
+    let out = Command::new(get_coatl_bin()).arg(tmp_dir.join("export-prefix.coatl")).args(["--export-prefix=9x", "-o"]).arg(&bin).output().unwrap();
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[cfg(feature = "object")]
+#[test]
+fn test_emit_obj_builtin() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-emit-obj");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("obj.coatl");
+    fs::write(&src, "static PRIMES: [i32; 4] = [2, 3, 5, 7]\nfn dense(c: i32) returns i32 {\n  match c {\n    0 => { return 1 }\n    1 => { return 2 }\n    2 => { return 3 }\n    3 => { return 4 }\n  }\n  return 9\n}\nfn main() returns i32 {\n  println(\"built in\")\n  let i: i32 = 0\n  let sum: i32 = 0\n  while i < 4 {\n    sum = sum + PRIMES[i] * dense(i)\n    i = i + 1\n  }\n  return sum\n}\n").unwrap();
+    // No `$CC` is run to make the object, only to link it.
+    let obj = tmp_dir.join("obj.o");
+    let out = Command::new(get_coatl_bin()).arg(&src).args(["--emit=obj", "-o"]).arg(&obj).env("CC", "false").output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let bin = tmp_dir.join("obj");
+    let status = Command::new("cc").args(["-fPIE", "-pie", "-e", "coatl_start"]).arg(&obj).arg("-o").arg(&bin).status().unwrap();
+    assert!(status.success());
+    let run = Command::new(&bin).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "built in\n");
+    // 2*1 + 3*2 + 5*3 + 7*4
+    assert_rc(51, run.status.code().unwrap_or(-1), "emit-obj");
+}
+
+#[test]
+fn test_parse_and_check_hostile_input() {
+    let deep_types = format!("fn main() returns i32 {{\n  let p: {}i32 = 0\n  return 0\n}}\n", "*".repeat(100_000));
+    let deep_exprs = format!("fn main() returns i32 {{\n  return {}1\n}}\n", "-".repeat(100_000));
+    let deep_macros = macro_chain(30, 200);
+    let deep_cfg = format!("#[cfg({}target = \"x86_64\"{})]\nfn main() returns i32 {{\n  return 0\n}}\n", "not(".repeat(100_000), ")".repeat(100_000));
+    let inputs: Vec<(&[u8], &str)> = vec![
+        (b"fn main() returns i32 {\n  return \xff\n}\n", "not valid UTF-8"),
+        (b"fn main() returns i32 {\n  let s: str = \"abc", "Unterminated string"),
+        (b"fn main() returns i32 {\n  let s: str = \"\\x", "Invalid escape"),
+        (b"fn main() returns i32 {\n  let s: str = \"\\xzz\"\n  return 0\n}\n", "Invalid escape \\xzz at 2:17"),
+        (b"fn main() returns i32 {\n  return 1 \xc2\xa7 2\n}\n", "Unexpected token"),
+        (b"fn main() returns i32 {\n  return", "end of input"),
+        (b"import \"secrets\"\nfn main() returns i32 {\n  return 0\n}\n", "cannot import"),
+        (b"fn f(a: i32) returns i32 {\n  return a\n}\nfn main() returns i32 {\n  return f(1, 2)\n}\n", "expects 1 argument(s), got 2"),
+        (deep_types.as_bytes(), "Nesting deeper"),
+        (deep_exprs.as_bytes(), "Nesting deeper"),
+        (deep_cfg.as_bytes(), "Nesting deeper"),
+        (b"fn main() returns i32 {\n  return str_len()\n}\n", "str_len takes one argument"),
+        (b"struct A { a: A }\nfn main() returns i32 {\n  let x: A\n  let y: A = x\n  return 0\n}\n", "contains itself"),
+        (b"#[inline(a", "Unterminated attribute arguments"),
+        (deep_macros.as_bytes(), "Nesting deeper"),
+    ];
+    for (input, expected) in inputs {
+        let errors = coatl::parse_and_check(input).expect_err(expected);
+        assert!(errors.iter().any(|e| e.text().contains(expected)), "{:?} has no `{}`", errors, expected);
+    }
+    let ir = coatl::parse_and_check(b"fn main() returns i32 {\n  println(\"hi\")\n  return 0\n}\n").unwrap();
+    assert!(ir.to_ir().contains("(fn main"));
+}
+
+#[test]
+fn test_string_payloads_not_interned() {
+    fn payloads(n: &coatl::IRNode, out: &mut Vec<coatl::IRNode>) {
+        let Some(l) = n.as_list() else { return };
+        if l.len() == 2 && l[0].as_atom().is_some_and(|h| h == "string_typed" || h == "doc") { out.push(l[1].clone()); }
+        for child in l { payloads(child, out); }
+    }
+    let ir = coatl::parse_and_check(b"/// Says hello.\nfn main() returns i32 {\n  println(\"hello there\")\n  return 0\n}\n").unwrap();
+    let mut found = Vec::new();
+    payloads(&ir, &mut found);
+    // String literals and doc text are owned, not interned like names; the IR text is unchanged.
+    for text in ["hello there", "Says hello."] {
+        assert!(found.contains(&coatl::IRNode::Str(text.to_string())), "{:?}", found);
+    }
+    assert!(ir.to_ir().contains("(string_typed \"hello there\")"), "{}", ir.to_ir());
+}
+
+#[test]
+fn test_panic_hook() {
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-panic-hook");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("risky.coatl");
+    fs::write(&src, "pub fn risky(x: i32) returns i32 {\n  if x > 3 {\n    panic(\"too big\")\n  }\n  return x\n}\n").unwrap();
+    let status = Command::new(get_coatl_bin()).arg(&src).args(["--panic=hook", "-o"]).arg(tmp_dir.join("librisky.so")).status().unwrap();
+    assert!(status.success());
+    // The host recovers from each panic by jumping back out of the hook.
+    let host = tmp_dir.join("host.c");
+    fs::write(&host, concat!(
+        "#include <setjmp.h>\n#include <stdio.h>\n#include <string.h>\n",
+        "static jmp_buf env;\nstatic char caught[64];\nint risky(int);\n",
+        "void coatl_panic_hook(const char *msg, const char *loc) { snprintf(caught, sizeof caught, \"%s at %s\", msg, strrchr(loc, '/') + 1); longjmp(env, 1); }\n",
+        "int main(void) {\n  int total = 0;\n  for (int i = 1; i <= 5; i++) { if (setjmp(env) == 0) total += risky(i); else printf(\"caught: %s\\n\", caught); }\n  printf(\"%d\\n\", total);\n  return 0;\n}\n",
+    )).unwrap();
+    let status = Command::new("cc").arg(&host).arg("-L").arg(&tmp_dir).arg("-lrisky").arg("-o").arg(tmp_dir.join("host")).status().unwrap();
+    assert!(status.success());
+    let output = Command::new(tmp_dir.join("host")).env("LD_LIBRARY_PATH", &tmp_dir).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "caught: too big at risky.coatl:3:5\ncaught: too big at risky.coatl:3:5\n6\n");
+    assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_rc(0, output.status.code().unwrap_or(-1), "panic-hook");
+
+    let out = Command::new(get_coatl_bin()).arg(&src).args(["--panic=unwind", "-o"]).arg(tmp_dir.join("librisky.so")).output().unwrap();
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn test_try_operator() {
+    let src = concat!(
+        "fn check(n: i32) returns i32 {\n  if n > 2 {\n    return -5\n  }\n  return n\n}\n",
+        "fn run(limit: i32) returns i32 {\n  let i: i32 = 0\n  let total: i32 = 0\n  while i < limit {\n    let v: i32 = check(i)?\n    total = total + v\n    check(i)?\n    i = i + 1\n  }\n  total = check(total)?\n  return total + 10\n}\n",
+        "fn main() returns i32 {\n  print_int(run(2))\n  print_int(run(3))\n  print_int(run(5))\n  return 0\n}\n",
+    );
+    let tmp_dir = env::temp_dir().join("coatl-test-try");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src_path = tmp_dir.join("try.coatl");
+    fs::write(&src_path, src).unwrap();
+    let out = compile_source("fn ok() returns bool {\n  let v: i32 = f()?\n  return true\n}\n", "try-bool", &[]);
+    assert_eq!(out.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("`?` returns the error from a function returning bool, not i32 or i64, at 2:19"), "{}", String::from_utf8_lossy(&out.stderr));
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let bin = build_bin(src_path.to_str().unwrap(), "try", "x86_64").expect("build failed");
+    let output = Command::new(&bin).output().unwrap();
+    // run(3) sums to 3, which check rejects; run(5) fails inside the loop.
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "11-5-5");
+}
+
+#[test]
+fn test_string_builder() {
+    let src = concat!(
+        "fn main() returns i32 {\n  let sb: i32 = sb_new(1, 1000, 24)\n  let i: i32 = 0\n",
+        "  while i < 3 {\n    sb_push_str(sb, \"item \")\n    sb_push_int(sb, i * -1000)\n    sb_push_byte(sb, 10)\n    i = i + 1\n  }\n",
+        "  sb_push_str(sb, \"longer than the twelve byte buffer\\n\")\n  sb_push_int(sb, 2147483647)\n",
+        "  return sb_flush(sb)\n}\n",
+    );
+    let tmp_dir = env::temp_dir().join("coatl-test-string-builder");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src_path = tmp_dir.join("sb.coatl");
+    fs::write(&src_path, src).unwrap();
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let bin = build_bin(src_path.to_str().unwrap(), "string-builder", "x86_64").expect("build failed");
+    let output = Command::new(&bin).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "item 0\nitem -1000\nitem -2000\nlonger than the twelve byte buffer\n2147483647");
+    // The last flush writes the ten digits left in the buffer.
+    assert_rc(10, output.status.code().unwrap_or(-1), "string-builder");
+}
+
+#[test]
+fn test_power_of_two_arithmetic() {
+    let src = "fn main() returns i32 {\n  let a: i32 = 0 - 7\n  let n: i32 = 13\n  print_int(a / 4)\n  print_int(n / 8)\n  print_int(a * 8)\n  print_int(4 * n)\n  print_int((0 - 64) / 64)\n  return 0\n}\n";
+    let out = compile_source(src, "pow2", &["--emit=asm", "--arch=x86_64"]);
+    assert!(out.status.success());
+    let asm = fs::read_to_string(env::temp_dir().join("coatl-test-pow2").join("pow2.s")).unwrap();
+    let main = &asm[asm.find("\nmain:").unwrap()..];
+    let main = &main[..main.find("  ret").unwrap()];
+    assert!(!main.contains("imul") && !main.contains("idiv"), "{}", main);
+    assert!(main.contains("shl rax, 3") && main.contains("sar rax, 2"), "{}", main);
+
+    let release = compile_source("fn f(x: i32) returns i32 {\n  return (x + 0) * 1 / 1 - 0 + (1 * x) + (0 + x)\n}\n", "pow2-fold", &["--profile=release", "--emit=ir"]);
+    assert!(release.status.success());
+    let ir = fs::read_to_string(env::temp_dir().join("coatl-test-pow2-fold").join("pow2-fold.s")).unwrap();
+    assert!(ir.contains("(return (binary add (binary add (ident x) (ident x)) (ident x)))"), "{}", ir);
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-pow2");
+    let bin = build_bin(tmp_dir.join("pow2.coatl").to_str().unwrap(), "pow2", "x86_64").expect("build failed");
+    let output = Command::new(&bin).output().unwrap();
+    // Division still rounds toward zero for negative dividends.
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "-11-5652-1");
+}
+
+#[test]
+fn test_positional_io() {
+    let src = concat!(
+        "fn main() returns i32 {\n  __path_create(\"data.txt\", 2000)\n  let fd: i32 = __mem_load(2000)\n",
+        "  pwrite_bytes(fd, cstr_bytes(\"world\\n\"), 6)\n  pwrite_bytes(fd, cstr_bytes(\"hello \"), 0)\n  __fd_close(fd)\n",
+        "  __path_open(3, 0, \"data.txt\", 8, 1, 0, 0, 0, 2000)\n  fd = __mem_load(2000)\n",
+        "  let buf: []u8 = []u8 { ptr: 1024, len: 6 }\n  let n: i32 = pread_bytes(fd, buf, 6)\n  write_bytes(1, buf[0..n])\n",
+        "  n = pread_bytes(fd, buf, 0)\n  write_bytes(1, buf[0..n])\n  print_int(n)\n  return 0\n}\n",
+    );
+    let tmp_dir = env::temp_dir().join("coatl-test-pio");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src_path = tmp_dir.join("pio.coatl");
+    fs::write(&src_path, src).unwrap();
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    for (name, args) in [("pio", vec![]), ("pio-libc", vec!["--libc"])] {
+        let bin = tmp_dir.join(name);
+        let status = Command::new(get_coatl_bin()).arg(&src_path).args(&args).arg("-o").arg(&bin).status().unwrap();
+        assert!(status.success(), "{}", name);
+        let output = Command::new(&bin).current_dir(&tmp_dir).output().unwrap();
+        // Each read starts at its own offset, whatever the file position.
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "world\nhello 6", "{}", name);
+    }
+}
+
+#[test]
+fn test_sleep_ms() {
+    let src = "fn main() returns i32 {\n  __sleep_ms(0 - 5)\n  __sleep_ms(0)\n  return __sleep_ms(1250) + 7\n}\n";
+    let tmp_dir = env::temp_dir().join("coatl-test-sleep");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src_path = tmp_dir.join("sleep.coatl");
+    fs::write(&src_path, src).unwrap();
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let bin = build_bin(src_path.to_str().unwrap(), "sleep", "x86_64").expect("build failed");
+    let start = std::time::Instant::now();
+    let output = Command::new(&bin).output().unwrap();
+    let elapsed = start.elapsed();
+    assert_rc(7, output.status.code().unwrap_or(-1), "sleep");
+    assert!(elapsed >= std::time::Duration::from_millis(1250) && elapsed < std::time::Duration::from_secs(10), "{:?}", elapsed);
+}
+
+#[test]
+fn test_read_line() {
+    use std::io::Write;
+    let src = "fn main() returns i32 {\n  print_int(read_line(1024, 0))\n  let n: i32 = read_line(1024, 8)\n  while n > 0 {\n    print_int(n)\n    __print(\":\")\n    write_bytes(1, []u8 { ptr: 1024, len: n })\n    n = read_line(1024, 8)\n  }\n  return 0\n}\n";
+    let tmp_dir = env::temp_dir().join("coatl-test-read-line");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src_path = tmp_dir.join("read_line.coatl");
+    fs::write(&src_path, src).unwrap();
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let bin = build_bin(src_path.to_str().unwrap(), "read-line", "x86_64").expect("build failed");
+    let mut child = Command::new(&bin).stdin(std::process::Stdio::piped()).stdout(std::process::Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(b"ab\n\nlonger line\nend").unwrap();
+    let output = child.wait_with_output().unwrap();
+    // No room is an error, not the end of input. A line longer than the buffer comes back
+    // in pieces; the last needs no newline.
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "-223:ab\n1:\n8:longer l4:ine\n3:end");
+}
+
+#[test]
+fn test_read_write_file() {
+    let src = concat!(
+        "fn main() returns i32 {\n  let msg: i32 = \"saved contents\\n\"\n",
+        "  if write_file(\"out.txt\", msg, cstr_len(msg)) != 0 { return 1 }\n",
+        "  let n: i32 = read_file(\"out.txt\", 1024, 4096)\n  write_bytes(1, []u8 { ptr: 1024, len: n })\n",
+        "  print_int(read_file(\"out.txt\", 1024, 5))\n  print_int(read_file(\"missing.txt\", 1024, 16))\n",
+        "  print_int(write_file(\"no/such/dir/x\", msg, 1))\n  write_file(\"no/such/dir/x\", msg, 1)?\n  return 0\n}\n",
+    );
+    let tmp_dir = env::temp_dir().join("coatl-test-files");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let _ = fs::remove_file(tmp_dir.join("out.txt"));
+    let src_path = tmp_dir.join("files.coatl");
+    fs::write(&src_path, src).unwrap();
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let bin = build_bin(src_path.to_str().unwrap(), "files", "x86_64").expect("build failed");
+    let output = Command::new(&bin).current_dir(&tmp_dir).output().unwrap();
+    // Failed opens and creates return what `__path_open` and `__path_create` do, negated: -1.
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "saved contents\n5-1-1");
+    assert_rc(255, output.status.code().unwrap_or(-1), "files");
+    assert_eq!(fs::read_to_string(tmp_dir.join("out.txt")).unwrap(), "saved contents\n");
+}
+
+#[test]
+fn test_target_and_emit() {
+    let tmp_dir = env::temp_dir().join("coatl-test-emit");
+    let _ = fs::create_dir_all(&tmp_dir);
+    let src = tmp_dir.join("emit.coatl");
+    fs::write(&src, "fn main() returns i32 {\n  return 3\n}\n").unwrap();
+
+    let out = Command::new(get_coatl_bin()).arg(&src).args(["--target=aarch64-linux", "--emit=asm"]).output().unwrap();
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("ret"));
+    assert!(!String::from_utf8_lossy(&out.stdout).contains(".intel_syntax"));
+
+    // `--emit` wins over the extension of `-o`.
+    let ir_path = tmp_dir.join("emit.txt");
+    let status = Command::new(get_coatl_bin()).arg(&src).args(["--emit=ir", "-o"]).arg(&ir_path).status().unwrap();
+    assert!(status.success());
+    assert!(fs::read_to_string(&ir_path).unwrap().starts_with("(coatl_ir v1"));
+
+    let out = Command::new(get_coatl_bin()).arg(&src).arg("--target=riscv64-linux").output().unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("unknown --target `riscv64-linux`"));
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let obj = tmp_dir.join("emit.o");
+    let status = Command::new(get_coatl_bin()).arg(&src).args(["--target=x86_64-linux", "-o"]).arg(&obj).status().unwrap();
+    assert!(status.success());
+    assert_eq!(&fs::read(&obj).unwrap()[..4], b"\x7fELF");
+    let bin = tmp_dir.join("emit-bin");
+    let status = Command::new("cc").args(["-fPIE", "-pie", "-e", "coatl_start"]).arg(&obj).arg("-o").arg(&bin).status().unwrap();
+    assert!(status.success());
+    assert_rc(3, Command::new(&bin).status().unwrap().code().unwrap_or(-1), "emit-obj");
+}
+
+#[test]
+fn test_project_manifest() {
+    use std::io::Write;
+    let project = env::temp_dir().join("coatl-test-manifest");
+    let _ = fs::remove_dir_all(&project);
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(project.join("coatl.toml"), "# demo project\n[package]\nname = \"demo\"\n\n[build]\nmain = \"src/main.coatl\"\noutput = \"build/demo.ir\"  # relative to the manifest\nprelude = false\n").unwrap();
+    fs::write(project.join("src").join("main.coatl"), "fn main() returns i32 {\n  return 5\n}\n").unwrap();
+
+    // Run from a subdirectory: the manifest is found in a parent.
+    let status = Command::new(get_coatl_bin()).arg("build").current_dir(project.join("src")).status().unwrap();
+    assert!(status.success());
+    let ir = fs::read_to_string(project.join("build").join("demo.ir")).unwrap();
+    assert!(ir.contains("(fn main") && !ir.contains("prelude__"), "{}", ir);
+
+    // Flags after `build` override the manifest.
+    let status = Command::new(get_coatl_bin()).args(["build", "-o", "demo.s"]).current_dir(&project).status().unwrap();
+    assert!(status.success());
+    assert!(fs::read_to_string(project.join("demo.s")).unwrap().contains("main:"));
+
+    // `build -` compiles stdin as the root file, with the manifest's settings (here no
+    // prelude, so `println` is not resolved to it) or outside a project without them.
+    for (dir, prelude) in [(project.clone(), false), (env::temp_dir(), true)] {
+        let mut child = Command::new(get_coatl_bin()).args(["build", "-", "--emit=ir", "-o", "-"]).current_dir(dir)
+            .stdin(std::process::Stdio::piped()).stdout(std::process::Stdio::piped()).stderr(std::process::Stdio::piped()).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(b"fn main() returns i32 {\n  println(\"hi\")\n  return 77\n}\n").unwrap();
+        let out = child.wait_with_output().unwrap();
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+        assert_eq!(String::from_utf8_lossy(&out.stdout).contains("prelude__println"), prelude);
+    }
+
+    fs::write(project.join("coatl.toml"), "[package]\nname = \"demo\"\n[build]\nopt-level = 3\n").unwrap();
+    let out = Command::new(get_coatl_bin()).arg("build").current_dir(&project).output().unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("coatl.toml:4: unknown key `opt-level` in [build]"));
+}
+
+#[test]
+fn test_package_dependencies() {
+    let root = env::temp_dir().join("coatl-test-deps");
+    let _ = fs::remove_dir_all(&root);
+    for dir in ["app", "geom/shapes", "num", "loop"] { fs::create_dir_all(root.join(dir)).unwrap(); }
+    fs::write(root.join("app/coatl.toml"), "[package]\nname = \"app\"\n[build]\noutput = \"app.ir\"\n[dependencies]\ngeom = \"../geom\"\n").unwrap();
+    fs::write(root.join("app/main.coatl"), "import \"geom\"\nimport \"geom/shapes/square\"\nfn main() returns i32 {\n  return geom::perimeter(2, 3) + square::area(4)\n}\n").unwrap();
+    // `geom` has its own dependency and a manifest naming its root file.
+    fs::write(root.join("geom/coatl.toml"), "[package]\nname = \"geom\"\n[build]\nmain = \"lib.coatl\"\n[dependencies]\nnum = \"../num\"\n").unwrap();
+    fs::write(root.join("geom/lib.coatl"), "import \"num\"\npub fn perimeter(w: i32, h: i32) returns i32 {\n  return num::twice(w + h)\n}\n").unwrap();
+    fs::write(root.join("geom/shapes/square.coatl"), "pub fn area(s: i32) returns i32 {\n  return s * s\n}\n").unwrap();
+    // `num` has no manifest: its root file is main.coatl.
+    fs::write(root.join("num/main.coatl"), "pub fn twice(x: i32) returns i32 {\n  return x * 2\n}\n").unwrap();
+
+    let out = Command::new(get_coatl_bin()).arg("build").current_dir(root.join("app")).output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let ir = fs::read_to_string(root.join("app/app.ir")).unwrap();
+    for f in ["(fn geom__perimeter", "(fn square__area", "(fn num__twice"] { assert!(ir.contains(f), "{}\n{}", f, ir); }
+
+    // A package that depends on itself through another is reported, not followed.
+    fs::write(root.join("num/main.coatl"), "import \"loop\"\npub fn twice(x: i32) returns i32 {\n  return x * 2\n}\n").unwrap();
+    fs::write(root.join("num/coatl.toml"), "[package]\nname = \"num\"\n[dependencies]\nloop = \"../loop\"\n").unwrap();
+    fs::write(root.join("loop/coatl.toml"), "[package]\nname = \"loop\"\n[dependencies]\nnum = \"../num\"\n").unwrap();
+    fs::write(root.join("loop/main.coatl"), "import \"num\"\n").unwrap();
+    let out = Command::new(get_coatl_bin()).arg("build").current_dir(root.join("app")).output().unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("dependency cycle: num -> loop -> num"), "{}", String::from_utf8_lossy(&out.stderr));
+
+    let out = Command::new(get_coatl_bin()).arg(root.join("app/main.coatl")).arg("--dep=geom=missing").output().unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("dependency `geom` at missing"));
+}
+
+#[test]
+fn test_link_ir() {
+    let dir = env::temp_dir().join("coatl-test-link");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let files = [
+        ("main", "extern fn scale(x: i32) returns i32\nfn main() returns i32 {\n  println(\"main\")\n  return scale(20)\n}\n"),
+        ("lib", "pub fn scale(x: i32) returns i32 {\n  println(\"lib\")\n  return x + 2\n}\n"),
+        ("private", "fn scale(x: i32) returns i32 {\n  return x\n}\n"),
+        ("wide", "pub fn scale(x: i32, y: i32) returns i32 {\n  return x * y\n}\n"),
+    ];
+    for (name, source) in files {
+        fs::write(dir.join(format!("{}.coatl", name)), source).unwrap();
+        let status = Command::new(get_coatl_bin()).current_dir(&dir).args([format!("{}.coatl", name), "--emit=ir".to_string(), "-o".to_string(), format!("{}.ir", name)]).status().unwrap();
+        assert!(status.success(), "{}", name);
+    }
+
+    let out = Command::new(get_coatl_bin()).current_dir(&dir).args(["link", "main.ir", "lib.ir", "--emit=ir"]).output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let ir = String::from_utf8_lossy(&out.stdout);
+    // The extern is bound to lib's definition and the prelude's println is kept once.
+    assert!(ir.contains("(externs)") && ir.contains("(fn scale") && ir.matches("(fn prelude__println").count() == 1, "{}", ir);
+
+    for (other, message) in [
+        ("main.ir", "fn `main` is defined in both main.ir and main.ir"),
+        ("private.ir", "main.ir declares extern fn `scale`, but it is not pub in private.ir"),
+        ("wide.ir", "main.ir declares extern fn `scale(i32) returns i32`, but wide.ir defines it as `scale(i32, i32) returns i32`"),
+    ] {
+        let out = Command::new(get_coatl_bin()).current_dir(&dir).args(["link", "main.ir", other, "--emit=ir"]).output().unwrap();
+        assert!(!out.status.success(), "{}", other);
+        assert!(String::from_utf8_lossy(&out.stderr).contains(message), "{}", String::from_utf8_lossy(&out.stderr));
+    }
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let status = Command::new(get_coatl_bin()).current_dir(&dir).args(["link", "main.ir", "lib.ir", "-o", "linked"]).status().unwrap();
+    assert!(status.success());
+    let out = Command::new(dir.join("linked")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "main\nlib\n");
+    assert_rc(22, out.status.code().unwrap_or(-1), "linked");
+}
+
+#[test]
+fn test_build_profiles() {
+    let source = "fn main() returns i32 {\n  let x: i32 = 2 * 3 + 4 * (10 - 5)\n  if (1 < 2) { x = x + 1 }\n  return x + 2147483647 + 1 - 2147483647\n}\n";
+    let release = compile_source(source, "profile-release", &["--profile=release", "--emit=ir"]);
+    assert!(release.status.success());
+    let ir = fs::read_to_string(env::temp_dir().join("coatl-test-profile-release").join("profile-release.s")).unwrap();
+    assert!(ir.contains("(let x i32 (int 26))") && ir.contains("(if (int 1)"), "{}", ir);
+    // A sum past i32 is left to run time.
+    assert!(ir.contains("(binary add (binary add (ident x) (int 2147483647)) (int 1))"), "{}", ir);
+
+    let debug = compile_source(source, "profile-debug", &["--profile=debug"]);
+    assert!(debug.status.success());
+    let asm = fs::read_to_string(env::temp_dir().join("coatl-test-profile-debug").join("profile-debug.s")).unwrap();
+    assert!(asm.contains("__coatl_stack_limit"));
+
+    if env::consts::OS != "linux" || env::consts::ARCH != "x86_64" {
+        return;
+    }
+    let tmp_dir = env::temp_dir().join("coatl-test-profile-release");
+    let src = tmp_dir.join("profile-release.coatl");
+    for (profile, section, present) in [("release", &b".symtab"[..], false), ("debug", &b".debug_line"[..], true)] {
+        let bin = tmp_dir.join(format!("profile-{}", profile));
+        let status = Command::new(get_coatl_bin()).arg(&src).arg(format!("--profile={}", profile)).arg("-o").arg(&bin).status().unwrap();
+        assert!(status.success());
+        let bytes = fs::read(&bin).unwrap();
+        assert_eq!(bytes.windows(section.len()).any(|w| w == section), present, "{}", profile);
+        assert_rc(28, Command::new(&bin).status().unwrap().code().unwrap_or(-1), profile);
+    }
+}